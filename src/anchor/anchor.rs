@@ -0,0 +1,202 @@
+//!This module anchors bridged events on an EVM chain for tamper-evidence.
+//!The events delivered within each closed window, an hour by default, are
+//!hashed into a Merkle tree whose root is posted to the configured contract.
+//!The proof path of every event is stored next to the anchor, so anyone can
+//!later check an event against the root recorded on chain.
+
+use super::MerkleTree;
+use crate::common::config::AnchorConfig;
+use crate::common::error;
+use crate::common::validation;
+use crate::db::entities::prelude::{AnchorActiveModel, AnchorProofActiveModel};
+use crate::db::Storage;
+use alloy::network::EthereumWallet;
+use alloy::primitives::{Address, B256};
+use alloy::providers::ProviderBuilder;
+use alloy::signers::local::PrivateKeySigner;
+use alloy::sol;
+use chrono::{DateTime, TimeDelta, Utc};
+use sea_orm::Set;
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// Interval between two checks for closed windows.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+sol! {
+    #[sol(rpc)]
+    contract AclAnchor {
+        function anchor(bytes32 root, uint64 windowStart, uint64 windowEnd, uint32 eventCount) external;
+    }
+}
+
+/// Periodically anchors the Merkle roots of delivered events.
+pub struct Anchorer {
+    store: Storage,
+    rpc_url: url::Url,
+    contract: Address,
+    signer: PrivateKeySigner,
+    window: TimeDelta,
+}
+
+impl Anchorer {
+    pub fn new(config: &AnchorConfig, store: Storage) -> error::Result<Self> {
+        let rpc_url =
+            config
+                .rpc_url
+                .parse()
+                .map_err(|e: url::ParseError| error::Error::InvalidUrl {
+                    field: "anchor.rpc_url",
+                    value: config.rpc_url.clone(),
+                    reason: e.to_string(),
+                })?;
+
+        Ok(Self {
+            store,
+            rpc_url,
+            contract: validation::evm_address("anchor.contract_address", &config.contract_address)?,
+            signer: validation::evm_key("anchor.private_key", config.private_key.expose())?,
+            window: TimeDelta::seconds(config.window_secs as i64),
+        })
+    }
+
+    /// Anchors every window closed since the latest anchor, checking once a
+    /// minute.
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            match self.anchor_closed_windows().await {
+                Ok(0) => {}
+                Ok(windows) => tracing::info!("anchored {} windows", windows),
+                Err(e) => tracing::warn!("anchoring events failed: {}", e),
+            }
+        }
+    }
+
+    /// Anchors the windows following the latest anchor that have ended. The
+    /// first run starts with the window before the current one.
+    ///
+    /// # Returns
+    ///
+    /// The number of anchored windows.
+    async fn anchor_closed_windows(&self) -> error::Result<usize> {
+        let now = Utc::now();
+        let mut start = match self.store.latest_anchor().await? {
+            Some(anchor) => anchor.window_end.with_timezone(&Utc),
+            None => {
+                let window = self.window.num_seconds();
+                let floor = now.timestamp() - now.timestamp().rem_euclid(window);
+                DateTime::from_timestamp(floor, 0).unwrap_or(now) - self.window
+            }
+        };
+        let mut anchored = 0;
+        while start + self.window <= now {
+            let end = start + self.window;
+            self.anchor_window(start, end).await?;
+            anchored += 1;
+            start = end;
+        }
+
+        Ok(anchored)
+    }
+
+    /// Posts the root of the events delivered within `[start, end)` and
+    /// records it with the proof of every event. Empty windows are recorded
+    /// without a transaction.
+    async fn anchor_window(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> error::Result<()> {
+        let mut seen = HashSet::new();
+        let events: Vec<(String, [u8; 32])> = self
+            .store
+            .delivered_between(start, end)
+            .await?
+            .into_iter()
+            .filter(|row| seen.insert(row.event_id.clone()))
+            .filter_map(|row| {
+                let mut id = [0u8; 32];
+                hex::decode_to_slice(&row.event_id, &mut id).ok()?;
+                Some((row.event_id, id))
+            })
+            .collect();
+        let ids: Vec<[u8; 32]> = events.iter().map(|(_, id)| *id).collect();
+        let tree = MerkleTree::new(&ids);
+        let root = tree.root();
+
+        let tx_hash = if events.is_empty() {
+            None
+        } else {
+            Some(self.post(root, start, end, events.len() as u32).await?)
+        };
+        tracing::debug!(
+            "anchored {} events from {} to {} as {}",
+            events.len(),
+            start,
+            end,
+            root
+        );
+
+        let anchor = AnchorActiveModel {
+            window_start: Set(start.fixed_offset()),
+            window_end: Set(end.fixed_offset()),
+            root: Set(root.to_string()),
+            event_count: Set(events.len() as i32),
+            tx_hash: Set(tx_hash),
+            created_at: Set(Utc::now().fixed_offset()),
+            ..Default::default()
+        };
+        let proofs = events
+            .into_iter()
+            .enumerate()
+            .map(|(index, (event_id, _))| {
+                let proof: Vec<String> = tree.proof(index).iter().map(B256::to_string).collect();
+                Ok(AnchorProofActiveModel {
+                    event_id: Set(event_id),
+                    leaf_index: Set(index as i32),
+                    proof: Set(serde_json::to_string(&proof)?),
+                    ..Default::default()
+                })
+            })
+            .collect::<error::Result<Vec<_>>>()?;
+        self.store.add_anchor(anchor, proofs).await
+    }
+
+    /// Sends the anchoring transaction and waits for its receipt.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the transaction.
+    async fn post(
+        &self,
+        root: B256,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        events: u32,
+    ) -> error::Result<String> {
+        let failed = |e: String| error::Error::AnchorError(e);
+        let provider = ProviderBuilder::new()
+            .with_recommended_fillers()
+            .wallet(EthereumWallet::from(self.signer.clone()))
+            .on_http(self.rpc_url.clone());
+        let receipt = AclAnchor::new(self.contract, provider)
+            .anchor(
+                root,
+                start.timestamp() as u64,
+                end.timestamp() as u64,
+                events,
+            )
+            .send()
+            .await
+            .map_err(|e| failed(e.to_string()))?
+            .get_receipt()
+            .await
+            .map_err(|e| failed(e.to_string()))?;
+        if !receipt.status() {
+            return Err(failed(format!(
+                "transaction {} reverted",
+                receipt.transaction_hash
+            )));
+        }
+
+        Ok(receipt.transaction_hash.to_string())
+    }
+}
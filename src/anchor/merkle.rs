@@ -0,0 +1,122 @@
+//!This module builds the Merkle trees anchored on chain. Leaves are the
+//!keccak256 hashes of the raw 32 byte event ids, and each parent hashes its
+//!two children in ascending order, the layout OpenZeppelin's `MerkleProof`
+//!verifies. A node without a sibling is promoted to the next level as is.
+
+use alloy::primitives::{keccak256, B256};
+
+/// A Merkle tree over a batch of event ids.
+pub struct MerkleTree {
+    /// Levels of the tree, from the leaves up to the root.
+    levels: Vec<Vec<B256>>,
+}
+
+impl MerkleTree {
+    /// Builds the tree over `event_ids`, in the given order.
+    pub fn new(event_ids: &[[u8; 32]]) -> Self {
+        let mut levels = vec![event_ids.iter().map(keccak256).collect::<Vec<_>>()];
+        while levels.last().is_some_and(|level| level.len() > 1) {
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => hash_pair(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+
+        Self { levels }
+    }
+
+    /// Root of the tree, zero for an empty batch.
+    pub fn root(&self) -> B256 {
+        self.levels
+            .last()
+            .and_then(|level| level.first())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Sibling hashes from the leaf at `index` up to the root.
+    pub fn proof(&self, mut index: usize) -> Vec<B256> {
+        let mut proof = Vec::new();
+        for level in &self.levels[..self.levels.len().saturating_sub(1)] {
+            if let Some(sibling) = level.get(index ^ 1) {
+                proof.push(*sibling);
+            }
+            index /= 2;
+        }
+        proof
+    }
+}
+
+/// Hashes two nodes in ascending order.
+fn hash_pair(a: &B256, b: &B256) -> B256 {
+    let (low, high) = if a <= b { (a, b) } else { (b, a) };
+    let mut bytes = [0u8; 64];
+    bytes[..32].copy_from_slice(low.as_slice());
+    bytes[32..].copy_from_slice(high.as_slice());
+    keccak256(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(count: u8) -> Vec<[u8; 32]> {
+        (0..count).map(|i| [i; 32]).collect()
+    }
+
+    /// Folds a proof the way OpenZeppelin's `MerkleProof.verify` does.
+    fn verify(id: &[u8; 32], proof: &[B256], root: B256) -> bool {
+        proof
+            .iter()
+            .fold(keccak256(id), |node, sibling| hash_pair(&node, sibling))
+            == root
+    }
+
+    #[test]
+    fn empty_batch_has_a_zero_root() {
+        assert_eq!(MerkleTree::new(&[]).root(), B256::ZERO);
+    }
+
+    #[test]
+    fn single_leaf_is_the_root() {
+        let tree = MerkleTree::new(&ids(1));
+        assert_eq!(tree.root(), keccak256([0u8; 32]));
+        assert!(tree.proof(0).is_empty());
+    }
+
+    #[test]
+    fn pairs_are_hashed_in_ascending_order() {
+        let ids = ids(2);
+        let forward = MerkleTree::new(&ids);
+        let reversed = MerkleTree::new(&[ids[1], ids[0]]);
+        assert_eq!(forward.root(), reversed.root());
+    }
+
+    #[test]
+    fn every_proof_verifies() {
+        for count in 2..=7 {
+            let ids = ids(count);
+            let tree = MerkleTree::new(&ids);
+            for (index, id) in ids.iter().enumerate() {
+                assert!(
+                    verify(id, &tree.proof(index), tree.root()),
+                    "{count}/{index}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn proof_of_another_leaf_fails() {
+        let ids = ids(4);
+        let tree = MerkleTree::new(&ids);
+        assert!(!verify(&ids[0], &tree.proof(1), tree.root()));
+    }
+}
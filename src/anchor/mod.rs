@@ -0,0 +1,5 @@
+mod anchor;
+mod merkle;
+
+pub use anchor::*;
+pub use merkle::*;
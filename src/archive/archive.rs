@@ -0,0 +1,189 @@
+//!This module archives bridged Nostr events to S3-compatible object storage.
+//!On a schedule, the events recorded since the last run are written as a
+//!gzip compressed JSONL object, one event per line, together with a manifest
+//!describing it. The manifest of the latest batch doubles as the cursor of
+//!the next run, so the archive resumes where it stopped after a restart.
+
+use crate::common::config::ArchiveConfig;
+use crate::common::error;
+use crate::db::{self, DeliveryStatus};
+use chrono::Utc;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use object_store::aws::{AmazonS3, AmazonS3Builder};
+use object_store::path::Path;
+use object_store::{ObjectStore, PutPayload};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::time::Duration;
+
+/// Describes one archived object.
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    /// Key of the archived object.
+    object: String,
+    /// Database ids of the first and last rows covered by the batch.
+    first_row: i32,
+    last_row: i32,
+    /// Number of events in the object; dead-lettered rows are skipped.
+    events: usize,
+    /// Hex encoded SHA-256 of the compressed object.
+    sha256: String,
+    created_at: i64,
+}
+
+/// Periodically archives settled events.
+pub struct Archiver {
+    config: ArchiveConfig,
+    store: db::Storage,
+    bucket: AmazonS3,
+}
+
+impl Archiver {
+    /// Builds the object store client. Credentials not set in the config are
+    /// read from the standard `AWS_*` environment variables.
+    pub fn new(config: ArchiveConfig, store: db::Storage) -> error::Result<Self> {
+        let mut builder = AmazonS3Builder::from_env()
+            .with_bucket_name(&config.bucket)
+            .with_region(&config.region);
+        if let Some(endpoint) = &config.endpoint {
+            builder = builder
+                .with_endpoint(endpoint)
+                .with_allow_http(endpoint.starts_with("http://"));
+        }
+        if let Some(key) = &config.access_key_id {
+            builder = builder.with_access_key_id(key);
+        }
+        if let Some(secret) = &config.secret_access_key {
+            builder = builder.with_secret_access_key(secret.expose());
+        }
+
+        Ok(Self {
+            bucket: builder.build()?,
+            config,
+            store,
+        })
+    }
+
+    /// Archives the new events every `interval_secs` seconds.
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval_secs));
+        loop {
+            interval.tick().await;
+            match self.archive().await {
+                Ok(0) => {}
+                Ok(events) => tracing::info!("archived {} events", events),
+                Err(e) => tracing::warn!("archiving events failed: {}", e),
+            }
+        }
+    }
+
+    /// Archives every settled event recorded after the latest manifest, in
+    /// batches of `batch_size` rows.
+    ///
+    /// # Returns
+    ///
+    /// The number of archived events.
+    async fn archive(&self) -> error::Result<usize> {
+        let mut cursor = self.latest().await?.map_or(0, |manifest| manifest.last_row);
+        let mut archived = 0;
+        loop {
+            let rows = self
+                .store
+                .events_after(cursor, self.config.batch_size)
+                .await?;
+            let full = rows.len() as u64 == self.config.batch_size;
+            // Stop at the first pending row, so no event is skipped by the
+            // cursor before it settles.
+            let settled: Vec<_> = rows
+                .into_iter()
+                .take_while(|row| row.status != DeliveryStatus::Pending.as_str())
+                .collect();
+            let (Some(first), Some(last)) = (settled.first(), settled.last()) else {
+                return Ok(archived);
+            };
+            let (first_row, last_row) = (first.id, last.id);
+            let settled_all = settled.len() as u64 == self.config.batch_size;
+
+            let lines: Vec<&str> = settled
+                .iter()
+                .filter(|row| row.status == DeliveryStatus::Delivered.as_str())
+                .filter_map(|row| row.payload.as_deref())
+                .collect();
+            self.write_batch(first_row, last_row, &lines).await?;
+            archived += lines.len();
+            cursor = last_row;
+
+            if !(full && settled_all) {
+                return Ok(archived);
+            }
+        }
+    }
+
+    /// Writes one object and its manifest, then advances `latest.json`.
+    async fn write_batch(
+        &self,
+        first_row: i32,
+        last_row: i32,
+        lines: &[&str],
+    ) -> error::Result<()> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        for line in lines {
+            encoder.write_all(line.as_bytes())?;
+            encoder.write_all(b"\n")?;
+        }
+        let object = encoder.finish()?;
+
+        let now = Utc::now();
+        let key = format!(
+            "{}/events/{}/{:012}-{:012}.jsonl.gz",
+            self.config.prefix,
+            now.format("%Y/%m/%d"),
+            first_row,
+            last_row
+        );
+        let manifest = Manifest {
+            object: key.clone(),
+            first_row,
+            last_row,
+            events: lines.len(),
+            sha256: hex::encode(Sha256::digest(&object)),
+            created_at: now.timestamp(),
+        };
+        let manifest = serde_json::to_vec_pretty(&manifest)?;
+
+        self.bucket
+            .put(&Path::from(key.as_str()), PutPayload::from(object))
+            .await?;
+        let manifest_key = format!(
+            "{}/manifests/{:012}-{:012}.json",
+            self.config.prefix, first_row, last_row
+        );
+        self.bucket
+            .put(
+                &Path::from(manifest_key.as_str()),
+                PutPayload::from(manifest.clone()),
+            )
+            .await?;
+        self.bucket
+            .put(&self.latest_path(), PutPayload::from(manifest))
+            .await?;
+        tracing::debug!("archived rows {} to {} as {}", first_row, last_row, key);
+
+        Ok(())
+    }
+
+    fn latest_path(&self) -> Path {
+        Path::from(format!("{}/manifests/latest.json", self.config.prefix))
+    }
+
+    /// Reads the manifest of the latest batch, if any.
+    async fn latest(&self) -> error::Result<Option<Manifest>> {
+        match self.bucket.get(&self.latest_path()).await {
+            Ok(object) => Ok(Some(serde_json::from_slice(&object.bytes().await?)?)),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! Module for bridging historical events.
+//!
+//! The subcommand delivers the events created in a past time range to the
+//! sink of an `n2*` direction, next to or without a running gateway. The
+//! checkpoints of the live pipelines are left untouched.
+
+use crate::common::config;
+use crate::common::error;
+use crate::common::logging;
+use crate::services::{backfill, check_directions, App, BackfillOptions};
+use clap::Parser;
+use nostr_sdk::Timestamp;
+
+/// Represents the backfill subcommand parsed from the command line.
+#[derive(Debug, Clone, Parser)]
+pub struct BackfillCmd {
+    /// The direction whose sink the events are delivered to, one of the
+    /// directions fetching from nostr, e.g. 'n2w'.
+    #[arg(short, long, required = true)]
+    direction: String,
+
+    /// The path to the configuration file.
+    #[arg(short, long, value_name = "FILE", required = true)]
+    config_file: String,
+
+    /// The named profile of the configuration file to apply.
+    #[arg(short, long)]
+    profile: Option<String>,
+
+    /// Unix timestamp of the oldest event to bridge.
+    #[arg(long, required = true)]
+    since: u64,
+
+    /// Unix timestamp of the newest event to bridge, now by default.
+    #[arg(long)]
+    until: Option<u64>,
+
+    /// The seconds of history fetched at once.
+    #[arg(long, default_value_t = 3600)]
+    window_secs: u64,
+}
+
+impl BackfillCmd {
+    /// Runs the backfill and exits with a failure status if it failed or
+    /// an event could not be delivered.
+    pub async fn run(&self) {
+        let failed = match self.execute().await {
+            Ok(failed) => failed,
+            Err(e) => {
+                tracing::error!("backfill failed: {}", e);
+                logging::flush();
+                std::process::exit(e.exit_status());
+            }
+        };
+        logging::flush();
+        if failed > 0 {
+            std::process::exit(1);
+        }
+    }
+
+    /// Returns the number of events that could not be delivered.
+    async fn execute(&self) -> error::Result<u64> {
+        let config =
+            config::Config::load_profile(self.config_file.clone().into(), self.profile.as_deref())?;
+        let direction = check_directions([self.direction.as_str()])?[0];
+        let options = BackfillOptions {
+            since: self.since,
+            until: self.until.unwrap_or_else(|| Timestamp::now().as_u64()),
+            window_secs: self.window_secs,
+        };
+
+        let app = App::new(config).await?;
+        let report = backfill(&app, direction, options).await;
+        app.shutdown().await;
+        let report = report?;
+
+        println!(
+            "windows: {}, fetched: {}, delivered: {}, failed: {}",
+            report.windows, report.fetched, report.delivered, report.failed
+        );
+        Ok(report.failed)
+    }
+}
@@ -1,6 +1,13 @@
+use super::backfill_cmd::BackfillCmd;
+use super::config_cmd::ConfigCmd;
+use super::ctl_cmd::CtlCmd;
+use super::dlq_cmd::DlqCmd;
 use super::migrate_cmd::MigrateCmd;
 use super::run_cmd::RunCmd;
-use crate::common::consts;
+use super::status_cmd::StatusCmd;
+use crate::common::config::LogConfig;
+use crate::common::consts::{self, LOG_PATH};
+use crate::common::logging;
 use clap::{Parser, Subcommand};
 
 /// Main CLI structure
@@ -20,10 +27,28 @@ enum Commands {
 
     /// database migration
     Migrate(MigrateCmd),
+
+    /// configuration inspection
+    Config(ConfigCmd),
+
+    /// control a running server
+    Ctl(CtlCmd),
+
+    /// inspect and replay dead-lettered events
+    Dlq(DlqCmd),
+
+    /// bridge historical events
+    Backfill(BackfillCmd),
+
+    /// show the checkpoints and queues of the pipelines
+    Status(StatusCmd),
 }
 
 /// CLI processing logic
 /// This function encapsulates both parsing and command handling.
+///
+/// The `run` subcommand initializes logging itself once its configuration
+/// is loaded; every other subcommand uses the default logging setup.
 pub async fn handle_cli() {
     // Parse the CLI arguments
     let cli = Cli::parse();
@@ -33,6 +58,26 @@ pub async fn handle_cli() {
             cmd.run().await;
         }
         Some(Commands::Migrate(cmd)) => {
+            let _logging = logging::logging_init(LOG_PATH, &LogConfig::default(), None).unwrap();
+            cmd.run().await;
+        }
+        Some(Commands::Config(cmd)) => {
+            let _logging = logging::logging_init(LOG_PATH, &LogConfig::default(), None).unwrap();
+            cmd.run().await;
+        }
+        Some(Commands::Ctl(cmd)) => {
+            cmd.run().await;
+        }
+        Some(Commands::Dlq(cmd)) => {
+            let _logging = logging::logging_init(LOG_PATH, &LogConfig::default(), None).unwrap();
+            cmd.run().await;
+        }
+        Some(Commands::Backfill(cmd)) => {
+            let _logging = logging::logging_init(LOG_PATH, &LogConfig::default(), None).unwrap();
+            cmd.run().await;
+        }
+        Some(Commands::Status(cmd)) => {
+            let _logging = logging::logging_init(LOG_PATH, &LogConfig::default(), None).unwrap();
             cmd.run().await;
         }
         None => {
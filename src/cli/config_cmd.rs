@@ -0,0 +1,76 @@
+//! Module for inspecting configuration files from the command line.
+
+use crate::common::config;
+use crate::common::error;
+use clap::{Parser, Subcommand};
+
+/// Represents the `config` subcommand parsed from the command line.
+#[derive(Debug, Clone, Parser)]
+pub struct ConfigCmd {
+    #[command(subcommand)]
+    action: ConfigAction,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum ConfigAction {
+    /// Print the effective configuration with secrets redacted.
+    Show {
+        /// The path to the configuration file.
+        #[arg(short, long, value_name = "FILE")]
+        config_file: String,
+
+        /// The named profile of the configuration file to apply.
+        #[arg(short, long)]
+        profile: Option<String>,
+    },
+
+    /// Check the configuration, listing every invalid value.
+    Check {
+        /// The path to the configuration file.
+        #[arg(short, long, value_name = "FILE")]
+        config_file: String,
+
+        /// The named profile of the configuration file to apply.
+        #[arg(short, long)]
+        profile: Option<String>,
+    },
+
+    /// Print the JSON Schema of the configuration file format.
+    Schema,
+}
+
+impl ConfigCmd {
+    /// Handles the execution of the config subcommand.
+    pub async fn run(&self) {
+        if let Err(e) = self.execute() {
+            eprintln!("config failed: {}", e);
+            std::process::exit(e.exit_status());
+        }
+    }
+
+    fn execute(&self) -> error::Result<()> {
+        match &self.action {
+            ConfigAction::Show {
+                config_file,
+                profile,
+            } => {
+                let config = config::Config::load_profile(config_file.into(), profile.as_deref())?;
+                print!("{}", config.to_redacted_yaml()?);
+            }
+            ConfigAction::Check {
+                config_file,
+                profile,
+            } => match config::Config::load_profile(config_file.into(), profile.as_deref()) {
+                Ok(_) => println!("{}: valid", config_file),
+                Err(e) => {
+                    eprintln!("{}: {}", config_file, e);
+                    std::process::exit(e.exit_status());
+                }
+            },
+            ConfigAction::Schema => {
+                println!("{}", config::Config::json_schema()?);
+            }
+        }
+        Ok(())
+    }
+}
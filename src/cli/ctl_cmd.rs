@@ -0,0 +1,83 @@
+//! Module for controlling a running gateway over its control socket.
+
+use crate::common::error::{self, ResultExt};
+use crate::common::logging;
+use clap::{Parser, ValueEnum};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+/// The commands understood by the control socket.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum CtlCommand {
+    Status,
+    Pause,
+    Resume,
+    Replay,
+    Reload,
+}
+
+/// Represents the ctl subcommand parsed from the command line.
+#[derive(Debug, Clone, Parser)]
+pub struct CtlCmd {
+    /// The path of the control socket, `server.control_socket` of the gateway.
+    #[arg(short, long, value_name = "FILE", required = true)]
+    socket: String,
+
+    /// The command to send.
+    #[arg(value_enum)]
+    command: CtlCommand,
+
+    /// The id of the event to replay.
+    #[arg(required_if_eq("command", "replay"))]
+    event_id: Option<String>,
+}
+
+impl CtlCmd {
+    /// Sends the command to the gateway and prints its response. Exits with a
+    /// failure status if the gateway could not be reached or refused it.
+    pub async fn run(&self) {
+        match self.execute().await {
+            Ok(true) => {}
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                tracing::error!("ctl failed: {}", e);
+                logging::flush();
+                std::process::exit(e.exit_status());
+            }
+        }
+    }
+
+    /// Sends the command and prints the response, telling whether the
+    /// gateway carried the command out.
+    async fn execute(&self) -> error::Result<bool> {
+        let request = match self.command {
+            CtlCommand::Status => json!({ "command": "status" }),
+            CtlCommand::Pause => json!({ "command": "pause" }),
+            CtlCommand::Resume => json!({ "command": "resume" }),
+            CtlCommand::Replay => json!({ "command": "replay", "event_id": self.event_id }),
+            CtlCommand::Reload => json!({ "command": "reload" }),
+        };
+
+        let stream = UnixStream::connect(&self.socket)
+            .await
+            .context(|| format!("connecting to {}", self.socket))?;
+        let (reader, mut writer) = stream.into_split();
+        writer
+            .write_all(format!("{}\n", request).as_bytes())
+            .await
+            .context(|| format!("sending the command to {}", self.socket))?;
+
+        let line = BufReader::new(reader)
+            .lines()
+            .next_line()
+            .await
+            .context(|| format!("reading the response from {}", self.socket))?
+            .ok_or_else(|| {
+                error::Error::CustomError("the gateway closed the connection".to_string())
+            })?;
+        let response: Value = serde_json::from_str(&line)?;
+        println!("{}", serde_json::to_string_pretty(&response)?);
+        Ok(response["ok"] == Value::Bool(true))
+    }
+}
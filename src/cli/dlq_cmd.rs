@@ -0,0 +1,119 @@
+//! Module for inspecting and replaying dead-lettered events.
+//!
+//! The subcommand works on the database of the configuration file, so it
+//! does not need the gateway to run. A replayed event is handed to the retry
+//! queue and delivered by the next retry round of its direction.
+
+use crate::common::config;
+use crate::common::error;
+use crate::common::logging;
+use crate::db::{self, HistoryFilter};
+use clap::{Parser, Subcommand};
+
+/// The operations on the dead-letter queue.
+#[derive(Debug, Clone, Subcommand)]
+enum DlqCommand {
+    /// List the dead letters, newest first, with the error that failed them.
+    List {
+        /// Only list the dead letters of this direction, e.g. n2w.
+        #[arg(short, long)]
+        direction: Option<String>,
+
+        /// Only list the dead letters of this sink, e.g. waku.
+        #[arg(short, long)]
+        sink: Option<String>,
+
+        /// The maximum number of dead letters to list.
+        #[arg(short, long, default_value_t = 50)]
+        limit: u64,
+    },
+
+    /// Move a dead letter back into the pipeline of its direction.
+    Replay {
+        /// The id of the dead letter, as listed.
+        id: i64,
+    },
+
+    /// Delete the dead letters.
+    Purge {
+        /// Only delete the dead letters of this direction.
+        #[arg(short, long)]
+        direction: Option<String>,
+    },
+}
+
+/// Represents the dlq subcommand parsed from the command line.
+#[derive(Debug, Clone, Parser)]
+pub struct DlqCmd {
+    /// The path to the configuration file.
+    #[arg(short, long, value_name = "FILE", required = true)]
+    config_file: String,
+
+    /// The named profile of the configuration file to apply.
+    #[arg(short, long)]
+    profile: Option<String>,
+
+    #[command(subcommand)]
+    command: DlqCommand,
+}
+
+impl DlqCmd {
+    /// Runs the operation and exits with a failure status if it failed.
+    pub async fn run(&self) {
+        if let Err(e) = self.execute().await {
+            tracing::error!("dlq failed: {}", e);
+            logging::flush();
+            std::process::exit(e.exit_status());
+        }
+    }
+
+    async fn execute(&self) -> error::Result<()> {
+        let config =
+            config::Config::load_profile(self.config_file.clone().into(), self.profile.as_deref())?;
+        let store = db::Storage::connect(config.database).await?;
+
+        match &self.command {
+            DlqCommand::List {
+                direction,
+                sink,
+                limit,
+            } => {
+                let filter = HistoryFilter {
+                    direction: direction.clone(),
+                    sink: sink.clone(),
+                    ..Default::default()
+                };
+                let (rows, total) = store.dead_letters_page(&filter, 0, *limit).await?;
+                for row in &rows {
+                    println!(
+                        "{}\t{}\t{}\t{}\t{}\t{}",
+                        row.id,
+                        row.created_at.to_rfc3339(),
+                        row.direction,
+                        row.sink,
+                        row.event_id,
+                        row.error
+                    );
+                }
+                println!("{} of {} dead letters", rows.len(), total);
+            }
+            DlqCommand::Replay { id } => match store.replay_dead_letter(*id).await? {
+                Some(dead_letter) => println!(
+                    "event {} queued for delivery to {} by the {} pipeline",
+                    dead_letter.event_id, dead_letter.sink, dead_letter.direction
+                ),
+                None => {
+                    return Err(error::Error::CustomError(format!(
+                        "no dead letter with id {}",
+                        id
+                    )))
+                }
+            },
+            DlqCommand::Purge { direction } => {
+                let purged = store.purge_dead_letters(direction.as_deref()).await?;
+                println!("{} dead letters purged", purged);
+            }
+        }
+        Ok(())
+    }
+}
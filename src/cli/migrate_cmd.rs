@@ -10,6 +10,10 @@ pub struct MigrateCmd {
 
     #[arg(short, long)]
     config_file: Option<String>,
+
+    /// The named profile of the configuration file to apply.
+    #[arg(short, long, requires = "config_file")]
+    profile: Option<String>,
 }
 
 impl MigrateCmd {
@@ -24,7 +28,8 @@ impl MigrateCmd {
         }
 
         if let Some(config) = &self.config_file {
-            let config = config::Config::load_config(config.into()).unwrap();
+            let config =
+                config::Config::load_profile(config.into(), self.profile.as_deref()).unwrap();
 
             if let Ok(url) = url::Url::parse(&config.database.db_url) {
                 let db_name = url.path().trim_start_matches('/');
@@ -5,8 +5,13 @@
 //! It typically defines a function, such as `handle_cli`, which serves as the  
 //! entry point for the CLI application.
 
+mod backfill_cmd;
 mod cli;
+mod config_cmd;
+mod ctl_cmd;
+mod dlq_cmd;
 mod migrate_cmd;
 mod run_cmd;
+mod status_cmd;
 
 pub use cli::handle_cli;
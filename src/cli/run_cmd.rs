@@ -5,8 +5,13 @@
 //! and handle configuration files specified by the user.
 
 use crate::common::config;
-use crate::services::App;
+use crate::common::consts::UNAVAILABLE_EXIT_CODE;
+use crate::common::{crash, error_reporting, logging, systemd, telemetry};
+use crate::services::{
+    check_dependencies, check_directions, shutdown, simulate, Bridge, SimulationOptions,
+};
 use clap::Parser;
+use std::time::Duration;
 
 /// Represents the configuration subcommand parsed from the command line.  
 ///  
@@ -18,27 +23,180 @@ pub struct RunCmd {
     /// The direction of event:
     /// 'n2w' - from nostr to waku.
     /// 'w2n' - from waku to nostr.
-    /// 'n2i' - from waku to index db.
+    /// 'n2w2n' - from nostr to waku and back, without looping events.
+    /// 'n2i' - from nostr to index db.
+    /// 'w2i' - from waku to index db.
+    /// 'n2k' - from nostr to kafka.
+    /// 'k2n' - from kafka to nostr.
+    /// 'n2m' - from nostr to mqtt.
+    /// 'm2n' - from mqtt to nostr.
+    /// 'n2h' - from nostr to webhooks.
+    /// 'n2x' - from nostr to a matrix room.
+    /// 'n2q' - from nostr to an sqs queue or sns topic.
+    /// 'n2e' - from nostr to email recipients.
+    /// 'n2s' - from nostr to every sink listed in `sinks`.
+    ///
+    /// Several directions separated by commas, e.g. 'n2w,n2i', run
+    /// concurrently and share the relay connection and the database. Each
+    /// direction keeps its own checkpoints and dedupe records, so every
+    /// event reaches the sink of each direction.
     #[arg(short, long, required = true)]
     direction: String,
 
     /// The path to the configuration file.  
     #[arg(short, long, value_name = "FILE", required = true)]
     config_file: String,
+
+    /// The named profile of the configuration file to apply (e.g. dev, prod).
+    #[arg(short, long)]
+    profile: Option<String>,
+
+    /// Bridge synthetic events through in-memory stand-ins of the relay, the
+    /// database and the sink instead of the configured services. Only the
+    /// 'n2w' and 'n2i' directions can be simulated.
+    #[arg(long)]
+    simulate: bool,
+
+    /// The number of synthetic events of a simulated run.
+    #[arg(long, default_value_t = 100, requires = "simulate")]
+    simulate_events: u32,
+
+    /// The share of simulated deliveries failing with a transient error.
+    #[arg(long, default_value_t = 0.2, requires = "simulate")]
+    simulate_failure_rate: f64,
+
+    /// The seconds after which a simulated run gives up on undelivered events.
+    #[arg(long, default_value_t = 120, requires = "simulate")]
+    simulate_timeout: u64,
 }
 
 impl RunCmd {
     /// Handles the execution of the configuration subcommand.  
     pub async fn run(&self) {
-        let config = config::Config::load_config(self.config_file.clone().into()).unwrap();
-        let server = App::new(config).await.unwrap();
+        let config = match config::Config::load_profile(
+            self.config_file.clone().into(),
+            self.profile.as_deref(),
+        ) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("invalid configuration {}: {}", self.config_file, e);
+                std::process::exit(e.exit_status());
+            }
+        };
+        let _sentry = error_reporting::init(config.sentry.as_ref());
+        let _logging = logging::logging_init(
+            &config.log.directory,
+            &config.log,
+            config.telemetry.as_ref(),
+        )
+        .unwrap();
+
+        if self.simulate {
+            return self.run_simulation(config).await;
+        }
+
+        let directions = match check_directions(self.direction.split(',').map(str::trim)) {
+            Ok(directions) => directions,
+            Err(e) => {
+                tracing::error!("startup aborted: {}", e);
+                logging::flush();
+                std::process::exit(e.exit_status());
+            }
+        };
+
+        crash::set_database(config.database.clone());
+        systemd::init(config.systemd);
+        for direction in &directions {
+            if let Err(e) = check_dependencies(&config, direction).await {
+                tracing::error!("startup aborted: {}", e);
+                logging::flush();
+                std::process::exit(UNAVAILABLE_EXIT_CODE);
+            }
+        }
+        let bridge = directions
+            .iter()
+            .fold(Bridge::builder(config), |builder, direction| {
+                builder.direction(direction)
+            });
+        let bridge = match bridge.build().await {
+            Ok(bridge) => bridge,
+            Err(e) => {
+                tracing::error!("startup aborted: {}", e);
+                logging::flush();
+                std::process::exit(UNAVAILABLE_EXIT_CODE);
+            }
+        };
+        let server = bridge.app();
+        shutdown::listen();
+        server.start_heartbeat().unwrap();
+        server.start_peer_monitor();
+        if let Err(e) = server.start_archiver() {
+            tracing::error!("failed to start the archiver: {}", e);
+        }
+        if let Err(e) = server.start_anchorer() {
+            tracing::error!("failed to start the anchorer: {}", e);
+        }
+        server.start_admin();
+        server.start_grpc();
+        server.start_graphql();
+        server.start_health(&directions);
+        server.start_control(
+            self.config_file.clone().into(),
+            self.profile.clone(),
+            &self.direction,
+        );
+        server.start_config_watcher(self.config_file.clone().into(), self.profile.clone());
+        let _watchdog = systemd::spawn_watchdog();
         tracing::info!("{:?}", "HH");
 
-        match self.direction.as_str() {
-            "n2w" => server.from_nostr_to_waku().await,
-            "w2n" => server.from_waku_to_nostr().await,
-            "n2i" => server.from_nostr_to_indexdb().await,
-            _ => tracing::error!("unkown direction"),
+        bridge.run().await;
+
+        systemd::notify_stopping();
+        telemetry::shutdown();
+        logging::flush();
+    }
+
+    /// Runs the pipeline of the direction on synthetic events and exits
+    /// with a failure status unless every event was bridged exactly once.
+    async fn run_simulation(&self, config: config::Config) {
+        let direction = match self.direction.as_str() {
+            "n2w" => "n2w",
+            "n2i" => "n2i",
+            other => {
+                tracing::error!("direction `{}` cannot be simulated, use n2w or n2i", other);
+                logging::flush();
+                std::process::exit(1);
+            }
+        };
+        let options = SimulationOptions {
+            events: self.simulate_events,
+            failure_rate: self.simulate_failure_rate.clamp(0.0, 1.0),
+            timeout: Duration::from_secs(self.simulate_timeout),
+        };
+        let report = match simulate(config, direction, options).await {
+            Ok(report) => report,
+            Err(e) => {
+                tracing::error!("simulation aborted: {}", e);
+                logging::flush();
+                std::process::exit(e.exit_status());
+            }
+        };
+
+        println!(
+            "published: {}, delivered: {}, dead-lettered: {}, duplicated: {}, failed attempts: {}, missing: {}",
+            report.published,
+            report.delivered,
+            report.dead_lettered,
+            report.duplicated,
+            report.failed_attempts,
+            report.missing.len()
+        );
+        for event_id in &report.missing {
+            println!("missing event {}", event_id);
+        }
+        logging::flush();
+        if !report.is_success() {
+            std::process::exit(1);
         }
     }
 }
@@ -0,0 +1,239 @@
+//! Module for inspecting the state of the pipelines.
+//!
+//! The subcommand reads the checkpoints and the recorded, retried and
+//! dead-lettered events from the database of the configuration file, so it
+//! works whether the gateway runs or not. The peers of the embedded Waku node
+//! and the circuit breakers of the pipelines are asked from the admin API of
+//! the running gateway and omitted when it is not reachable.
+
+use crate::common::config;
+use crate::common::error;
+use crate::common::http::HttpClient;
+use crate::common::logging;
+use crate::common::retry::RetryPolicy;
+use crate::db;
+use crate::metrics::{BreakerStats, PeerStats};
+use crate::waku::TopicRouter;
+use clap::Parser;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Represents the status subcommand parsed from the command line.
+#[derive(Debug, Clone, Parser)]
+pub struct StatusCmd {
+    /// The path to the configuration file.
+    #[arg(short, long, value_name = "FILE", required = true)]
+    config_file: String,
+
+    /// The named profile of the configuration file to apply.
+    #[arg(short, long)]
+    profile: Option<String>,
+
+    /// Print the status as JSON instead of tab-separated tables.
+    #[arg(long)]
+    json: bool,
+}
+
+/// The checkpoint of a relay for a direction, or of a Waku content topic.
+#[derive(Debug, Serialize)]
+struct Checkpoint {
+    /// Unset for Waku and legacy checkpoints.
+    direction: Option<String>,
+    source: String,
+    /// Unix timestamp, in seconds for relays and nanoseconds for Waku.
+    last_update: i64,
+    updated_at: String,
+}
+
+/// The number of recorded events of a direction in a delivery status.
+#[derive(Debug, Serialize)]
+struct EventCount {
+    direction: Option<String>,
+    status: String,
+    count: u64,
+}
+
+/// The configured endpoints.
+#[derive(Debug, Serialize)]
+struct Endpoints {
+    relays: Vec<String>,
+    waku_send_api: String,
+    waku_store_api: Option<String>,
+    waku_pubsub_topic: String,
+    waku_content_topics: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Status {
+    endpoints: Endpoints,
+    relay_checkpoints: Vec<Checkpoint>,
+    waku_checkpoints: Vec<Checkpoint>,
+    events: Vec<EventCount>,
+    retries: u64,
+    dead_letters: u64,
+    /// Absent unless the admin API of the running gateway answered.
+    waku_peers: Option<PeerStats>,
+    /// Empty unless the admin API of the running gateway answered.
+    circuit_breakers: Vec<BreakerStats>,
+}
+
+impl StatusCmd {
+    /// Prints the status and exits with a failure status if it could not be
+    /// read.
+    pub async fn run(&self) {
+        if let Err(e) = self.execute().await {
+            tracing::error!("status failed: {}", e);
+            logging::flush();
+            std::process::exit(e.exit_status());
+        }
+    }
+
+    async fn execute(&self) -> error::Result<()> {
+        let config =
+            config::Config::load_profile(self.config_file.clone().into(), self.profile.as_deref())?;
+        let store = db::Storage::connect(config.database.clone()).await?;
+
+        let status = Status {
+            endpoints: Endpoints {
+                relays: config.nostr.relays(),
+                waku_send_api: config.waku.send_api.clone(),
+                waku_store_api: config.waku.store_api.clone(),
+                waku_pubsub_topic: config.waku.pubsub_topic.clone(),
+                waku_content_topics: TopicRouter::new(&config.waku)
+                    .topics()
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+            },
+            relay_checkpoints: store
+                .get_checkpoints()
+                .await?
+                .into_iter()
+                .map(|row| Checkpoint {
+                    direction: row.direction,
+                    source: row.relay.unwrap_or_else(|| config.nostr.ws_url.clone()),
+                    last_update: row.last_update,
+                    updated_at: row.updated_at.to_rfc3339(),
+                })
+                .collect(),
+            waku_checkpoints: store
+                .waku_checkpoints()
+                .await?
+                .into_iter()
+                .map(|row| Checkpoint {
+                    direction: None,
+                    source: row.content_topic,
+                    last_update: row.timestamp,
+                    updated_at: row.updated_at.to_rfc3339(),
+                })
+                .collect(),
+            events: store
+                .count_events()
+                .await?
+                .into_iter()
+                .map(|(direction, status, count)| EventCount {
+                    direction,
+                    status,
+                    count,
+                })
+                .collect(),
+            retries: store.count_retries().await?,
+            dead_letters: store.count_dead_letters().await?,
+            waku_peers: admin_get(&config, "peers").await.flatten(),
+            circuit_breakers: admin_get(&config, "breakers").await.unwrap_or_default(),
+        };
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&status)?);
+        } else {
+            print_tables(&status);
+        }
+        Ok(())
+    }
+}
+
+/// Asks the admin API of the running gateway for `path`, e.g. the peers of
+/// its Waku node.
+async fn admin_get<T: DeserializeOwned>(config: &config::Config, path: &str) -> Option<T> {
+    let retry = RetryPolicy {
+        max_attempts: 1,
+        ..RetryPolicy::default()
+    };
+    let http = HttpClient::new(&config.http, None, None, retry).ok()?;
+    let host = match config.server.host.as_str() {
+        "0.0.0.0" => "127.0.0.1",
+        host => host,
+    };
+    let url = format!("http://{}:{}/{}", host, config.server.port, path);
+    match http.get_json("admin api", &url, &[]).await {
+        Ok(value) => Some(value),
+        Err(e) => {
+            tracing::debug!("no {} from {}: {}", path, url, e);
+            None
+        }
+    }
+}
+
+/// Prints the status as tab-separated tables.
+fn print_tables(status: &Status) {
+    let endpoints = &status.endpoints;
+    println!("ENDPOINT\tVALUE");
+    for relay in &endpoints.relays {
+        println!("relay\t{}", relay);
+    }
+    println!("waku send api\t{}", endpoints.waku_send_api);
+    if let Some(store_api) = &endpoints.waku_store_api {
+        println!("waku store api\t{}", store_api);
+    }
+    println!("waku pubsub topic\t{}", endpoints.waku_pubsub_topic);
+    for topic in &endpoints.waku_content_topics {
+        println!("waku content topic\t{}", topic);
+    }
+
+    println!();
+    println!("DIRECTION\tCHECKPOINT\tLAST UPDATE\tUPDATED AT");
+    for checkpoint in status
+        .relay_checkpoints
+        .iter()
+        .chain(&status.waku_checkpoints)
+    {
+        println!(
+            "{}\t{}\t{}\t{}",
+            checkpoint.direction.as_deref().unwrap_or("-"),
+            checkpoint.source,
+            checkpoint.last_update,
+            checkpoint.updated_at
+        );
+    }
+
+    println!();
+    println!("DIRECTION\tSTATUS\tEVENTS");
+    for count in &status.events {
+        println!(
+            "{}\t{}\t{}",
+            count.direction.as_deref().unwrap_or("-"),
+            count.status,
+            count.count
+        );
+    }
+
+    println!();
+    println!("retry queue\t{}", status.retries);
+    println!("dead letters\t{}", status.dead_letters);
+    if let Some(peers) = &status.waku_peers {
+        println!(
+            "waku peers\t{} (min {}, {} redials)",
+            peers.connected, peers.min_peers, peers.redials
+        );
+    }
+    for breaker in &status.circuit_breakers {
+        println!(
+            "{} circuit breaker\t{} on {} ({} failures, {} trips)",
+            breaker.direction,
+            breaker.state.as_str(),
+            breaker.sink,
+            breaker.consecutive_failures,
+            breaker.trips
+        );
+    }
+}
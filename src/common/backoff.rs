@@ -0,0 +1,158 @@
+//! Exponential backoff with full jitter.
+//!
+//! Every retry and reconnect loop waits the same way: the delay after the
+//! n-th failure is drawn uniformly from `[0, min(max, base * 2^(n-1))]`, so
+//! clients that failed together do not retry together. An optional budget
+//! bounds the number of retries.
+
+use rand::Rng;
+use std::time::Duration;
+
+/// Produces the delays between consecutive attempts of an operation.
+///
+/// Used as an iterator, it yields one delay per retry and ends once the
+/// budget is exhausted.
+///
+/// # Examples
+/// ```
+/// use nostr_gateway::common::backoff::Backoff;
+/// use std::time::Duration;
+///
+/// // Stands for a connection refused twice before it succeeds.
+/// let mut refusals = 2;
+/// let mut connect = || -> Result<(), &'static str> {
+///     if refusals == 0 {
+///         return Ok(());
+///     }
+///     refusals -= 1;
+///     Err("connection refused")
+/// };
+///
+/// let mut backoff = Backoff::new(Duration::from_millis(5), Duration::from_millis(50))
+///     .with_budget(3);
+/// while let Err(e) = connect() {
+///     let Some(delay) = backoff.next() else {
+///         panic!("gave up: {}", e);
+///     };
+///     std::thread::sleep(delay);
+/// }
+/// assert_eq!(backoff.retries(), 2);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+    jitter: bool,
+    budget: Option<u32>,
+    retries: u32,
+}
+
+impl Backoff {
+    /// Creates an unbounded, jittered backoff starting at `base` and never
+    /// waiting more than `max`.
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            base,
+            max,
+            jitter: true,
+            budget: None,
+            retries: 0,
+        }
+    }
+
+    /// Limits the number of delays yielded, i.e. of retries.
+    pub fn with_budget(mut self, retries: u32) -> Self {
+        self.budget = Some(retries);
+        self
+    }
+
+    /// Enables or disables the jitter.
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Returns the number of delays yielded so far.
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Starts over, e.g. after the operation succeeded.
+    pub fn reset(&mut self) {
+        self.retries = 0;
+    }
+
+    /// Returns the delay to wait after the given failed attempt (1-based),
+    /// regardless of the budget.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exp = attempt.saturating_sub(1).min(31);
+        let ceiling = self.base.saturating_mul(1 << exp).min(self.max);
+
+        if self.jitter && !ceiling.is_zero() {
+            let ms = ceiling.as_millis() as u64;
+            Duration::from_millis(rand::thread_rng().gen_range(0..=ms))
+        } else {
+            ceiling
+        }
+    }
+}
+
+impl Iterator for Backoff {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        if self.budget.is_some_and(|budget| self.retries >= budget) {
+            return None;
+        }
+        self.retries += 1;
+        Some(self.delay(self.retries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn doubles_up_to_the_max() {
+        let backoff = Backoff::new(ms(100), ms(1000)).with_jitter(false);
+        let delays: Vec<_> = backoff.take(6).collect();
+        assert_eq!(
+            delays,
+            vec![ms(100), ms(200), ms(400), ms(800), ms(1000), ms(1000)]
+        );
+    }
+
+    #[test]
+    fn late_attempts_do_not_overflow() {
+        let backoff = Backoff::new(ms(100), ms(1000)).with_jitter(false);
+        assert_eq!(backoff.delay(u32::MAX), ms(1000));
+    }
+
+    #[test]
+    fn jitter_stays_below_the_ceiling() {
+        let backoff = Backoff::new(ms(100), ms(1000));
+        for attempt in 1..=10 {
+            let ceiling = ms(100 << (attempt - 1)).min(ms(1000));
+            assert!(backoff.delay(attempt) <= ceiling);
+        }
+    }
+
+    #[test]
+    fn budget_bounds_the_retries() {
+        let mut backoff = Backoff::new(ms(10), ms(100))
+            .with_jitter(false)
+            .with_budget(2);
+        assert_eq!(backoff.next(), Some(ms(10)));
+        assert_eq!(backoff.next(), Some(ms(20)));
+        assert_eq!(backoff.next(), None);
+        assert_eq!(backoff.retries(), 2);
+
+        backoff.reset();
+        assert_eq!(backoff.next(), Some(ms(10)));
+    }
+}
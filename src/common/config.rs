@@ -1,56 +1,1403 @@
+use crate::common::consts;
 use crate::common::error;
-use serde::Deserialize;
+use crate::common::rate_limit::RateLimit;
+use crate::common::retry::RetryPolicy;
+use crate::common::secret::{self, Secret};
+use crate::common::validation;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct ServerConfig {
     pub host: String,
     pub port: String,
+    /// Port of the gRPC API on `host`, which is disabled when unset.
+    pub grpc_port: Option<String>,
+    /// Port of the read-only GraphQL API on `host`, which is disabled when
+    /// unset.
+    pub graphql_port: Option<String>,
+    /// Path of the local control socket, which is disabled when unset.
+    pub control_socket: Option<String>,
+    /// Port of the `/healthz` and `/readyz` endpoints on `host`, which are
+    /// disabled when unset.
+    pub health_port: Option<String>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct DatabaseConfig {
     pub db_url: String,
     pub max_connect_pool: u32,
     pub min_connect_pool: u32,
     pub connect_timeout: u64,
     pub acquire_timeout: u64,
+    /// Number of dedupe and checkpoint writes held in memory while the
+    /// database is unavailable.
+    #[serde(default = "default_fallback_buffer")]
+    pub fallback_buffer: usize,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+fn default_fallback_buffer() -> usize {
+    10_000
+}
+
+/// TLS settings for an HTTP client.
+///
+/// The relay pool of `nostr-sdk` takes no TLS settings, so `nostr.tls` is
+/// rejected for `wss` relays, which are verified against the system trust
+/// store. Relays behind a private CA need it in the system store.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct TlsConfig {
+    /// Path to a PEM file with additional root certificates.
+    pub ca_bundle: Option<String>,
+    /// Skip certificate verification. Only meant for lab environments.
+    #[serde(default)]
+    pub danger_accept_invalid_certs: bool,
+}
+
+/// Where the `n2i` pipeline delivers its invites.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexdbSink {
+    /// POST to `invite_url`, or the url of the ACL event type.
+    #[default]
+    Http,
+    /// Publish to the JetStream subject of the `nats` section.
+    Nats,
+    /// Append to the stream of the `redis` section.
+    Redis,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct IndexdbBackendConfig {
     pub invite_url: String,
+    /// Endpoint of the `auth` events, which are skipped when unset.
+    pub auth_url: Option<String>,
+    /// Endpoint of the `grant` events, which are skipped when unset.
+    pub grant_url: Option<String>,
+    /// Endpoint of the `revoke` events, which are skipped when unset.
+    pub revoke_url: Option<String>,
+    /// Endpoint of the `membership` events, which are skipped when unset.
+    pub membership_url: Option<String>,
+    pub tls: Option<TlsConfig>,
+    /// Overrides the global retry policy for IndexDB deliveries.
+    pub retry: Option<RetryPolicy>,
+    /// Limits the rate of the IndexDB posts, unlimited when unset.
+    pub rate_limit: Option<RateLimit>,
+    #[serde(default)]
+    pub sink: IndexdbSink,
+    /// Envelope of the invites posted over HTTP.
+    #[serde(default)]
+    pub format: SinkFormat,
+}
+
+/// Payload a sink delivers for each bridged event.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkPayload {
+    /// The nostr event as JSON.
+    Event,
+    /// The ACL invite converted from the event, as posted to IndexDB.
+    #[default]
+    Invite,
+}
+
+/// Envelope of the payloads a sink delivers.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkFormat {
+    /// The payload as is.
+    #[default]
+    Raw,
+    /// The payload as the `data` of a CloudEvents 1.0 event in structured
+    /// JSON mode, whose `id` is the event id, `source` the relay url and
+    /// `type` the ACL event type.
+    CloudEvents,
+}
+
+/// Webhook sink settings of the `n2h` direction.
+///
+/// Templates may reference `{{id}}`, `{{pubkey}}`, `{{kind}}`,
+/// `{{created_at}}`, `{{content}}`, `{{correlation_id}}` and `{{payload}}`,
+/// the latter being the encoded `payload`.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct WebhookConfig {
+    /// Urls every event is posted to.
+    pub urls: Vec<String>,
+    /// Body template, defaults to `{{payload}}`. Values are JSON escaped when
+    /// the content type is JSON.
+    pub body_template: Option<String>,
+    #[serde(default = "default_webhook_content_type")]
+    pub content_type: String,
+    /// Extra headers, whose values are templates too.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Secret the body is signed with, using HMAC-SHA256.
+    pub secret: Option<Secret<String>>,
+    /// Header carrying the `sha256=<hex>` signature.
+    #[serde(default = "default_webhook_signature_header")]
+    pub signature_header: String,
+    #[serde(default)]
+    pub payload: SinkPayload,
+    /// Envelope of the payload. With `cloudevents`, set `content_type` to
+    /// `application/cloudevents+json`.
+    #[serde(default)]
+    pub format: SinkFormat,
+    pub tls: Option<TlsConfig>,
+    /// Overrides the global retry policy for webhook posts.
+    pub retry: Option<RetryPolicy>,
+}
+
+fn default_webhook_content_type() -> String {
+    "application/json".to_string()
+}
+
+fn default_webhook_signature_header() -> String {
+    "X-Signature-256".to_string()
+}
+
+/// Matrix sink settings of the `n2x` direction.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct MatrixConfig {
+    /// Base url of the homeserver, e.g. `https://matrix.example.com`.
+    pub homeserver_url: String,
+    /// Id of the room to post to, e.g. `!abc:example.com`.
+    pub room_id: String,
+    /// Access token of the posting user, which must have joined the room.
+    pub access_token: Secret<String>,
+    /// ACL event types posted to the room; other events are skipped.
+    #[serde(default = "default_matrix_event_types")]
+    pub event_types: Vec<String>,
+    pub tls: Option<TlsConfig>,
+    /// Overrides the global retry policy for Matrix messages.
+    pub retry: Option<RetryPolicy>,
+}
+
+fn default_matrix_event_types() -> Vec<String> {
+    vec!["invite".to_string(), "revoke".to_string()]
+}
+
+/// SMTP sink settings of the `n2e` direction.
+///
+/// The subject and body templates may reference `{{event_type}}`,
+/// `{{inviter}}`, `{{invitee}}`, `{{project}}`, `{{id}}`, `{{pubkey}}` and
+/// `{{created_at}}`.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct SmtpConfig {
+    /// Host of the SMTP server, e.g. `smtp.example.com`.
+    pub host: String,
+    #[serde(default = "default_smtp_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    /// Sender of the notifications, e.g. `ACL bridge <acl@example.com>`.
+    pub from: String,
+    /// Recipients per ACL event type; events of other types are skipped.
+    pub recipients: BTreeMap<String, Vec<String>>,
+    #[serde(default = "default_smtp_subject")]
+    pub subject: String,
+    #[serde(default = "default_smtp_body")]
+    pub body: String,
+}
+
+/// Encryption of the connection to the SMTP server.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Plain connection upgraded with STARTTLS, usually on port 587.
+    #[default]
+    Starttls,
+    /// TLS from the start, usually on port 465.
+    Tls,
+    /// No encryption, for local relays only.
+    None,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_smtp_subject() -> String {
+    "ACL {{event_type}} on project {{project}}".to_string()
+}
+
+fn default_smtp_body() -> String {
+    "{{inviter}} sent a {{event_type}} to {{invitee}} on project {{project}}.\n\nEvent: {{id}}\nCreated at: {{created_at}}\n".to_string()
+}
+
+/// Archival of bridged events to S3-compatible object storage.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ArchiveConfig {
+    pub bucket: String,
+    #[serde(default = "default_archive_region")]
+    pub region: String,
+    /// Endpoint of an S3-compatible store, e.g. `http://127.0.0.1:9000`.
+    pub endpoint: Option<String>,
+    /// Read from `AWS_ACCESS_KEY_ID` when unset.
+    pub access_key_id: Option<String>,
+    /// Read from `AWS_SECRET_ACCESS_KEY` when unset.
+    pub secret_access_key: Option<Secret<String>>,
+    /// Key prefix of the objects and manifests.
+    #[serde(default = "default_archive_prefix")]
+    pub prefix: String,
+    #[serde(default = "default_archive_interval")]
+    pub interval_secs: u64,
+    /// Maximum number of events per object.
+    #[serde(default = "default_archive_batch_size")]
+    pub batch_size: u64,
+}
+
+fn default_archive_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_archive_prefix() -> String {
+    "acl-archive".to_string()
+}
+
+fn default_archive_interval() -> u64 {
+    3600
+}
+
+fn default_archive_batch_size() -> u64 {
+    10_000
+}
+
+/// Anchoring of the Merkle roots of bridged events to an EVM contract.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct AnchorConfig {
+    /// JSON-RPC endpoint of the chain, e.g. `https://rpc.example.org`.
+    pub rpc_url: String,
+    /// Address of the contract exposing
+    /// `anchor(bytes32 root, uint64 windowStart, uint64 windowEnd, uint32 eventCount)`.
+    pub contract_address: String,
+    /// Hex encoded key of the account sending the anchoring transactions.
+    pub private_key: Secret<String>,
+    /// Length of the anchored windows.
+    #[serde(default = "default_anchor_window")]
+    pub window_secs: u64,
+}
+
+fn default_anchor_window() -> u64 {
+    3600
+}
+
+/// Offloading of large event payloads to IPFS on the Waku and IndexDB
+/// pipelines.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct IpfsConfig {
+    /// RPC API of the IPFS node, e.g. `http://127.0.0.1:5001`.
+    pub api_url: String,
+    /// Events whose JSON encoding has at least this many bytes are pinned,
+    /// and only their CID and hash are forwarded.
+    #[serde(default = "default_ipfs_min_size")]
+    pub min_size_bytes: usize,
+    pub tls: Option<TlsConfig>,
+    /// Overrides the global retry policy for IPFS requests.
+    pub retry: Option<RetryPolicy>,
+}
+
+fn default_ipfs_min_size() -> usize {
+    64 * 1024
+}
+
+/// MQTT sink and source settings of the `n2m` and `m2n` directions.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct MqttConfig {
+    /// Broker host name or address.
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    /// Client id prefix; `-sink` or `-source` is appended.
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    /// Topic bridged events are published to.
+    pub topic: String,
+    /// Topic events are consumed from, defaults to `topic`.
+    pub source_topic: Option<String>,
+    /// Quality of service level: 0, 1 or 2.
+    #[serde(default = "default_mqtt_qos")]
+    pub qos: u8,
+    #[serde(default = "default_mqtt_keep_alive")]
+    pub keep_alive_secs: u64,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_client_id() -> String {
+    "nostr_gateway".to_string()
+}
+
+fn default_mqtt_qos() -> u8 {
+    1
+}
+
+fn default_mqtt_keep_alive() -> u64 {
+    30
+}
+
+/// NATS JetStream sink settings.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct NatsConfig {
+    /// Server url, e.g. `nats://127.0.0.1:4222`.
+    pub url: String,
+    /// Subject of a JetStream stream to publish to.
+    pub subject: String,
+    /// Credentials file for decentralized auth.
+    pub credentials_file: Option<String>,
+    #[serde(default)]
+    pub payload: SinkPayload,
+}
+
+/// Redis Streams sink settings.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct RedisConfig {
+    /// Server url, e.g. `redis://127.0.0.1:6379/0`.
+    pub url: String,
+    /// Key of the stream to append to.
+    pub stream: String,
+    /// How long the dedupe key of an appended event is kept.
+    #[serde(default = "default_redis_dedupe_ttl")]
+    pub dedupe_ttl_secs: u64,
+    #[serde(default)]
+    pub payload: SinkPayload,
+}
+
+fn default_redis_dedupe_ttl() -> u64 {
+    7 * 24 * 60 * 60
+}
+
+/// A WASM plugin transforming or filtering the events of the pipelines.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct PluginConfig {
+    /// Path of the compiled module.
+    pub path: String,
+    /// Directions the plugin applies to, e.g. `n2w`; all when empty.
+    #[serde(default)]
+    pub directions: Vec<String>,
+    /// Fuel budget of one call, roughly the number of executed instructions.
+    #[serde(default = "default_plugin_fuel")]
+    pub fuel: u64,
+}
+
+fn default_plugin_fuel() -> u64 {
+    10_000_000
+}
+
+/// A rule filtering or altering the events of the pipelines, see
+/// `transform`.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct TransformRule {
+    /// Directions the rule applies to, e.g. `n2w`; all when empty.
+    #[serde(default)]
+    pub directions: Vec<String>,
+    /// The events the rule applies to; all when empty.
+    #[serde(default, rename = "match")]
+    pub matches: TransformMatch,
+    pub action: TransformAction,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+/// Conditions an event must all meet for a rule to apply. Each list
+/// matches if any of its entries does, or if it is empty.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct TransformMatch {
+    #[serde(default)]
+    pub kinds: Vec<u16>,
+    /// Tags as `name:value`, e.g. `t:acl`, or `name` for any value.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Authors, in hex or bech32 (`npub`) form.
+    #[serde(default)]
+    pub pubkeys: Vec<String>,
+    /// Regular expression the content must match.
+    pub content: Option<String>,
+}
+
+/// What a rule does to the events it matches.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransformAction {
+    /// Drops the event.
+    Drop,
+    /// Replaces the value `from` of the tags `name` with `to`.
+    RewriteTag {
+        name: String,
+        from: String,
+        to: String,
+    },
+    /// Removes the tags with this name.
+    StripTag(String),
+    /// Empties the content.
+    StripContent,
+    /// Delivers the event to this topic of the sink instead of its
+    /// configured one. Only the waku, kafka and mqtt sinks route events.
+    Route(String),
+}
+
+/// AWS sink settings of the `n2q` direction. Exactly one of `queue_url` and
+/// `topic_arn` must be set.
+///
+/// Credentials default to the standard AWS chain (environment, profile,
+/// instance or task role). Static keys replace it, and `role_arn` is assumed
+/// with the resulting credentials.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct SqsConfig {
+    /// Url of the SQS queue to send to.
+    pub queue_url: Option<String>,
+    /// Arn of the SNS topic to publish to.
+    pub topic_arn: Option<String>,
+    /// Read from `AWS_REGION` when unset.
+    pub region: Option<String>,
+    /// Endpoint of an AWS-compatible service, e.g. `http://127.0.0.1:4566`.
+    pub endpoint: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<Secret<String>>,
+    /// Role assumed through STS.
+    pub role_arn: Option<String>,
+    /// Message group of FIFO queues and topics.
+    #[serde(default = "default_sqs_message_group_id")]
+    pub message_group_id: String,
+    /// Maximum number of messages per batch, at most 10.
+    #[serde(default = "default_sqs_batch_size")]
+    pub batch_size: usize,
+    /// How long a batch waits for more messages before it is sent.
+    #[serde(default = "default_sqs_linger_ms")]
+    pub linger_ms: u64,
+    #[serde(default)]
+    pub payload: SinkPayload,
+    #[serde(default)]
+    pub format: SinkFormat,
+}
+
+fn default_sqs_message_group_id() -> String {
+    "acl-events".to_string()
+}
+
+fn default_sqs_batch_size() -> usize {
+    10
+}
+
+fn default_sqs_linger_ms() -> u64 {
+    100
+}
+
+/// Kafka producer and consumer settings of the `n2k` and `k2n` directions.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct KafkaConfig {
+    /// Comma separated `host:port` list of bootstrap brokers.
+    pub brokers: String,
+    /// Topic bridged events are produced to.
+    pub topic: String,
+    /// Topic events are consumed from, defaults to `topic`.
+    pub source_topic: Option<String>,
+    /// Consumer group of the `k2n` direction.
+    #[serde(default = "default_kafka_group_id")]
+    pub group_id: String,
+    /// Extra librdkafka properties, e.g. `security.protocol: SASL_SSL`.
+    #[serde(default)]
+    pub properties: BTreeMap<String, String>,
+    /// Envelope of the produced events.
+    #[serde(default)]
+    pub format: SinkFormat,
+}
+
+fn default_kafka_group_id() -> String {
+    "nostr_gateway".to_string()
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct WakuConfig {
     pub node_url: String,
     pub send_api: String,
     pub pubsub_topic: String,
     pub content_topic: String,
+    /// Content topics of the events of given kinds or hashtags, the first
+    /// matching route winning. The other events go to `content_topic`.
+    #[serde(default)]
+    pub routes: Vec<WakuTopicRoute>,
     pub node_addr: String,
-    pub cluster_id: String,
-    pub shared: String,
+    /// Cluster of the relay shards, e.g. 1 for The Waku Network. Older
+    /// configs give it as a string.
+    #[serde(deserialize_with = "deserialize_cluster_id")]
+    #[schemars(with = "u16")]
+    pub cluster_id: u16,
+    /// Shards of the cluster the node relays, as a list or a comma separated
+    /// string. Read from `shared` in older configs.
+    #[serde(alias = "shared", deserialize_with = "deserialize_shards")]
+    #[schemars(with = "Vec<u32>")]
+    pub shards: Vec<u32>,
     pub waku_bin: String,
+    /// Hex encoded secp256k1 key of the embedded node, for a stable peer id.
+    /// A random key is generated when absent.
+    pub node_key: Option<Secret<String>>,
+    pub tls: Option<TlsConfig>,
+    /// Store query REST endpoint, e.g. `http://127.0.0.1:8645/store/v3/messages`,
+    /// used at startup to check whether pending events were already published.
+    pub store_api: Option<String>,
+    /// Overrides the global retry policy for Waku publishes.
+    pub retry: Option<RetryPolicy>,
+    /// Limits the rate of the requests to the Waku REST API, unlimited when
+    /// unset.
+    pub rate_limit: Option<RateLimit>,
+    /// How the `n2w` and `w2n` pipelines reach the relay mesh.
+    #[serde(default)]
+    pub transport: WakuTransport,
+    /// How messages are published: through the relay of the node, or pushed
+    /// to a lightpush service node. With the `rest` transport, the protocol
+    /// `send_api` speaks.
+    #[serde(default)]
+    pub publish: WakuPublish,
+    /// Overrides `http.timeout_secs` for the requests to the Waku REST API.
+    pub timeout_secs: Option<u64>,
+    /// Settings of the `gossipsub` transport.
+    #[serde(default)]
+    pub gossipsub: GossipsubConfig,
+    /// Peer monitoring of the embedded node.
+    #[serde(default)]
+    pub peers: WakuPeersConfig,
+    /// Encryption of the published payloads, disabled when unset. Every
+    /// bridge on the content topics needs the matching key.
+    pub encryption: Option<WakuEncryption>,
+}
+
+impl WakuConfig {
+    /// Returns the cluster and shard of `pubsub_topic` if it names a static
+    /// shard, i.e. is of the form `/waku/2/rs/<cluster>/<shard>`.
+    pub fn pubsub_shard(&self) -> Option<(u16, u32)> {
+        let (cluster, shard) = self
+            .pubsub_topic
+            .strip_prefix("/waku/2/rs/")?
+            .split_once('/')?;
+        Some((cluster.parse().ok()?, shard.parse().ok()?))
+    }
+}
+
+/// A number, or a string holding one or several comma separated numbers.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumbersValue {
+    Number(u64),
+    List(Vec<u64>),
+    Text(String),
+}
+
+impl NumbersValue {
+    fn into_numbers<E: serde::de::Error>(self) -> Result<Vec<u64>, E> {
+        match self {
+            NumbersValue::Number(n) => Ok(vec![n]),
+            NumbersValue::List(list) => Ok(list),
+            NumbersValue::Text(text) => text
+                .split(',')
+                .map(str::trim)
+                .filter(|n| !n.is_empty())
+                .map(|n| {
+                    n.parse()
+                        .map_err(|_| E::custom(format!("{:?} is not a number", n)))
+                })
+                .collect(),
+        }
+    }
+}
+
+fn deserialize_cluster_id<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<u16, D::Error> {
+    use serde::de::Error;
+    match NumbersValue::deserialize(deserializer)?
+        .into_numbers::<D::Error>()?
+        .as_slice()
+    {
+        [id] => u16::try_from(*id)
+            .map_err(|_| D::Error::custom(format!("cluster id {} is out of range", id))),
+        _ => Err(D::Error::custom("expected a single cluster id")),
+    }
+}
+
+fn deserialize_shards<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<u32>, D::Error> {
+    use serde::de::Error;
+    NumbersValue::deserialize(deserializer)?
+        .into_numbers::<D::Error>()?
+        .into_iter()
+        .map(|shard| {
+            u32::try_from(shard)
+                .map_err(|_| D::Error::custom(format!("shard {} is out of range", shard)))
+        })
+        .collect()
+}
+
+/// Key material of the Waku payload encryption, see `waku::PayloadCipher`.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum WakuEncryption {
+    /// AES-256-GCM with a shared key.
+    Symmetric {
+        /// Hex encoded 32 byte key.
+        key: Secret<String>,
+    },
+    /// ECIES to the holder of a secp256k1 key.
+    Asymmetric {
+        /// Recipient key, in hex or bech32 (`npub`) form, to publish.
+        public_key: Option<String>,
+        /// Recipient secret key, in hex or bech32 (`nsec`) form, to receive.
+        secret_key: Option<Secret<String>>,
+    },
+}
+
+/// Publishes the events of any of `kinds`, or tagged with any of `hashtags`,
+/// to `content_topic`.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct WakuTopicRoute {
+    pub content_topic: String,
+    #[serde(default)]
+    pub kinds: Vec<u16>,
+    /// Values of `t` tags, e.g. `invite`.
+    #[serde(default)]
+    pub hashtags: Vec<String>,
+}
+
+/// Transport of the Waku pipelines.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WakuTransport {
+    /// Publish through the REST API of a Waku node.
+    #[default]
+    Rest,
+    /// Join the gossipsub mesh directly. Requires the `gossipsub` feature.
+    Gossipsub,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+/// Protocol of the publishes through the Waku REST API.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WakuPublish {
+    /// Relay the message from the node, e.g. to `/relay/v1/auto/messages`.
+    #[default]
+    Relay,
+    /// Push the message through a lightpush service node, e.g. to
+    /// `/lightpush/v1/message`, for nodes that do not run relay themselves.
+    Lightpush,
+}
+
+/// Direct gossipsub transport settings.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct GossipsubConfig {
+    /// Multiaddress the swarm listens on.
+    pub listen_addr: String,
+    /// Multiaddresses of the peers dialed at startup; `node_addr` when empty.
+    pub bootstrap_peers: Vec<String>,
+}
+
+impl Default for GossipsubConfig {
+    fn default() -> Self {
+        Self {
+            listen_addr: "/ip4/0.0.0.0/tcp/60000".to_string(),
+            bootstrap_peers: Vec::new(),
+        }
+    }
+}
+
+/// Peer monitoring of the embedded Waku node.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct WakuPeersConfig {
+    /// The bootstrap peers are dialed again when fewer peers are connected.
+    pub min_peers: usize,
+    /// Interval between two checks of the connected peers, in seconds.
+    pub check_interval_secs: u64,
+    /// Multiaddresses dialed besides `node_addr`.
+    pub bootstrap_peers: Vec<String>,
+    /// `enrtree://` url whose peers are discovered over DNS and dialed too.
+    pub dns_discovery_url: Option<String>,
+}
+
+impl Default for WakuPeersConfig {
+    fn default() -> Self {
+        Self {
+            min_peers: 1,
+            check_interval_secs: 30,
+            bootstrap_peers: Vec::new(),
+            dns_discovery_url: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct NostrConfig {
-    pub priv_key: String,
+    pub priv_key: Secret<String>,
     pub ws_url: String,
+    /// Additional relays fetched from and published to, besides `ws_url`.
+    /// Each relay keeps its own checkpoint.
+    #[serde(default)]
+    pub ws_urls: Vec<String>,
+    /// How new events are received from the relays.
+    #[serde(default)]
+    pub mode: NostrMode,
+    /// Answers the NIP-42 AUTH challenges of the relays with `priv_key`,
+    /// again after every reconnect, as private and paid relays require.
+    #[serde(default)]
+    pub auth: bool,
+    /// The events fetched from and subscribed to on the relays, matching any
+    /// of the filters. Defaults to the text notes tagged `#waku`.
+    #[serde(default)]
+    pub filters: Vec<NostrFilter>,
+    /// Overrides the global retry policy for Nostr publishes.
+    pub retry: Option<RetryPolicy>,
+    /// Limits the rate of the Nostr publishes, unlimited when unset.
+    pub rate_limit: Option<RateLimit>,
+    /// Only accepted without a CA bundle nor `danger_accept_invalid_certs`,
+    /// which the relay websockets can not honour.
+    pub tls: Option<TlsConfig>,
+}
+
+/// A filter of the events received from the relays. Events match if they
+/// match every non-empty field.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct NostrFilter {
+    /// Event kinds.
+    #[serde(default)]
+    pub kinds: Vec<u16>,
+    /// Author public keys, in hex or bech32 (`npub`) form.
+    #[serde(default)]
+    pub authors: Vec<String>,
+    /// Values of the `t` tag, without the `#`.
+    #[serde(default)]
+    pub hashtags: Vec<String>,
+    /// Unix timestamp of the oldest event, later than the checkpoint.
+    pub since: Option<u64>,
+    /// Unix timestamp of the newest event.
+    pub until: Option<u64>,
+}
+
+/// How the fetch loops receive new events from the relays.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NostrMode {
+    /// Fetch the events newer than the checkpoint every
+    /// `sync.poll_interval_secs`.
+    #[default]
+    Poll,
+    /// Keep a subscription open and receive the events as they are
+    /// published, catching up by polling after a restart or a lag.
+    Subscribe,
+}
+
+impl NostrConfig {
+    /// Returns `ws_url` followed by `ws_urls`, without duplicates.
+    pub fn relays(&self) -> Vec<String> {
+        let mut relays = vec![self.ws_url.clone()];
+        for url in &self.ws_urls {
+            if !relays.contains(url) {
+                relays.push(url.clone());
+            }
+        }
+        relays
+    }
+}
+
+/// Which nostr relays are reached through the SOCKS5 proxy.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyTarget {
+    /// Every relay connection goes through the proxy.
+    #[default]
+    All,
+    /// Only `.onion` relays go through the proxy (typical Tor setup).
+    Onion,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ProxyConfig {
+    /// Proxy used by the HTTP clients (Waku REST, IndexDB).
+    /// Accepts `http://`, `https://`, `socks5://` and `socks5h://` urls.
+    pub http_url: Option<String>,
+    /// SOCKS5 proxy address (`host:port`) used for nostr relay websockets,
+    /// e.g. `127.0.0.1:9050` for a local Tor daemon.
+    pub nostr_socks5: Option<String>,
+    #[serde(default)]
+    pub nostr_target: ProxyTarget,
+}
+
+/// Output format of the console and file logs.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, for Loki/Elasticsearch ingestion.
+    Json,
+}
+
+/// When the log file is rotated.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    /// One file per process start.
+    Never,
+    Hourly,
+    #[default]
+    Daily,
+    /// A new file every `max_size_mb` megabytes.
+    Size,
+}
+
+/// Logging settings.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// Filter directives, e.g. `info,waku=debug`, applied unless the
+    /// `RUST_LOG` environment variable is set.
+    pub level: String,
+    /// Directory of the log files.
+    pub directory: String,
+    pub rotation: LogRotation,
+    /// File size limit for the `size` rotation, in megabytes.
+    pub max_size_mb: u64,
+    /// Keep at most this many log files.
+    pub max_files: Option<usize>,
+    /// Delete log files older than this many days.
+    pub max_age_days: Option<u64>,
+    /// Repeated errors of the same class within this many seconds are
+    /// collapsed into a single "repeated N times" line. `0` disables it.
+    pub dedup_window_secs: u64,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Text,
+            level: consts::LOG_DEFAULT_LEVEL.to_string(),
+            directory: consts::LOG_PATH.to_string(),
+            rotation: LogRotation::Daily,
+            max_size_mb: 100,
+            max_files: None,
+            max_age_days: None,
+            dedup_window_secs: 60,
+        }
+    }
+}
+
+/// OpenTelemetry export settings.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct TelemetryConfig {
+    /// OTLP gRPC endpoint of the collector, e.g. `http://localhost:4317`.
+    pub otlp_endpoint: String,
+    /// Value of the `service.name` resource attribute.
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+}
+
+fn default_service_name() -> String {
+    "nostr_gateway".to_string()
+}
+
+/// Periodic heartbeat published so remote monitors can detect a dead bridge.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct HeartbeatConfig {
+    /// Seconds between two heartbeats.
+    #[serde(default = "default_heartbeat_interval")]
+    pub interval_secs: u64,
+    /// Waku content topic the heartbeat is published to.
+    pub waku_content_topic: Option<String>,
+    /// Nostr event kind the heartbeat is published as.
+    pub nostr_kind: Option<u16>,
+    /// `d` tag of the heartbeat events, so relays keep the latest heartbeat
+    /// of each gateway. Gateways sharing a key need distinct identifiers.
+    /// Defaults to `nostr_gateway/heartbeat`.
+    pub identifier: Option<String>,
+}
+
+fn default_heartbeat_interval() -> u64 {
+    60
+}
+
+/// Alarm on a pipeline checkpoint that stopped being committed.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct CheckpointAlarmConfig {
+    /// Seconds the checkpoint may go without a commit before alerting.
+    #[serde(default = "default_checkpoint_sla")]
+    pub sla_secs: u64,
+    /// Seconds between two checks.
+    #[serde(default = "default_checkpoint_check_interval")]
+    pub check_interval_secs: u64,
+}
+
+fn default_checkpoint_sla() -> u64 {
+    300
+}
+
+fn default_checkpoint_check_interval() -> u64 {
+    60
+}
+
+/// Thresholds, in milliseconds, above which an operation is reported as slow.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct SlowOpsConfig {
+    pub fetch_ms: u64,
+    pub db_ms: u64,
+    pub waku_ms: u64,
+    pub indexdb_ms: u64,
+    /// Deliveries to pluggable sinks, e.g. Kafka.
+    pub sink_ms: u64,
+}
+
+impl Default for SlowOpsConfig {
+    fn default() -> Self {
+        Self {
+            fetch_ms: 5_000,
+            db_ms: 500,
+            waku_ms: 2_000,
+            indexdb_ms: 2_000,
+            sink_ms: 2_000,
+        }
+    }
+}
+
+/// Hard limits, in seconds, on a single external operation. An operation
+/// still pending after its limit is abandoned and fails with a retryable
+/// timeout error, so a hung peer cannot stall a pipeline.
+/// Pacing of the fetch loops.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// Interval between two fetch rounds, in seconds.
+    pub poll_interval_secs: u64,
+    /// Maximum number of events fetched from a relay per round.
+    pub batch_limit: usize,
+    /// Events queued between a fetch loop and its sender task.
+    pub channel_capacity: usize,
+    /// Queued events above which the fetch rounds wait for the sender,
+    /// half of `channel_capacity` when unset.
+    pub high_watermark: Option<usize>,
+}
+
+impl SyncConfig {
+    /// Returns the queued events above which the fetch rounds wait.
+    pub fn high_watermark(&self) -> usize {
+        self.high_watermark
+            .unwrap_or(self.channel_capacity / 2)
+            .min(self.channel_capacity)
+    }
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_secs: 10,
+            batch_limit: 100,
+            channel_capacity: 100,
+            high_watermark: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct TimeoutsConfig {
+    pub fetch_secs: u64,
+    pub db_secs: u64,
+    /// Bounds each attempt of a Waku publish, and the store query of the
+    /// embedded node.
+    pub waku_secs: u64,
+    /// Bounds each attempt of an IndexDB post.
+    pub indexdb_secs: u64,
+    /// Bounds each delivery attempt to the other sinks, e.g. Kafka.
+    pub sink_secs: u64,
+}
+
+impl Default for TimeoutsConfig {
+    fn default() -> Self {
+        Self {
+            fetch_secs: 30,
+            db_secs: 15,
+            waku_secs: 120,
+            indexdb_secs: 120,
+            sink_secs: 60,
+        }
+    }
+}
+
+/// Timeouts shared by every HTTP client.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Upper bound for a whole request, in seconds.
+    pub timeout_secs: u64,
+    /// Upper bound for establishing a connection, in seconds.
+    pub connect_timeout_secs: u64,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 30,
+            connect_timeout_secs: 10,
+        }
+    }
+}
+
+/// Delivery attempt limits of the pipeline senders.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct DeliveryConfig {
+    /// Attempts after which an event that keeps failing, or keeps crashing
+    /// the sender, is moved to the dead-letter queue.
+    pub max_attempts: u32,
+    /// Delay before retrying a failed delivery for the first time, in
+    /// milliseconds. Later retries double it.
+    pub retry_base_delay_ms: u64,
+    /// Upper bound of the delay between two delivery attempts, in
+    /// milliseconds.
+    pub retry_max_delay_ms: u64,
+    /// Stops delivering to a sink that keeps failing, disabled when unset.
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+}
+
+impl Default for DeliveryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            retry_base_delay_ms: 1_000,
+            retry_max_delay_ms: 300_000,
+            circuit_breaker: None,
+        }
+    }
+}
+
+/// Circuit breaker around the sink of the `n2*` pipelines.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct CircuitBreakerConfig {
+    /// Transient failures in a row after which the breaker opens.
+    pub failure_threshold: u32,
+    /// Time the breaker stays open before probing the sink, in seconds.
+    pub open_secs: u64,
+    /// Successful probes in a row after which the breaker closes.
+    pub probe_successes: u32,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_secs: 30,
+            probe_successes: 1,
+        }
+    }
+}
+
+/// Dependency checks run before the pipelines start.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct StartupConfig {
+    /// Timeout of a single dependency check, in seconds.
+    pub check_timeout_secs: u64,
+    /// Wait for unavailable dependencies instead of exiting at once.
+    pub wait: bool,
+    /// How long to wait for the dependencies, in seconds.
+    pub deadline_secs: u64,
+    /// Delay before the first re-check, in milliseconds.
+    pub base_delay_ms: u64,
+    /// Upper bound of the delay between two checks, in milliseconds.
+    pub max_delay_ms: u64,
+}
+
+impl Default for StartupConfig {
+    fn default() -> Self {
+        Self {
+            check_timeout_secs: 10,
+            wait: false,
+            deadline_secs: 300,
+            base_delay_ms: 1000,
+            max_delay_ms: 30_000,
+        }
+    }
+}
+
+/// Graceful shutdown on SIGINT or SIGTERM.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct ShutdownConfig {
+    /// Time given to the sender tasks to deliver the queued events, in
+    /// seconds. Events still queued afterwards are delivered by the next run.
+    pub drain_timeout_secs: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            drain_timeout_secs: 30,
+        }
+    }
+}
+
+/// Restart policy of the pipeline tasks after a panic.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct SupervisorConfig {
+    /// Restarts after which a task is given up.
+    pub max_restarts: u32,
+    /// Delay before the first restart, in milliseconds.
+    pub base_delay_ms: u64,
+    /// Upper bound of the delay between two restarts, in milliseconds.
+    pub max_delay_ms: u64,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            base_delay_ms: 1_000,
+            max_delay_ms: 60_000,
+        }
+    }
+}
+
+/// Traffic counters reported by the `/stats` endpoint.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct StatsConfig {
+    /// Windows, in seconds, recent traffic is counted over.
+    pub windows_secs: Vec<u64>,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self {
+            windows_secs: vec![60, 900, 3600],
+        }
+    }
+}
+
+/// Webhook alerting on sustained failures.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct AlertingConfig {
+    /// Slack compatible incoming webhook url.
+    pub webhook_url: String,
+    /// Failure ratio (0 to 1) of a sink above which an alert fires.
+    #[serde(default = "default_error_rate_threshold")]
+    pub error_rate_threshold: f64,
+    /// Sliding window, in seconds, the failure ratio is computed over.
+    #[serde(default = "default_alert_window")]
+    pub window_secs: u64,
+    /// Minimum number of deliveries in the window before alerting.
+    #[serde(default = "default_alert_min_events")]
+    pub min_events: usize,
+    /// Minimum seconds between two alerts of the same kind.
+    #[serde(default = "default_alert_cooldown")]
+    pub cooldown_secs: u64,
+    /// Dead-letter queue size above which an alert fires.
+    pub dead_letter_limit: Option<u64>,
+}
+
+fn default_error_rate_threshold() -> f64 {
+    0.5
+}
+
+fn default_alert_window() -> u64 {
+    300
+}
+
+fn default_alert_min_events() -> usize {
+    10
+}
+
+fn default_alert_cooldown() -> u64 {
+    900
+}
+
+/// Destinations of the audit trail of bridged events.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct AuditConfig {
+    /// Record to the `audit_log` table.
+    #[serde(default = "default_true")]
+    pub database: bool,
+    /// Also append JSON lines to this file.
+    pub file: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Sentry error reporting settings.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct SentryConfig {
+    pub dsn: Secret<String>,
+    pub environment: Option<String>,
+    /// Fraction of errors that are reported, between 0 and 1.
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: f32,
+}
+
+fn default_sample_rate() -> f32 {
+    1.0
+}
+
+/// Which timestamp of an event drives the `since` checkpoint.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampSource {
+    /// The `created_at` reported inside the event.
+    #[default]
+    CreatedAt,
+    /// The local time at which the bridge received the event.
+    ReceivedAt,
+}
+
+/// What to do with an event whose `created_at` is too far in the future.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FutureTimestampPolicy {
+    /// Use the receive time instead.
+    #[default]
+    Clamp,
+    /// Forward the event but do not let it move the checkpoint.
+    Ignore,
+}
+
+/// Settings governing how event timestamps advance the checkpoint.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct TimestampConfig {
+    pub source: TimestampSource,
+    /// Allowed clock skew, in seconds, for `created_at` values in the future.
+    pub max_future_skew_secs: u64,
+    pub on_future: FutureTimestampPolicy,
+}
+
+impl Default for TimestampConfig {
+    fn default() -> Self {
+        Self {
+            source: TimestampSource::CreatedAt,
+            max_future_skew_secs: 300,
+            on_future: FutureTimestampPolicy::Clamp,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Config {
     pub server: ServerConfig,
     pub database: DatabaseConfig,
     pub indexdb_backend: IndexdbBackendConfig,
     pub waku: WakuConfig,
     pub nostr: NostrConfig,
+    /// Kafka sink and source, required by the `n2k` and `k2n` directions.
+    pub kafka: Option<KafkaConfig>,
+    /// NATS JetStream sink, required by `indexdb_backend.sink: nats`.
+    pub nats: Option<NatsConfig>,
+    /// Redis Streams sink, required by `indexdb_backend.sink: redis`.
+    pub redis: Option<RedisConfig>,
+    /// MQTT sink and source, required by the `n2m` and `m2n` directions.
+    pub mqtt: Option<MqttConfig>,
+    /// SQS or SNS sink, required by the `n2q` direction.
+    pub sqs: Option<SqsConfig>,
+    /// Webhook sink, required by the `n2h` direction.
+    pub webhook: Option<WebhookConfig>,
+    /// Matrix sink, required by the `n2x` direction.
+    pub matrix: Option<MatrixConfig>,
+    /// SMTP sink, required by the `n2e` direction.
+    pub smtp: Option<SmtpConfig>,
+    /// Archival of bridged events, disabled when unset.
+    pub archive: Option<ArchiveConfig>,
+    /// WASM plugins run on every event between source and sink, in order.
+    #[serde(default)]
+    pub plugins: Vec<PluginConfig>,
+    /// Rules run on every event after the plugins, in order.
+    #[serde(default)]
+    pub transforms: Vec<TransformRule>,
+    /// Offloading of large payloads to IPFS, disabled when unset.
+    pub ipfs: Option<IpfsConfig>,
+    /// On-chain anchoring of bridged events, disabled when unset.
+    pub anchor: Option<AnchorConfig>,
+    pub proxy: Option<ProxyConfig>,
+    /// Retry policy used by every sink without its own `retry` section.
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub timestamps: TimestampConfig,
+    /// Exports tracing spans over OTLP when present.
+    pub telemetry: Option<TelemetryConfig>,
+    #[serde(default)]
+    pub log: LogConfig,
+    /// Reports panics and errors to Sentry when present.
+    pub sentry: Option<SentryConfig>,
+    /// Publishes periodic heartbeats when present.
+    pub heartbeat: Option<HeartbeatConfig>,
+    /// Records an audit trail of bridged events when present.
+    pub audit: Option<AuditConfig>,
+    #[serde(default)]
+    pub slow_ops: SlowOpsConfig,
+    #[serde(default)]
+    pub timeouts: TimeoutsConfig,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    /// Posts alerts to a webhook when present.
+    pub alerting: Option<AlertingConfig>,
+    /// Alerts on a stale database checkpoint when present.
+    pub checkpoint_alarm: Option<CheckpointAlarmConfig>,
+    #[serde(default)]
+    pub stats: StatsConfig,
+    #[serde(default)]
+    pub supervisor: SupervisorConfig,
+    #[serde(default)]
+    pub delivery: DeliveryConfig,
+    #[serde(default)]
+    pub startup: StartupConfig,
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    /// Sinks the `n2s` direction delivers every event to, in order, e.g.
+    /// `[waku, indexdb]`. Each one is configured by its own section.
+    #[serde(default)]
+    pub sinks: Vec<String>,
+    /// Sends readiness and watchdog notifications to systemd.
+    #[serde(default)]
+    pub systemd: bool,
 }
 
+/// Top level key holding the named profiles of a config file.
+const PROFILES_KEY: &str = "profiles";
+
 impl Config {
+    /// Returns the sink specific retry policy, falling back to the global one.
+    pub fn retry_policy(&self, sink: Option<&RetryPolicy>) -> RetryPolicy {
+        sink.unwrap_or(&self.retry).clone()
+    }
+
     pub fn load_config(path: PathBuf) -> error::Result<Config> {
+        Self::load_profile(path, None)
+    }
+
+    /// Loads the config file and applies the named profile on top of it.
+    ///
+    /// A config file may contain a `profiles` mapping (e.g. `dev`, `staging`,
+    /// `prod`). Each profile only lists the values that differ from the base
+    /// document; they are merged recursively over the base before it is
+    /// deserialized. Without a profile the `profiles` section is ignored.
+    ///
+    /// Secrets can be kept out of the file: `${VAR}` in a string value is
+    /// replaced by the environment variable `VAR`, and a `<key>_env: VAR`
+    /// entry sets `<key>` to the value of `VAR`, e.g. `priv_key_env:
+    /// NOSTR_KEY`. Both apply to the merged document and yield strings.
+    ///
+    /// The resulting configuration is validated, see `validation::validate`.
+    pub fn load_profile(path: PathBuf, profile: Option<&str>) -> error::Result<Config> {
         let p: &Path = path.as_ref();
         let config_yaml = std::fs::read_to_string(p).map_err(|err| match err {
             e @ std::io::Error { .. } if e.kind() == std::io::ErrorKind::NotFound => {
@@ -59,8 +1406,230 @@ impl Config {
             _ => err.into(),
         })?;
 
-        let config: Config =
+        let mut doc: Value =
             serde_yaml::from_str(&config_yaml).map_err(error::Error::SerializationError)?;
+        let profiles = match doc.as_mapping_mut() {
+            Some(map) => map.remove(PROFILES_KEY),
+            None => None,
+        };
+
+        if let Some(name) = profile {
+            let overlay = profiles
+                .as_ref()
+                .and_then(|p| p.get(name))
+                .cloned()
+                .ok_or_else(|| {
+                    error::Error::InvalidConfig(format!("profile '{}' not found", name))
+                })?;
+            merge_yaml(&mut doc, overlay);
+        }
+
+        let mut missing = Vec::new();
+        resolve_env(&mut doc, &mut missing);
+        if !missing.is_empty() {
+            return Err(error::Error::InvalidConfig(format!(
+                "environment variables not set: {}",
+                missing.join(", ")
+            )));
+        }
+
+        let config: Config =
+            serde_yaml::from_value(doc).map_err(error::Error::SerializationError)?;
+        validation::validate(&config)?;
         Ok(config)
     }
 }
+
+impl Config {
+    /// Renders the effective configuration as YAML with secrets redacted.
+    ///
+    /// The `Secret` settings (private keys, passwords, tokens...) serialize as
+    /// a placeholder and the password part of any url (e.g. the database url)
+    /// is masked, so the output can be attached to support tickets safely.
+    pub fn to_redacted_yaml(&self) -> error::Result<String> {
+        let mut doc = serde_yaml::to_value(self).map_err(error::Error::SerializationError)?;
+        mask_url_passwords(&mut doc);
+        serde_yaml::to_string(&doc).map_err(error::Error::SerializationError)
+    }
+}
+
+impl Config {
+    /// Returns the JSON Schema describing the config file format, suitable
+    /// for editor validation of the YAML and for linting configs in CI.
+    pub fn json_schema() -> error::Result<String> {
+        let schema = schemars::schema_for!(Config);
+        serde_json::to_string_pretty(&schema)
+            .map_err(|e| error::Error::CustomError(format!("schema serialization: {}", e)))
+    }
+}
+
+/// Recursively masks the password part of the urls of a YAML document in
+/// place.
+fn mask_url_passwords(value: &mut Value) {
+    match value {
+        Value::Mapping(map) => map.values_mut().for_each(mask_url_passwords),
+        Value::Sequence(seq) => seq.iter_mut().for_each(mask_url_passwords),
+        Value::String(s) => {
+            if let Ok(mut url) = url::Url::parse(s) {
+                if url.password().is_some() && url.set_password(Some(secret::REDACTED)).is_ok() {
+                    *s = url.to_string();
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Suffix of the keys naming the environment variable holding a value.
+const ENV_SUFFIX: &str = "_env";
+
+/// Recursively replaces the `<key>_env` entries of a YAML document by
+/// `<key>` set from the environment, and interpolates `${VAR}` in strings.
+/// The variables that are not set are added to `missing`.
+fn resolve_env(value: &mut Value, missing: &mut Vec<String>) {
+    match value {
+        Value::Mapping(map) => {
+            let env_keys: Vec<Value> = map
+                .keys()
+                .filter(|key| key.as_str().is_some_and(|k| k.ends_with(ENV_SUFFIX)))
+                .cloned()
+                .collect();
+            for env_key in env_keys {
+                let Some(Value::String(var)) = map.remove(&env_key) else {
+                    continue;
+                };
+                let key = env_key.as_str().unwrap_or_default();
+                match std::env::var(&var) {
+                    Ok(resolved) => {
+                        map.insert(
+                            Value::String(key[..key.len() - ENV_SUFFIX.len()].to_string()),
+                            Value::String(resolved),
+                        );
+                    }
+                    Err(_) => missing.push(format!("{} ({})", var, key)),
+                }
+            }
+            map.values_mut()
+                .for_each(|value| resolve_env(value, missing));
+        }
+        Value::Sequence(seq) => seq.iter_mut().for_each(|value| resolve_env(value, missing)),
+        Value::String(s) => *s = interpolate_env(s, missing),
+        _ => {}
+    }
+}
+
+/// Returns `s` with every `${VAR}` replaced by the environment variable
+/// `VAR`. Unset variables are added to `missing` and left in place.
+fn interpolate_env(s: &str, missing: &mut Vec<String>) -> String {
+    let mut interpolated = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        let var = &rest[start + 2..start + end];
+        interpolated.push_str(&rest[..start]);
+        match std::env::var(var) {
+            Ok(resolved) => interpolated.push_str(&resolved),
+            Err(_) => {
+                missing.push(var.to_string());
+                interpolated.push_str(&rest[start..=start + end]);
+            }
+        }
+        rest = &rest[start + end + 1..];
+    }
+    interpolated.push_str(rest);
+    interpolated
+}
+
+/// Recursively merges `overlay` into `base`. Mappings are merged key by key,
+/// any other value in `overlay` replaces the one in `base`.
+fn merge_yaml(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_yaml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(s: &str) -> Value {
+        serde_yaml::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn interpolates_set_variables() {
+        std::env::set_var("CONFIG_TEST_HOST", "relay.example");
+        let mut missing = Vec::new();
+        let s = interpolate_env("wss://${CONFIG_TEST_HOST}/ws", &mut missing);
+        assert_eq!(s, "wss://relay.example/ws");
+        assert!(missing.is_empty());
+    }
+
+    #[test]
+    fn keeps_unset_variables_and_reports_them() {
+        let mut missing = Vec::new();
+        let s = interpolate_env("${CONFIG_TEST_UNSET} and ${", &mut missing);
+        assert_eq!(s, "${CONFIG_TEST_UNSET} and ${");
+        assert_eq!(missing, vec!["CONFIG_TEST_UNSET"]);
+    }
+
+    #[test]
+    fn resolves_env_keys() {
+        std::env::set_var("CONFIG_TEST_PRIV_KEY", "nsec1secret");
+        std::env::set_var("CONFIG_TEST_PORT", "5432");
+        let mut doc = yaml(
+            "nostr:\n  priv_key_env: CONFIG_TEST_PRIV_KEY\n  token_env: CONFIG_TEST_MISSING\n\
+             database:\n  url: postgres://db:${CONFIG_TEST_PORT}/bridge\n",
+        );
+        let mut missing = Vec::new();
+        resolve_env(&mut doc, &mut missing);
+
+        assert_eq!(doc["nostr"]["priv_key"], yaml("nsec1secret"));
+        assert!(doc["nostr"].get("priv_key_env").is_none());
+        assert!(doc["nostr"].get("token").is_none());
+        assert_eq!(doc["database"]["url"], yaml("postgres://db:5432/bridge"));
+        assert_eq!(missing, vec!["CONFIG_TEST_MISSING (token_env)"]);
+    }
+
+    #[test]
+    fn redacts_secrets() {
+        let encryption: WakuEncryption = serde_yaml::from_value(yaml(
+            "mode: asymmetric\npublic_key: npub1\nsecret_key: nsec1\n",
+        ))
+        .unwrap();
+        let WakuEncryption::Asymmetric { secret_key, .. } = &encryption else {
+            panic!("expected the asymmetric mode");
+        };
+        assert_eq!(secret_key.as_ref().unwrap().expose(), "nsec1");
+
+        let doc = serde_yaml::to_value(&encryption).unwrap();
+        assert_eq!(doc["secret_key"], yaml(secret::REDACTED));
+        assert_eq!(doc["public_key"], yaml("npub1"));
+        assert_eq!(doc["mode"], yaml("asymmetric"));
+    }
+
+    #[test]
+    fn masks_url_passwords() {
+        let mut doc = yaml(
+            "database:\n  url: postgres://bridge:hunter2@db:5432/bridge\n\
+             nostr:\n  relays: [wss://relay.example]\n",
+        );
+        mask_url_passwords(&mut doc);
+
+        let url = doc["database"]["url"].as_str().unwrap();
+        assert_eq!(url, "postgres://bridge:***@db:5432/bridge");
+        assert_eq!(doc["nostr"]["relays"][0], yaml("wss://relay.example"));
+    }
+}
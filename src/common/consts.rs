@@ -7,7 +7,7 @@
 //! ## Usage
 //! Import the constants as needed in your modules to ensure consistent values are used.
 //! ```rust
-//! use crate::consts::{CONSTANT_NAME};
+//! use nostr_gateway::common::consts::LOG_TIME_FORMAT;
 //! ```
 
 /// Format string for timestamp used in log file names.
@@ -25,5 +25,20 @@ pub const LOG_KEY_ENV: &str = "RUST_LOG";
 /// Default logging level if `RUST_LOG` environment variable is not set.
 pub const LOG_DEFAULT_LEVEL: &str = "info";
 
+/// Hashtag of the Nostr events fetched by the bridge.
+pub const BRIDGE_HASHTAG: &str = "waku";
+
+/// Nostr event kind used to sign heartbeats when no kind is configured.
+pub const HEARTBEAT_KIND: u16 = 30078;
+
+/// `d` tag of the heartbeat events when no identifier is configured.
+pub const HEARTBEAT_IDENTIFIER: &str = "nostr_gateway/heartbeat";
+
 /// client version
 pub const CLI_VERSION: &str = "1.0";
+
+/// Exit code of the process after a panic.
+pub const PANIC_EXIT_CODE: i32 = 70;
+
+/// Exit code of the process when a dependency is unavailable at startup.
+pub const UNAVAILABLE_EXIT_CODE: i32 = 69;
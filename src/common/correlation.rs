@@ -0,0 +1,46 @@
+//! Correlation identifiers following an event through a pipeline.
+//!
+//! An id is generated when an event enters a pipeline. It is recorded as a
+//! field of the event's tracing span (and therefore of every log line emitted
+//! while handling the event), sent to downstream HTTP services in the
+//! `X-Correlation-ID` header and stored with the event in the database.
+
+use std::fmt;
+use uuid::Uuid;
+
+/// Name of the HTTP header carrying the correlation id.
+pub const CORRELATION_HEADER: &str = "X-Correlation-ID";
+
+/// A unique id assigned to an event when it enters a pipeline.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CorrelationId(String);
+
+impl CorrelationId {
+    /// Generates a new random correlation id.
+    pub fn new() -> Self {
+        Self(Uuid::new_v4().simple().to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for CorrelationId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<String> for CorrelationId {
+    /// Restores a correlation id read back from the database.
+    fn from(id: String) -> Self {
+        Self(id)
+    }
+}
+
+impl fmt::Display for CorrelationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
@@ -0,0 +1,132 @@
+//! Process wide panic handling.
+//!
+//! A panic anywhere, including in a spawned task, is logged with its
+//! backtrace, the log files are flushed, a crash marker row is written to the
+//! database if one is configured and the process exits with `PANIC_EXIT_CODE`
+//! so the service manager restarts it. Panics of futures run through
+//! `catch_panic` are only logged and handed back to the caller instead.
+
+use crate::common::config::DatabaseConfig;
+use crate::common::consts::PANIC_EXIT_CODE;
+use crate::common::{error_reporting, logging};
+use crate::db;
+use futures::FutureExt;
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Upper bound for writing the crash marker before exiting.
+const CRASH_RECORD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Upper bound for sending the panic report to Sentry before exiting.
+const REPORT_FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+tokio::task_local! {
+    /// Set while a future run by `catch_panic` is polled.
+    static CATCHING: ();
+}
+
+static DATABASE: OnceLock<DatabaseConfig> = OnceLock::new();
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// Sets the database crash markers are written to.
+pub fn set_database(config: DatabaseConfig) {
+    let _ = DATABASE.set(config);
+}
+
+/// Installs the panic hook. Should be called first thing in `main`.
+pub fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        // Panics of supervised tasks are recovered by their supervisor.
+        if CATCHING.try_with(|_| ()).is_ok() {
+            tracing::error!(
+                panic.location = info.location().map(|l| l.to_string()).as_deref(),
+                panic.backtrace = Backtrace::force_capture().to_string().as_str(),
+                "panic in supervised task: {}",
+                panic_message(info.payload())
+            );
+            return;
+        }
+
+        // A panic while handling a panic only gets the default treatment.
+        if PANICKING.swap(true, Ordering::SeqCst) {
+            eprintln!("panic while handling a panic: {}", info);
+            std::process::exit(PANIC_EXIT_CODE);
+        }
+
+        let message = panic_message(info.payload());
+        let location = info.location().map(|l| l.to_string());
+        let backtrace = Backtrace::force_capture().to_string();
+        tracing::error!(
+            panic.location = location.as_deref().unwrap_or("unknown"),
+            panic.backtrace = backtrace.as_str(),
+            "panic: {}",
+            message
+        );
+
+        if let Some(config) = DATABASE.get() {
+            record_crash(config.clone(), message, location, backtrace);
+        }
+
+        error_reporting::flush(REPORT_FLUSH_TIMEOUT);
+        logging::flush();
+        std::process::exit(PANIC_EXIT_CODE);
+    }));
+}
+
+/// Runs `fut`, turning a panic into an `Err` with the panic message instead
+/// of exiting the process.
+pub async fn catch_panic<F: Future>(fut: F) -> Result<F::Output, String> {
+    CATCHING
+        .scope((), AssertUnwindSafe(fut).catch_unwind())
+        .await
+        .map_err(|payload| panic_message(payload.as_ref()))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+/// Writes a crash marker on a separate thread with its own runtime, since the
+/// panicking thread may be a runtime worker itself.
+fn record_crash(
+    config: DatabaseConfig,
+    message: String,
+    location: Option<String>,
+    backtrace: String,
+) {
+    let writer = std::thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                tracing::error!("failed to record crash marker: {}", e);
+                return;
+            }
+        };
+        runtime.block_on(async move {
+            let record = async {
+                let store = db::Storage::connect(config).await?;
+                store.add_crash_marker(message, location, backtrace).await
+            };
+            match tokio::time::timeout(CRASH_RECORD_TIMEOUT, record).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => tracing::error!("failed to record crash marker: {}", e),
+                Err(_) => tracing::error!("timed out recording crash marker"),
+            }
+        });
+    });
+    let _ = writer.join();
+}
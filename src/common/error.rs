@@ -6,6 +6,7 @@
 //! provided for convenience.use std::path::PathBuf;
 
 use std::path::PathBuf;
+use std::time::Duration;
 use thiserror::Error;
 
 /// A convenient type alias for results used throughout the gateway application.
@@ -13,23 +14,73 @@ use thiserror::Error;
 ///
 /// # Examples
 /// ```
+/// use nostr_gateway::common::error::Result;
+///
 /// fn example_function() -> Result<()> {
 ///     // Your logic here
 ///     Ok(())
 /// }
+/// # example_function().unwrap();
 /// ```
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// Enumeration of predefined error codes for the gateway application.
 /// These codes provide a standard way to classify errors.
 ///
-/// # Variants
-/// - `Success`: Indicates a successful operation, with an HTTP status code of 200.
+/// The thousands digit tells the area of the error, and is the exit status
+/// of a command failing with it, see `Error::exit_status`:
+/// - `1xxx`: configuration.
+/// - `2xxx`: database.
+/// - `3xxx`: Nostr.
+/// - `4xxx`: Waku.
+/// - `5xxx`: IndexDB.
+/// - `6xxx`: the other sinks and services.
+/// - `9xxx`: internal errors.
 #[repr(u16)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ErrorCodes {
-    /// Represents a successful operation (HTTP 200).
-    Success = 200,
+    ConfigMissing = 1001,
+    ConfigSyntax = 1002,
+    InvalidConfig = 1003,
+    InvalidKey = 1004,
+    InvalidTopic = 1005,
+    InvalidUrl = 1006,
+
+    Database = 2001,
+    DatabaseConflict = 2002,
+    DatabaseTimeout = 2003,
+
+    NostrKey = 3001,
+    NostrEvent = 3002,
+    NostrRelay = 3003,
+    NostrTimeout = 3004,
+
+    WakuTransport = 4001,
+    WakuTimeout = 4002,
+
+    IndexdbTimeout = 5001,
+    IndexdbRejected = 5002,
+    IndexdbUnavailable = 5003,
+
+    Http = 6001,
+    Kafka = 6002,
+    Nats = 6003,
+    Mqtt = 6004,
+    Sqs = 6005,
+    Smtp = 6006,
+    Redis = 6007,
+    Ipfs = 6008,
+    Anchor = 6009,
+    Plugin = 6010,
+    ObjectStore = 6011,
+    CircuitOpen = 6012,
+
+    Custom = 9000,
+    Io = 9001,
+    Telemetry = 9002,
+    Json = 9003,
+    Timeout = 9004,
+    DependencyUnavailable = 9005,
 }
 
 /// Enumeration of possible errors in the gateway application.
@@ -42,6 +93,13 @@ pub enum ErrorCodes {
 /// - `SerializationError`: Indicates a configuration file deserialize failed.
 /// - `IoError`: Represents an I/O-related error.
 /// - `TracingError`: Represents an error while initializing the tracing system.
+/// - `InvalidConfig`: Represents a configuration value that could not be interpreted.
+/// - `InvalidKey`, `InvalidTopic`, `InvalidUrl`: A key, topic or url of the configuration is malformed.
+/// - `Timeout`: An external operation did not complete within its limit.
+/// - `DependencyUnavailable`: A dependency could not be reached at startup.
+/// - `Conflict`: A write kept losing races with concurrent writers.
+/// - `CircuitOpen`: A sink was not called as its circuit breaker is open.
+/// - `Context`: Wraps another error with a description of the failed operation.
 /// - `CustomError`: Represents any custom error with a descriptive message.
 #[derive(Error, Debug)]
 pub enum Error {
@@ -60,6 +118,54 @@ pub enum Error {
     #[error("Tracing error: {0}")]
     TracingError(#[from] tracing::dispatcher::SetGlobalDefaultError),
 
+    /// Error encountered while setting up the OpenTelemetry exporter.
+    #[error("Telemetry error: {0}")]
+    TelemetryError(String),
+
+    /// A configuration value could not be interpreted.
+    #[error("Invalid config: {0}")]
+    InvalidConfig(String),
+
+    /// An external operation did not complete within its configured limit.
+    #[error("{operation} operation timed out after {after:?}")]
+    Timeout {
+        operation: &'static str,
+        after: Duration,
+    },
+
+    /// One or more dependencies could not be reached at startup.
+    #[error("Dependencies unavailable: {0}")]
+    DependencyUnavailable(String),
+
+    /// A key of the configuration is malformed. The key itself is not
+    /// included, as it may be secret.
+    #[error("Invalid key in {field}: {reason}")]
+    InvalidKey { field: &'static str, reason: String },
+
+    /// A Waku topic of the configuration is malformed.
+    #[error("Invalid topic {value:?} in {field}: {reason}")]
+    InvalidTopic {
+        field: &'static str,
+        value: String,
+        reason: String,
+    },
+
+    /// An url or address of the configuration is malformed.
+    #[error("Invalid url {value:?} in {field}: {reason}")]
+    InvalidUrl {
+        field: &'static str,
+        value: String,
+        reason: String,
+    },
+
+    /// A write kept losing races with concurrent writers.
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    /// The circuit breaker of a sink is open, so the sink was not called.
+    #[error("Circuit breaker of the {0} sink is open")]
+    CircuitOpen(&'static str),
+
     /// Custom error with a descriptive string message.
     #[error("Custom error: {0}")]
     CustomError(String),
@@ -72,6 +178,10 @@ pub enum Error {
     #[error(transparent)]
     NostrSdkClientError(#[from] nostr_sdk::client::Error),
 
+    /// Nostr event builder error
+    #[error(transparent)]
+    NostrEventBuilderError(#[from] nostr_sdk::event::builder::Error),
+
     /// Nostr Sdk database error
     #[error(transparent)]
     NostrSdkDBError(#[from] nostr_sdk::prelude::DatabaseError),
@@ -79,25 +189,178 @@ pub enum Error {
     /// Sea ORM database error
     #[error(transparent)]
     SeaOrmDBError(#[from] sea_orm::DbErr),
+
+    /// Kafka client error
+    #[cfg(feature = "kafka")]
+    #[error(transparent)]
+    KafkaError(#[from] rdkafka::error::KafkaError),
+
+    /// NATS client error
+    #[error("NATS error: {0}")]
+    NatsError(String),
+
+    /// Redis client error
+    #[cfg(feature = "redis")]
+    #[error("Redis error: {0}")]
+    RedisError(#[from] redis::RedisError),
+
+    /// Object store error
+    #[cfg(feature = "archive")]
+    #[error("Object store error: {0}")]
+    ObjectStoreError(#[from] object_store::Error),
+
+    /// Gossipsub transport error
+    #[cfg(feature = "gossipsub")]
+    #[error("Gossipsub error: {0}")]
+    GossipsubError(String),
+
+    /// WASM plugin error
+    #[error("Plugin error: {0}")]
+    PluginError(String),
+
+    /// On-chain anchoring error
+    #[error("Anchor error: {0}")]
+    AnchorError(String),
+
+    /// IPFS offloading error
+    #[error("IPFS error: {0}")]
+    IpfsError(String),
+
+    /// Error response of the IndexDB server
+    #[error("IndexDB error: {0}")]
+    IndexdbError(#[from] crate::indexdb::IndexdbError),
+
+    /// SQS or SNS client error
+    #[error("SQS error: {0}")]
+    SqsError(String),
+
+    /// SMTP client error, which is transient unless the server rejected the
+    /// message permanently
+    #[error("SMTP error: {reason}")]
+    SmtpError { reason: String, transient: bool },
+
+    /// MQTT client error
+    #[error("MQTT error: {0}")]
+    MqttError(String),
+
+    /// Reqwest http client error
+    #[error(transparent)]
+    HttpClientError(#[from] reqwest::Error),
+
+    /// JSON encoding or decoding error
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    /// An error annotated with the operation that failed, e.g.
+    /// `n2w: publishing event <id> to waku topic <topic>`.
+    #[error("{context}: {source}")]
+    Context {
+        context: String,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+/// Extension trait adding operation context to errors.
+///
+/// # Examples
+/// ```
+/// use nostr_gateway::common::error::{Result, ResultExt};
+///
+/// fn read_checkpoint(path: &str) -> Result<String> {
+///     std::fs::read_to_string(path).context(|| format!("reading checkpoint {}", path))
+/// }
+///
+/// let error = read_checkpoint("/nonexistent/checkpoint").unwrap_err();
+/// assert!(error.to_string().starts_with("reading checkpoint /nonexistent/checkpoint: "));
+/// ```
+pub trait ResultExt<T> {
+    /// Wraps the error, if any, in `Error::Context` with the given description.
+    fn context<F: FnOnce() -> String>(self, context: F) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for std::result::Result<T, E> {
+    fn context<F: FnOnce() -> String>(self, context: F) -> Result<T> {
+        self.map_err(|e| Error::Context {
+            context: context(),
+            source: Box::new(e.into()),
+        })
+    }
 }
 
 impl Error {
     /// Retrieves the error code associated with the current error variant.
     ///
     /// # Returns
-    /// Returns the error code as a `u16`. Currently, all errors default to `ErrorCodes::Success`.
+    /// Returns the error code as a `u16`, see `ErrorCodes`. An error with
+    /// context has the code of the error it wraps.
     ///
     /// # Examples
     /// ```
-    /// let error = Error::CustomError("Something went wrong".to_string());
-    /// assert_eq!(error.error_code(), 200);
+    /// use nostr_gateway::common::error::Error;
+    ///
+    /// let error = Error::InvalidConfig("sync.batch_limit: must be positive".to_string());
+    /// assert_eq!(error.error_code(), 1003);
     /// ```
     pub fn error_code(&self) -> u16 {
+        self.code() as u16
+    }
+
+    /// Retrieves the `ErrorCodes` variant of the error.
+    pub fn code(&self) -> ErrorCodes {
         match self {
-            _ => ErrorCodes::Success as u16,
+            Error::ConfigMissing(_) => ErrorCodes::ConfigMissing,
+            Error::SerializationError(_) => ErrorCodes::ConfigSyntax,
+            Error::InvalidConfig(_) => ErrorCodes::InvalidConfig,
+            Error::InvalidKey { .. } => ErrorCodes::InvalidKey,
+            Error::InvalidTopic { .. } => ErrorCodes::InvalidTopic,
+            Error::InvalidUrl { .. } => ErrorCodes::InvalidUrl,
+            Error::SeaOrmDBError(_) | Error::NostrSdkDBError(_) => ErrorCodes::Database,
+            Error::Conflict(_) => ErrorCodes::DatabaseConflict,
+            Error::NostrSdkKeyError(_) => ErrorCodes::NostrKey,
+            Error::NostrEventBuilderError(_) => ErrorCodes::NostrEvent,
+            Error::NostrSdkClientError(_) => ErrorCodes::NostrRelay,
+            #[cfg(feature = "gossipsub")]
+            Error::GossipsubError(_) => ErrorCodes::WakuTransport,
+            Error::Timeout { .. } => match self.module() {
+                Some("db") => ErrorCodes::DatabaseTimeout,
+                Some("nostr") => ErrorCodes::NostrTimeout,
+                Some("waku") => ErrorCodes::WakuTimeout,
+                Some("indexdb") => ErrorCodes::IndexdbTimeout,
+                _ => ErrorCodes::Timeout,
+            },
+            Error::IndexdbError(e) if e.is_retryable() => ErrorCodes::IndexdbUnavailable,
+            Error::IndexdbError(_) => ErrorCodes::IndexdbRejected,
+            Error::HttpClientError(_) => ErrorCodes::Http,
+            #[cfg(feature = "kafka")]
+            Error::KafkaError(_) => ErrorCodes::Kafka,
+            Error::NatsError(_) => ErrorCodes::Nats,
+            Error::MqttError(_) => ErrorCodes::Mqtt,
+            Error::SqsError(_) => ErrorCodes::Sqs,
+            Error::SmtpError { .. } => ErrorCodes::Smtp,
+            #[cfg(feature = "redis")]
+            Error::RedisError(_) => ErrorCodes::Redis,
+            Error::IpfsError(_) => ErrorCodes::Ipfs,
+            Error::AnchorError(_) => ErrorCodes::Anchor,
+            Error::PluginError(_) => ErrorCodes::Plugin,
+            #[cfg(feature = "archive")]
+            Error::ObjectStoreError(_) => ErrorCodes::ObjectStore,
+            Error::CircuitOpen(_) => ErrorCodes::CircuitOpen,
+            Error::CustomError(_) => ErrorCodes::Custom,
+            Error::IoError(_) => ErrorCodes::Io,
+            Error::TracingError(_) | Error::TelemetryError(_) => ErrorCodes::Telemetry,
+            Error::JsonError(_) => ErrorCodes::Json,
+            Error::DependencyUnavailable(_) => ErrorCodes::DependencyUnavailable,
+            Error::Context { source, .. } => source.code(),
         }
     }
 
+    /// Retrieves the exit status of a command failing with this error: the
+    /// thousands digit of its code, e.g. 1 for a configuration error.
+    pub fn exit_status(&self) -> i32 {
+        (self.error_code() / 1000) as i32
+    }
+
     /// Retrieves a human-readable error message for the current error variant.
     ///
     /// This method utilizes the `to_string` implementation provided by `thiserror`.
@@ -107,6 +370,9 @@ impl Error {
     ///
     /// # Examples
     /// ```
+    /// use nostr_gateway::common::error::Error;
+    /// use std::path::PathBuf;
+    ///
     /// let error = Error::ConfigMissing(PathBuf::from("/path/to/config"));
     /// assert_eq!(
     ///     error.error_message(),
@@ -116,4 +382,128 @@ impl Error {
     pub fn error_message(&self) -> String {
         self.to_string()
     }
+
+    /// Retrieves a coarse classification of the error, used to group
+    /// repeated errors in logs and metrics.
+    ///
+    /// # Returns
+    /// Returns a short, stable name such as `"http"` or `"db"`.
+    pub fn class(&self) -> &'static str {
+        match self {
+            Error::ConfigMissing(_)
+            | Error::SerializationError(_)
+            | Error::InvalidConfig(_)
+            | Error::InvalidKey { .. }
+            | Error::InvalidTopic { .. }
+            | Error::InvalidUrl { .. } => "config",
+            Error::IoError(_) => "io",
+            Error::TracingError(_) | Error::TelemetryError(_) => "telemetry",
+            Error::CustomError(_) => "custom",
+            Error::Timeout { .. } => "timeout",
+            Error::DependencyUnavailable(_) => "dependency",
+            Error::Conflict(_) => "conflict",
+            Error::CircuitOpen(_) => "circuit_open",
+            Error::NostrSdkKeyError(_) | Error::NostrEventBuilderError(_) => "nostr_event",
+            Error::NostrSdkClientError(_) => "nostr_client",
+            Error::NostrSdkDBError(_) | Error::SeaOrmDBError(_) => "db",
+            Error::HttpClientError(e) if e.is_timeout() => "http_timeout",
+            Error::HttpClientError(e) if e.is_connect() => "http_connect",
+            Error::HttpClientError(_) => "http",
+            Error::JsonError(_) => "json",
+            #[cfg(feature = "kafka")]
+            Error::KafkaError(_) => "kafka",
+            Error::NatsError(_) => "nats",
+            Error::MqttError(_) => "mqtt",
+            Error::SqsError(_) => "sqs",
+            Error::SmtpError { .. } => "smtp",
+            #[cfg(feature = "redis")]
+            Error::RedisError(_) => "redis",
+            Error::IpfsError(_) => "ipfs",
+            Error::IndexdbError(_) => "indexdb",
+            Error::AnchorError(_) => "anchor",
+            Error::PluginError(_) => "plugin",
+            #[cfg(feature = "gossipsub")]
+            Error::GossipsubError(_) => "gossipsub",
+            #[cfg(feature = "archive")]
+            Error::ObjectStoreError(_) => "object_store",
+            Error::Context { source, .. } => source.class(),
+        }
+    }
+
+    /// Retrieves the module the error originates from, when the error itself
+    /// tells: `nostr`, `waku`, `db` or `indexdb`.
+    pub fn module(&self) -> Option<&'static str> {
+        match self {
+            Error::NostrSdkKeyError(_)
+            | Error::NostrSdkClientError(_)
+            | Error::NostrEventBuilderError(_) => Some("nostr"),
+            Error::NostrSdkDBError(_) | Error::SeaOrmDBError(_) => Some("db"),
+            #[cfg(feature = "kafka")]
+            Error::KafkaError(_) => Some("kafka"),
+            Error::NatsError(_) => Some("nats"),
+            Error::MqttError(_) => Some("mqtt"),
+            Error::SqsError(_) => Some("sqs"),
+            Error::SmtpError { .. } => Some("smtp"),
+            #[cfg(feature = "redis")]
+            Error::RedisError(_) => Some("redis"),
+            Error::IpfsError(_) => Some("ipfs"),
+            Error::IndexdbError(_) => Some("indexdb"),
+            Error::AnchorError(_) => Some("anchor"),
+            Error::PluginError(_) => Some("plugin"),
+            #[cfg(feature = "gossipsub")]
+            Error::GossipsubError(_) => Some("waku"),
+            #[cfg(feature = "archive")]
+            Error::ObjectStoreError(_) => Some("archive"),
+            Error::Timeout { operation, .. } => match *operation {
+                "fetch" | "nostr relay" => Some("nostr"),
+                "db" | "database" => Some("db"),
+                "waku" | "waku node" => Some("waku"),
+                "indexdb" => Some("indexdb"),
+                _ => None,
+            },
+            Error::Context { source, .. } => source.module(),
+            _ => None,
+        }
+    }
+
+    /// Tells whether the operation that failed with this error may succeed
+    /// when retried, e.g. after a timeout or a 5xx response. Other errors,
+    /// such as a malformed payload or a 4xx response, are permanent.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::IoError(_)
+            | Error::NostrSdkClientError(_)
+            | Error::NostrSdkDBError(_)
+            | Error::SeaOrmDBError(_)
+            | Error::Timeout { .. }
+            | Error::Conflict(_)
+            | Error::CircuitOpen(_)
+            | Error::NatsError(_)
+            | Error::MqttError(_)
+            | Error::SqsError(_) => true,
+            Error::SmtpError { transient, .. } => *transient,
+            Error::IndexdbError(e) => e.is_retryable(),
+            #[cfg(feature = "gossipsub")]
+            Error::GossipsubError(_) => true,
+            Error::HttpClientError(e) => match e.status() {
+                Some(status) => {
+                    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                }
+                None => !e.is_builder() && !e.is_decode(),
+            },
+            #[cfg(feature = "kafka")]
+            Error::KafkaError(e) => !matches!(e, rdkafka::error::KafkaError::ClientConfig(..)),
+            #[cfg(feature = "archive")]
+            Error::ObjectStoreError(e) => matches!(e, object_store::Error::Generic { .. }),
+            #[cfg(feature = "redis")]
+            Error::RedisError(e) => {
+                e.is_io_error()
+                    || e.is_timeout()
+                    || e.is_connection_dropped()
+                    || e.is_connection_refusal()
+            }
+            Error::Context { source, .. } => source.is_transient(),
+            _ => false,
+        }
+    }
 }
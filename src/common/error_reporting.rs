@@ -0,0 +1,88 @@
+//! Optional Sentry (or Sentry compatible) error reporting.
+//!
+//! When a DSN is configured, panics and error level log events are reported
+//! together with the pipeline context (direction, sink, and the fields of
+//! the current event span such as the event id). Without the `sentry`
+//! feature nothing is reported and a `sentry` section is rejected by the
+//! validation.
+
+use crate::common::config::SentryConfig;
+#[cfg(feature = "sentry")]
+use sentry::{Hub, SentryFutureExt};
+use std::future::Future;
+#[cfg(feature = "sentry")]
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Flushes pending reports when dropped, see `init`.
+#[cfg(feature = "sentry")]
+pub type Guard = sentry::ClientInitGuard;
+
+/// Flushes pending reports when dropped, see `init`.
+#[cfg(not(feature = "sentry"))]
+pub struct Guard;
+
+/// Initializes the Sentry client.
+///
+/// The returned guard flushes pending reports when dropped and must be kept
+/// alive for as long as errors should be reported.
+#[cfg(feature = "sentry")]
+pub fn init(config: Option<&SentryConfig>) -> Option<Guard> {
+    let config = config?;
+    let guard = sentry::init((
+        config.dsn.expose().as_str(),
+        sentry::ClientOptions {
+            release: sentry::release_name!(),
+            environment: config.environment.clone().map(Into::into),
+            sample_rate: config.sample_rate,
+            attach_stacktrace: true,
+            ..Default::default()
+        },
+    ));
+    Some(guard)
+}
+
+/// Initializes the Sentry client, never without the `sentry` feature.
+#[cfg(not(feature = "sentry"))]
+pub fn init(_config: Option<&SentryConfig>) -> Option<Guard> {
+    None
+}
+
+/// Sends pending reports, waiting at most `timeout`. Used before exiting
+/// without dropping the guard returned by `init`.
+pub fn flush(timeout: Duration) {
+    #[cfg(feature = "sentry")]
+    if let Some(client) = Hub::main().client() {
+        client.flush(Some(timeout));
+    }
+    #[cfg(not(feature = "sentry"))]
+    let _ = timeout;
+}
+
+/// Spawns a pipeline task whose panics and errors are reported with the
+/// `direction` and `sink` tags.
+pub fn spawn_reported<F>(
+    direction: &'static str,
+    sink: &'static str,
+    fut: F,
+) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(feature = "sentry")]
+    {
+        let hub = Arc::new(Hub::new_from_top(Hub::current()));
+        hub.configure_scope(|scope| {
+            scope.set_tag("direction", direction);
+            scope.set_tag("sink", sink);
+        });
+        tokio::task::spawn(fut.bind_hub(hub))
+    }
+    #[cfg(not(feature = "sentry"))]
+    {
+        let _ = (direction, sink);
+        tokio::task::spawn(fut)
+    }
+}
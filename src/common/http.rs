@@ -0,0 +1,358 @@
+//! Shared HTTP client of every HTTP sink.
+//!
+//! Waku REST publishes, IndexDB posts, heartbeats, alert webhooks, the
+//! webhook sink and Matrix messages all go through `HttpClient`, which
+//! applies the proxy and TLS settings, the request and connect timeouts of
+//! the `http` config section, the retry policy and rate limit of the sink and
+//! a tracing span per attempt. Clients built `with_timing` also time each
+//! attempt, see `timing::timed`.
+
+use crate::common::config::{HttpConfig, ProxyConfig, TlsConfig};
+use crate::common::correlation::{CorrelationId, CORRELATION_HEADER};
+use crate::common::error;
+use crate::common::rate_limit::RateLimiter;
+use crate::common::retry::RetryPolicy;
+use crate::common::timing::{timed, Operation};
+use crate::common::{proxy, telemetry, tls};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+/// Turns the status and body of a non 2xx response into the error of the
+/// server it came from, see `HttpClient::with_rejection`.
+pub type Rejection = fn(u16, &str) -> error::Error;
+
+/// A reqwest client bundled with the retry policy of its sink.
+#[derive(Clone, Debug)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    retry: RetryPolicy,
+    rejection: Option<Rejection>,
+    limiter: Option<Arc<RateLimiter>>,
+    timing: Option<Operation>,
+}
+
+impl HttpClient {
+    /// Builds a client honouring the timeout, proxy and TLS configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the proxy or TLS settings are invalid.
+    pub fn new(
+        config: &HttpConfig,
+        proxy: Option<&ProxyConfig>,
+        tls: Option<&TlsConfig>,
+        retry: RetryPolicy,
+    ) -> error::Result<Self> {
+        let builder = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs));
+        let builder = proxy::apply_http_proxy(builder, proxy)?;
+        let client = tls::apply_tls(builder, tls)?.build()?;
+
+        Ok(Self {
+            client,
+            retry,
+            rejection: None,
+            limiter: None,
+            timing: None,
+        })
+    }
+
+    /// Takes a token of `limiter` before every attempt, see `rate_limit`.
+    pub fn with_rate_limit(mut self, limiter: Option<Arc<RateLimiter>>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// Times every attempt as `operation`, abandoning it once the limit of
+    /// the operation expired, so the limit bounds each attempt and not the
+    /// retries as a whole.
+    pub fn with_timing(mut self, operation: Operation) -> Self {
+        self.timing = Some(operation);
+        self
+    }
+
+    /// Reads the body of the non 2xx responses and turns them into errors
+    /// with `rejection`, instead of the plain status error of reqwest. The
+    /// retries follow the classification of the returned error.
+    pub fn with_rejection(mut self, rejection: Rejection) -> Self {
+        self.rejection = Some(rejection);
+        self
+    }
+
+    /// POSTs `body` as JSON to `url`, retrying transient failures.
+    ///
+    /// Every attempt carries the W3C trace headers and, when given, the
+    /// correlation id header. Non 2xx responses are errors.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - Short description used in logs, e.g. `waku publish`.
+    /// * `url` - The url to post to.
+    /// * `body` - The JSON body.
+    /// * `correlation_id` - Correlation id of the bridged event, if any.
+    pub async fn post_json<B: Serialize + ?Sized>(
+        &self,
+        operation: &str,
+        url: &str,
+        body: &B,
+        correlation_id: Option<&CorrelationId>,
+    ) -> error::Result<reqwest::Response> {
+        let client = &self.client;
+        self.retry
+            .retry(operation, || {
+                self.attempt(
+                    async move {
+                        self.throttle().await;
+                        let started = Instant::now();
+                        let mut request = client
+                            .post(url)
+                            .headers(telemetry::trace_headers())
+                            .json(body);
+                        if let Some(correlation_id) = correlation_id {
+                            request = request.header(CORRELATION_HEADER, correlation_id.as_str());
+                        }
+
+                        let result = request.send().await;
+                        self.check(started, result).await
+                    }
+                    .instrument(tracing::debug_span!(
+                        "http_request",
+                        operation,
+                        method = "POST",
+                        url
+                    )),
+                )
+            })
+            .await
+    }
+
+    /// POSTs a pre-encoded `body` with the given headers to `url`, retrying
+    /// transient failures. Used when the body must be sent byte for byte as
+    /// encoded, e.g. because it is signed.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - Short description used in logs, e.g. `webhook`.
+    /// * `url` - The url to post to.
+    /// * `headers` - Headers of every attempt, besides the trace headers.
+    /// * `body` - The encoded body.
+    pub async fn post_bytes(
+        &self,
+        operation: &str,
+        url: &str,
+        headers: &reqwest::header::HeaderMap,
+        body: &[u8],
+    ) -> error::Result<reqwest::Response> {
+        let client = &self.client;
+        self.retry
+            .retry(operation, || {
+                self.attempt(
+                    async move {
+                        self.throttle().await;
+                        let started = Instant::now();
+                        let result = client
+                            .post(url)
+                            .headers(telemetry::trace_headers())
+                            .headers(headers.clone())
+                            .body(body.to_vec())
+                            .send()
+                            .await;
+                        self.check(started, result).await
+                    }
+                    .instrument(tracing::debug_span!(
+                        "http_request",
+                        operation,
+                        method = "POST",
+                        url
+                    )),
+                )
+            })
+            .await
+    }
+
+    /// POSTs `content` as the `file` field of a multipart form to `url`,
+    /// retrying transient failures.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - Short description used in logs, e.g. `ipfs add`.
+    /// * `url` - The url to post to.
+    /// * `content` - The file content.
+    pub async fn post_file(
+        &self,
+        operation: &str,
+        url: &str,
+        content: &[u8],
+    ) -> error::Result<reqwest::Response> {
+        let client = &self.client;
+        self.retry
+            .retry(operation, || {
+                self.attempt(
+                    async move {
+                        self.throttle().await;
+                        let started = Instant::now();
+                        let form = reqwest::multipart::Form::new()
+                            .part("file", reqwest::multipart::Part::bytes(content.to_vec()));
+                        let result = client
+                            .post(url)
+                            .headers(telemetry::trace_headers())
+                            .multipart(form)
+                            .send()
+                            .await;
+                        self.check(started, result).await
+                    }
+                    .instrument(tracing::debug_span!(
+                        "http_request",
+                        operation,
+                        method = "POST",
+                        url
+                    )),
+                )
+            })
+            .await
+    }
+
+    /// PUTs `body` as JSON with the given headers to `url`, retrying
+    /// transient failures.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - Short description used in logs, e.g. `matrix send`.
+    /// * `url` - The url to put to.
+    /// * `headers` - Headers of every attempt, besides the trace headers.
+    /// * `body` - The JSON body.
+    pub async fn put_json<B: Serialize + ?Sized>(
+        &self,
+        operation: &str,
+        url: &str,
+        headers: &reqwest::header::HeaderMap,
+        body: &B,
+    ) -> error::Result<reqwest::Response> {
+        let client = &self.client;
+        self.retry
+            .retry(operation, || {
+                self.attempt(
+                    async move {
+                        self.throttle().await;
+                        let started = Instant::now();
+                        let result = client
+                            .put(url)
+                            .headers(telemetry::trace_headers())
+                            .headers(headers.clone())
+                            .json(body)
+                            .send()
+                            .await;
+                        self.check(started, result).await
+                    }
+                    .instrument(tracing::debug_span!(
+                        "http_request",
+                        operation,
+                        method = "PUT",
+                        url
+                    )),
+                )
+            })
+            .await
+    }
+
+    /// GETs `url` with the given query parameters and decodes the JSON
+    /// response, retrying transient failures.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - Short description used in logs, e.g. `waku store query`.
+    /// * `url` - The url to get.
+    /// * `query` - Query parameters appended to the url.
+    pub async fn get_json<T: DeserializeOwned>(
+        &self,
+        operation: &str,
+        url: &str,
+        query: &[(&str, String)],
+    ) -> error::Result<T> {
+        let client = &self.client;
+        self.retry
+            .retry(operation, || {
+                self.attempt(
+                    async move {
+                        self.throttle().await;
+                        let started = Instant::now();
+                        let result = client
+                            .get(url)
+                            .headers(telemetry::trace_headers())
+                            .query(query)
+                            .send()
+                            .await;
+                        Ok(self.check(started, result).await?.json::<T>().await?)
+                    }
+                    .instrument(tracing::debug_span!(
+                        "http_request",
+                        operation,
+                        method = "GET",
+                        url
+                    )),
+                )
+            })
+            .await
+    }
+
+    /// Runs one attempt, timed as configured by `with_timing`.
+    async fn attempt<T>(&self, fut: impl Future<Output = error::Result<T>>) -> error::Result<T> {
+        match self.timing {
+            Some(operation) => timed(operation, fut).await,
+            None => fut.await,
+        }
+    }
+
+    /// Waits for a token of the rate limit, if any.
+    async fn throttle(&self) {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire().await;
+        }
+    }
+
+    /// Logs an attempt and turns a non 2xx response into an error, see
+    /// `with_rejection`.
+    async fn check(
+        &self,
+        started: Instant,
+        result: reqwest::Result<reqwest::Response>,
+    ) -> error::Result<reqwest::Response> {
+        log_attempt(started, &result);
+        let response = result?;
+        let status = response.status();
+        match self.rejection {
+            Some(rejection) if status.is_client_error() || status.is_server_error() => {
+                let body = response.text().await.unwrap_or_default();
+                Err(rejection(status.as_u16(), &body))
+            }
+            _ => Ok(response.error_for_status()?),
+        }
+    }
+
+    /// Sends a single GET to `url`, without retries, to tell whether the
+    /// server is reachable. Any response, whatever its status, counts.
+    pub async fn probe(&self, url: &str) -> error::Result<()> {
+        let started = Instant::now();
+        let result = self.client.get(url).send().await;
+        log_attempt(started, &result);
+        result?;
+        Ok(())
+    }
+}
+
+fn log_attempt(started: Instant, result: &reqwest::Result<reqwest::Response>) {
+    let latency_ms = started.elapsed().as_millis() as u64;
+    match result {
+        Ok(response) => tracing::debug!(
+            status = response.status().as_u16(),
+            latency_ms,
+            "http request succeeded"
+        ),
+        Err(e) => tracing::debug!(latency_ms, "http request failed: {}", e),
+    }
+}
@@ -0,0 +1,174 @@
+//! Size based log file rotation and retention cleanup.
+//!
+//! Time based rotation is handled by `tracing_appender`; this module adds a
+//! writer rotating on file size and a cleaner deleting old log files so a
+//! long running bridge does not fill its disk. The size based writer cleans
+//! up on every rotation, time based rotations run the cleaner periodically.
+
+use crate::common::consts;
+use chrono::{Local, NaiveDateTime};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Retention limits applied to the log directory.
+#[derive(Debug, Clone, Copy)]
+pub struct Retention {
+    /// Keep at most this many log files.
+    pub max_files: Option<usize>,
+    /// Delete log files older than this.
+    pub max_age: Option<Duration>,
+}
+
+/// A log file writer that starts a new file once `max_bytes` is reached.
+pub struct SizeRotatingFile {
+    dir: PathBuf,
+    max_bytes: u64,
+    retention: Retention,
+    file: File,
+    written: u64,
+}
+
+impl SizeRotatingFile {
+    /// Opens a new log file in `dir`.
+    pub fn new(dir: &str, max_bytes: u64, retention: Retention) -> io::Result<Self> {
+        let dir = PathBuf::from(dir);
+        let file = Self::open_file(&dir)?;
+        Ok(Self {
+            dir,
+            max_bytes,
+            retention,
+            file,
+            written: 0,
+        })
+    }
+
+    fn open_file(dir: &Path) -> io::Result<File> {
+        let name = format!(
+            "{}_{}.log",
+            Local::now().format(consts::LOG_TIME_FORMAT),
+            consts::LOG_BASE_NAME
+        );
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(name))
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file = Self::open_file(&self.dir)?;
+        self.written = 0;
+        cleanup_logs(&self.dir, self.retention);
+        Ok(())
+    }
+}
+
+impl Write for SizeRotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Deletes log files in `dir` that exceed the retention limits.
+///
+/// Only the files named by this crate's writers are considered, see
+/// [`is_log_file`]. Failures are reported on stderr since the
+/// logging system may not be available yet.
+pub fn cleanup_logs(dir: &Path, retention: Retention) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("log cleanup: failed to read {}: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    let mut files: Vec<(PathBuf, SystemTime)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| is_log_file(&entry.file_name().to_string_lossy()))
+        .filter_map(|entry| {
+            let modified = entry.metadata().and_then(|m| m.modified()).ok()?;
+            Some((entry.path(), modified))
+        })
+        .collect();
+
+    // Newest first, so the files to keep come first.
+    files.sort_by(|a, b| b.1.cmp(&a.1));
+
+    let now = SystemTime::now();
+    for (index, (path, modified)) in files.iter().enumerate() {
+        let too_many = retention.max_files.is_some_and(|max| index >= max);
+        let too_old = retention
+            .max_age
+            .is_some_and(|max_age| now.duration_since(*modified).unwrap_or_default() > max_age);
+
+        if too_many || too_old {
+            if let Err(e) = fs::remove_file(path) {
+                eprintln!("log cleanup: failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Tells whether `name` is a log file written by this crate: `app.<date>.log`
+/// from the time based rotations or `<time>_app.log` from the others.
+fn is_log_file(name: &str) -> bool {
+    let rolled = format!("{}.", consts::LOG_BASE_NAME);
+    if let Some(date) = name
+        .strip_prefix(&rolled)
+        .and_then(|rest| rest.strip_suffix(".log"))
+    {
+        return !date.is_empty() && date.chars().all(|c| c.is_ascii_digit() || c == '-');
+    }
+
+    let stamped = format!("_{}.log", consts::LOG_BASE_NAME);
+    name.strip_suffix(&stamped)
+        .is_some_and(|time| NaiveDateTime::parse_from_str(time, consts::LOG_TIME_FORMAT).is_ok())
+}
+
+/// Runs `cleanup_logs` on `dir` every `interval` from a background thread,
+/// for the rotations that do not go through `SizeRotatingFile`.
+pub fn spawn_cleaner(dir: PathBuf, retention: Retention, interval: Duration) {
+    let spawned = thread::Builder::new()
+        .name("log-cleaner".to_string())
+        .spawn(move || loop {
+            thread::sleep(interval);
+            cleanup_logs(&dir, retention);
+        });
+    if let Err(e) = spawned {
+        eprintln!("log cleanup: failed to start the cleaner: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_own_log_files() {
+        assert!(is_log_file("app.2024-05-01.log"));
+        assert!(is_log_file("app.2024-05-01-13.log"));
+        assert!(is_log_file("2024-05-01_13-45-00_app.log"));
+    }
+
+    #[test]
+    fn ignores_other_log_files() {
+        assert!(!is_log_file("webapp.log"));
+        assert!(!is_log_file("app.log"));
+        assert!(!is_log_file("app.backup.log"));
+        assert!(!is_log_file("my_app.log"));
+        assert!(!is_log_file("2024-05-01_13-45-00_app.log.gz"));
+    }
+}
@@ -2,27 +2,88 @@
 //! It supports logging to both the console and rolling log files with optional
 //! environment-based log level configuration.
 
+use crate::common::config::{LogConfig, LogFormat, LogRotation, TelemetryConfig};
 use crate::common::consts;
 use crate::common::error;
+use crate::common::log_rotation::{self, Retention, SizeRotatingFile};
+#[cfg(feature = "telemetry")]
+use crate::common::telemetry;
 use chrono::Local;
+use std::collections::HashMap;
+use std::fmt::Display;
 use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing_appender::non_blocking::WorkerGuard;
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
-use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter};
+#[cfg(not(all(feature = "telemetry", feature = "sentry")))]
+use tracing_subscriber::layer::Identity;
+use tracing_subscriber::{fmt, layer::SubscriberExt, reload, EnvFilter, Registry};
+
+/// How often the files of the hourly and daily rotations are checked against
+/// `log.max_age_days`.
+const LOG_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Keeps the background log file writer alive.
+///
+/// File writes are handed off to a worker thread so that logging never blocks
+/// the pipelines. Dropping the handle flushes the remaining buffered lines, so
+/// it must be held until the application exits.
+#[must_use = "dropping the handle stops file logging"]
+pub struct LoggingHandle {
+    _private: (),
+}
+
+impl Drop for LoggingHandle {
+    fn drop(&mut self) {
+        flush();
+    }
+}
+
+/// Guard of the background log file writer, taken by `flush`.
+static WORKER_GUARD: Mutex<Option<WorkerGuard>> = Mutex::new(None);
+
+/// Stops the background log file writer after writing all buffered lines.
+///
+/// Lines logged afterwards only reach the console. Used on shutdown and by the
+/// panic hook, which cannot rely on the `LoggingHandle` being dropped.
+pub fn flush() {
+    let guard = WORKER_GUARD
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take();
+    drop(guard);
+}
 
 /// Initializes the logging system for the application.
 ///
 /// This function sets up logging to both the console and log files. Log files are
-/// rolled manually (not automatically by size or time) and are stored in the
-/// specified directory. The logging level can be controlled via the `RUST_LOG`
-/// environment variable or defaults to `info`.
+/// stored in the specified directory and rotated hourly, daily, by size or never,
+/// as configured. Files exceeding the retention limits are deleted at startup
+/// and on every rotation. The logging level can be controlled via the `RUST_LOG`
+/// environment variable or defaults to `log.level`, and can be changed at runtime
+/// with `set_log_filter`. When a telemetry config is
+/// given, spans are additionally exported to an OTLP collector. Error events
+/// are forwarded to Sentry once `error_reporting::init` has been called.
+///
+/// With the `json` format every line is a JSON object whose fields are
+/// flattened, so pipeline fields such as `event_id`, `direction`, `sink` and
+/// `latency_ms` appear under stable top-level names. Lines logged while an
+/// event is bridged carry the fields of its span, including the `relay` the
+/// event was fetched from, under `span`.
 ///
 /// # Arguments
 ///
 /// * `log_dir` - Path to the directory where log files will be stored.
+/// * `log` - Logging settings such as the output format.
+/// * `telemetry` - Optional OpenTelemetry export settings.
 ///
 /// # Returns
 ///
-/// Returns a `error::Result<()>` indicating success or failure.
+/// Returns a `error::Result<LoggingHandle>` whose handle must be kept alive for
+/// as long as log lines should reach the log files.
 ///
 /// # Errors
 ///
@@ -31,47 +92,280 @@ use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter};
 ///
 /// # Example
 ///
+/// ```no_run
+/// use nostr_gateway::common::config::LogConfig;
+/// use nostr_gateway::common::logging::logging_init;
+///
+/// let _logging = logging_init("/path/to/logs", &LogConfig::default(), None).unwrap();
 /// ```
-/// logging_init("/path/to/logs").unwrap();
-/// ```
-pub fn logging_init(log_dir: &str) -> error::Result<()> {
-    let log_file = format!(
-        "{}_{}.log",
-        Local::now().format(consts::LOG_TIME_FORMAT),
-        consts::LOG_BASE_NAME
-    );
-
-    // Create a rolling file appender that does not rotate automatically.
-    let file_appender = RollingFileAppender::new(Rotation::NEVER, log_dir, log_file);
-    //let (file_writer, _guard) = non_blocking(file_appender);
-    let file_writer = file_appender;
-
+pub fn logging_init(
+    log_dir: &str,
+    log: &LogConfig,
+    telemetry: Option<&TelemetryConfig>,
+) -> error::Result<LoggingHandle> {
     // Ensure the log directory exists, create if necessary.
     fs::create_dir_all(log_dir)?;
 
-    // Define a logging layer for writing to log files with timestamps and line numbers.
-    let file_layer = fmt::Layer::default()
-        .with_writer(file_writer)
-        .with_line_number(true)
-        .with_ansi(false); // Disable ANSI colors for log files.
+    // Remove log files left over from previous runs beyond the retention limits.
+    let retention = Retention {
+        max_files: log.max_files,
+        max_age: log
+            .max_age_days
+            .map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+    };
+    log_rotation::cleanup_logs(Path::new(log_dir), retention);
+
+    let _ = DEDUP_WINDOW.set(Duration::from_secs(log.dedup_window_secs));
+
+    let file_writer: Box<dyn Write + Send> = match log.rotation {
+        LogRotation::Never => {
+            let log_file = format!(
+                "{}_{}.log",
+                Local::now().format(consts::LOG_TIME_FORMAT),
+                consts::LOG_BASE_NAME
+            );
+            // Create a rolling file appender that does not rotate automatically.
+            Box::new(RollingFileAppender::new(Rotation::NEVER, log_dir, log_file))
+        }
+        LogRotation::Hourly | LogRotation::Daily => {
+            let rotation = if log.rotation == LogRotation::Hourly {
+                Rotation::HOURLY
+            } else {
+                Rotation::DAILY
+            };
+            let mut builder = RollingFileAppender::builder()
+                .rotation(rotation)
+                .filename_prefix(consts::LOG_BASE_NAME)
+                .filename_suffix("log");
+            if let Some(max_files) = log.max_files {
+                builder = builder.max_log_files(max_files);
+            }
+            let appender = builder
+                .build(log_dir)
+                .map_err(|e| error::Error::CustomError(format!("log appender: {}", e)))?;
+            // `max_log_files` only bounds the number of files, so the age
+            // limit is applied by the cleaner.
+            if retention.max_age.is_some() {
+                log_rotation::spawn_cleaner(log_dir.into(), retention, LOG_CLEANUP_INTERVAL);
+            }
+            Box::new(appender)
+        }
+        LogRotation::Size => {
+            let writer = SizeRotatingFile::new(log_dir, log.max_size_mb * 1024 * 1024, retention)?;
+            Box::new(writer)
+        }
+    };
+    // Write log files from a background worker; the guard flushes it on drop.
+    let (file_writer, guard) = tracing_appender::non_blocking(file_writer);
+
+    // Define the logging layers for log files and console output, either as
+    // text with timestamps and line numbers or as flattened JSON objects.
+    let (text_layers, json_layers) = match log.format {
+        LogFormat::Text => {
+            let file_layer = fmt::Layer::default()
+                .with_writer(file_writer)
+                .with_line_number(true)
+                .with_ansi(false); // Disable ANSI colors for log files.
+            let stdout_layer = fmt::Layer::default()
+                .with_writer(std::io::stdout)
+                .with_line_number(true);
+            (Some((stdout_layer, file_layer)), None)
+        }
+        LogFormat::Json => {
+            let file_layer = fmt::Layer::default()
+                .json()
+                .flatten_event(true)
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_writer(file_writer)
+                .with_line_number(true);
+            let stdout_layer = fmt::Layer::default()
+                .json()
+                .flatten_event(true)
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_writer(std::io::stdout)
+                .with_line_number(true);
+            (None, Some((stdout_layer, file_layer)))
+        }
+    };
+    let (text_stdout, text_file) = text_layers.unzip();
+    let (json_stdout, json_file) = json_layers.unzip();
 
-    // Define a logging layer for console output with timestamps and line numbers.
-    let stdout_layer = fmt::Layer::default()
-        .with_writer(std::io::stdout)
-        .with_line_number(true);
+    // Get the logging level from the environment or use the configured one.
+    let rust_log = std::env::var(consts::LOG_KEY_ENV).unwrap_or_else(|_| log.level.clone());
 
-    // Get the logging level from the environment or use the default.
-    let rust_log = std::env::var(consts::LOG_KEY_ENV)
-        .unwrap_or_else(|_| consts::LOG_DEFAULT_LEVEL.to_string());
+    // Define an optional layer exporting spans to an OTLP collector.
+    #[cfg(feature = "telemetry")]
+    let otel_layer = match telemetry {
+        Some(config) => {
+            let tracer = telemetry::init_tracer(config)?;
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        None => None,
+    };
+    // The validation rejects a telemetry config without the feature.
+    #[cfg(not(feature = "telemetry"))]
+    let otel_layer: Option<Identity> = {
+        let _ = telemetry;
+        None
+    };
+    #[cfg(feature = "sentry")]
+    let sentry_layer = Some(sentry_tracing::layer());
+    #[cfg(not(feature = "sentry"))]
+    let sentry_layer: Option<Identity> = None;
+
+    // Wrap the filter in a reload layer so it can be swapped at runtime.
+    let (filter, filter_handle) = reload::Layer::new(EnvFilter::new(rust_log));
 
     // Create a tracing subscriber with environment-based filtering and layered output.
     let subscriber = tracing_subscriber::registry()
-        .with(EnvFilter::new(rust_log))
-        .with(stdout_layer)
-        .with(file_layer);
+        .with(filter)
+        .with(text_stdout)
+        .with(text_file)
+        .with(json_stdout)
+        .with(json_file)
+        .with(otel_layer)
+        .with(sentry_layer);
 
     // Set the global default subscriber for tracing.
     tracing::subscriber::set_global_default(subscriber)?;
+    let _ = FILTER_HANDLE.set(filter_handle);
+
+    *WORKER_GUARD.lock().unwrap_or_else(|e| e.into_inner()) = Some(guard);
+
+    Ok(LoggingHandle { _private: () })
+}
+
+/// Handle for swapping the log filter of the global subscriber.
+static FILTER_HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
 
+/// Returns the active log filter, e.g. `info,waku=debug`, or `None` if
+/// logging has not been initialized.
+pub fn log_filter() -> Option<String> {
+    FILTER_HANDLE.get()?.with_current(|f| f.to_string()).ok()
+}
+
+/// Replaces the log filter of the running process.
+///
+/// # Arguments
+///
+/// * `directives` - Filter in `RUST_LOG` syntax, e.g. `info,waku=debug`.
+///
+/// # Errors
+///
+/// Returns an error if the directives cannot be parsed or logging has not
+/// been initialized.
+pub fn set_log_filter(directives: &str) -> error::Result<()> {
+    let filter = EnvFilter::try_new(directives)
+        .map_err(|e| error::Error::InvalidConfig(format!("log filter: {}", e)))?;
+    let handle = FILTER_HANDLE
+        .get()
+        .ok_or_else(|| error::Error::CustomError("logging is not initialized".to_string()))?;
+    handle
+        .reload(filter)
+        .map_err(|e| error::Error::CustomError(format!("log filter reload: {}", e)))?;
+    tracing::info!(filter = directives, "log filter changed");
     Ok(())
 }
+
+/// Window within which repeated errors of the same class are collapsed.
+static DEDUP_WINDOW: OnceLock<Duration> = OnceLock::new();
+
+/// Errors suppressed per class since the last one that was written.
+static SUPPRESSED: OnceLock<Mutex<HashMap<String, Suppressed>>> = OnceLock::new();
+
+struct Suppressed {
+    logged_at: Instant,
+    count: u64,
+}
+
+/// Logs an error, collapsing repeats of the same class.
+///
+/// The first error of a class is written as is. Further errors of that class
+/// within the configured `dedup_window_secs` are only counted, and how many
+/// were dropped is reported when the window closes, so a sink that is down
+/// for an hour produces a handful of lines instead of thousands. The report
+/// is written by the next error of the class, or by a timer at the end of the
+/// window if none comes.
+///
+/// # Arguments
+///
+/// * `class` - Key grouping identical failures, e.g. `"waku:http_connect"`.
+/// * `message` - The error message to log.
+pub fn error_deduped(class: &str, message: impl Display) {
+    let window = DEDUP_WINDOW.get().copied().unwrap_or_default();
+    if window.is_zero() {
+        tracing::error!(error_class = class, "{}", message);
+        return;
+    }
+
+    let now = Instant::now();
+    let mut suppressed = SUPPRESSED
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    match suppressed.get_mut(class) {
+        Some(entry) if now.duration_since(entry.logged_at) < window => {
+            entry.count += 1;
+            if entry.count == 1 {
+                report_when_closed(class, entry.logged_at + window);
+            }
+        }
+        Some(entry) => {
+            let repeated = std::mem::take(&mut entry.count);
+            entry.logged_at = now;
+            if repeated > 0 {
+                tracing::error!(
+                    error_class = class,
+                    "{} (previous message repeated {} times)",
+                    message,
+                    repeated
+                );
+            } else {
+                tracing::error!(error_class = class, "{}", message);
+            }
+        }
+        None => {
+            suppressed.insert(
+                class.to_string(),
+                Suppressed {
+                    logged_at: now,
+                    count: 0,
+                },
+            );
+            tracing::error!(error_class = class, "{}", message);
+        }
+    }
+}
+
+/// Reports the errors of `class` suppressed in the window closing at
+/// `closes_at`, unless an error written in between reported them. Without a
+/// tokio runtime they wait for the next error of the class.
+fn report_when_closed(class: &str, closes_at: Instant) {
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+    let class = class.to_string();
+    runtime.spawn(async move {
+        tokio::time::sleep_until(closes_at.into()).await;
+        let mut suppressed = SUPPRESSED
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let Some(entry) = suppressed.get_mut(&class) else {
+            return;
+        };
+        let window = DEDUP_WINDOW.get().copied().unwrap_or_default();
+        if entry.count == 0 || entry.logged_at.elapsed() < window {
+            return;
+        }
+        let repeated = std::mem::take(&mut entry.count);
+        tracing::error!(
+            error_class = class.as_str(),
+            "{} similar errors suppressed in the last {:?}",
+            repeated,
+            window
+        );
+    });
+}
@@ -1,4 +1,21 @@
+pub mod backoff;
 pub mod config;
 pub mod consts;
+pub mod correlation;
+pub mod crash;
 pub mod error;
+pub mod error_reporting;
+pub mod http;
+pub mod log_rotation;
 pub mod logging;
+pub mod proxy;
+pub mod rate_limit;
+pub mod retry;
+pub mod secret;
+pub mod sink;
+pub mod systemd;
+pub mod telemetry;
+pub mod template;
+pub mod timing;
+pub mod tls;
+pub mod validation;
@@ -0,0 +1,52 @@
+//! Helpers for routing outbound connections through an HTTP or SOCKS5 proxy.
+//!
+//! Both the reqwest clients (Waku REST, IndexDB) and the nostr-sdk websocket
+//! connections are configured from the same optional `proxy` config section.
+
+use crate::common::config::{ProxyConfig, ProxyTarget};
+use crate::common::error;
+use nostr_sdk::prelude::{Connection, ConnectionTarget};
+use std::net::SocketAddr;
+
+/// Applies the configured HTTP proxy, if any, to a reqwest client builder.
+///
+/// # Errors
+///
+/// Returns `InvalidConfig` if the proxy url can not be parsed.
+pub fn apply_http_proxy(
+    builder: reqwest::ClientBuilder,
+    config: Option<&ProxyConfig>,
+) -> error::Result<reqwest::ClientBuilder> {
+    match config.and_then(|c| c.http_url.as_deref()) {
+        Some(url) => {
+            let proxy = reqwest::Proxy::all(url).map_err(|e| {
+                error::Error::InvalidConfig(format!("proxy.http_url '{}': {}", url, e))
+            })?;
+            Ok(builder.proxy(proxy))
+        }
+        None => Ok(builder),
+    }
+}
+
+/// Returns the nostr-sdk connection settings for the configured SOCKS5 proxy.
+///
+/// `None` means relays are reached directly.
+pub fn nostr_connection(config: Option<&ProxyConfig>) -> error::Result<Option<Connection>> {
+    let Some(config) = config else {
+        return Ok(None);
+    };
+
+    match config.nostr_socks5.as_deref() {
+        Some(addr) => {
+            let addr: SocketAddr = addr.parse().map_err(|e| {
+                error::Error::InvalidConfig(format!("proxy.nostr_socks5 '{}': {}", addr, e))
+            })?;
+            let target = match config.nostr_target {
+                ProxyTarget::All => ConnectionTarget::All,
+                ProxyTarget::Onion => ConnectionTarget::Onion,
+            };
+            Ok(Some(Connection::new().proxy(addr).target(target)))
+        }
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,142 @@
+//! Token bucket rate limiting of outbound requests.
+//!
+//! Backfills and event storms would otherwise publish as fast as the relays
+//! deliver. A sink with a `rate_limit` section takes a token before every
+//! request, retries included; tokens are refilled at `per_second` and up to
+//! `burst` of them accumulate while the sink is idle. A request without a
+//! token waits for the next one instead of failing.
+
+use crate::metrics;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Sustained rate and burst of the requests of a sink.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+pub struct RateLimit {
+    /// Requests per second in the long run.
+    pub per_second: f64,
+    /// Requests sent at once after an idle period, `per_second` rounded up
+    /// when unset.
+    pub burst: Option<u32>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Available tokens, negative while requests wait for reserved ones.
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// A token bucket shared by every request of a sink.
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// Name of the sink, used in logs and metrics.
+    sink: &'static str,
+    per_second: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    /// Creates a full bucket for the requests of `sink`.
+    pub fn new(sink: &'static str, limit: &RateLimit) -> Self {
+        let burst = limit
+            .burst
+            .map(f64::from)
+            .unwrap_or_else(|| limit.per_second.ceil())
+            .max(1.0);
+        Self {
+            sink,
+            per_second: limit.per_second,
+            burst,
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Creates the limiter of `sink` if a limit is configured.
+    pub fn from_config(sink: &'static str, limit: Option<&RateLimit>) -> Option<Self> {
+        limit.map(|limit| Self::new(sink, limit))
+    }
+
+    /// Takes a token, waiting until one is available.
+    ///
+    /// The token is reserved right away, so concurrent callers are served in
+    /// the order they arrived.
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let refilled = now.duration_since(bucket.refilled_at).as_secs_f64() * self.per_second;
+            bucket.tokens = (bucket.tokens + refilled).min(self.burst) - 1.0;
+            bucket.refilled_at = now;
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-bucket.tokens / self.per_second)
+        };
+
+        metrics::metrics()
+            .rate_limited_total
+            .with_label_values(&[self.sink])
+            .inc();
+        tracing::debug!(
+            sink = self.sink,
+            wait_ms = wait.as_millis() as u64,
+            "rate limited"
+        );
+        tokio::time::sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(sink: &'static str, per_second: f64, burst: Option<u32>) -> RateLimiter {
+        RateLimiter::new(sink, &RateLimit { per_second, burst })
+    }
+
+    fn rate_limited(sink: &str) -> u64 {
+        metrics::metrics()
+            .rate_limited_total
+            .with_label_values(&[sink])
+            .get()
+    }
+
+    #[test]
+    fn burst_defaults_to_the_rate_rounded_up() {
+        assert_eq!(limiter("test", 2.5, None).burst, 3.0);
+        assert_eq!(limiter("test", 0.1, None).burst, 1.0);
+        assert_eq!(limiter("test", 0.1, Some(0)).burst, 1.0);
+        assert_eq!(limiter("test", 1.0, Some(5)).burst, 5.0);
+        assert!(RateLimiter::from_config("test", None).is_none());
+    }
+
+    #[tokio::test]
+    async fn serves_the_burst_at_once() {
+        let limiter = limiter("test-burst", 1.0, Some(3));
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert_eq!(rate_limited("test-burst"), 0);
+    }
+
+    #[tokio::test]
+    async fn waits_once_the_burst_is_spent() {
+        let limiter = limiter("test-spent", 20.0, Some(1));
+        let start = Instant::now();
+        limiter.acquire().await;
+        limiter.acquire().await;
+        limiter.acquire().await;
+        // Two tokens are refilled at 20 per second.
+        assert!(start.elapsed() >= Duration::from_millis(90));
+        assert_eq!(rate_limited("test-spent"), 2);
+    }
+}
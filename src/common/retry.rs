@@ -0,0 +1,156 @@
+//! Retry policy shared by every outbound client.
+//!
+//! The Nostr publisher, the Waku sender and the IndexDB sender all retry
+//! failed operations the same way: exponential backoff starting at
+//! `base_delay_ms`, capped at `max_delay_ms`, optionally randomized, and
+//! bounded by `max_attempts`, as implemented by `common::backoff`. A global policy is configured at the top level
+//! of the config file and each sink may override it.
+
+use crate::common::backoff::Backoff;
+use crate::common::error;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::Duration;
+
+/// Describes how often and how fast a failed operation is retried.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry, in milliseconds.
+    pub base_delay_ms: u64,
+    /// Upper bound of the delay between two attempts, in milliseconds.
+    pub max_delay_ms: u64,
+    /// Randomize each delay to avoid synchronized retries.
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts, starting at 500ms and never waiting more than 10s.
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay_ms: 500,
+            max_delay_ms: 10_000,
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the backoff of this policy, yielding one delay per retry.
+    pub fn backoff(&self) -> Backoff {
+        Backoff::new(
+            Duration::from_millis(self.base_delay_ms),
+            Duration::from_millis(self.max_delay_ms),
+        )
+        .with_jitter(self.jitter)
+        .with_budget(self.max_attempts.saturating_sub(1))
+    }
+
+    /// Runs `f` until it succeeds, fails permanently or the attempt budget is
+    /// exhausted. Only transient errors are retried.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - Short description used in the retry log lines.
+    /// * `f` - Produces a new future for every attempt.
+    ///
+    /// # Returns
+    ///
+    /// The first successful result, or the error of the last attempt.
+    pub async fn retry<T, F, Fut>(&self, operation: &str, mut f: F) -> error::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = error::Result<T>>,
+    {
+        let mut backoff = self.backoff();
+        loop {
+            let e = match f().await {
+                Ok(value) => return Ok(value),
+                Err(e) if e.is_transient() => e,
+                Err(e) => return Err(e),
+            };
+            let Some(delay) = backoff.next() else {
+                return Err(e);
+            };
+            tracing::warn!(
+                "{} failed (attempt {}/{}): {}, retrying in {:?}",
+                operation,
+                backoff.retries(),
+                self.max_attempts,
+                e,
+                delay
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay_ms: 1,
+            max_delay_ms: 4,
+            jitter: false,
+        }
+    }
+
+    #[test]
+    fn backoff_follows_the_policy() {
+        let delays: Vec<_> = policy(5).backoff().collect();
+        assert_eq!(delays, [1, 2, 4, 4].map(Duration::from_millis).to_vec());
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors_until_the_budget_is_spent() {
+        let attempts = Cell::new(0);
+        let result: error::Result<()> = policy(3)
+            .retry("test", || {
+                attempts.set(attempts.get() + 1);
+                async { Err(error::Error::Conflict("busy".to_string())) }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn returns_the_first_success() {
+        let attempts = Cell::new(0);
+        let result = policy(3)
+            .retry("test", || {
+                attempts.set(attempts.get() + 1);
+                let attempt = attempts.get();
+                async move {
+                    if attempt < 2 {
+                        Err(error::Error::Conflict("busy".to_string()))
+                    } else {
+                        Ok(attempt)
+                    }
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_permanent_errors() {
+        let attempts = Cell::new(0);
+        let result: error::Result<()> = policy(3)
+            .retry("test", || {
+                attempts.set(attempts.get() + 1);
+                async { Err(error::Error::InvalidConfig("bad".to_string())) }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+}
@@ -0,0 +1,119 @@
+//! Secret configuration values.
+//!
+//! Private keys, passwords and tokens of the config are wrapped in [`Secret`],
+//! which deserializes transparently but serializes and debug-prints as
+//! [`REDACTED`]. Rendering the configuration, e.g. with `config show`, or
+//! logging part of it thus never leaks a secret, whatever the setting is
+//! called.
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize, Serializer};
+use std::cell::Cell;
+use std::fmt;
+
+/// Placeholder written in place of secret values.
+pub const REDACTED: &str = "***";
+
+thread_local! {
+    /// Set while `revealed` runs, to serialize the secrets as they are.
+    static REVEAL: Cell<bool> = const { Cell::new(false) };
+}
+
+/// A config value that must not be displayed.
+///
+/// # Examples
+/// ```
+/// use nostr_gateway::common::secret::Secret;
+///
+/// let password = Secret::new("hunter2".to_string());
+/// assert_eq!(password.expose(), "hunter2");
+/// assert_eq!(serde_json::to_string(&password).unwrap(), r#""***""#);
+/// assert_eq!(format!("{:?}", password), "***");
+/// ```
+#[derive(Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    /// Wraps `value`.
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    /// Returns the secret value, to hand it to the client needing it.
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T: Serialize> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if REVEAL.with(Cell::get) {
+            self.0.serialize(serializer)
+        } else {
+            serializer.serialize_str(REDACTED)
+        }
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Secret<T> {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        T::schema_name()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        T::json_schema(gen)
+    }
+}
+
+/// Runs `f` with the secrets serialized as they are, e.g. to tell whether a
+/// reloaded config changes one of them.
+pub fn revealed<R>(f: impl FnOnce() -> R) -> R {
+    struct Reset(bool);
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            REVEAL.with(|reveal| reveal.set(self.0));
+        }
+    }
+
+    let _reset = Reset(REVEAL.with(|reveal| reveal.replace(true)));
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserializes_transparently() {
+        let secret: Option<Secret<String>> = serde_json::from_str(r#""nsec1""#).unwrap();
+        assert_eq!(secret.unwrap().expose(), "nsec1");
+    }
+
+    #[test]
+    fn reveals_only_within_revealed() {
+        let secret = Secret::new("nsec1".to_string());
+        let revealed = revealed(|| serde_json::to_string(&secret).unwrap());
+        assert_eq!(revealed, r#""nsec1""#);
+        assert_eq!(serde_json::to_string(&secret).unwrap(), r#""***""#);
+    }
+}
@@ -0,0 +1,231 @@
+//! Extension points of the bridge pipelines.
+//!
+//! An `EventSink` receives the events fetched from the nostr relay, an
+//! `EventSource` produces events to publish to it. `App::from_nostr_to_sink`
+//! and `App::from_source_to_nostr` run any implementation, including the
+//! built-in Waku and IndexDB ones, with the same dedupe, checkpointing,
+//! retries, dead-lettering and metrics.
+//!
+//! A source is consumed as an `EventStream`, see `stream`, so
+//! `App::from_stream_to_sink` bridges any stream of events, whatever
+//! protocol it comes from.
+
+use crate::common::config::{Config, SinkFormat, SinkPayload};
+use crate::common::correlation::CorrelationId;
+use crate::common::error::{self, ResultExt};
+use crate::common::timing::{timed, Operation};
+use crate::indexdb::InviteMsg;
+use async_trait::async_trait;
+use chrono::DateTime;
+use futures::stream::{BoxStream, StreamExt};
+use nostr_sdk::{Event, JsonUtil};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Names of the sinks that can be listed in `sinks`.
+pub const SINK_NAMES: &[&str] = &[
+    "waku", "indexdb", "kafka", "mqtt", "webhook", "matrix", "smtp", "sqs",
+];
+
+/// A destination of bridged events.
+#[async_trait]
+pub trait EventSink: Send + Sync {
+    /// Short, stable name used in logs, metrics, audit records and dead
+    /// letters, e.g. `kafka`.
+    fn name(&self) -> &'static str;
+
+    /// The topic `event` is delivered to, reported with the traffic
+    /// counters, e.g. its Waku content topic.
+    fn topic(&self, _event: &Event) -> Option<&str> {
+        None
+    }
+
+    /// Tells whether the sink times each of its delivery attempts, e.g.
+    /// because it retries them itself. The deliveries to the other sinks are
+    /// timed as a whole, see `deliver`.
+    fn times_attempts(&self) -> bool {
+        false
+    }
+
+    /// Delivers one event. Transient errors are retried by the pipeline.
+    async fn send(&self, event: &Event, correlation_id: &CorrelationId) -> error::Result<()>;
+
+    /// Delivers one event to `topic` instead of the configured one, as
+    /// routed by a transform rule. Sinks without topics reject routed
+    /// events.
+    async fn send_to(
+        &self,
+        event: &Event,
+        correlation_id: &CorrelationId,
+        topic: Option<&str>,
+    ) -> error::Result<()> {
+        match topic {
+            None => self.send(event, correlation_id).await,
+            Some(topic) => Err(error::Error::InvalidConfig(format!(
+                "the {} sink cannot route events to topic {}",
+                self.name(),
+                topic
+            ))),
+        }
+    }
+
+    /// Applies the endpoints of a reloaded configuration, e.g. the urls
+    /// posted to. Sinks without reloadable endpoints keep their own.
+    fn reload(&self, _config: &Config) {}
+}
+
+/// Delivers `event` to `sink`, routed to `topic` if any, and abandons the
+/// delivery with `Error::Timeout` once the limit of `Operation::Sink`
+/// expired, unless the sink bounds each of its attempts itself.
+pub async fn deliver(
+    sink: &dyn EventSink,
+    event: &Event,
+    correlation_id: &CorrelationId,
+    topic: Option<&str>,
+) -> error::Result<()> {
+    let delivery = sink.send_to(event, correlation_id, topic);
+    if sink.times_attempts() {
+        delivery.await
+    } else {
+        timed(Operation::Sink, delivery).await
+    }
+}
+
+/// An origin of events to publish to the nostr relay.
+#[async_trait]
+pub trait EventSource: Send + Sync {
+    /// Short, stable name used in logs and metrics, e.g. `kafka`.
+    fn name(&self) -> &'static str;
+
+    /// Receives events until the source is exhausted or fails, handing each
+    /// one to `tx`. Malformed input should be logged and skipped, not
+    /// returned as an error.
+    async fn run(&self, tx: mpsc::Sender<Event>) -> error::Result<()>;
+}
+
+/// The events received from a source, in order.
+pub type EventStream = BoxStream<'static, Event>;
+
+/// Runs `source` in the background and returns its events as a stream of at
+/// most `capacity` buffered events. The source stops once the stream is
+/// dropped, and the stream ends once the source stops.
+pub fn stream(source: Arc<dyn EventSource>, capacity: usize) -> EventStream {
+    let (tx, rx) = mpsc::channel(capacity);
+    tokio::spawn(async move {
+        if let Err(e) = source.run(tx).await {
+            tracing::error!("{} source stopped: {}", source.name(), e);
+        }
+    });
+    ReceiverStream::new(rx).boxed()
+}
+
+/// Delivers every event to several sinks in turn, as listed in `sinks`.
+///
+/// An event is delivered once every sink accepted it. A failure retries the
+/// event on all of them, so the sinks before the failed one may receive it
+/// again and should deduplicate by event id.
+pub struct FanOutSink {
+    sinks: Vec<Arc<dyn EventSink>>,
+}
+
+impl FanOutSink {
+    pub fn new(sinks: Vec<Arc<dyn EventSink>>) -> Self {
+        Self { sinks }
+    }
+}
+
+#[async_trait]
+impl EventSink for FanOutSink {
+    fn name(&self) -> &'static str {
+        "sinks"
+    }
+
+    /// Each sink is timed on its own.
+    fn times_attempts(&self) -> bool {
+        true
+    }
+
+    async fn send(&self, event: &Event, correlation_id: &CorrelationId) -> error::Result<()> {
+        self.send_to(event, correlation_id, None).await
+    }
+
+    async fn send_to(
+        &self,
+        event: &Event,
+        correlation_id: &CorrelationId,
+        topic: Option<&str>,
+    ) -> error::Result<()> {
+        for sink in &self.sinks {
+            deliver(sink.as_ref(), event, correlation_id, topic)
+                .await
+                .context(|| format!("delivering to {}", sink.name()))?;
+        }
+        Ok(())
+    }
+
+    fn reload(&self, config: &Config) {
+        for sink in &self.sinks {
+            sink.reload(config);
+        }
+    }
+}
+
+/// Encodes an event as configured: as is, or converted to an ACL invite.
+pub fn encode(event: &Event, payload: &SinkPayload) -> error::Result<Vec<u8>> {
+    match payload {
+        SinkPayload::Event => Ok(event.as_json().into_bytes()),
+        SinkPayload::Invite => {
+            let invite = InviteMsg::try_from(event.clone())
+                .context(|| format!("converting event {} to an invite", event.id))?;
+            Ok(serde_json::to_vec(&invite)?)
+        }
+    }
+}
+
+/// Content type of a CloudEvent in structured mode.
+pub const CLOUDEVENTS_CONTENT_TYPE: &str = "application/cloudevents+json";
+
+/// Wraps an encoded payload as configured: as is, or as a CloudEvents 1.0
+/// envelope in structured JSON mode.
+///
+/// # Arguments
+///
+/// * `event` - The bridged event, whose id becomes the CloudEvent id.
+/// * `data` - The JSON payload encoded by `encode`.
+/// * `source` - The CloudEvent source, e.g. the relay url.
+/// * `format` - The configured format.
+pub fn wrap(
+    event: &Event,
+    data: Vec<u8>,
+    source: &str,
+    format: &SinkFormat,
+) -> error::Result<Vec<u8>> {
+    match format {
+        SinkFormat::Raw => Ok(data),
+        SinkFormat::CloudEvents => {
+            let data: serde_json::Value = serde_json::from_slice(&data)?;
+            let time = DateTime::from_timestamp(event.created_at.as_u64() as i64, 0)
+                .map(|time| time.to_rfc3339());
+            Ok(serde_json::to_vec(&json!({
+                "specversion": "1.0",
+                "id": event.id.to_hex(),
+                "source": source,
+                "type": acl_event_type(event),
+                "time": time,
+                "datacontenttype": "application/json",
+                "data": data,
+            }))?)
+        }
+    }
+}
+
+/// Returns the ACL event type in the content of `event`, e.g. `invite`, or
+/// `nostr.kind.<kind>` for events that are not ACL events.
+fn acl_event_type(event: &Event) -> String {
+    serde_json::from_str::<serde_json::Value>(&event.content)
+        .ok()
+        .and_then(|content| content.get("type")?.as_str().map(str::to_string))
+        .unwrap_or_else(|| format!("nostr.kind.{}", event.kind.as_u16()))
+}
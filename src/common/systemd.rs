@@ -0,0 +1,86 @@
+//! Optional systemd integration.
+//!
+//! When enabled and the bridge runs as a `Type=notify` unit, READY=1 is sent
+//! once the pipeline has started. If the unit sets `WatchdogSec`, the watchdog
+//! is petted only while the pipeline loop keeps making progress, so systemd
+//! restarts a process that is alive but wedged. `WatchdogSec` must therefore
+//! be longer than one fetch round. Pipelines that never report progress, such
+//! as the Waku listener, are only checked for being alive.
+
+use sd_notify::NotifyState;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static LAST_PROGRESS: OnceLock<Mutex<Instant>> = OnceLock::new();
+
+/// Enables or disables the notifications sent to systemd.
+pub fn init(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+fn notify(state: NotifyState) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Err(e) = sd_notify::notify(false, &[state]) {
+        tracing::warn!("failed to notify systemd: {}", e);
+    }
+}
+
+/// Tells systemd that the bridge is up. Extra calls are harmless.
+pub fn notify_ready() {
+    notify(NotifyState::Ready);
+}
+
+/// Tells systemd that the bridge is shutting down.
+pub fn notify_stopping() {
+    notify(NotifyState::Stopping);
+}
+
+/// Records that the pipeline loop completed a round.
+pub fn progress() {
+    let last_progress = LAST_PROGRESS.get_or_init(|| Mutex::new(Instant::now()));
+    *last_progress.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+}
+
+/// Starts petting the systemd watchdog if the unit enables it.
+///
+/// The watchdog is petted at half the configured interval, as long as
+/// `progress` was called within the interval or has never been called.
+///
+/// # Returns
+///
+/// Returns the handle of the background task, or `None` if systemd support
+/// or the watchdog is disabled.
+pub fn spawn_watchdog() -> Option<JoinHandle<()>> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    let mut usec = 0;
+    if !sd_notify::watchdog_enabled(false, &mut usec) || usec == 0 {
+        return None;
+    }
+    let timeout = Duration::from_micros(usec);
+
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(timeout / 2);
+        loop {
+            interval.tick().await;
+            let stalled = LAST_PROGRESS
+                .get()
+                .map(|at| at.lock().unwrap_or_else(|e| e.into_inner()).elapsed())
+                .unwrap_or_default();
+            if stalled < timeout {
+                notify(NotifyState::Watchdog);
+            } else {
+                tracing::warn!(
+                    stalled_secs = stalled.as_secs(),
+                    "pipeline made no progress, withholding systemd watchdog"
+                );
+            }
+        }
+    }))
+}
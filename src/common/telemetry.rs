@@ -0,0 +1,100 @@
+//! OpenTelemetry integration.
+//!
+//! Builds the OTLP tracing layer installed by `logging::logging_init` and
+//! propagates the current trace context to downstream HTTP services using
+//! the W3C `traceparent` header.
+//!
+//! Without the `telemetry` feature nothing is exported and no header is
+//! propagated; a `telemetry` section is then rejected by the validation.
+
+#[cfg(feature = "telemetry")]
+use crate::common::config::TelemetryConfig;
+#[cfg(feature = "telemetry")]
+use crate::common::error;
+#[cfg(feature = "telemetry")]
+use opentelemetry::propagation::{Injector, TextMapPropagator};
+#[cfg(feature = "telemetry")]
+use opentelemetry::trace::TracerProvider as _;
+#[cfg(feature = "telemetry")]
+use opentelemetry::KeyValue;
+#[cfg(feature = "telemetry")]
+use opentelemetry_otlp::WithExportConfig;
+#[cfg(feature = "telemetry")]
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+#[cfg(feature = "telemetry")]
+use opentelemetry_sdk::trace::{Tracer, TracerProvider};
+#[cfg(feature = "telemetry")]
+use opentelemetry_sdk::{runtime, Resource};
+use reqwest::header::HeaderMap;
+#[cfg(feature = "telemetry")]
+use reqwest::header::{HeaderName, HeaderValue};
+#[cfg(feature = "telemetry")]
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Creates the OTLP exporter and registers it as the global tracer provider.
+///
+/// # Returns
+///
+/// The tracer to be used by the `tracing-opentelemetry` layer.
+#[cfg(feature = "telemetry")]
+pub fn init_tracer(config: &TelemetryConfig) -> error::Result<Tracer> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(config.otlp_endpoint.clone())
+        .build()
+        .map_err(|e| error::Error::TelemetryError(e.to_string()))?;
+
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            config.service_name.clone(),
+        )]))
+        .build();
+
+    let tracer = provider.tracer(config.service_name.clone());
+    opentelemetry::global::set_tracer_provider(provider);
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    Ok(tracer)
+}
+
+/// Flushes pending spans and shuts down the exporter.
+pub fn shutdown() {
+    #[cfg(feature = "telemetry")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Adapter writing propagation fields into reqwest headers.
+#[cfg(feature = "telemetry")]
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+#[cfg(feature = "telemetry")]
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+/// Returns the trace context headers of the current span.
+///
+/// The map is empty when telemetry is disabled.
+#[cfg(feature = "telemetry")]
+pub fn trace_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let context = tracing::Span::current().context();
+    TraceContextPropagator::new().inject_context(&context, &mut HeaderInjector(&mut headers));
+    headers
+}
+
+/// Returns the trace context headers of the current span, none without the
+/// `telemetry` feature.
+#[cfg(not(feature = "telemetry"))]
+pub fn trace_headers() -> HeaderMap {
+    HeaderMap::new()
+}
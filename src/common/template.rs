@@ -0,0 +1,73 @@
+//! Rendering of the `{{name}}` templates used by the notification sinks.
+
+use crate::common::error;
+
+/// Replaces every `{{name}}` of `template` with its value. `what` names the
+/// template in errors, e.g. `webhook template`.
+///
+/// # Errors
+///
+/// Returns `InvalidConfig` for unknown placeholders and unterminated braces.
+pub fn render<F: Fn(&str) -> Option<String>>(
+    what: &str,
+    template: &str,
+    value: F,
+) -> error::Result<String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after.find("}}").ok_or_else(|| {
+            error::Error::InvalidConfig(format!("{}: unterminated `{{{{` in {:?}", what, template))
+        })?;
+        let name = after[..end].trim();
+        let value = value(name).ok_or_else(|| {
+            error::Error::InvalidConfig(format!("{}: unknown placeholder `{}`", what, name))
+        })?;
+        rendered.push_str(&value);
+        rest = &after[end + 2..];
+    }
+    rendered.push_str(rest);
+
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(name: &str) -> Option<String> {
+        match name {
+            "kind" => Some("1".to_string()),
+            "content" => Some("hello".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn replaces_placeholders() {
+        let rendered = render("test", "kind {{kind}}: {{ content }}", value).unwrap();
+        assert_eq!(rendered, "kind 1: hello");
+    }
+
+    #[test]
+    fn keeps_text_without_placeholders() {
+        assert_eq!(
+            render("test", "plain {text}", value).unwrap(),
+            "plain {text}"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders() {
+        let err = render("test", "{{missing}}", value).unwrap_err();
+        assert!(err.to_string().contains("unknown placeholder `missing`"));
+    }
+
+    #[test]
+    fn rejects_unterminated_braces() {
+        let err = render("test", "{{kind", value).unwrap_err();
+        assert!(err.to_string().contains("unterminated"));
+    }
+}
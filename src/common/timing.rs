@@ -0,0 +1,132 @@
+//! Duration tracking of external operations.
+//!
+//! Every relay fetch, database query, Waku publish and IndexDB post is timed.
+//! Durations are recorded in a histogram and operations slower than their
+//! configured threshold are logged at WARN level and counted, so degradations
+//! become visible before they turn into outages. Operations exceeding their
+//! hard limit are abandoned with `Error::Timeout`.
+
+use crate::common::config::{SlowOpsConfig, TimeoutsConfig};
+use crate::common::error;
+use crate::metrics;
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Kind of external operation being timed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Fetching events from a nostr relay.
+    Fetch,
+    /// A database query.
+    Db,
+    /// Publishing to a Waku node.
+    Waku,
+    /// Posting to the IndexDB backend.
+    Indexdb,
+    /// Delivering to a pluggable sink.
+    Sink,
+}
+
+impl Operation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Fetch => "fetch",
+            Operation::Db => "db",
+            Operation::Waku => "waku",
+            Operation::Indexdb => "indexdb",
+            Operation::Sink => "sink",
+        }
+    }
+
+    fn threshold(&self, config: &SlowOpsConfig) -> Duration {
+        let ms = match self {
+            Operation::Fetch => config.fetch_ms,
+            Operation::Db => config.db_ms,
+            Operation::Waku => config.waku_ms,
+            Operation::Indexdb => config.indexdb_ms,
+            Operation::Sink => config.sink_ms,
+        };
+        Duration::from_millis(ms)
+    }
+
+    fn limit(&self, config: &TimeoutsConfig) -> Duration {
+        let secs = match self {
+            Operation::Fetch => config.fetch_secs,
+            Operation::Db => config.db_secs,
+            Operation::Waku => config.waku_secs,
+            Operation::Indexdb => config.indexdb_secs,
+            Operation::Sink => config.sink_secs,
+        };
+        Duration::from_secs(secs)
+    }
+}
+
+static THRESHOLDS: OnceLock<SlowOpsConfig> = OnceLock::new();
+static LIMITS: OnceLock<TimeoutsConfig> = OnceLock::new();
+
+/// Sets the slow operation thresholds. Only the first call has an effect;
+/// until then the defaults apply.
+pub fn set_thresholds(config: SlowOpsConfig) {
+    let _ = THRESHOLDS.set(config);
+}
+
+/// Sets the hard limits of the operations. Only the first call has an effect;
+/// until then the defaults apply.
+pub fn set_timeouts(config: TimeoutsConfig) {
+    let _ = LIMITS.set(config);
+}
+
+/// Awaits `fut` for at most the limit of `operation`, recording its duration
+/// and warning if it was slow.
+///
+/// # Returns
+///
+/// The output of `fut`, or `Error::Timeout` if the limit expired first.
+pub async fn timed<T, E, F>(operation: Operation, fut: F) -> error::Result<T>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Into<error::Error>,
+{
+    let limit = operation.limit(LIMITS.get_or_init(TimeoutsConfig::default));
+    let started = Instant::now();
+    let output = tokio::time::timeout(limit, fut).await;
+    let elapsed = started.elapsed();
+
+    let metrics = metrics::metrics();
+    metrics
+        .operation_duration_seconds
+        .with_label_values(&[operation.as_str()])
+        .observe(elapsed.as_secs_f64());
+
+    let threshold = operation.threshold(THRESHOLDS.get_or_init(SlowOpsConfig::default));
+    if elapsed > threshold {
+        metrics
+            .slow_operations_total
+            .with_label_values(&[operation.as_str()])
+            .inc();
+        tracing::warn!(
+            operation = operation.as_str(),
+            latency_ms = elapsed.as_millis() as u64,
+            threshold_ms = threshold.as_millis() as u64,
+            "slow {} operation",
+            operation.as_str()
+        );
+    }
+
+    match output {
+        Ok(result) => result.map_err(Into::into),
+        Err(_) => {
+            tracing::warn!(
+                operation = operation.as_str(),
+                limit_ms = limit.as_millis() as u64,
+                "{} operation timed out",
+                operation.as_str()
+            );
+            Err(error::Error::Timeout {
+                operation: operation.as_str(),
+                after: limit,
+            })
+        }
+    }
+}
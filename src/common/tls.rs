@@ -0,0 +1,49 @@
+//! TLS settings for the outbound HTTP clients.
+//!
+//! Internal Waku/IndexDB endpoints are often served with certificates issued by
+//! a private CA, and lab environments sometimes use self-signed ones. This
+//! module applies the per-client `tls` config section to reqwest builders.
+
+use crate::common::config::TlsConfig;
+use crate::common::error;
+
+/// Applies the TLS configuration, if any, to a reqwest client builder.
+///
+/// Every certificate found in `ca_bundle` is added to the trust store in
+/// addition to the system roots. `danger_accept_invalid_certs` disables
+/// certificate verification entirely and is logged loudly.
+///
+/// # Errors
+///
+/// Returns an error if the CA bundle can not be read or contains no valid
+/// PEM certificate.
+pub fn apply_tls(
+    mut builder: reqwest::ClientBuilder,
+    config: Option<&TlsConfig>,
+) -> error::Result<reqwest::ClientBuilder> {
+    let Some(config) = config else {
+        return Ok(builder);
+    };
+
+    if let Some(path) = &config.ca_bundle {
+        let pem = std::fs::read(path)?;
+        let certs = reqwest::Certificate::from_pem_bundle(&pem)
+            .map_err(|e| error::Error::InvalidConfig(format!("tls.ca_bundle '{}': {}", path, e)))?;
+        if certs.is_empty() {
+            return Err(error::Error::InvalidConfig(format!(
+                "tls.ca_bundle '{}': no certificate found",
+                path
+            )));
+        }
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    if config.danger_accept_invalid_certs {
+        tracing::warn!("TLS certificate verification is DISABLED for this client");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    Ok(builder)
+}
@@ -0,0 +1,612 @@
+//! Fail-fast validation of keys, topics and urls of the configuration.
+//!
+//! Loading a config file, and `App::new` for configurations built in code,
+//! validate every value the clients are built from before building them, so
+//! a typo in the config file is reported with the offending field instead of
+//! a panic deep inside a client constructor. All the problems of a
+//! configuration are reported at once.
+
+#[cfg(feature = "waku-ffi")]
+use crate::common::config::WakuConfig;
+use crate::common::config::{Config, NostrConfig, WakuTransport};
+use crate::common::error::{Error, Result};
+use crate::common::rate_limit::RateLimit;
+use crate::common::sink::SINK_NAMES;
+#[cfg(feature = "nostr")]
+use crate::nostr;
+use crate::transform::TransformChain;
+use crate::waku::PayloadCipher;
+#[cfg(feature = "anchor")]
+use alloy::primitives::Address;
+#[cfg(feature = "anchor")]
+use alloy::signers::local::PrivateKeySigner;
+use nostr_sdk::Keys;
+#[cfg(feature = "waku-ffi")]
+use secp256k1::SecretKey;
+#[cfg(feature = "waku-ffi")]
+use std::str::FromStr;
+use tracing_subscriber::EnvFilter;
+use url::Url;
+#[cfg(feature = "waku-ffi")]
+use waku_bindings::{Multiaddr, WakuContentTopic, WakuPubSubTopic};
+
+/// The problems found in a configuration, so all of them are reported at
+/// once.
+#[derive(Default)]
+struct Problems(Vec<Error>);
+
+impl Problems {
+    /// Records the error of `result`, if any.
+    fn check<T>(&mut self, result: Result<T>) {
+        if let Err(e) = result {
+            self.0.push(e);
+        }
+    }
+
+    /// Records an invalid value.
+    fn add(&mut self, problem: impl Into<String>) {
+        self.0.push(Error::InvalidConfig(problem.into()));
+    }
+}
+
+/// Validates the values of `config` used to build the clients.
+///
+/// # Errors
+///
+/// Returns `InvalidKey`, `InvalidTopic`, `InvalidUrl` or `InvalidConfig`
+/// naming the invalid field, or an `InvalidConfig` listing every problem if
+/// there are several.
+pub fn validate(config: &Config) -> Result<()> {
+    let mut problems = problems(config);
+    match problems.len() {
+        0 => Ok(()),
+        1 => Err(problems.remove(0)),
+        count => Err(Error::InvalidConfig(format!(
+            "{} problems:\n{}",
+            count,
+            problems
+                .iter()
+                .map(|problem| format!("- {}", problem))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ))),
+    }
+}
+
+/// Returns every problem of `config`, in the order of the fields.
+pub fn problems(config: &Config) -> Vec<Error> {
+    let mut problems = Problems::default();
+    problems.check(nostr_key("nostr.priv_key", config.nostr.priv_key.expose()));
+    problems.check(url("nostr.ws_url", &config.nostr.ws_url, &["ws", "wss"]));
+    for ws_url in &config.nostr.ws_urls {
+        problems.check(url("nostr.ws_urls", ws_url, &["ws", "wss"]));
+    }
+    problems.check(relay_tls(&config.nostr));
+    // The filters are only built by the relay client.
+    #[cfg(feature = "nostr")]
+    problems.check(nostr::build_filters(&config.nostr.filters));
+    problems.check(rate_limit("nostr.rate_limit", &config.nostr.rate_limit));
+
+    let waku = &config.waku;
+    if waku.content_topic.trim().is_empty() {
+        problems.add("waku.content_topic must not be empty");
+    }
+    if waku.pubsub_topic.trim().is_empty() {
+        problems.add("waku.pubsub_topic must not be empty");
+    }
+    if waku.shards.is_empty() {
+        problems.add("waku.shards must list at least one shard");
+    }
+    if let Some((cluster_id, shard)) = waku.pubsub_shard() {
+        if cluster_id != waku.cluster_id {
+            problems.add(format!(
+                "waku.pubsub_topic is on cluster {} but waku.cluster_id is {}",
+                cluster_id, waku.cluster_id
+            ));
+        }
+        if !waku.shards.contains(&shard) {
+            problems.add(format!(
+                "waku.pubsub_topic is on shard {} which waku.shards does not list",
+                shard
+            ));
+        }
+    }
+    #[cfg(feature = "waku-ffi")]
+    waku_node(waku, &mut problems);
+    problems.check(url("waku.send_api", &waku.send_api, &["http", "https"]));
+    if waku.timeout_secs == Some(0) {
+        problems.add("waku.timeout_secs must be positive");
+    }
+    problems.check(rate_limit("waku.rate_limit", &waku.rate_limit));
+    if let Some(store_api) = &waku.store_api {
+        problems.check(url("waku.store_api", store_api, &["http", "https"]));
+    }
+    if waku
+        .routes
+        .iter()
+        .any(|route| route.kinds.is_empty() && route.hashtags.is_empty())
+    {
+        problems.add("waku.routes: every route needs kinds or hashtags");
+    }
+    if let Some(encryption) = &waku.encryption {
+        problems.check(PayloadCipher::new(encryption));
+    }
+    if waku.transport == WakuTransport::Gossipsub && !cfg!(feature = "gossipsub") {
+        problems.add(
+            "waku.transport is gossipsub but the gateway was built without the `gossipsub` feature",
+        );
+    }
+
+    problems.check(url(
+        "indexdb_backend.invite_url",
+        &config.indexdb_backend.invite_url,
+        &["http", "https"],
+    ));
+    let indexdb = &config.indexdb_backend;
+    for (field, value) in [
+        ("indexdb_backend.auth_url", &indexdb.auth_url),
+        ("indexdb_backend.grant_url", &indexdb.grant_url),
+        ("indexdb_backend.revoke_url", &indexdb.revoke_url),
+        ("indexdb_backend.membership_url", &indexdb.membership_url),
+    ] {
+        if let Some(value) = value {
+            problems.check(url(field, value, &["http", "https"]));
+        }
+    }
+    problems.check(rate_limit(
+        "indexdb_backend.rate_limit",
+        &indexdb.rate_limit,
+    ));
+
+    if let Err(e) = EnvFilter::try_new(&config.log.level) {
+        problems.add(format!("log.level: {}", e));
+    }
+
+    let sync = &config.sync;
+    if sync.poll_interval_secs == 0 || sync.batch_limit == 0 || sync.channel_capacity == 0 {
+        problems.add(
+            "sync.poll_interval_secs, sync.batch_limit and sync.channel_capacity must be positive",
+        );
+    }
+    if sync.high_watermark == Some(0) {
+        problems.add("sync.high_watermark must be positive");
+    }
+
+    if let Some(breaker) = &config.delivery.circuit_breaker {
+        if breaker.failure_threshold == 0 || breaker.open_secs == 0 || breaker.probe_successes == 0
+        {
+            problems.add(
+                "delivery.circuit_breaker.failure_threshold, open_secs and probe_successes must be positive",
+            );
+        }
+    }
+
+    for sink in &config.sinks {
+        if !SINK_NAMES.contains(&sink.as_str()) {
+            problems.add(format!(
+                "sinks: unknown sink `{}`, expected one of {}",
+                sink,
+                SINK_NAMES.join(", ")
+            ));
+        }
+    }
+
+    problems.check(TransformChain::new(&config.transforms));
+
+    if let Some(sqs) = &config.sqs {
+        if sqs.queue_url.is_some() == sqs.topic_arn.is_some() {
+            problems.add("sqs needs exactly one of queue_url and topic_arn");
+        }
+        if !(1..=10).contains(&sqs.batch_size) {
+            problems.add("sqs.batch_size must be between 1 and 10");
+        }
+        if let Some(queue_url) = &sqs.queue_url {
+            problems.check(url("sqs.queue_url", queue_url, &["http", "https"]));
+        }
+        if let Some(endpoint) = &sqs.endpoint {
+            problems.check(url("sqs.endpoint", endpoint, &["http", "https"]));
+        }
+    }
+    #[cfg(feature = "anchor")]
+    if let Some(anchor) = &config.anchor {
+        problems.check(url("anchor.rpc_url", &anchor.rpc_url, &["http", "https"]));
+        problems.check(evm_key("anchor.private_key", anchor.private_key.expose()));
+        problems.check(evm_address(
+            "anchor.contract_address",
+            &anchor.contract_address,
+        ));
+        if anchor.window_secs == 0 {
+            problems.add("anchor.window_secs must be positive");
+        }
+    }
+    if let Some(ipfs) = &config.ipfs {
+        problems.check(url("ipfs.api_url", &ipfs.api_url, &["http", "https"]));
+    }
+    if let Some(endpoint) = config.archive.as_ref().and_then(|a| a.endpoint.as_ref()) {
+        problems.check(url("archive.endpoint", endpoint, &["http", "https"]));
+    }
+    if let Some(matrix) = &config.matrix {
+        problems.check(url(
+            "matrix.homeserver_url",
+            &matrix.homeserver_url,
+            &["http", "https"],
+        ));
+    }
+    if let Some(webhook) = &config.webhook {
+        for webhook_url in &webhook.urls {
+            problems.check(url("webhook.urls", webhook_url, &["http", "https"]));
+        }
+    }
+
+    for (field, set, feature, built) in [
+        (
+            "server.grpc_port",
+            config.server.grpc_port.is_some(),
+            "grpc",
+            cfg!(feature = "grpc"),
+        ),
+        (
+            "server.graphql_port",
+            config.server.graphql_port.is_some(),
+            "graphql",
+            cfg!(feature = "graphql"),
+        ),
+        (
+            "kafka",
+            config.kafka.is_some(),
+            "kafka",
+            cfg!(feature = "kafka"),
+        ),
+        (
+            "mqtt",
+            config.mqtt.is_some(),
+            "mqtt",
+            cfg!(feature = "mqtt"),
+        ),
+        (
+            "nats",
+            config.nats.is_some(),
+            "nats",
+            cfg!(feature = "nats"),
+        ),
+        (
+            "redis",
+            config.redis.is_some(),
+            "redis",
+            cfg!(feature = "redis"),
+        ),
+        ("sqs", config.sqs.is_some(), "sqs", cfg!(feature = "sqs")),
+        (
+            "smtp",
+            config.smtp.is_some(),
+            "smtp",
+            cfg!(feature = "smtp"),
+        ),
+        (
+            "plugins",
+            !config.plugins.is_empty(),
+            "plugins",
+            cfg!(feature = "plugins"),
+        ),
+        (
+            "archive",
+            config.archive.is_some(),
+            "archive",
+            cfg!(feature = "archive"),
+        ),
+        (
+            "anchor",
+            config.anchor.is_some(),
+            "anchor",
+            cfg!(feature = "anchor"),
+        ),
+        (
+            "sentry",
+            config.sentry.is_some(),
+            "sentry",
+            cfg!(feature = "sentry"),
+        ),
+        (
+            "telemetry",
+            config.telemetry.is_some(),
+            "telemetry",
+            cfg!(feature = "telemetry"),
+        ),
+    ] {
+        if set && !built {
+            problems.add(format!(
+                "{} is set but the gateway was built without the `{}` feature",
+                field, feature
+            ));
+        }
+    }
+
+    problems.0
+}
+
+/// Validates the key, topics and addresses of the Waku node.
+#[cfg(feature = "waku-ffi")]
+fn waku_node(waku: &WakuConfig, problems: &mut Problems) {
+    if let Some(key) = &waku.node_key {
+        problems.check(secret_key("waku.node_key", key.expose()));
+    }
+    problems.check(content_topic("waku.content_topic", &waku.content_topic));
+    for route in &waku.routes {
+        problems.check(content_topic(
+            "waku.routes.content_topic",
+            &route.content_topic,
+        ));
+    }
+    problems.check(pubsub_topic("waku.pubsub_topic", &waku.pubsub_topic));
+    problems.check(multiaddr("waku.node_addr", &waku.node_addr));
+    for peer in &waku.peers.bootstrap_peers {
+        problems.check(multiaddr("waku.peers.bootstrap_peers", peer));
+    }
+    if let Some(dns_discovery_url) = &waku.peers.dns_discovery_url {
+        problems.check(url(
+            "waku.peers.dns_discovery_url",
+            dns_discovery_url,
+            &["enrtree"],
+        ));
+    }
+    if waku.peers.check_interval_secs == 0 {
+        problems.add("waku.peers.check_interval_secs must be positive");
+    }
+    if waku.transport == WakuTransport::Gossipsub {
+        problems.check(multiaddr(
+            "waku.gossipsub.listen_addr",
+            &waku.gossipsub.listen_addr,
+        ));
+        for peer in &waku.gossipsub.bootstrap_peers {
+            problems.check(multiaddr("waku.gossipsub.bootstrap_peers", peer));
+        }
+    }
+}
+
+/// Parses a nostr private key, in hex or bech32 (`nsec`) form.
+pub fn nostr_key(field: &'static str, value: &str) -> Result<Keys> {
+    Keys::parse(value).map_err(|e| Error::InvalidKey {
+        field,
+        reason: e.to_string(),
+    })
+}
+
+/// Parses a hex encoded EVM account key.
+#[cfg(feature = "anchor")]
+pub fn evm_key(field: &'static str, value: &str) -> Result<PrivateKeySigner> {
+    value
+        .trim_start_matches("0x")
+        .parse::<PrivateKeySigner>()
+        .map_err(|e| Error::InvalidKey {
+            field,
+            reason: e.to_string(),
+        })
+}
+
+/// Parses a hex encoded EVM address.
+#[cfg(feature = "anchor")]
+pub fn evm_address(field: &'static str, value: &str) -> Result<Address> {
+    value.parse::<Address>().map_err(|e| Error::InvalidKey {
+        field,
+        reason: e.to_string(),
+    })
+}
+
+/// Parses a hex encoded secp256k1 secret key.
+#[cfg(feature = "waku-ffi")]
+pub fn secret_key(field: &'static str, value: &str) -> Result<SecretKey> {
+    SecretKey::from_str(value.trim_start_matches("0x")).map_err(|e| Error::InvalidKey {
+        field,
+        reason: e.to_string(),
+    })
+}
+
+/// Parses a content topic of the form `/{application}/{version}/{name}/{encoding}`.
+#[cfg(feature = "waku-ffi")]
+pub fn content_topic(field: &'static str, value: &str) -> Result<WakuContentTopic> {
+    WakuContentTopic::from_str(value).map_err(|e| Error::InvalidTopic {
+        field,
+        value: value.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+/// Parses a pubsub topic, e.g. `/waku/2/rs/1/6`.
+#[cfg(feature = "waku-ffi")]
+pub fn pubsub_topic(field: &'static str, value: &str) -> Result<WakuPubSubTopic> {
+    WakuPubSubTopic::from_str(value).map_err(|e| Error::InvalidTopic {
+        field,
+        value: value.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+/// Parses the multiaddress of a peer.
+#[cfg(feature = "waku-ffi")]
+pub fn multiaddr(field: &'static str, value: &str) -> Result<Multiaddr> {
+    value
+        .parse()
+        .map_err(|e: <Multiaddr as FromStr>::Err| Error::InvalidUrl {
+            field,
+            value: value.to_string(),
+            reason: e.to_string(),
+        })
+}
+
+/// Parses an url and checks that its scheme is one of `schemes`.
+pub fn url(field: &'static str, value: &str, schemes: &[&str]) -> Result<Url> {
+    let invalid = |reason: String| Error::InvalidUrl {
+        field,
+        value: value.to_string(),
+        reason,
+    };
+    let url = Url::parse(value).map_err(|e| invalid(e.to_string()))?;
+    if !schemes.contains(&url.scheme()) {
+        return Err(invalid(format!("expected one of {:?}", schemes)));
+    }
+    if url.host_str().is_none() {
+        return Err(invalid("missing host".to_string()));
+    }
+
+    Ok(url)
+}
+
+/// Checks that no TLS setting is set for `wss` relays, since the relay pool
+/// verifies them against the system trust store whatever the config says.
+pub fn relay_tls(nostr: &NostrConfig) -> Result<()> {
+    let Some(tls) = &nostr.tls else {
+        return Ok(());
+    };
+    let secure = std::iter::once(&nostr.ws_url)
+        .chain(&nostr.ws_urls)
+        .any(|relay| relay.starts_with("wss://"));
+    if secure && (tls.ca_bundle.is_some() || tls.danger_accept_invalid_certs) {
+        return Err(Error::InvalidConfig(
+            "nostr.tls is not supported for wss relays, which are verified against \
+             the system trust store: add the CA bundle to the system store instead"
+                .to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks that a rate limit, if set, lets requests through.
+pub fn rate_limit(field: &'static str, value: &Option<RateLimit>) -> Result<()> {
+    let Some(limit) = value else {
+        return Ok(());
+    };
+    if !(limit.per_second.is_finite() && limit.per_second > 0.0) {
+        return Err(Error::InvalidConfig(format!(
+            "{}.per_second must be positive",
+            field
+        )));
+    }
+    if limit.burst == Some(0) {
+        return Err(Error::InvalidConfig(format!(
+            "{}.burst must be positive",
+            field
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::TlsConfig;
+    use crate::common::secret::Secret;
+
+    fn template() -> Config {
+        serde_yaml::from_str(include_str!("../../templates/config.yaml")).unwrap()
+    }
+
+    #[test]
+    fn parses_nostr_keys() {
+        let keys = Keys::generate();
+        let hex = keys.secret_key().to_secret_hex();
+        assert_eq!(
+            nostr_key("key", &hex).unwrap().public_key(),
+            keys.public_key()
+        );
+        assert!(matches!(
+            nostr_key("nostr.priv_key", "nsec1typo"),
+            Err(Error::InvalidKey {
+                field: "nostr.priv_key",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn checks_url_schemes_and_hosts() {
+        assert!(url("nostr.ws_url", "wss://relay.example", &["ws", "wss"]).is_ok());
+        for value in ["https://relay.example", "relay.example", "ws://"] {
+            assert!(
+                matches!(
+                    url("nostr.ws_url", value, &["ws", "wss"]),
+                    Err(Error::InvalidUrl {
+                        field: "nostr.ws_url",
+                        ..
+                    })
+                ),
+                "{value}"
+            );
+        }
+    }
+
+    #[test]
+    fn checks_rate_limits() {
+        let limit = |per_second, burst| {
+            rate_limit("waku.rate_limit", &Some(RateLimit { per_second, burst }))
+        };
+        assert!(rate_limit("waku.rate_limit", &None).is_ok());
+        assert!(limit(0.5, None).is_ok());
+        assert!(limit(0.0, None).is_err());
+        assert!(limit(f64::NAN, None).is_err());
+        assert!(limit(10.0, Some(0)).is_err());
+    }
+
+    #[cfg(feature = "waku-ffi")]
+    #[test]
+    fn parses_topics() {
+        assert!(content_topic("waku.content_topic", "/acl/1/invites/proto").is_ok());
+        assert!(content_topic("waku.content_topic", "acl-invites").is_err());
+        assert!(pubsub_topic("waku.pubsub_topic", "/waku/2/rs/1/6").is_ok());
+    }
+
+    #[test]
+    fn accepts_the_template() {
+        let config = template();
+        assert!(problems(&config).is_empty());
+        assert!(validate(&config).is_ok());
+    }
+
+    #[test]
+    fn reports_a_single_problem_as_is() {
+        let mut config = template();
+        config.nostr.priv_key = Secret::new("nsec1typo".to_string());
+        assert!(matches!(
+            validate(&config),
+            Err(Error::InvalidKey {
+                field: "nostr.priv_key",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_tls_settings_for_wss_relays() {
+        let mut config = template();
+        config.nostr.tls = Some(TlsConfig {
+            ca_bundle: Some("/etc/ssl/internal-ca.pem".to_string()),
+            danger_accept_invalid_certs: false,
+        });
+        assert!(relay_tls(&config.nostr).is_ok());
+
+        config.nostr.ws_urls = vec!["wss://relay.example".to_string()];
+        assert!(matches!(
+            relay_tls(&config.nostr),
+            Err(Error::InvalidConfig(_))
+        ));
+
+        config.nostr.tls = Some(TlsConfig::default());
+        assert!(relay_tls(&config.nostr).is_ok());
+    }
+
+    #[test]
+    fn reports_every_problem_at_once() {
+        let mut config = template();
+        config.nostr.priv_key = Secret::new("nsec1typo".to_string());
+        config.nostr.ws_url = "https://relay.example".to_string();
+        assert_eq!(problems(&config).len(), 2);
+
+        let Err(Error::InvalidConfig(message)) = validate(&config) else {
+            panic!("expected the list of problems");
+        };
+        assert!(message.starts_with("2 problems:\n- "), "{message}");
+        assert!(message.contains("nostr.priv_key"), "{message}");
+        assert!(message.contains("nostr.ws_url"), "{message}");
+    }
+}
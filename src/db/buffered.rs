@@ -0,0 +1,298 @@
+//! Storage that keeps the pipelines running through short database outages.
+//!
+//! Dedupe records and checkpoint commits that fail with a transient error are
+//! kept in a bounded in-memory buffer instead of failing the fetch round. The
+//! buffer is flushed in order before the next write once the database answers
+//! again. Only when the buffer is full do writes fail.
+
+use super::database::skips;
+use super::{NewEvent, Storage};
+use crate::common::error;
+use crate::metrics;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use tokio::sync::Mutex;
+
+#[derive(Debug, Default)]
+struct PendingWrites {
+    /// Events recorded while the database was down, oldest first.
+    events: VecDeque<NewEvent>,
+    /// The directions that recorded each of `events`, by id, for
+    /// deduplication.
+    event_ids: HashMap<String, HashSet<String>>,
+    /// The latest checkpoint of each direction and relay that could not be
+    /// committed.
+    checkpoints: BTreeMap<(String, String), u64>,
+}
+
+impl PendingWrites {
+    fn len(&self) -> usize {
+        self.events.len() + self.checkpoints.len()
+    }
+
+    fn record(&mut self, event: &NewEvent) {
+        self.event_ids
+            .entry(event.event_id.clone())
+            .or_default()
+            .insert(event.direction.clone());
+    }
+
+    fn forget(&mut self, event: &NewEvent) {
+        if let Some(directions) = self.event_ids.get_mut(&event.event_id) {
+            directions.remove(&event.direction);
+            if directions.is_empty() {
+                self.event_ids.remove(&event.event_id);
+            }
+        }
+    }
+}
+
+/// `Storage` with an in-memory fallback for dedupe and checkpoint writes.
+#[derive(Debug)]
+pub struct BufferedStorage {
+    store: Storage,
+    capacity: usize,
+    pending: Mutex<PendingWrites>,
+}
+
+impl BufferedStorage {
+    /// Wraps `store`, buffering at most `capacity` writes.
+    pub fn new(store: Storage, capacity: usize) -> Self {
+        Self {
+            store,
+            capacity,
+            pending: Mutex::new(PendingWrites::default()),
+        }
+    }
+
+    /// Returns the checkpoint of `relay` for `direction`, preferring a
+    /// buffered one that is not yet committed. Falls back to the buffered
+    /// checkpoint if the database is unavailable.
+    pub async fn get_last_update(
+        &self,
+        direction: &str,
+        relay: &str,
+        init: u64,
+    ) -> error::Result<u64> {
+        let mut pending = self.pending.lock().await;
+        self.flush(&mut pending).await;
+        let buffered = pending.checkpoints.get(&key(direction, relay)).copied();
+        match (
+            self.store.get_last_update(direction, relay, init).await,
+            buffered,
+        ) {
+            (Ok(stored), Some(buffered)) => Ok(stored.max(buffered)),
+            (Ok(stored), None) => Ok(stored),
+            (Err(e), Some(buffered)) if e.is_transient() => Ok(buffered),
+            (Err(e), _) => Err(e),
+        }
+    }
+
+    /// Tells whether `direction` has to skip an event, see
+    /// `Storage::is_event_existed`, also considering events that are only
+    /// recorded in the buffer.
+    pub async fn is_event_existed(&self, direction: &str, id: String) -> error::Result<bool> {
+        if let Some(recorded_by) = self.pending.lock().await.event_ids.get(&id) {
+            if recorded_by
+                .iter()
+                .any(|recorded_by| skips(direction, Some(recorded_by)))
+            {
+                return Ok(true);
+            }
+        }
+        self.store.is_event_existed(direction, id).await
+    }
+
+    /// Records a bridged event, buffering it if the database is unavailable.
+    pub async fn add_new_event(&self, event: NewEvent) -> error::Result<()> {
+        let mut pending = self.pending.lock().await;
+        self.flush(&mut pending).await;
+        if pending.events.is_empty() {
+            match self.store.add_new_event(event.clone()).await {
+                Err(e) if e.is_transient() => self.buffer(&mut pending, e)?,
+                result => return result,
+            }
+        } else {
+            self.buffer_capacity(&pending)?;
+        }
+
+        pending.record(&event);
+        pending.events.push_back(event);
+        observe_buffered(&pending);
+        Ok(())
+    }
+
+    /// Records the events of a fetch round together with the checkpoint of
+    /// `relay` for `direction` covering them, see `Storage::commit_batch`. If
+    /// the database is unavailable both are buffered, and flushed events
+    /// first.
+    pub async fn commit_batch(
+        &self,
+        direction: &str,
+        relay: &str,
+        events: Vec<NewEvent>,
+        last: u64,
+    ) -> error::Result<()> {
+        let mut pending = self.pending.lock().await;
+        self.flush(&mut pending).await;
+        if pending.len() == 0 {
+            match self
+                .store
+                .commit_batch(direction, relay, events.clone(), last)
+                .await
+            {
+                Err(e) if e.is_transient() => {
+                    if self.buffer_batch(&pending, events.len()).is_err() {
+                        return Err(e);
+                    }
+                    tracing::warn!("database unavailable, buffering writes in memory: {}", e);
+                }
+                result => return result,
+            }
+        } else {
+            self.buffer_batch(&pending, events.len())?;
+        }
+
+        for event in events {
+            pending.record(&event);
+            pending.events.push_back(event);
+        }
+        pending.checkpoints.insert(key(direction, relay), last);
+        observe_buffered(&pending);
+        Ok(())
+    }
+
+    /// Commits the checkpoint of `relay` for `direction`, buffering it if the
+    /// database is unavailable.
+    pub async fn update_last_update(
+        &self,
+        direction: &str,
+        relay: &str,
+        last: u64,
+    ) -> error::Result<()> {
+        let key = key(direction, relay);
+        let mut pending = self.pending.lock().await;
+        self.flush(&mut pending).await;
+        if pending.events.is_empty() {
+            match self.store.update_last_update(direction, relay, last).await {
+                Err(e) if e.is_transient() => {
+                    if !pending.checkpoints.contains_key(&key) {
+                        self.buffer(&mut pending, e)?;
+                    }
+                }
+                result => {
+                    pending.checkpoints.remove(&key);
+                    observe_buffered(&pending);
+                    return result;
+                }
+            }
+        } else if !pending.checkpoints.contains_key(&key) {
+            self.buffer_capacity(&pending)?;
+        }
+
+        // Commit the checkpoint only after the events it covers.
+        pending.checkpoints.insert(key, last);
+        observe_buffered(&pending);
+        Ok(())
+    }
+
+    /// Writes the buffered events and checkpoints, as done on shutdown.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the number of writes still buffered if the
+    /// database is unavailable.
+    pub async fn flush_pending(&self) -> error::Result<()> {
+        let mut pending = self.pending.lock().await;
+        self.flush(&mut pending).await;
+        match pending.len() {
+            0 => Ok(()),
+            len => Err(error::Error::CustomError(format!(
+                "{} buffered database writes could not be flushed",
+                len
+            ))),
+        }
+    }
+
+    /// Accepts a write into the buffer after the database failed with `e`.
+    fn buffer(&self, pending: &mut PendingWrites, e: error::Error) -> error::Result<()> {
+        self.buffer_capacity(pending).map_err(|_| e)?;
+        if pending.len() == 0 {
+            tracing::warn!("database unavailable, buffering writes in memory: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Checks that `events` and a checkpoint fit in the buffer, so a batch
+    /// is never buffered partially.
+    fn buffer_batch(&self, pending: &PendingWrites, events: usize) -> error::Result<()> {
+        if pending.len() + events + 1 > self.capacity {
+            return Err(error::Error::CustomError(format!(
+                "database write buffer full ({} writes)",
+                self.capacity
+            )));
+        }
+        Ok(())
+    }
+
+    fn buffer_capacity(&self, pending: &PendingWrites) -> error::Result<()> {
+        self.buffer_batch(pending, 0)
+    }
+
+    /// Writes buffered events, then the buffered checkpoints, stopping at the
+    /// first transient failure.
+    async fn flush(&self, pending: &mut PendingWrites) {
+        if pending.len() == 0 {
+            return;
+        }
+
+        while let Some(event) = pending.events.front() {
+            match self.store.add_new_event(event.clone()).await {
+                Err(e) if e.is_transient() => {
+                    observe_buffered(pending);
+                    return;
+                }
+                Err(e) => tracing::warn!("dropping buffered event {}: {}", event.event_id, e),
+                Ok(()) => {}
+            }
+            if let Some(event) = pending.events.pop_front() {
+                pending.forget(&event);
+            }
+        }
+
+        while let Some(((direction, relay), checkpoint)) = pending.checkpoints.pop_first() {
+            match self
+                .store
+                .update_last_update(&direction, &relay, checkpoint)
+                .await
+            {
+                Err(e) if e.is_transient() => {
+                    pending.checkpoints.insert((direction, relay), checkpoint);
+                    observe_buffered(pending);
+                    return;
+                }
+                Err(e) => tracing::warn!(
+                    "{}: dropping buffered checkpoint {} of {}: {}",
+                    direction,
+                    checkpoint,
+                    relay,
+                    e
+                ),
+                Ok(()) => {}
+            }
+        }
+
+        tracing::info!("database available again, buffered writes flushed");
+        observe_buffered(pending);
+    }
+}
+
+/// Key of the buffered checkpoint of `relay` for `direction`.
+fn key(direction: &str, relay: &str) -> (String, String) {
+    (direction.to_string(), relay.to_string())
+}
+
+fn observe_buffered(pending: &PendingWrites) {
+    metrics::metrics()
+        .db_buffered_writes
+        .set(pending.len() as f64);
+}
@@ -1,15 +1,29 @@
 use super::entities::prelude::{
-    LastUpdateActiveModel, LastUpdateEntity, NostrEventActiveModel, NostrEventColumn,
-    NostrEventEntity,
+    AnchorActiveModel, AnchorColumn, AnchorEntity, AnchorModel, AnchorProofActiveModel,
+    AnchorProofColumn, AnchorProofEntity, AnchorProofModel, AuditLogActiveModel,
+    CrashMarkerActiveModel, DeadLetterActiveModel, DeadLetterColumn, DeadLetterEntity,
+    DeadLetterModel, LastUpdateActiveModel, LastUpdateColumn, LastUpdateEntity, LastUpdateModel,
+    NostrEventActiveModel, NostrEventColumn, NostrEventEntity, NostrEventModel,
+    RetryQueueActiveModel, RetryQueueColumn, RetryQueueEntity, RetryQueueModel,
+    WakuCheckpointActiveModel, WakuCheckpointColumn, WakuCheckpointEntity, WakuCheckpointModel,
 };
 use super::migration::Migrator;
 use crate::common::config::DatabaseConfig;
 use crate::common::error;
+use crate::common::timing::{timed, Operation};
 use chrono;
 use sea_orm::*;
 use sea_orm_migration::prelude::*;
+use std::collections::HashSet;
 use std::{sync::Arc, time::Duration};
 
+/// Attempts to commit a checkpoint before giving up on concurrent writers.
+const CHECKPOINT_CAS_ATTEMPTS: u32 = 5;
+/// Proof rows per insert, well below the bind parameter limit of Postgres.
+const PROOF_INSERT_CHUNK: usize = 1000;
+/// Event rows per insert, well below the bind parameter limit of Postgres.
+const EVENT_INSERT_CHUNK: usize = 1000;
+
 pub async fn setup_db(req_url: &str, db_name: &str) -> Result<DatabaseConnection, DbErr> {
     let db = Database::connect(req_url).await?;
     let db = match db.get_database_backend() {
@@ -47,13 +61,59 @@ pub async fn setup_db(req_url: &str, db_name: &str) -> Result<DatabaseConnection
     Ok(db)
 }
 
+/// Delivery state of a recorded event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// Queued for delivery, not yet delivered.
+    Pending,
+    /// Delivered to the sink.
+    Delivered,
+    /// Moved to the dead-letter queue.
+    DeadLettered,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::DeadLettered => "dead_lettered",
+        }
+    }
+}
+
+/// A fetched event to record as pending before it is queued for delivery.
+#[derive(Debug, Clone)]
+pub struct NewEvent {
+    pub event_id: String,
+    pub correlation_id: String,
+    pub direction: String,
+    /// The event as JSON, so it can be re-enqueued after a restart.
+    pub payload: String,
+}
+
+/// Selects the rows of the history queries. Unset fields match everything.
+#[derive(Debug, Default, Clone)]
+pub struct HistoryFilter {
+    pub event_id: Option<String>,
+    pub direction: Option<String>,
+    /// Delivery status of recorded events; ignored for dead letters.
+    pub status: Option<String>,
+    /// Sink of dead letters; ignored for recorded events.
+    pub sink: Option<String>,
+    /// Inclusive lower bound of the update, or dead-lettering, time.
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    /// Exclusive upper bound of the update, or dead-lettering, time.
+    pub until: Option<chrono::DateTime<chrono::Utc>>,
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct Storage {
     pub conn: Arc<DatabaseConnection>,
 }
 
 impl Storage {
-    pub async fn new(config: DatabaseConfig) -> Self {
+    pub async fn connect(config: DatabaseConfig) -> error::Result<Self> {
         //let url = format!("{}/{}", config.url, config.db_name);
         let mut opt = ConnectOptions::new(&config.db_url);
         opt.max_connections(config.max_connect_pool)
@@ -61,65 +121,1036 @@ impl Storage {
             .connect_timeout(Duration::from_secs(config.connect_timeout))
             .acquire_timeout(Duration::from_secs(config.acquire_timeout));
 
-        let db = Database::connect(opt.clone())
-            .await
-            .expect("failed to connect to database");
+        let db = Database::connect(opt.clone()).await?;
+
+        Ok(Self { conn: Arc::new(db) })
+    }
+
+    /// Opens an empty in-memory SQLite database with the schema applied, as
+    /// used by simulated runs.
+    pub async fn in_memory() -> error::Result<Self> {
+        let mut opt = ConnectOptions::new("sqlite::memory:");
+        // Each connection would otherwise open its own, empty, database.
+        opt.max_connections(1).min_connections(1);
+
+        let db = Database::connect(opt).await?;
+        Migrator::up(&db, None).await?;
+
+        Ok(Self { conn: Arc::new(db) })
+    }
+
+    /// Returns the checkpoint of `relay` for `direction`, creating it if
+    /// needed. Each direction fetching from the relays keeps its own
+    /// checkpoints, so directions sharing the database do not move each
+    /// other past events they did not deliver.
+    ///
+    /// A new relay starts from the lowest checkpoint of the other relays of
+    /// `direction`, or `init` if there is none. A checkpoint committed before
+    /// checkpoints were kept per direction, of this relay or of a single
+    /// relay setup naming no relay, is taken over by the first direction
+    /// asking for one.
+    pub async fn get_last_update(
+        &self,
+        direction: &str,
+        relay: &str,
+        init: u64,
+    ) -> error::Result<u64> {
+        if let Some(last) = timed(
+            Operation::Db,
+            LastUpdateEntity::find()
+                .filter(LastUpdateColumn::Direction.eq(direction))
+                .filter(LastUpdateColumn::Relay.eq(relay))
+                .one(self.conn.as_ref()),
+        )
+        .await?
+        {
+            return Ok(last.last_update as u64);
+        }
+
+        for legacy in [
+            LastUpdateColumn::Relay.eq(relay),
+            LastUpdateColumn::Relay.is_null(),
+        ] {
+            let Some(legacy) = timed(
+                Operation::Db,
+                LastUpdateEntity::find()
+                    .filter(LastUpdateColumn::Direction.is_null())
+                    .filter(legacy)
+                    .one(self.conn.as_ref()),
+            )
+            .await?
+            else {
+                continue;
+            };
+            let taken = timed(
+                Operation::Db,
+                LastUpdateEntity::update_many()
+                    .col_expr(LastUpdateColumn::Direction, Expr::value(direction))
+                    .col_expr(LastUpdateColumn::Relay, Expr::value(relay))
+                    .filter(LastUpdateColumn::Id.eq(legacy.id))
+                    .filter(LastUpdateColumn::Direction.is_null())
+                    .exec(self.conn.as_ref()),
+            )
+            .await?;
+            if taken.rows_affected > 0 {
+                return Ok(legacy.last_update as u64);
+            }
+        }
+
+        let init = match self.direction_checkpoint(direction).await? {
+            Some(lowest) => lowest.last_update as u64,
+            None => init,
+        };
+        let new_last_update = LastUpdateActiveModel {
+            last_update: Set(init as i64),
+            updated_at: Set(chrono::Utc::now().into()),
+            relay: Set(Some(relay.to_string())),
+            direction: Set(Some(direction.to_string())),
+            ..Default::default()
+        };
+        timed(Operation::Db, new_last_update.insert(self.conn.as_ref())).await?;
+        Ok(init)
+    }
+
+    /// Commits the checkpoint of `relay` for `direction` with optimistic
+    /// locking.
+    ///
+    /// The row is only updated if its version did not change since it was
+    /// read, and the checkpoint never moves backwards, so concurrent writers
+    /// sharing the database cannot clobber each other's progress. On a
+    /// conflict the row is read again, up to `CHECKPOINT_CAS_ATTEMPTS` times.
+    /// A missing row is created, and committing the current checkpoint again
+    /// records that it is still current.
+    pub async fn update_last_update(
+        &self,
+        direction: &str,
+        relay: &str,
+        last: u64,
+    ) -> error::Result<()> {
+        Self::commit_checkpoint(self.conn.as_ref(), direction, relay, last).await
+    }
+
+    async fn commit_checkpoint<C: ConnectionTrait>(
+        conn: &C,
+        direction: &str,
+        relay: &str,
+        last: u64,
+    ) -> error::Result<()> {
+        let last = last as i64;
+        for _ in 0..CHECKPOINT_CAS_ATTEMPTS {
+            let Some(current) = timed(
+                Operation::Db,
+                LastUpdateEntity::find()
+                    .filter(LastUpdateColumn::Direction.eq(direction))
+                    .filter(LastUpdateColumn::Relay.eq(relay))
+                    .one(conn),
+            )
+            .await?
+            else {
+                // A concurrent writer may create the row first, which is read
+                // on the next attempt.
+                let row = LastUpdateActiveModel {
+                    last_update: Set(last),
+                    updated_at: Set(chrono::Utc::now().into()),
+                    relay: Set(Some(relay.to_string())),
+                    direction: Set(Some(direction.to_string())),
+                    ..Default::default()
+                };
+                let inserted = timed(
+                    Operation::Db,
+                    LastUpdateEntity::insert(row)
+                        .on_conflict(
+                            sea_query::OnConflict::columns([
+                                LastUpdateColumn::Direction,
+                                LastUpdateColumn::Relay,
+                            ])
+                            .do_nothing()
+                            .to_owned(),
+                        )
+                        .exec_without_returning(conn),
+                )
+                .await?;
+                if inserted > 0 {
+                    return Ok(());
+                }
+                continue;
+            };
+            if current.last_update > last {
+                tracing::debug!(
+                    "{}: checkpoint {} of {} not committed, already at {}",
+                    direction,
+                    last,
+                    relay,
+                    current.last_update
+                );
+                return Ok(());
+            }
+            if current.last_update == last {
+                // The checkpoint is current, which the lag monitor reads from
+                // `updated_at`.
+                timed(
+                    Operation::Db,
+                    LastUpdateEntity::update_many()
+                        .col_expr(
+                            LastUpdateColumn::UpdatedAt,
+                            Expr::value(chrono::Utc::now().fixed_offset()),
+                        )
+                        .filter(LastUpdateColumn::Id.eq(current.id))
+                        .exec(conn),
+                )
+                .await?;
+                return Ok(());
+            }
+
+            let result = timed(
+                Operation::Db,
+                LastUpdateEntity::update_many()
+                    .col_expr(LastUpdateColumn::LastUpdate, Expr::value(last))
+                    .col_expr(LastUpdateColumn::Version, Expr::value(current.version + 1))
+                    .col_expr(
+                        LastUpdateColumn::UpdatedAt,
+                        Expr::value(chrono::Utc::now().fixed_offset()),
+                    )
+                    .filter(LastUpdateColumn::Id.eq(current.id))
+                    .filter(LastUpdateColumn::Version.eq(current.version))
+                    .exec(conn),
+            )
+            .await?;
+            if result.rows_affected > 0 {
+                return Ok(());
+            }
+            tracing::debug!("checkpoint changed concurrently, reading it again");
+        }
+
+        Err(error::Error::Conflict(format!(
+            "{}: checkpoint {} of {} lost {} races with concurrent writers",
+            direction, last, relay, CHECKPOINT_CAS_ATTEMPTS
+        )))
+    }
+
+    /// Checks that the database answers.
+    pub async fn ping(&self) -> error::Result<()> {
+        Ok(timed(Operation::Db, self.conn.ping()).await?)
+    }
+
+    /// Returns the lowest checkpoint, i.e. the one of the relay and
+    /// direction lagging the most.
+    pub async fn get_checkpoint(&self) -> error::Result<Option<LastUpdateModel>> {
+        Ok(timed(
+            Operation::Db,
+            LastUpdateEntity::find()
+                .order_by_asc(LastUpdateColumn::LastUpdate)
+                .one(self.conn.as_ref()),
+        )
+        .await?)
+    }
+
+    /// Returns the lowest checkpoint of `direction`, i.e. the one of its
+    /// relay lagging the most.
+    pub async fn direction_checkpoint(
+        &self,
+        direction: &str,
+    ) -> error::Result<Option<LastUpdateModel>> {
+        Ok(timed(
+            Operation::Db,
+            LastUpdateEntity::find()
+                .filter(LastUpdateColumn::Direction.eq(direction))
+                .order_by_asc(LastUpdateColumn::LastUpdate)
+                .one(self.conn.as_ref()),
+        )
+        .await?)
+    }
+
+    /// Returns the checkpoints of every direction and relay.
+    pub async fn get_checkpoints(&self) -> error::Result<Vec<LastUpdateModel>> {
+        Ok(timed(
+            Operation::Db,
+            LastUpdateEntity::find()
+                .order_by_asc(LastUpdateColumn::Direction)
+                .order_by_asc(LastUpdateColumn::Id)
+                .all(self.conn.as_ref()),
+        )
+        .await?)
+    }
+
+    /// Returns the number of recorded events per direction and delivery
+    /// status, ordered by direction. Events recorded before directions were
+    /// tracked have no direction.
+    pub async fn count_events(&self) -> error::Result<Vec<(Option<String>, String, u64)>> {
+        let counts: Vec<(Option<String>, String, i64)> = timed(
+            Operation::Db,
+            NostrEventEntity::find()
+                .select_only()
+                .column(NostrEventColumn::Direction)
+                .column(NostrEventColumn::Status)
+                .column_as(NostrEventColumn::Id.count(), "count")
+                .group_by(NostrEventColumn::Direction)
+                .group_by(NostrEventColumn::Status)
+                .order_by_asc(NostrEventColumn::Direction)
+                .order_by_asc(NostrEventColumn::Status)
+                .into_tuple()
+                .all(self.conn.as_ref()),
+        )
+        .await?;
+
+        Ok(counts
+            .into_iter()
+            .map(|(direction, status, count)| (direction, status, count as u64))
+            .collect())
+    }
+
+    /// Tells whether `direction` has to skip the event `id`: it recorded
+    /// the event already, or the direction it loops back with did, see
+    /// `skips`.
+    pub async fn is_event_existed(&self, direction: &str, id: String) -> error::Result<bool> {
+        let loops = LOOPS
+            .iter()
+            .flat_map(|&(a, b)| [(a, b), (b, a)])
+            .filter(|&(a, _)| a == direction)
+            .map(|(_, b)| b);
+        let existing = timed(
+            Operation::Db,
+            NostrEventEntity::find()
+                .filter(NostrEventColumn::EventId.eq(id))
+                .filter(
+                    Condition::any()
+                        .add(NostrEventColumn::Direction.eq(direction))
+                        .add(NostrEventColumn::Direction.is_null())
+                        .add(NostrEventColumn::Direction.is_in(loops)),
+                )
+                .count(self.conn.as_ref()),
+        )
+        .await?;
+
+        Ok(existing > 0)
+    }
+
+    /// Records a new event. Recording an event again keeps the first record,
+    /// so two writers racing on the same event both succeed.
+    pub async fn add_new_event(&self, event: NewEvent) -> error::Result<()> {
+        Self::insert_events(self.conn.as_ref(), vec![event]).await
+    }
+
+    /// Records the new events of a fetch round and commits the checkpoint
+    /// of `relay` for `direction` covering them in one transaction, so a
+    /// crash cannot leave a checkpoint past events that were not recorded,
+    /// nor recorded events the checkpoint does not cover.
+    pub async fn commit_batch(
+        &self,
+        direction: &str,
+        relay: &str,
+        events: Vec<NewEvent>,
+        last: u64,
+    ) -> error::Result<()> {
+        let txn = self.conn.begin().await?;
+        Self::insert_events(&txn, events).await?;
+        Self::commit_checkpoint(&txn, direction, relay, last).await?;
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    async fn insert_events<C: ConnectionTrait>(
+        conn: &C,
+        mut events: Vec<NewEvent>,
+    ) -> error::Result<()> {
+        while !events.is_empty() {
+            let rest = events.split_off(events.len().min(EVENT_INSERT_CHUNK));
+            let models = std::mem::replace(&mut events, rest)
+                .into_iter()
+                .map(|event| NostrEventActiveModel {
+                    event_id: Set(event.event_id),
+                    updated_at: Set(chrono::Utc::now().into()),
+                    correlation_id: Set(Some(event.correlation_id)),
+                    status: Set(DeliveryStatus::Pending.as_str().to_string()),
+                    direction: Set(Some(event.direction)),
+                    payload: Set(Some(event.payload)),
+                    ..Default::default()
+                });
+            timed(
+                Operation::Db,
+                NostrEventEntity::insert_many(models)
+                    .on_conflict(
+                        sea_query::OnConflict::columns([
+                            NostrEventColumn::Direction,
+                            NostrEventColumn::EventId,
+                        ])
+                        .do_nothing()
+                        .to_owned(),
+                    )
+                    .exec_without_returning(conn),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves an event `direction` recorded as pending to `status`.
+    ///
+    /// Only pending events transition, so a late or concurrent writer
+    /// cannot overwrite the final state set by another one.
+    ///
+    /// # Returns
+    ///
+    /// Whether the event was pending and its status changed.
+    pub async fn set_delivery_status(
+        &self,
+        direction: &str,
+        event_id: &str,
+        status: DeliveryStatus,
+    ) -> error::Result<bool> {
+        let result = timed(
+            Operation::Db,
+            NostrEventEntity::update_many()
+                .col_expr(NostrEventColumn::Status, Expr::value(status.as_str()))
+                .col_expr(
+                    NostrEventColumn::UpdatedAt,
+                    Expr::value(chrono::Utc::now().fixed_offset()),
+                )
+                .filter(NostrEventColumn::Direction.eq(direction))
+                .filter(NostrEventColumn::EventId.eq(event_id))
+                .filter(NostrEventColumn::Status.eq(DeliveryStatus::Pending.as_str()))
+                .exec(self.conn.as_ref()),
+        )
+        .await?;
+
+        Ok(result.rows_affected > 0)
+    }
+
+    /// Moves an event back to pending with no attempts in every direction
+    /// that recorded it, so it is delivered again.
+    ///
+    /// # Returns
+    ///
+    /// The directions that recorded the event, none if it was never
+    /// recorded.
+    pub async fn reopen_event(&self, event_id: &str) -> error::Result<Vec<String>> {
+        let directions: Vec<Option<String>> = timed(
+            Operation::Db,
+            NostrEventEntity::find()
+                .select_only()
+                .column(NostrEventColumn::Direction)
+                .filter(NostrEventColumn::EventId.eq(event_id))
+                .into_tuple()
+                .all(self.conn.as_ref()),
+        )
+        .await?;
+        timed(
+            Operation::Db,
+            NostrEventEntity::update_many()
+                .col_expr(
+                    NostrEventColumn::Status,
+                    Expr::value(DeliveryStatus::Pending.as_str()),
+                )
+                .col_expr(NostrEventColumn::Attempts, Expr::value(0))
+                .col_expr(
+                    NostrEventColumn::UpdatedAt,
+                    Expr::value(chrono::Utc::now().fixed_offset()),
+                )
+                .filter(NostrEventColumn::EventId.eq(event_id))
+                .exec(self.conn.as_ref()),
+        )
+        .await?;
+
+        Ok(directions.into_iter().flatten().collect())
+    }
+
+    /// Returns the recorded delivery state of an event, if it was fetched,
+    /// as first recorded by any direction.
+    pub async fn event_state(&self, event_id: &str) -> error::Result<Option<NostrEventModel>> {
+        Ok(timed(
+            Operation::Db,
+            NostrEventEntity::find()
+                .filter(NostrEventColumn::EventId.eq(event_id))
+                .order_by_asc(NostrEventColumn::Id)
+                .one(self.conn.as_ref()),
+        )
+        .await?)
+    }
+
+    /// Returns the delivery state of an event recorded by `direction`.
+    pub async fn direction_event_state(
+        &self,
+        direction: &str,
+        event_id: &str,
+    ) -> error::Result<Option<NostrEventModel>> {
+        Ok(timed(
+            Operation::Db,
+            NostrEventEntity::find()
+                .filter(NostrEventColumn::Direction.eq(direction))
+                .filter(NostrEventColumn::EventId.eq(event_id))
+                .one(self.conn.as_ref()),
+        )
+        .await?)
+    }
+
+    /// Returns up to `limit` recorded events with a row id above `after`,
+    /// in insertion order.
+    pub async fn events_after(
+        &self,
+        after: i32,
+        limit: u64,
+    ) -> error::Result<Vec<NostrEventModel>> {
+        Ok(timed(
+            Operation::Db,
+            NostrEventEntity::find()
+                .filter(NostrEventColumn::Id.gt(after))
+                .order_by_asc(NostrEventColumn::Id)
+                .limit(limit)
+                .all(self.conn.as_ref()),
+        )
+        .await?)
+    }
+
+    /// Returns a page of the recorded events matching `filter`, most recently
+    /// updated first, along with the number of matching events.
+    pub async fn events_page(
+        &self,
+        filter: &HistoryFilter,
+        offset: u64,
+        limit: u64,
+    ) -> error::Result<(Vec<NostrEventModel>, u64)> {
+        let mut query = NostrEventEntity::find();
+        if let Some(event_id) = &filter.event_id {
+            query = query.filter(NostrEventColumn::EventId.eq(event_id.as_str()));
+        }
+        if let Some(direction) = &filter.direction {
+            query = query.filter(NostrEventColumn::Direction.eq(direction.as_str()));
+        }
+        if let Some(status) = &filter.status {
+            query = query.filter(NostrEventColumn::Status.eq(status.as_str()));
+        }
+        if let Some(since) = filter.since {
+            query = query.filter(NostrEventColumn::UpdatedAt.gte(since.fixed_offset()));
+        }
+        if let Some(until) = filter.until {
+            query = query.filter(NostrEventColumn::UpdatedAt.lt(until.fixed_offset()));
+        }
+
+        let total = timed(Operation::Db, query.clone().count(self.conn.as_ref())).await?;
+        let rows = timed(
+            Operation::Db,
+            query
+                .order_by_desc(NostrEventColumn::UpdatedAt)
+                .order_by_desc(NostrEventColumn::Id)
+                .offset(offset)
+                .limit(limit)
+                .all(self.conn.as_ref()),
+        )
+        .await?;
+        Ok((rows, total))
+    }
+
+    /// Returns a page of the dead letters matching `filter`, newest first,
+    /// along with the number of matching dead letters.
+    pub async fn dead_letters_page(
+        &self,
+        filter: &HistoryFilter,
+        offset: u64,
+        limit: u64,
+    ) -> error::Result<(Vec<DeadLetterModel>, u64)> {
+        let mut query = DeadLetterEntity::find();
+        if let Some(event_id) = &filter.event_id {
+            query = query.filter(DeadLetterColumn::EventId.eq(event_id.as_str()));
+        }
+        if let Some(direction) = &filter.direction {
+            query = query.filter(DeadLetterColumn::Direction.eq(direction.as_str()));
+        }
+        if let Some(sink) = &filter.sink {
+            query = query.filter(DeadLetterColumn::Sink.eq(sink.as_str()));
+        }
+        if let Some(since) = filter.since {
+            query = query.filter(DeadLetterColumn::CreatedAt.gte(since.fixed_offset()));
+        }
+        if let Some(until) = filter.until {
+            query = query.filter(DeadLetterColumn::CreatedAt.lt(until.fixed_offset()));
+        }
 
-        Self { conn: Arc::new(db) }
+        let total = timed(Operation::Db, query.clone().count(self.conn.as_ref())).await?;
+        let rows = timed(
+            Operation::Db,
+            query
+                .order_by_desc(DeadLetterColumn::Id)
+                .offset(offset)
+                .limit(limit)
+                .all(self.conn.as_ref()),
+        )
+        .await?;
+        Ok((rows, total))
     }
 
-    pub async fn get_last_update(&self, init: u64) -> error::Result<u64> {
-        match LastUpdateEntity::find().one(self.conn.as_ref()).await? {
-            Some(last) => Ok(last.last_update as u64),
+    /// Returns the events delivered within `[start, end)`, in insertion
+    /// order.
+    pub async fn delivered_between(
+        &self,
+        start: chrono::DateTime<chrono::Utc>,
+        end: chrono::DateTime<chrono::Utc>,
+    ) -> error::Result<Vec<NostrEventModel>> {
+        Ok(timed(
+            Operation::Db,
+            NostrEventEntity::find()
+                .filter(NostrEventColumn::Status.eq(DeliveryStatus::Delivered.as_str()))
+                .filter(NostrEventColumn::UpdatedAt.gte(start.fixed_offset()))
+                .filter(NostrEventColumn::UpdatedAt.lt(end.fixed_offset()))
+                .order_by_asc(NostrEventColumn::Id)
+                .all(self.conn.as_ref()),
+        )
+        .await?)
+    }
+
+    /// Returns the timestamp, in nanoseconds, of the last Waku message
+    /// processed on `content_topic`, if any.
+    pub async fn waku_checkpoint(&self, content_topic: &str) -> error::Result<Option<i64>> {
+        Ok(timed(
+            Operation::Db,
+            WakuCheckpointEntity::find()
+                .filter(WakuCheckpointColumn::ContentTopic.eq(content_topic))
+                .one(self.conn.as_ref()),
+        )
+        .await?
+        .map(|checkpoint| checkpoint.timestamp))
+    }
+
+    /// Returns the Waku checkpoints of every content topic.
+    pub async fn waku_checkpoints(&self) -> error::Result<Vec<WakuCheckpointModel>> {
+        Ok(timed(
+            Operation::Db,
+            WakuCheckpointEntity::find()
+                .order_by_asc(WakuCheckpointColumn::ContentTopic)
+                .all(self.conn.as_ref()),
+        )
+        .await?)
+    }
+
+    /// Records `timestamp` as the last Waku message processed on
+    /// `content_topic`. The checkpoint never moves backwards.
+    pub async fn set_waku_checkpoint(
+        &self,
+        content_topic: &str,
+        timestamp: i64,
+    ) -> error::Result<()> {
+        let current = timed(
+            Operation::Db,
+            WakuCheckpointEntity::find()
+                .filter(WakuCheckpointColumn::ContentTopic.eq(content_topic))
+                .one(self.conn.as_ref()),
+        )
+        .await?;
+        match current {
             None => {
-                let new_last_update = LastUpdateActiveModel {
-                    last_update: Set(init as i64),
+                let checkpoint = WakuCheckpointActiveModel {
+                    content_topic: Set(content_topic.to_string()),
+                    timestamp: Set(timestamp),
                     updated_at: Set(chrono::Utc::now().into()),
                     ..Default::default()
                 };
-                new_last_update.insert(self.conn.as_ref()).await?;
-                Ok(init)
+                timed(Operation::Db, checkpoint.insert(self.conn.as_ref())).await?;
             }
+            Some(current) if current.timestamp < timestamp => {
+                timed(
+                    Operation::Db,
+                    WakuCheckpointEntity::update_many()
+                        .col_expr(WakuCheckpointColumn::Timestamp, Expr::value(timestamp))
+                        .col_expr(
+                            WakuCheckpointColumn::UpdatedAt,
+                            Expr::value(chrono::Utc::now().fixed_offset()),
+                        )
+                        .filter(WakuCheckpointColumn::Id.eq(current.id))
+                        .filter(WakuCheckpointColumn::Timestamp.lt(timestamp))
+                        .exec(self.conn.as_ref()),
+                )
+                .await?;
+            }
+            Some(_) => {}
         }
+
+        Ok(())
     }
 
-    pub async fn update_last_update(&self, last: u64) -> error::Result<()> {
-        if let Some(mut last_update) = LastUpdateEntity::find()
-            .one(self.conn.as_ref())
-            .await?
-            .map(|l| l.into_active_model())
-        {
-            last_update.last_update = Set(last as i64);
-            last_update.updated_at = Set(chrono::Utc::now().into());
+    /// Returns the anchor of the latest window, if any.
+    pub async fn latest_anchor(&self) -> error::Result<Option<AnchorModel>> {
+        Ok(timed(
+            Operation::Db,
+            AnchorEntity::find()
+                .order_by_desc(AnchorColumn::WindowEnd)
+                .one(self.conn.as_ref()),
+        )
+        .await?)
+    }
 
-            last_update.update(self.conn.as_ref()).await?;
+    /// Records an anchor and the proofs of its events in one transaction.
+    pub async fn add_anchor(
+        &self,
+        anchor: AnchorActiveModel,
+        mut proofs: Vec<AnchorProofActiveModel>,
+    ) -> error::Result<()> {
+        let txn = self.conn.begin().await?;
+        let anchor = timed(Operation::Db, anchor.insert(&txn)).await?;
+        for proof in &mut proofs {
+            proof.anchor_id = Set(anchor.id);
+        }
+        while !proofs.is_empty() {
+            let rest = proofs.split_off(proofs.len().min(PROOF_INSERT_CHUNK));
+            timed(
+                Operation::Db,
+                AnchorProofEntity::insert_many(std::mem::replace(&mut proofs, rest)).exec(&txn),
+            )
+            .await?;
         }
+        txn.commit().await?;
 
         Ok(())
     }
 
-    pub async fn is_event_existed(&self, id: String) -> Option<()> {
-        if NostrEventEntity::find()
-            .filter(NostrEventColumn::EventId.eq(id))
-            .one(self.conn.as_ref())
-            .await
-            .is_ok()
-        {
-            Some(())
-        } else {
-            None
+    /// Returns the proof of an anchored event and the anchor it belongs to.
+    pub async fn anchor_proof(
+        &self,
+        event_id: &str,
+    ) -> error::Result<Option<(AnchorProofModel, AnchorModel)>> {
+        let Some(proof) = timed(
+            Operation::Db,
+            AnchorProofEntity::find()
+                .filter(AnchorProofColumn::EventId.eq(event_id))
+                .one(self.conn.as_ref()),
+        )
+        .await?
+        else {
+            return Ok(None);
+        };
+        let anchor = timed(
+            Operation::Db,
+            AnchorEntity::find_by_id(proof.anchor_id).one(self.conn.as_ref()),
+        )
+        .await?;
+
+        Ok(anchor.map(|anchor| (proof, anchor)))
+    }
+
+    /// Counts a delivery attempt by `direction` of an event.
+    ///
+    /// # Returns
+    ///
+    /// The number of attempts made so far, including this one.
+    pub async fn record_attempt(&self, direction: &str, event_id: &str) -> error::Result<u32> {
+        timed(
+            Operation::Db,
+            NostrEventEntity::update_many()
+                .col_expr(
+                    NostrEventColumn::Attempts,
+                    Expr::col(NostrEventColumn::Attempts).add(1),
+                )
+                .filter(NostrEventColumn::Direction.eq(direction))
+                .filter(NostrEventColumn::EventId.eq(event_id))
+                .exec(self.conn.as_ref()),
+        )
+        .await?;
+
+        let event = self.direction_event_state(direction, event_id).await?;
+        // The row may not be written yet while the database was unavailable.
+        Ok(event.map_or(1, |event| event.attempts.max(1) as u32))
+    }
+
+    /// Returns the events of `direction` that were recorded but never
+    /// delivered nor dead-lettered, oldest first.
+    pub async fn pending_events(&self, direction: &str) -> error::Result<Vec<NostrEventModel>> {
+        Ok(timed(
+            Operation::Db,
+            NostrEventEntity::find()
+                .filter(NostrEventColumn::Status.eq(DeliveryStatus::Pending.as_str()))
+                .filter(NostrEventColumn::Direction.eq(direction))
+                .order_by_asc(NostrEventColumn::Id)
+                .all(self.conn.as_ref()),
+        )
+        .await?)
+    }
+
+    /// Returns when the oldest event of `direction` waiting for delivery
+    /// became pending, if any is. A pending event keeps the time it was
+    /// recorded, or reopened, as its update time: attempts do not bump it.
+    pub async fn oldest_pending(
+        &self,
+        direction: &str,
+    ) -> error::Result<Option<chrono::DateTime<chrono::FixedOffset>>> {
+        let oldest: Option<Option<chrono::DateTime<chrono::FixedOffset>>> = timed(
+            Operation::Db,
+            NostrEventEntity::find()
+                .select_only()
+                .column_as(NostrEventColumn::UpdatedAt.min(), "oldest")
+                .filter(NostrEventColumn::Status.eq(DeliveryStatus::Pending.as_str()))
+                .filter(NostrEventColumn::Direction.eq(direction))
+                .into_tuple()
+                .one(self.conn.as_ref()),
+        )
+        .await?;
+
+        Ok(oldest.flatten())
+    }
+
+    pub async fn add_crash_marker(
+        &self,
+        message: String,
+        location: Option<String>,
+        backtrace: String,
+    ) -> error::Result<()> {
+        let marker = CrashMarkerActiveModel {
+            message: Set(message),
+            location: Set(location),
+            backtrace: Set(backtrace),
+            crashed_at: Set(chrono::Utc::now().into()),
+            ..Default::default()
+        };
+        marker.insert(self.conn.as_ref()).await?;
+
+        Ok(())
+    }
+
+    pub async fn add_dead_letter(&self, record: DeadLetterActiveModel) -> error::Result<()> {
+        timed(Operation::Db, record.insert(self.conn.as_ref())).await?;
+
+        Ok(())
+    }
+
+    pub async fn count_dead_letters(&self) -> error::Result<u64> {
+        Ok(timed(
+            Operation::Db,
+            DeadLetterEntity::find().count(self.conn.as_ref()),
+        )
+        .await?)
+    }
+
+    pub async fn count_retries(&self) -> error::Result<u64> {
+        Ok(timed(
+            Operation::Db,
+            RetryQueueEntity::find().count(self.conn.as_ref()),
+        )
+        .await?)
+    }
+
+    /// Schedules another delivery attempt of an event, replacing an earlier
+    /// schedule of the same event in the same direction.
+    pub async fn schedule_retry(&self, record: RetryQueueActiveModel) -> error::Result<()> {
+        timed(
+            Operation::Db,
+            RetryQueueEntity::insert(record)
+                .on_conflict(
+                    sea_query::OnConflict::columns([
+                        RetryQueueColumn::Direction,
+                        RetryQueueColumn::EventId,
+                    ])
+                    .update_columns([
+                        RetryQueueColumn::CorrelationId,
+                        RetryQueueColumn::Sink,
+                        RetryQueueColumn::Payload,
+                        RetryQueueColumn::Attempts,
+                        RetryQueueColumn::LastError,
+                        RetryQueueColumn::NextAttemptAt,
+                    ])
+                    .to_owned(),
+                )
+                .exec(self.conn.as_ref()),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Removes and returns at most `limit` retries of `direction` that are
+    /// due, earliest first.
+    pub async fn take_due_retries(
+        &self,
+        direction: &str,
+        limit: u64,
+    ) -> error::Result<Vec<RetryQueueModel>> {
+        let txn = self.conn.begin().await?;
+        let due = timed(
+            Operation::Db,
+            RetryQueueEntity::find()
+                .filter(RetryQueueColumn::Direction.eq(direction))
+                .filter(RetryQueueColumn::NextAttemptAt.lte(chrono::Utc::now().fixed_offset()))
+                .order_by_asc(RetryQueueColumn::NextAttemptAt)
+                .limit(limit)
+                .all(&txn),
+        )
+        .await?;
+        if !due.is_empty() {
+            timed(
+                Operation::Db,
+                RetryQueueEntity::delete_many()
+                    .filter(RetryQueueColumn::Id.is_in(due.iter().map(|retry| retry.id)))
+                    .exec(&txn),
+            )
+            .await?;
         }
+        txn.commit().await?;
+
+        Ok(due)
     }
 
-    pub async fn add_new_event(&self, id: String) -> error::Result<()> {
-        let new_event_id = NostrEventActiveModel {
-            event_id: Set(id),
-            updated_at: Set(chrono::Utc::now().into()),
+    /// Returns the ids of the events of `direction` waiting in the retry
+    /// queue.
+    pub async fn scheduled_retries(&self, direction: &str) -> error::Result<HashSet<String>> {
+        Ok(timed(
+            Operation::Db,
+            RetryQueueEntity::find()
+                .filter(RetryQueueColumn::Direction.eq(direction))
+                .all(self.conn.as_ref()),
+        )
+        .await?
+        .into_iter()
+        .map(|retry| retry.event_id)
+        .collect())
+    }
+
+    /// Moves the dead letter `id` back into the retry queue, due at once,
+    /// and resets the delivery attempts of its event. Returns the dead
+    /// letter, or `None` if there is none with this id.
+    pub async fn replay_dead_letter(&self, id: i64) -> error::Result<Option<DeadLetterModel>> {
+        let txn = self.conn.begin().await?;
+        let Some(dead_letter) =
+            timed(Operation::Db, DeadLetterEntity::find_by_id(id).one(&txn)).await?
+        else {
+            return Ok(None);
+        };
+
+        let now = chrono::Utc::now().fixed_offset();
+        let retry = RetryQueueActiveModel {
+            event_id: Set(dead_letter.event_id.clone()),
+            correlation_id: Set(dead_letter.correlation_id.clone()),
+            direction: Set(dead_letter.direction.clone()),
+            sink: Set(dead_letter.sink.clone()),
+            payload: Set(dead_letter.payload.clone()),
+            attempts: Set(0),
+            last_error: Set(dead_letter.error.clone()),
+            next_attempt_at: Set(now),
+            created_at: Set(now),
             ..Default::default()
         };
+        timed(
+            Operation::Db,
+            RetryQueueEntity::insert(retry)
+                .on_conflict(
+                    sea_query::OnConflict::columns([
+                        RetryQueueColumn::Direction,
+                        RetryQueueColumn::EventId,
+                    ])
+                    .update_columns([
+                        RetryQueueColumn::Attempts,
+                        RetryQueueColumn::LastError,
+                        RetryQueueColumn::NextAttemptAt,
+                    ])
+                    .to_owned(),
+                )
+                .exec(&txn),
+        )
+        .await?;
+        timed(
+            Operation::Db,
+            NostrEventEntity::update_many()
+                .col_expr(NostrEventColumn::Attempts, Expr::value(0))
+                .col_expr(
+                    NostrEventColumn::Status,
+                    Expr::value(DeliveryStatus::Pending.as_str()),
+                )
+                .col_expr(NostrEventColumn::UpdatedAt, Expr::value(now))
+                .filter(NostrEventColumn::Direction.eq(dead_letter.direction.as_str()))
+                .filter(NostrEventColumn::EventId.eq(dead_letter.event_id.as_str()))
+                .exec(&txn),
+        )
+        .await?;
+        timed(Operation::Db, DeadLetterEntity::delete_by_id(id).exec(&txn)).await?;
+        txn.commit().await?;
+
+        Ok(Some(dead_letter))
+    }
+
+    /// Deletes the dead letters, only those of `direction` if given, and
+    /// returns how many were deleted.
+    pub async fn purge_dead_letters(&self, direction: Option<&str>) -> error::Result<u64> {
+        let mut query = DeadLetterEntity::delete_many();
+        if let Some(direction) = direction {
+            query = query.filter(DeadLetterColumn::Direction.eq(direction));
+        }
+        Ok(timed(Operation::Db, query.exec(self.conn.as_ref()))
+            .await?
+            .rows_affected)
+    }
 
-        new_event_id.insert(self.conn.as_ref()).await?;
+    pub async fn add_audit_record(&self, record: AuditLogActiveModel) -> error::Result<()> {
+        timed(Operation::Db, record.insert(self.conn.as_ref())).await?;
 
         Ok(())
     }
 }
+
+/// The directions bridging the same networks opposite ways, whose events
+/// would loop: each publishes the events the other one receives.
+const LOOPS: [(&str, &str); 3] = [("w2n", "n2w"), ("k2n", "n2k"), ("m2n", "n2m")];
+
+/// Tells whether the events bridged by `a` come back to `b`, or the other
+/// way around, see `LOOPS`.
+pub fn loops_back(a: &str, b: &str) -> bool {
+    LOOPS.iter().any(|&pair| pair == (a, b) || pair == (b, a))
+}
+
+/// Tells whether `direction` has to skip an event recorded by `recorded_by`.
+///
+/// Every direction records the events it bridges and skips those, so
+/// directions sharing the database each bridge every event once. A
+/// direction also skips the events of the direction it loops back with,
+/// e.g. `n2w` the events `w2n` published to the relays and `w2n` the
+/// events `n2w` published to Waku. Events recorded before directions were
+/// tracked are skipped by every direction.
+pub fn skips(direction: &str, recorded_by: Option<&str>) -> bool {
+    match recorded_by {
+        Some(recorded_by) => recorded_by == direction || loops_back(direction, recorded_by),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waku_directions_loop_back() {
+        assert!(skips("n2w", Some("w2n")));
+        assert!(skips("w2n", Some("n2w")));
+    }
+
+    #[test]
+    fn kafka_directions_loop_back() {
+        assert!(skips("n2k", Some("k2n")));
+        assert!(skips("k2n", Some("n2k")));
+    }
+
+    #[test]
+    fn mqtt_directions_loop_back() {
+        assert!(skips("n2m", Some("m2n")));
+        assert!(skips("m2n", Some("n2m")));
+    }
+
+    #[test]
+    fn other_directions_bridge_every_event() {
+        for (direction, recorded_by) in [
+            ("n2i", "w2i"),
+            ("w2i", "n2w"),
+            ("n2i", "w2n"),
+            ("k2n", "n2w"),
+            ("n2k", "m2n"),
+            ("n2w", "n2i"),
+        ] {
+            assert!(
+                !skips(direction, Some(recorded_by)),
+                "{direction} skips {recorded_by}"
+            );
+        }
+    }
+
+    #[test]
+    fn skips_its_own_and_untracked_events() {
+        assert!(skips("n2i", Some("n2i")));
+        assert!(skips("w2i", Some("w2i")));
+        assert!(skips("w2i", None));
+    }
+}
@@ -9,6 +9,9 @@ pub struct Model {
     pub id: i32,
     pub last_update: i64,
     pub updated_at: DateTimeWithTimeZone,
+    pub version: i32,
+    pub relay: Option<String>,
+    pub direction: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
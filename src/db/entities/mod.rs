@@ -2,5 +2,12 @@
 
 pub mod prelude;
 
+pub mod anchor;
+pub mod anchor_proof;
+pub mod audit_log;
+pub mod crash_marker;
+pub mod dead_letter;
 pub mod last_update;
 pub mod nostr_event;
+pub mod retry_queue;
+pub mod waku_checkpoint;
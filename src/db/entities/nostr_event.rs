@@ -9,6 +9,12 @@ pub struct Model {
     pub id: i32,
     pub event_id: String,
     pub updated_at: DateTimeWithTimeZone,
+    pub correlation_id: Option<String>,
+    pub status: String,
+    pub direction: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub payload: Option<String>,
+    pub attempts: i32,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
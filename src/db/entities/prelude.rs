@@ -1,7 +1,33 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.0.1
 
+pub use super::anchor::ActiveModel as AnchorActiveModel;
+pub use super::anchor::Column as AnchorColumn;
+pub use super::anchor::Entity as AnchorEntity;
+pub use super::anchor::Model as AnchorModel;
+pub use super::anchor_proof::ActiveModel as AnchorProofActiveModel;
+pub use super::anchor_proof::Column as AnchorProofColumn;
+pub use super::anchor_proof::Entity as AnchorProofEntity;
+pub use super::anchor_proof::Model as AnchorProofModel;
+pub use super::audit_log::ActiveModel as AuditLogActiveModel;
+pub use super::audit_log::Entity as AuditLogEntity;
+pub use super::crash_marker::ActiveModel as CrashMarkerActiveModel;
+pub use super::dead_letter::ActiveModel as DeadLetterActiveModel;
+pub use super::dead_letter::Column as DeadLetterColumn;
+pub use super::dead_letter::Entity as DeadLetterEntity;
+pub use super::dead_letter::Model as DeadLetterModel;
 pub use super::last_update::ActiveModel as LastUpdateActiveModel;
+pub use super::last_update::Column as LastUpdateColumn;
 pub use super::last_update::Entity as LastUpdateEntity;
+pub use super::last_update::Model as LastUpdateModel;
 pub use super::nostr_event::ActiveModel as NostrEventActiveModel;
 pub use super::nostr_event::Column as NostrEventColumn;
 pub use super::nostr_event::Entity as NostrEventEntity;
+pub use super::nostr_event::Model as NostrEventModel;
+pub use super::retry_queue::ActiveModel as RetryQueueActiveModel;
+pub use super::retry_queue::Column as RetryQueueColumn;
+pub use super::retry_queue::Entity as RetryQueueEntity;
+pub use super::retry_queue::Model as RetryQueueModel;
+pub use super::waku_checkpoint::ActiveModel as WakuCheckpointActiveModel;
+pub use super::waku_checkpoint::Column as WakuCheckpointColumn;
+pub use super::waku_checkpoint::Entity as WakuCheckpointEntity;
+pub use super::waku_checkpoint::Model as WakuCheckpointModel;
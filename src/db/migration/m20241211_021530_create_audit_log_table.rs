@@ -0,0 +1,98 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::DbBackend;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuditLog::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AuditLog::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AuditLog::EventId).string().not_null())
+                    .col(ColumnDef::new(AuditLog::CorrelationId).string().not_null())
+                    .col(ColumnDef::new(AuditLog::Direction).string().not_null())
+                    .col(ColumnDef::new(AuditLog::Source).string().not_null())
+                    .col(ColumnDef::new(AuditLog::Sink).string().not_null())
+                    .col(
+                        ColumnDef::new(AuditLog::EventCreatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(AuditLog::ReceivedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(AuditLog::CompletedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AuditLog::Result).string().not_null())
+                    .col(ColumnDef::new(AuditLog::Error).text().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // The audit trail is append-only: reject updates and deletes.
+        if manager.get_database_backend() == DbBackend::Postgres {
+            let db = manager.get_connection();
+            db.execute_unprepared(
+                r#"CREATE OR REPLACE FUNCTION audit_log_immutable() RETURNS trigger AS $$
+                BEGIN
+                    RAISE EXCEPTION 'audit_log is append-only';
+                END;
+                $$ LANGUAGE plpgsql;"#,
+            )
+            .await?;
+            db.execute_unprepared(
+                r#"CREATE TRIGGER audit_log_immutable
+                BEFORE UPDATE OR DELETE ON audit_log
+                FOR EACH ROW EXECUTE FUNCTION audit_log_immutable();"#,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() == DbBackend::Postgres {
+            manager
+                .get_connection()
+                .execute_unprepared("DROP FUNCTION IF EXISTS audit_log_immutable() CASCADE;")
+                .await?;
+        }
+
+        manager
+            .drop_table(Table::drop().table(AuditLog::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AuditLog {
+    Table,
+    Id,
+    EventId,
+    CorrelationId,
+    Direction,
+    Source,
+    Sink,
+    EventCreatedAt,
+    ReceivedAt,
+    CompletedAt,
+    Result,
+    Error,
+}
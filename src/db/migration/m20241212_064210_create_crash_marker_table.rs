@@ -0,0 +1,49 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CrashMarker::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CrashMarker::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(CrashMarker::Message).text().not_null())
+                    .col(ColumnDef::new(CrashMarker::Location).string().null())
+                    .col(ColumnDef::new(CrashMarker::Backtrace).text().not_null())
+                    .col(
+                        ColumnDef::new(CrashMarker::CrashedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CrashMarker::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CrashMarker {
+    Table,
+    Id,
+    Message,
+    Location,
+    Backtrace,
+    CrashedAt,
+}
@@ -0,0 +1,59 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(DeadLetter::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(DeadLetter::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(DeadLetter::EventId).string().not_null())
+                    .col(
+                        ColumnDef::new(DeadLetter::CorrelationId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(DeadLetter::Direction).string().not_null())
+                    .col(ColumnDef::new(DeadLetter::Sink).string().not_null())
+                    .col(ColumnDef::new(DeadLetter::Payload).text().not_null())
+                    .col(ColumnDef::new(DeadLetter::Error).text().not_null())
+                    .col(
+                        ColumnDef::new(DeadLetter::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(DeadLetter::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum DeadLetter {
+    Table,
+    Id,
+    EventId,
+    CorrelationId,
+    Direction,
+    Sink,
+    Payload,
+    Error,
+    CreatedAt,
+}
@@ -0,0 +1,67 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Rows recorded before delivery tracking are considered delivered.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(NostrEvent::Table)
+                    .add_column(
+                        ColumnDef::new(NostrEvent::Status)
+                            .string()
+                            .not_null()
+                            .default("delivered"),
+                    )
+                    .add_column(ColumnDef::new(NostrEvent::Direction).string().null())
+                    .add_column(ColumnDef::new(NostrEvent::Payload).text().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_nostr_event_status_direction")
+                    .table(NostrEvent::Table)
+                    .col(NostrEvent::Status)
+                    .col(NostrEvent::Direction)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_nostr_event_status_direction")
+                    .table(NostrEvent::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(NostrEvent::Table)
+                    .drop_column(NostrEvent::Status)
+                    .drop_column(NostrEvent::Direction)
+                    .drop_column(NostrEvent::Payload)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum NostrEvent {
+    Table,
+    Status,
+    Direction,
+    Payload,
+}
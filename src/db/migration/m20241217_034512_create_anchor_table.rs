@@ -0,0 +1,61 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Anchor::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Anchor::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Anchor::WindowStart)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Anchor::WindowEnd)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Anchor::Root).string().not_null())
+                    .col(ColumnDef::new(Anchor::EventCount).integer().not_null())
+                    .col(ColumnDef::new(Anchor::TxHash).string())
+                    .col(
+                        ColumnDef::new(Anchor::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Anchor::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Anchor {
+    Table,
+    Id,
+    WindowStart,
+    WindowEnd,
+    Root,
+    EventCount,
+    TxHash,
+    CreatedAt,
+}
@@ -0,0 +1,59 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AnchorProof::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AnchorProof::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(AnchorProof::AnchorId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AnchorProof::EventId).string().not_null())
+                    .col(ColumnDef::new(AnchorProof::LeafIndex).integer().not_null())
+                    .col(ColumnDef::new(AnchorProof::Proof).text().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_anchor_proof_event_id")
+                    .table(AnchorProof::Table)
+                    .col(AnchorProof::EventId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AnchorProof::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AnchorProof {
+    Table,
+    Id,
+    AnchorId,
+    EventId,
+    LeafIndex,
+    Proof,
+}
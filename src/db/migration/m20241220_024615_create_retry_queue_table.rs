@@ -0,0 +1,83 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RetryQueue::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RetryQueue::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(RetryQueue::EventId)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(RetryQueue::CorrelationId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(RetryQueue::Direction).string().not_null())
+                    .col(ColumnDef::new(RetryQueue::Sink).string().not_null())
+                    .col(ColumnDef::new(RetryQueue::Payload).text().not_null())
+                    .col(ColumnDef::new(RetryQueue::Attempts).integer().not_null())
+                    .col(ColumnDef::new(RetryQueue::LastError).text().not_null())
+                    .col(
+                        ColumnDef::new(RetryQueue::NextAttemptAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(RetryQueue::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-retry_queue-direction-next_attempt_at")
+                    .table(RetryQueue::Table)
+                    .col(RetryQueue::Direction)
+                    .col(RetryQueue::NextAttemptAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RetryQueue::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum RetryQueue {
+    Table,
+    Id,
+    EventId,
+    CorrelationId,
+    Direction,
+    Sink,
+    Payload,
+    Attempts,
+    LastError,
+    NextAttemptAt,
+    CreatedAt,
+}
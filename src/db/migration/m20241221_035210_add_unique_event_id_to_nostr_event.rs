@@ -0,0 +1,49 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Earlier runs could record an event twice; keep the first record.
+        // The derived table lets MySQL read the table it deletes from.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"DELETE FROM nostr_event WHERE id NOT IN (
+                    SELECT id FROM (
+                        SELECT MIN(id) AS id FROM nostr_event GROUP BY event_id
+                    ) AS kept
+                );"#,
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-nostr_event-event_id")
+                    .table(NostrEvent::Table)
+                    .col(NostrEvent::EventId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-nostr_event-event_id")
+                    .table(NostrEvent::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum NostrEvent {
+    Table,
+    EventId,
+}
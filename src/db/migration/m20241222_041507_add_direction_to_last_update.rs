@@ -0,0 +1,84 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(LastUpdate::Table)
+                    .add_column(ColumnDef::new(LastUpdate::Direction).string())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-last_update-relay")
+                    .table(LastUpdate::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-last_update-direction-relay")
+                    .table(LastUpdate::Table)
+                    .col(LastUpdate::Direction)
+                    .col(LastUpdate::Relay)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-last_update-direction-relay")
+                    .table(LastUpdate::Table)
+                    .to_owned(),
+            )
+            .await?;
+        // Only one checkpoint per relay fits the old index, keep the first.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"DELETE FROM last_update WHERE id NOT IN (
+                    SELECT id FROM (
+                        SELECT MIN(id) AS id FROM last_update GROUP BY relay
+                    ) AS kept
+                );"#,
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-last_update-relay")
+                    .table(LastUpdate::Table)
+                    .col(LastUpdate::Relay)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(LastUpdate::Table)
+                    .drop_column(LastUpdate::Direction)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum LastUpdate {
+    Table,
+    Direction,
+    Relay,
+}
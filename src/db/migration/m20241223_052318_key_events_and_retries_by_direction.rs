@@ -0,0 +1,210 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::DbBackend;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-nostr_event-event_id")
+                    .table(NostrEvent::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-nostr_event-direction-event_id")
+                    .table(NostrEvent::Table)
+                    .col(NostrEvent::Direction)
+                    .col(NostrEvent::EventId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        // The unique key of `retry_queue.event_id` was declared with the
+        // column, so how to drop it depends on the backend.
+        let conn = manager.get_connection();
+        match manager.get_database_backend() {
+            DbBackend::Postgres => {
+                conn.execute_unprepared(
+                    "ALTER TABLE retry_queue DROP CONSTRAINT IF EXISTS retry_queue_event_id_key;",
+                )
+                .await?;
+            }
+            DbBackend::MySql => {
+                conn.execute_unprepared("ALTER TABLE retry_queue DROP INDEX event_id;")
+                    .await?;
+            }
+            DbBackend::Sqlite => {
+                // SQLite cannot drop a constraint, the table is rebuilt.
+                manager
+                    .create_table(retry_queue_table(RetryQueue::New))
+                    .await?;
+                conn.execute_unprepared(
+                    r#"INSERT INTO retry_queue_new (
+                        id, event_id, correlation_id, direction, sink, payload,
+                        attempts, last_error, next_attempt_at, created_at
+                    ) SELECT
+                        id, event_id, correlation_id, direction, sink, payload,
+                        attempts, last_error, next_attempt_at, created_at
+                    FROM retry_queue;"#,
+                )
+                .await?;
+                manager
+                    .drop_table(Table::drop().table(RetryQueue::Table).to_owned())
+                    .await?;
+                manager
+                    .rename_table(
+                        Table::rename()
+                            .table(RetryQueue::New, RetryQueue::Table)
+                            .to_owned(),
+                    )
+                    .await?;
+                manager
+                    .create_index(
+                        Index::create()
+                            .name("idx-retry_queue-direction-next_attempt_at")
+                            .table(RetryQueue::Table)
+                            .col(RetryQueue::Direction)
+                            .col(RetryQueue::NextAttemptAt)
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+        }
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-retry_queue-direction-event_id")
+                    .table(RetryQueue::Table)
+                    .col(RetryQueue::Direction)
+                    .col(RetryQueue::EventId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-retry_queue-direction-event_id")
+                    .table(RetryQueue::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-nostr_event-direction-event_id")
+                    .table(NostrEvent::Table)
+                    .to_owned(),
+            )
+            .await?;
+        // Only one record per event fits the old indexes, keep the first.
+        let conn = manager.get_connection();
+        conn.execute_unprepared(
+            r#"DELETE FROM nostr_event WHERE id NOT IN (
+                SELECT id FROM (
+                    SELECT MIN(id) AS id FROM nostr_event GROUP BY event_id
+                ) AS kept
+            );"#,
+        )
+        .await?;
+        conn.execute_unprepared(
+            r#"DELETE FROM retry_queue WHERE id NOT IN (
+                SELECT id FROM (
+                    SELECT MIN(id) AS id FROM retry_queue GROUP BY event_id
+                ) AS kept
+            );"#,
+        )
+        .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-nostr_event-event_id")
+                    .table(NostrEvent::Table)
+                    .col(NostrEvent::EventId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-retry_queue-event_id")
+                    .table(RetryQueue::Table)
+                    .col(RetryQueue::EventId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// The `retry_queue` table as created by `m20241220_024615`, without the
+/// unique key of `event_id`, named `table`.
+fn retry_queue_table(table: RetryQueue) -> TableCreateStatement {
+    Table::create()
+        .table(table)
+        .col(
+            ColumnDef::new(RetryQueue::Id)
+                .big_integer()
+                .not_null()
+                .auto_increment()
+                .primary_key(),
+        )
+        .col(ColumnDef::new(RetryQueue::EventId).string().not_null())
+        .col(
+            ColumnDef::new(RetryQueue::CorrelationId)
+                .string()
+                .not_null(),
+        )
+        .col(ColumnDef::new(RetryQueue::Direction).string().not_null())
+        .col(ColumnDef::new(RetryQueue::Sink).string().not_null())
+        .col(ColumnDef::new(RetryQueue::Payload).text().not_null())
+        .col(ColumnDef::new(RetryQueue::Attempts).integer().not_null())
+        .col(ColumnDef::new(RetryQueue::LastError).text().not_null())
+        .col(
+            ColumnDef::new(RetryQueue::NextAttemptAt)
+                .timestamp_with_time_zone()
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(RetryQueue::CreatedAt)
+                .timestamp_with_time_zone()
+                .not_null(),
+        )
+        .to_owned()
+}
+
+#[derive(DeriveIden)]
+enum NostrEvent {
+    Table,
+    EventId,
+    Direction,
+}
+
+#[derive(DeriveIden)]
+enum RetryQueue {
+    Table,
+    #[sea_orm(iden = "retry_queue_new")]
+    New,
+    Id,
+    EventId,
+    CorrelationId,
+    Direction,
+    Sink,
+    Payload,
+    Attempts,
+    LastError,
+    NextAttemptAt,
+    CreatedAt,
+}
@@ -2,6 +2,21 @@ pub use sea_orm_migration::prelude::*;
 
 mod m20241204_062314_create_last_update_table;
 mod m20241204_062406_create_nostr_event_table;
+mod m20241210_083015_add_correlation_id_to_nostr_event;
+mod m20241211_021530_create_audit_log_table;
+mod m20241212_064210_create_crash_marker_table;
+mod m20241213_031845_create_dead_letter_table;
+mod m20241214_052730_add_delivery_state_to_nostr_event;
+mod m20241215_081204_add_attempts_to_nostr_event;
+mod m20241216_043317_add_version_to_last_update;
+mod m20241217_034512_create_anchor_table;
+mod m20241217_034608_create_anchor_proof_table;
+mod m20241218_025731_create_waku_checkpoint_table;
+mod m20241219_031422_add_relay_to_last_update;
+mod m20241220_024615_create_retry_queue_table;
+mod m20241221_035210_add_unique_event_id_to_nostr_event;
+mod m20241222_041507_add_direction_to_last_update;
+mod m20241223_052318_key_events_and_retries_by_direction;
 
 pub struct Migrator;
 
@@ -11,6 +26,21 @@ impl MigratorTrait for Migrator {
         vec![
             Box::new(m20241204_062314_create_last_update_table::Migration),
             Box::new(m20241204_062406_create_nostr_event_table::Migration),
+            Box::new(m20241210_083015_add_correlation_id_to_nostr_event::Migration),
+            Box::new(m20241211_021530_create_audit_log_table::Migration),
+            Box::new(m20241212_064210_create_crash_marker_table::Migration),
+            Box::new(m20241213_031845_create_dead_letter_table::Migration),
+            Box::new(m20241214_052730_add_delivery_state_to_nostr_event::Migration),
+            Box::new(m20241215_081204_add_attempts_to_nostr_event::Migration),
+            Box::new(m20241216_043317_add_version_to_last_update::Migration),
+            Box::new(m20241217_034512_create_anchor_table::Migration),
+            Box::new(m20241217_034608_create_anchor_proof_table::Migration),
+            Box::new(m20241218_025731_create_waku_checkpoint_table::Migration),
+            Box::new(m20241219_031422_add_relay_to_last_update::Migration),
+            Box::new(m20241220_024615_create_retry_queue_table::Migration),
+            Box::new(m20241221_035210_add_unique_event_id_to_nostr_event::Migration),
+            Box::new(m20241222_041507_add_direction_to_last_update::Migration),
+            Box::new(m20241223_052318_key_events_and_retries_by_direction::Migration),
         ]
     }
 }
@@ -1,6 +1,8 @@
+pub mod buffered;
 pub mod database;
 pub mod entities;
 pub mod migration;
 
+pub use buffered::BufferedStorage;
 pub use database::setup_db;
-pub use database::Storage;
+pub use database::{DeliveryStatus, HistoryFilter, NewEvent, Storage};
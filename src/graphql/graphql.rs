@@ -0,0 +1,247 @@
+//!This module provides the read-only GraphQL API over the bridge history.
+//!Auditors and dashboards can query the recorded events with their delivery
+//!state, the dead letters and the checkpoint at `/graphql`, with filtering and
+//!offset pagination, without credentials for the database itself.
+
+use crate::common::error;
+use crate::db::entities::prelude::{DeadLetterModel, LastUpdateModel, NostrEventModel};
+use crate::db::{self, HistoryFilter};
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, InputObject, Object, Schema, SimpleObject,
+};
+use async_graphql_axum::GraphQL;
+use axum::Router;
+use chrono::{DateTime, Utc};
+use tokio::net::TcpListener;
+
+/// Rows returned by a page unless the query asks for fewer.
+const DEFAULT_PAGE_SIZE: u64 = 50;
+/// Upper bound of the rows of a page.
+const MAX_PAGE_SIZE: u64 = 500;
+/// Nesting depth a query may not exceed.
+const MAX_QUERY_DEPTH: usize = 8;
+
+pub type HistorySchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Serves the GraphQL API until the task is dropped.
+pub struct GraphqlServer {
+    addr: String,
+    schema: HistorySchema,
+}
+
+impl GraphqlServer {
+    pub fn new(host: &str, port: &str, store: db::Storage) -> Self {
+        Self {
+            addr: format!("{}:{}", host, port),
+            schema: schema(store),
+        }
+    }
+
+    /// Binds the listen address and serves requests.
+    pub async fn run(self) -> error::Result<()> {
+        let listener = TcpListener::bind(&self.addr).await?;
+        tracing::info!("graphql api listening on {}", self.addr);
+        let router = Router::new().route_service("/graphql", GraphQL::new(self.schema));
+        axum::serve(listener, router).await?;
+        Ok(())
+    }
+}
+
+/// Builds the schema of the API, reading from `store`.
+pub fn schema(store: db::Storage) -> HistorySchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(store)
+        .limit_depth(MAX_QUERY_DEPTH)
+        .finish()
+}
+
+/// Selects the rows of a query. Unset fields match everything.
+#[derive(Debug, Default, InputObject)]
+pub struct Filter {
+    pub event_id: Option<String>,
+    pub direction: Option<String>,
+    /// Delivery status of events: `pending`, `delivered` or `dead_lettered`.
+    pub status: Option<String>,
+    /// Sink of dead letters.
+    pub sink: Option<String>,
+    /// Inclusive lower bound of the update time of events, or the creation
+    /// time of dead letters.
+    pub since: Option<DateTime<Utc>>,
+    /// Exclusive upper bound of the same time.
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl From<Filter> for HistoryFilter {
+    fn from(filter: Filter) -> Self {
+        Self {
+            event_id: filter.event_id,
+            direction: filter.direction,
+            status: filter.status,
+            sink: filter.sink,
+            since: filter.since,
+            until: filter.until,
+        }
+    }
+}
+
+/// A recorded event and its delivery state.
+#[derive(SimpleObject)]
+pub struct BridgedEventRecord {
+    pub event_id: String,
+    pub direction: Option<String>,
+    pub status: String,
+    pub correlation_id: Option<String>,
+    pub attempts: i32,
+    pub updated_at: DateTime<Utc>,
+    /// The event as JSON.
+    pub payload: Option<String>,
+}
+
+impl From<NostrEventModel> for BridgedEventRecord {
+    fn from(row: NostrEventModel) -> Self {
+        Self {
+            event_id: row.event_id,
+            direction: row.direction,
+            status: row.status,
+            correlation_id: row.correlation_id,
+            attempts: row.attempts,
+            updated_at: row.updated_at.with_timezone(&Utc),
+            payload: row.payload,
+        }
+    }
+}
+
+/// An event whose delivery failed permanently or ran out of retries.
+#[derive(SimpleObject)]
+pub struct DeadLetterRecord {
+    pub event_id: String,
+    pub correlation_id: String,
+    pub direction: String,
+    pub sink: String,
+    pub error: String,
+    pub created_at: DateTime<Utc>,
+    /// The event as JSON.
+    pub payload: String,
+}
+
+impl From<DeadLetterModel> for DeadLetterRecord {
+    fn from(row: DeadLetterModel) -> Self {
+        Self {
+            event_id: row.event_id,
+            correlation_id: row.correlation_id,
+            direction: row.direction,
+            sink: row.sink,
+            error: row.error,
+            created_at: row.created_at.with_timezone(&Utc),
+            payload: row.payload,
+        }
+    }
+}
+
+/// The fetch checkpoint of the bridge.
+#[derive(SimpleObject)]
+pub struct Checkpoint {
+    /// Direction the checkpoint belongs to, unset for a legacy checkpoint.
+    pub direction: Option<String>,
+    /// Relay the checkpoint belongs to, unset for a legacy checkpoint.
+    pub relay: Option<String>,
+    /// Unix time up to which events were fetched.
+    pub last_update: i64,
+    pub updated_at: DateTime<Utc>,
+    pub version: i32,
+}
+
+impl From<LastUpdateModel> for Checkpoint {
+    fn from(row: LastUpdateModel) -> Self {
+        Self {
+            direction: row.direction,
+            relay: row.relay,
+            last_update: row.last_update,
+            updated_at: row.updated_at.with_timezone(&Utc),
+            version: row.version,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct EventPage {
+    pub items: Vec<BridgedEventRecord>,
+    /// Number of matching events over all pages.
+    pub total: u64,
+}
+
+#[derive(SimpleObject)]
+pub struct DeadLetterPage {
+    pub items: Vec<DeadLetterRecord>,
+    /// Number of matching dead letters over all pages.
+    pub total: u64,
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Recorded events, most recently updated first.
+    async fn events(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: Filter,
+        #[graphql(default)] offset: u64,
+        #[graphql(default = DEFAULT_PAGE_SIZE)] limit: u64,
+    ) -> async_graphql::Result<EventPage> {
+        let store = ctx.data::<db::Storage>()?;
+        let (rows, total) = store
+            .events_page(&filter.into(), offset, limit.min(MAX_PAGE_SIZE))
+            .await?;
+        Ok(EventPage {
+            items: rows.into_iter().map(Into::into).collect(),
+            total,
+        })
+    }
+
+    /// The recorded event with id `event_id`.
+    async fn event(
+        &self,
+        ctx: &Context<'_>,
+        event_id: String,
+    ) -> async_graphql::Result<Option<BridgedEventRecord>> {
+        let store = ctx.data::<db::Storage>()?;
+        Ok(store.event_state(&event_id).await?.map(Into::into))
+    }
+
+    /// Dead letters, newest first.
+    async fn dead_letters(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: Filter,
+        #[graphql(default)] offset: u64,
+        #[graphql(default = DEFAULT_PAGE_SIZE)] limit: u64,
+    ) -> async_graphql::Result<DeadLetterPage> {
+        let store = ctx.data::<db::Storage>()?;
+        let (rows, total) = store
+            .dead_letters_page(&filter.into(), offset, limit.min(MAX_PAGE_SIZE))
+            .await?;
+        Ok(DeadLetterPage {
+            items: rows.into_iter().map(Into::into).collect(),
+            total,
+        })
+    }
+
+    /// The lowest fetch checkpoint over all relays, unset before the first
+    /// round.
+    async fn checkpoint(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Checkpoint>> {
+        let store = ctx.data::<db::Storage>()?;
+        Ok(store.get_checkpoint().await?.map(Into::into))
+    }
+
+    /// The fetch checkpoint of each direction and relay.
+    async fn checkpoints(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Checkpoint>> {
+        let store = ctx.data::<db::Storage>()?;
+        Ok(store
+            .get_checkpoints()
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+}
@@ -0,0 +1,3 @@
+mod graphql;
+
+pub use self::graphql::*;
@@ -0,0 +1,151 @@
+//!This module provides the gRPC API of the bridge. Internal services can
+//!stream bridged events as they are delivered, submit events for bridging
+//!and query the delivery state of an event, instead of reading the database.
+
+use crate::common::error;
+use crate::db;
+use crate::nostr::NostrClient;
+use crate::services::{feed, BridgedEvent};
+use nostr_sdk::{Event, JsonUtil};
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::{BroadcastStream, TcpListenerStream};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("acl_relay.v1");
+}
+
+use proto::bridge_server::{Bridge, BridgeServer};
+
+/// Serves the gRPC API until the task is dropped.
+pub struct GrpcServer {
+    addr: String,
+    service: BridgeService,
+}
+
+impl GrpcServer {
+    pub fn new(host: &str, port: &str, store: db::Storage, nostr_client: Arc<NostrClient>) -> Self {
+        Self {
+            addr: format!("{}:{}", host, port),
+            service: BridgeService {
+                store,
+                nostr_client,
+            },
+        }
+    }
+
+    /// Binds the listen address and serves requests.
+    pub async fn run(self) -> error::Result<()> {
+        let listener = TcpListener::bind(&self.addr).await?;
+        tracing::info!("grpc api listening on {}", self.addr);
+        tonic::transport::Server::builder()
+            .add_service(BridgeServer::new(self.service))
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .map_err(|e| error::Error::CustomError(format!("grpc server: {}", e)))
+    }
+}
+
+struct BridgeService {
+    store: db::Storage,
+    nostr_client: Arc<NostrClient>,
+}
+
+impl proto::StreamEventsRequest {
+    fn matches(&self, event: &BridgedEvent) -> bool {
+        (self.directions.is_empty() || self.directions.iter().any(|d| d == event.direction))
+            && (self.kinds.is_empty() || self.kinds.contains(&u32::from(event.event.kind.as_u16())))
+    }
+}
+
+impl From<BridgedEvent> for proto::BridgedEvent {
+    fn from(bridged: BridgedEvent) -> Self {
+        Self {
+            event_id: bridged.event.id.to_hex(),
+            direction: bridged.direction.to_string(),
+            sink: bridged.sink,
+            status: bridged.status.as_str().to_string(),
+            correlation_id: bridged.correlation_id,
+            kind: u32::from(bridged.event.kind.as_u16()),
+            event_json: bridged.event.as_json(),
+            bridged_at: bridged.bridged_at.timestamp(),
+        }
+    }
+}
+
+/// Maps an error to the status returned to the client. Transient errors are
+/// reported as `UNAVAILABLE`, so the client knows to retry.
+fn status(e: error::Error) -> Status {
+    if e.is_transient() {
+        Status::unavailable(e.to_string())
+    } else {
+        Status::internal(e.to_string())
+    }
+}
+
+#[tonic::async_trait]
+impl Bridge for BridgeService {
+    type StreamEventsStream =
+        Pin<Box<dyn Stream<Item = Result<proto::BridgedEvent, Status>> + Send + 'static>>;
+
+    async fn stream_events(
+        &self,
+        request: Request<proto::StreamEventsRequest>,
+    ) -> Result<Response<Self::StreamEventsStream>, Status> {
+        let filter = request.into_inner();
+        let stream = BroadcastStream::new(feed::subscribe()).filter_map(move |item| match item {
+            Ok(event) if filter.matches(&event) => Some(Ok(event.into())),
+            Ok(_) => None,
+            Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                tracing::warn!("grpc subscriber fell behind, skipped {} events", missed);
+                None
+            }
+        });
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn submit_event(
+        &self,
+        request: Request<proto::SubmitEventRequest>,
+    ) -> Result<Response<proto::SubmitEventResponse>, Status> {
+        let event = Event::from_json(&request.get_ref().event_json)
+            .map_err(|e| Status::invalid_argument(format!("malformed event: {}", e)))?;
+        event
+            .verify()
+            .map_err(|e| Status::invalid_argument(format!("invalid event: {}", e)))?;
+
+        let event_id = self.nostr_client.send_event(event).await.map_err(status)?;
+        tracing::info!("submitted event {} over grpc", event_id);
+
+        Ok(Response::new(proto::SubmitEventResponse {
+            event_id: event_id.to_hex(),
+        }))
+    }
+
+    async fn get_delivery_status(
+        &self,
+        request: Request<proto::GetDeliveryStatusRequest>,
+    ) -> Result<Response<proto::DeliveryStatus>, Status> {
+        let event_id = &request.get_ref().event_id;
+        let state = self
+            .store
+            .event_state(event_id)
+            .await
+            .map_err(status)?
+            .ok_or_else(|| Status::not_found(format!("event {} was not fetched", event_id)))?;
+
+        Ok(Response::new(proto::DeliveryStatus {
+            event_id: state.event_id,
+            status: state.status,
+            direction: state.direction.unwrap_or_default(),
+            attempts: state.attempts.max(0) as u32,
+            correlation_id: state.correlation_id.unwrap_or_default(),
+            updated_at: state.updated_at.timestamp(),
+        }))
+    }
+}
@@ -0,0 +1,127 @@
+//!This module provides the typed errors answered by the IndexDB server. The
+//!server reports failures as JSON bodies such as
+//!`{"code": "invalid_project", "message": "unknown project"}`; they are
+//!classified as retryable, e.g. while the server is overloaded, or permanent,
+//!e.g. for a rejected event, which the pipeline moves to the dead-letter
+//!queue instead of retrying.
+
+use crate::common::error;
+use serde::Deserialize;
+use std::fmt;
+
+/// JSON error body of the IndexDB server. Servers differ in the field names
+/// they use, so the usual ones are all accepted.
+#[derive(Debug, Default, Deserialize)]
+struct ErrorBody {
+    code: Option<serde_json::Value>,
+    message: Option<String>,
+    error: Option<String>,
+    #[serde(alias = "msg")]
+    detail: Option<String>,
+}
+
+/// A non 2xx response of the IndexDB server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexdbError {
+    /// HTTP status of the response.
+    pub status: u16,
+    /// Error code of the body, if any, e.g. `invalid_project`.
+    pub code: Option<String>,
+    /// Error message of the body, or the raw body if it is not JSON.
+    pub message: String,
+}
+
+impl IndexdbError {
+    /// Parses the response of the server answering `status` with `body`.
+    pub fn from_response(status: u16, body: &str) -> Self {
+        let parsed = serde_json::from_str::<ErrorBody>(body).unwrap_or_default();
+        let code = parsed.code.map(|code| match code {
+            serde_json::Value::String(code) => code,
+            code => code.to_string(),
+        });
+        let message = parsed
+            .message
+            .or(parsed.error)
+            .or(parsed.detail)
+            .unwrap_or_else(|| body.trim().to_string());
+        Self {
+            status,
+            code,
+            message,
+        }
+    }
+
+    /// Rejection handler of the `HttpClient` posting to IndexDB, see
+    /// `HttpClient::with_rejection`.
+    pub fn rejection(status: u16, body: &str) -> error::Error {
+        error::Error::IndexdbError(Self::from_response(status, body))
+    }
+
+    /// Tells whether the request may succeed when sent again: on a 5xx,
+    /// `408 Request Timeout` or `429 Too Many Requests` response. The other
+    /// responses reject the event itself.
+    pub fn is_retryable(&self) -> bool {
+        self.status >= 500 || self.status == 408 || self.status == 429
+    }
+}
+
+impl fmt::Display for IndexdbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "status {}", self.status)?;
+        if let Some(code) = &self.code {
+            write!(f, " ({})", code)?;
+        }
+        if !self.message.is_empty() {
+            write!(f, ": {}", self.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for IndexdbError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_json_bodies() {
+        let e = IndexdbError::from_response(
+            400,
+            r#"{"code": "invalid_project", "message": "unknown project"}"#,
+        );
+        assert_eq!(e.code.as_deref(), Some("invalid_project"));
+        assert_eq!(e.message, "unknown project");
+        assert_eq!(
+            e.to_string(),
+            "status 400 (invalid_project): unknown project"
+        );
+
+        let e = IndexdbError::from_response(422, r#"{"code": 17, "msg": "bad invitee"}"#);
+        assert_eq!(e.code.as_deref(), Some("17"));
+        assert_eq!(e.message, "bad invitee");
+    }
+
+    #[test]
+    fn keeps_other_bodies_as_the_message() {
+        let e = IndexdbError::from_response(502, " <html>Bad Gateway</html>\n");
+        assert_eq!(e.code, None);
+        assert_eq!(e.message, "<html>Bad Gateway</html>");
+        assert_eq!(
+            IndexdbError::from_response(500, "").to_string(),
+            "status 500"
+        );
+    }
+
+    #[test]
+    fn classifies_retryable_responses() {
+        for status in [408, 429, 500, 503] {
+            let e = IndexdbError::rejection(status, "");
+            assert!(e.is_transient(), "{status}");
+        }
+        for status in [400, 401, 404, 409, 422] {
+            let e = IndexdbError::rejection(status, "");
+            assert!(!e.is_transient(), "{status}");
+        }
+    }
+}
@@ -1,12 +1,11 @@
 //!This module provides functionality for handling and processing Nostr events,
-//!converting them into structured data, and sending them to an external
-//!IndexDB server for storage or further processing.
+//!converting them into the structured ACL messages consumed by IndexDB and
+//!the other sinks: invites, authorizations, grants, revokes and membership
+//!changes, as told by the `type` field of the event content.
 
 use crate::common::error;
-use crate::nostr;
-use reqwest::Client;
+use crate::ipfs::IpfsRef;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 
 /// Metadata associated with a Nostr event.
 #[derive(Serialize, Deserialize, Debug)]
@@ -40,6 +39,67 @@ struct NostrInviteEventContent {
     event_type: String,
 }
 
+/// Defines the content structure for an authorization, grant or revoke event.
+#[derive(Debug, Deserialize)]
+struct NostrPermissionEventContent {
+    user: String,
+    #[serde(default)]
+    scope: Vec<String>,
+    #[serde(rename = "projectId", alias = "project_id")]
+    project_id: String,
+    #[serde(rename = "type")]
+    event_type: String,
+}
+
+/// Defines the content structure for a membership event.
+#[derive(Debug, Deserialize)]
+struct NostrMembershipEventContent {
+    member: String,
+    role: Option<String>,
+    /// What happened to the membership, e.g. `join` or `leave`.
+    action: Option<String>,
+    #[serde(rename = "projectId", alias = "project_id")]
+    project_id: String,
+    #[serde(rename = "type")]
+    event_type: String,
+}
+
+/// Type of an ACL event, as given by the `type` field of its content.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AclEventType {
+    Invite,
+    Auth,
+    Grant,
+    Revoke,
+    Membership,
+}
+
+impl AclEventType {
+    /// Reads the type of the ACL event in the content of `event`, or `None`
+    /// if it is not an ACL event of a known type.
+    pub fn of(event: &nostr_sdk::Event) -> Option<Self> {
+        #[derive(Deserialize)]
+        struct Typed {
+            #[serde(rename = "type")]
+            event_type: AclEventType,
+        }
+        serde_json::from_str::<Typed>(&event.content)
+            .ok()
+            .map(|typed| typed.event_type)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AclEventType::Invite => "invite",
+            AclEventType::Auth => "auth",
+            AclEventType::Grant => "grant",
+            AclEventType::Revoke => "revoke",
+            AclEventType::Membership => "membership",
+        }
+    }
+}
+
 /// A simplified representation of an invite event.
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct InviteMsgEvent {
@@ -55,14 +115,45 @@ pub struct InviteMsg {
     account: String,
     event_type: String,
     event: InviteMsgEvent,
+    /// Reference to the full event when it was pinned to IPFS.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ipfs_ref: Option<IpfsRef>,
+}
+
+impl InviteMsg {
+    /// Attaches the IPFS reference of the full event.
+    pub fn with_ipfs_ref(mut self, ipfs_ref: Option<IpfsRef>) -> Self {
+        self.ipfs_ref = ipfs_ref;
+        self
+    }
+
+    /// Id of the project the invite applies to.
+    pub fn project(&self) -> &str {
+        &self.project
+    }
+
+    /// Type of the ACL event, e.g. `invite`.
+    pub fn event_type(&self) -> &str {
+        &self.event_type
+    }
+
+    /// Account that issued the invite.
+    pub fn inviter(&self) -> &str {
+        &self.event.from
+    }
+
+    /// Account the invite is for.
+    pub fn invitee(&self) -> &str {
+        &self.event.to
+    }
 }
 
 impl TryFrom<nostr_sdk::Event> for InviteMsg {
-    type Error = ();
+    type Error = error::Error;
 
     /// Attempts to convert a raw `nostr_sdk::Event` into an `InviteMsg`.
     fn try_from(event: nostr_sdk::Event) -> Result<Self, Self::Error> {
-        let invite: NostrInviteEventContent = serde_json::from_str(event.content.as_str()).unwrap();
+        let invite: NostrInviteEventContent = serde_json::from_str(event.content.as_str())?;
 
         Ok(Self {
             project: invite.project_id,
@@ -73,39 +164,121 @@ impl TryFrom<nostr_sdk::Event> for InviteMsg {
                 from: invite.inviter,
                 to: invite.invitee,
             },
+            ipfs_ref: None,
         })
     }
 }
 
-/// A client wrapper for sending events to an IndexDB server.
-pub struct IndexdbServer(reqwest::Client);
+/// Represents an authorization, grant or revoke of scopes to a user,
+/// converted from raw events.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct PermissionMsg {
+    project: String,
+    id: String,
+    account: String,
+    event_type: String,
+    user: String,
+    scope: Vec<String>,
+    /// Reference to the full event when it was pinned to IPFS.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ipfs_ref: Option<IpfsRef>,
+}
 
-impl IndexdbServer {
-    /// Creates a new IndexdbServer instance with the specified base URL.
-    pub fn new() -> Self {
-        IndexdbServer(reqwest::Client::new())
+impl TryFrom<nostr_sdk::Event> for PermissionMsg {
+    type Error = error::Error;
+
+    /// Attempts to convert a raw `nostr_sdk::Event` into a `PermissionMsg`.
+    fn try_from(event: nostr_sdk::Event) -> Result<Self, Self::Error> {
+        let permission: NostrPermissionEventContent = serde_json::from_str(event.content.as_str())?;
+
+        Ok(Self {
+            project: permission.project_id,
+            id: event.id.into(),
+            account: event.pubkey.to_string(),
+            event_type: permission.event_type,
+            user: permission.user,
+            scope: permission.scope,
+            ipfs_ref: None,
+        })
     }
+}
+
+/// Represents a change of the members of a project, converted from raw
+/// events.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct MembershipMsg {
+    project: String,
+    id: String,
+    account: String,
+    event_type: String,
+    member: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    action: Option<String>,
+    /// Reference to the full event when it was pinned to IPFS.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ipfs_ref: Option<IpfsRef>,
+}
 
-    /// Sends an invitation event to the IndexDB server.
-    /// Logs the status of the HTTP response.
-    pub async fn send_invite_event_to_indexdb(
-        &self,
-        url: &str,
-        event: nostr_sdk::Event,
-    ) -> error::Result<()> {
-        tracing::info!("got nostr event: {:?}", event);
+impl TryFrom<nostr_sdk::Event> for MembershipMsg {
+    type Error = error::Error;
 
-        let req: InviteMsg = event.try_into().unwrap();
-        let response = self.0.post(url).json(&req).send().await.unwrap();
+    /// Attempts to convert a raw `nostr_sdk::Event` into a `MembershipMsg`.
+    fn try_from(event: nostr_sdk::Event) -> Result<Self, Self::Error> {
+        let membership: NostrMembershipEventContent = serde_json::from_str(event.content.as_str())?;
 
-        tracing::info!("{:?}", response);
+        Ok(Self {
+            project: membership.project_id,
+            id: event.id.into(),
+            account: event.pubkey.to_string(),
+            event_type: membership.event_type,
+            member: membership.member,
+            role: membership.role,
+            action: membership.action,
+            ipfs_ref: None,
+        })
+    }
+}
+
+/// An ACL message of any supported type, as posted to IndexDB.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum AclMsg {
+    Invite(InviteMsg),
+    Permission(PermissionMsg),
+    Membership(MembershipMsg),
+}
 
-        if response.status().is_success() {
-            tracing::info!("success 200");
-        } else {
-            tracing::info!("responded with status: {}", response.status());
+impl AclMsg {
+    /// Attaches the IPFS reference of the full event.
+    pub fn with_ipfs_ref(self, ipfs_ref: Option<IpfsRef>) -> Self {
+        match self {
+            AclMsg::Invite(msg) => AclMsg::Invite(msg.with_ipfs_ref(ipfs_ref)),
+            AclMsg::Permission(msg) => AclMsg::Permission(PermissionMsg { ipfs_ref, ..msg }),
+            AclMsg::Membership(msg) => AclMsg::Membership(MembershipMsg { ipfs_ref, ..msg }),
         }
+    }
+}
+
+impl TryFrom<nostr_sdk::Event> for AclMsg {
+    type Error = error::Error;
 
-        Ok(())
+    /// Converts a raw `nostr_sdk::Event` with the converter of its ACL event
+    /// type.
+    fn try_from(event: nostr_sdk::Event) -> Result<Self, Self::Error> {
+        match AclEventType::of(&event) {
+            Some(AclEventType::Invite) => Ok(AclMsg::Invite(InviteMsg::try_from(event)?)),
+            Some(AclEventType::Auth | AclEventType::Grant | AclEventType::Revoke) => {
+                Ok(AclMsg::Permission(PermissionMsg::try_from(event)?))
+            }
+            Some(AclEventType::Membership) => {
+                Ok(AclMsg::Membership(MembershipMsg::try_from(event)?))
+            }
+            None => Err(error::Error::CustomError(format!(
+                "event {} is not an ACL event of a known type",
+                event.id
+            ))),
+        }
     }
 }
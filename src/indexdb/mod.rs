@@ -1,3 +1,9 @@
+mod error;
 mod indexdb;
+#[cfg(feature = "indexdb")]
+mod server;
 
+pub use error::IndexdbError;
 pub use indexdb::*;
+#[cfg(feature = "indexdb")]
+pub use server::*;
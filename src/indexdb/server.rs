@@ -0,0 +1,178 @@
+//!This module provides the client sending ACL events to an external IndexDB
+//!server for storage or further processing. Each ACL event type is posted to
+//!its own endpoint of `indexdb_backend`.
+
+use super::{AclEventType, AclMsg, IndexdbError};
+use crate::common::config::{Config, IndexdbBackendConfig, SinkFormat};
+use crate::common::correlation::{CorrelationId, CORRELATION_HEADER};
+use crate::common::error::{self, ResultExt};
+use crate::common::http::HttpClient;
+use crate::common::sink::{self, EventSink};
+use crate::ipfs::IpfsStore;
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+/// A client wrapper for sending events to an IndexDB server.
+pub struct IndexdbServer {
+    client: HttpClient,
+    ipfs: Option<Arc<IpfsStore>>,
+    format: SinkFormat,
+    /// CloudEvents source of the posted ACL messages.
+    source: String,
+}
+
+impl IndexdbServer {
+    /// Creates a new IndexdbServer instance posting through `client`. Error
+    /// responses of the server become `IndexdbError`s.
+    pub fn new(client: HttpClient) -> Self {
+        IndexdbServer {
+            client: client.with_rejection(IndexdbError::rejection),
+            ipfs: None,
+            format: SinkFormat::Raw,
+            source: String::new(),
+        }
+    }
+
+    /// Pins large events to IPFS and attaches their reference to the ACL
+    /// message.
+    pub fn with_ipfs(mut self, ipfs: Option<Arc<IpfsStore>>) -> Self {
+        self.ipfs = ipfs;
+        self
+    }
+
+    /// Posts the ACL messages in `format`, with `source` as CloudEvents
+    /// source.
+    pub fn with_format(mut self, format: SinkFormat, source: &str) -> Self {
+        self.format = format;
+        self.source = source.to_string();
+        self
+    }
+
+    /// Sends an ACL event to the IndexDB server, converted with the
+    /// converter of its type.
+    /// Logs the status of the HTTP response. Error responses are returned as
+    /// `IndexdbError`s, retried only if they are retryable; the others move
+    /// the event to the dead-letter queue.
+    pub async fn send_acl_event_to_indexdb(
+        &self,
+        url: &str,
+        event: nostr_sdk::Event,
+        correlation_id: &CorrelationId,
+    ) -> error::Result<()> {
+        tracing::info!("got nostr event: {:?}", event);
+
+        let event_id = event.id;
+        let ipfs_ref = match &self.ipfs {
+            Some(ipfs) => ipfs.pin(&event).await?,
+            None => None,
+        };
+        let req = AclMsg::try_from(event.clone())
+            .context(|| format!("converting event {} to an ACL message", event_id))?
+            .with_ipfs_ref(ipfs_ref);
+        let started = Instant::now();
+        let response = match self.format {
+            SinkFormat::Raw => {
+                self.client
+                    .post_json("indexdb post", url, &req, Some(correlation_id))
+                    .await
+            }
+            SinkFormat::CloudEvents => {
+                let body = sink::wrap(
+                    &event,
+                    serde_json::to_vec(&req)?,
+                    &self.source,
+                    &self.format,
+                )?;
+                let mut headers = HeaderMap::new();
+                headers.insert(
+                    CONTENT_TYPE,
+                    HeaderValue::from_static(sink::CLOUDEVENTS_CONTENT_TYPE),
+                );
+                if let Ok(value) = HeaderValue::from_str(correlation_id.as_str()) {
+                    headers.insert(CORRELATION_HEADER, value);
+                }
+                self.client
+                    .post_bytes("indexdb post", url, &headers, &body)
+                    .await
+            }
+        }
+        .context(|| format!("posting event {} to indexdb at {}", event_id, url))?;
+
+        tracing::info!(
+            sink = "indexdb",
+            latency_ms = started.elapsed().as_millis() as u64,
+            "responded with status: {}",
+            response.status()
+        );
+
+        Ok(())
+    }
+}
+
+/// Posts the ACL messages of bridged events to the IndexDB endpoint of their
+/// type, as the sink of the pipelines reading from an `EventSource`.
+pub struct AclSink {
+    server: Arc<IndexdbServer>,
+    /// `indexdb_backend`, updated on reloads.
+    config: RwLock<IndexdbBackendConfig>,
+}
+
+impl AclSink {
+    pub fn new(server: Arc<IndexdbServer>, config: &IndexdbBackendConfig) -> Self {
+        Self {
+            server,
+            config: RwLock::new(config.clone()),
+        }
+    }
+
+    /// Returns the endpoint of `event_type`, if configured.
+    fn url(&self, event_type: AclEventType) -> Option<String> {
+        let config = self.config.read().unwrap_or_else(|e| e.into_inner());
+        match event_type {
+            AclEventType::Invite => Some(config.invite_url.clone()),
+            AclEventType::Auth => config.auth_url.clone(),
+            AclEventType::Grant => config.grant_url.clone(),
+            AclEventType::Revoke => config.revoke_url.clone(),
+            AclEventType::Membership => config.membership_url.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl EventSink for AclSink {
+    fn name(&self) -> &'static str {
+        "indexdb"
+    }
+
+    fn times_attempts(&self) -> bool {
+        true
+    }
+
+    /// Events of a known type without a configured endpoint are skipped;
+    /// other events fail to convert.
+    async fn send(
+        &self,
+        event: &nostr_sdk::Event,
+        correlation_id: &CorrelationId,
+    ) -> error::Result<()> {
+        // Events that are no ACL events fail to convert, as invites did.
+        let event_type = AclEventType::of(event).unwrap_or(AclEventType::Invite);
+        let Some(url) = self.url(event_type) else {
+            tracing::info!(
+                "skipping {} event {}, no indexdb endpoint is configured",
+                event_type.as_str(),
+                event.id
+            );
+            return Ok(());
+        };
+        self.server
+            .send_acl_event_to_indexdb(&url, event.clone(), correlation_id)
+            .await
+    }
+
+    fn reload(&self, config: &Config) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config.indexdb_backend.clone();
+    }
+}
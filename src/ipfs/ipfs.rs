@@ -0,0 +1,131 @@
+//!This module offloads large event payloads to IPFS. Events whose JSON
+//!encoding reaches the configured size are pinned to an IPFS node through
+//!its RPC API, and only a small reference with the CID and the SHA-256 of the
+//!pinned bytes is forwarded. Receivers resolve references back into the
+//!event transparently and reject content that does not match the hash.
+
+use crate::common::config::IpfsConfig;
+use crate::common::error::{self, ResultExt};
+use crate::common::http::HttpClient;
+use nostr_sdk::{Event, JsonUtil};
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Reference forwarded in place of an offloaded event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpfsRef {
+    pub cid: String,
+    /// Hex encoded SHA-256 of the pinned bytes.
+    pub sha256: String,
+    pub size: usize,
+    /// Id of the offloaded event, so it can be matched without fetching it.
+    pub event_id: String,
+}
+
+/// Payload carrying an `IpfsRef`.
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    ipfs_ref: IpfsRef,
+}
+
+/// Response of the `add` RPC.
+#[derive(Debug, Deserialize)]
+struct AddResponse {
+    #[serde(rename = "Hash")]
+    hash: String,
+}
+
+/// Returns the reference carried by `payload`, if it is one.
+pub fn reference(payload: &[u8]) -> Option<IpfsRef> {
+    serde_json::from_slice::<Envelope>(payload)
+        .ok()
+        .map(|envelope| envelope.ipfs_ref)
+}
+
+/// Pins and fetches payloads through the RPC API of an IPFS node.
+pub struct IpfsStore {
+    client: HttpClient,
+    api_url: String,
+    min_size_bytes: usize,
+}
+
+impl IpfsStore {
+    pub fn new(config: &IpfsConfig, client: HttpClient) -> Self {
+        Self {
+            client,
+            api_url: config.api_url.trim_end_matches('/').to_string(),
+            min_size_bytes: config.min_size_bytes,
+        }
+    }
+
+    /// Pins the JSON encoding of `event` if it has at least
+    /// `min_size_bytes`, and returns the reference to forward in its place.
+    pub async fn pin(&self, event: &Event) -> error::Result<Option<IpfsRef>> {
+        let json = event.as_json();
+        if json.len() < self.min_size_bytes {
+            return Ok(None);
+        }
+
+        let url = format!("{}/api/v0/add?pin=true&cid-version=1", self.api_url);
+        let response: AddResponse = self
+            .client
+            .post_file("ipfs add", &url, json.as_bytes())
+            .await
+            .context(|| format!("pinning event {} to ipfs", event.id))?
+            .json()
+            .await?;
+        tracing::debug!(
+            "pinned event {} ({} bytes) as {}",
+            event.id,
+            json.len(),
+            response.hash
+        );
+
+        Ok(Some(IpfsRef {
+            cid: response.hash,
+            sha256: hex::encode(Sha256::digest(json.as_bytes())),
+            size: json.len(),
+            event_id: event.id.to_hex(),
+        }))
+    }
+
+    /// Returns the payload to forward for `event`: its JSON encoding, or a
+    /// reference to the pinned encoding if it has at least `min_size_bytes`.
+    pub async fn offload(&self, event: &Event) -> error::Result<String> {
+        match self.pin(event).await? {
+            Some(ipfs_ref) => Ok(serde_json::to_string(&Envelope { ipfs_ref })?),
+            None => Ok(event.as_json()),
+        }
+    }
+
+    /// Returns `payload`, or the pinned content if it is a reference.
+    ///
+    /// # Errors
+    ///
+    /// Returns `IpfsError` if the fetched content does not match the hash of
+    /// the reference.
+    pub async fn resolve(&self, payload: &[u8]) -> error::Result<Vec<u8>> {
+        let Some(reference) = reference(payload) else {
+            return Ok(payload.to_vec());
+        };
+
+        let url = format!("{}/api/v0/cat?arg={}", self.api_url, reference.cid);
+        let content = self
+            .client
+            .post_bytes("ipfs cat", &url, &HeaderMap::new(), &[])
+            .await
+            .context(|| format!("fetching {} from ipfs", reference.cid))?
+            .bytes()
+            .await?;
+        let sha256 = hex::encode(Sha256::digest(&content));
+        if sha256 != reference.sha256 {
+            return Err(error::Error::IpfsError(format!(
+                "content of {} has hash {}, expected {}",
+                reference.cid, sha256, reference.sha256
+            )));
+        }
+
+        Ok(content.to_vec())
+    }
+}
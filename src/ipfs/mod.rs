@@ -0,0 +1,3 @@
+mod ipfs;
+
+pub use ipfs::*;
@@ -0,0 +1,174 @@
+//!This module provides a Kafka sink and source for bridged Nostr events.
+//!Events are produced as their JSON encoding, keyed by the event id so that
+//!all versions of an event land in the same partition, and consumed back the
+//!same way.
+
+use crate::common::config::{KafkaConfig, SinkFormat};
+use crate::common::correlation::{CorrelationId, CORRELATION_HEADER};
+use crate::common::error;
+use crate::common::sink::{self, EventSink, EventSource};
+use async_trait::async_trait;
+use nostr_sdk::{Event, JsonUtil};
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::Message;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// How long a produce may wait for room in the local producer queue.
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Builds the librdkafka client configuration shared by the producer and
+/// the consumer.
+fn client_config(config: &KafkaConfig) -> ClientConfig {
+    let mut client = ClientConfig::new();
+    client.set("bootstrap.servers", &config.brokers);
+    for (key, value) in &config.properties {
+        client.set(key, value);
+    }
+    client
+}
+
+/// Produces bridged events to a Kafka topic.
+pub struct KafkaSink {
+    producer: FutureProducer,
+    topic: String,
+    format: SinkFormat,
+    /// CloudEvents source of the produced events.
+    source: String,
+}
+
+impl KafkaSink {
+    /// Creates a producer for the configured topic. `source` is the
+    /// CloudEvents source of the produced events.
+    pub fn new(config: &KafkaConfig, source: &str) -> error::Result<Self> {
+        let producer = client_config(config).create()?;
+        Ok(Self {
+            producer,
+            topic: config.topic.clone(),
+            format: config.format.clone(),
+            source: source.to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl EventSink for KafkaSink {
+    fn name(&self) -> &'static str {
+        "kafka"
+    }
+
+    async fn send(&self, event: &Event, correlation_id: &CorrelationId) -> error::Result<()> {
+        self.send_to(event, correlation_id, None).await
+    }
+
+    async fn send_to(
+        &self,
+        event: &Event,
+        correlation_id: &CorrelationId,
+        topic: Option<&str>,
+    ) -> error::Result<()> {
+        let topic = topic.unwrap_or(&self.topic);
+        let key = event.id.to_hex();
+        let payload = sink::wrap(
+            event,
+            event.as_json().into_bytes(),
+            &self.source,
+            &self.format,
+        )?;
+        let mut headers = OwnedHeaders::new().insert(Header {
+            key: CORRELATION_HEADER,
+            value: Some(correlation_id.as_str()),
+        });
+        if self.format == SinkFormat::CloudEvents {
+            headers = headers.insert(Header {
+                key: "content-type",
+                value: Some(sink::CLOUDEVENTS_CONTENT_TYPE),
+            });
+        }
+        let record = FutureRecord::to(topic)
+            .key(&key)
+            .payload(&payload)
+            .headers(headers);
+
+        let (partition, offset) = self
+            .producer
+            .send(record, QUEUE_TIMEOUT)
+            .await
+            .map_err(|(e, _)| e)?;
+        tracing::debug!(
+            sink = "kafka",
+            partition,
+            offset,
+            "produced event {} to {}",
+            key,
+            topic
+        );
+
+        Ok(())
+    }
+}
+
+/// Consumes events from a Kafka topic.
+pub struct KafkaSource {
+    consumer: StreamConsumer,
+    topic: String,
+}
+
+impl KafkaSource {
+    /// Creates a consumer of the configured source topic in the configured
+    /// consumer group.
+    pub fn new(config: &KafkaConfig) -> error::Result<Self> {
+        let consumer = client_config(config)
+            .set("group.id", &config.group_id)
+            .create()?;
+        Ok(Self {
+            consumer,
+            topic: config
+                .source_topic
+                .clone()
+                .unwrap_or_else(|| config.topic.clone()),
+        })
+    }
+}
+
+#[async_trait]
+impl EventSource for KafkaSource {
+    fn name(&self) -> &'static str {
+        "kafka"
+    }
+
+    async fn run(&self, tx: mpsc::Sender<Event>) -> error::Result<()> {
+        self.consumer.subscribe(&[self.topic.as_str()])?;
+        tracing::info!("consuming events from kafka topic {}", self.topic);
+
+        loop {
+            let message = match self.consumer.recv().await {
+                Ok(message) => message,
+                Err(e) => {
+                    tracing::warn!("failed to consume from {}: {}", self.topic, e);
+                    continue;
+                }
+            };
+            let Some(payload) = message.payload() else {
+                continue;
+            };
+            match Event::from_json(payload) {
+                Ok(event) => {
+                    if tx.send(event).await.is_err() {
+                        return Ok(());
+                    }
+                }
+                Err(e) => tracing::warn!(
+                    partition = message.partition(),
+                    offset = message.offset(),
+                    "skipping malformed event from {}: {}",
+                    self.topic,
+                    e
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,3 @@
+mod kafka;
+
+pub use kafka::*;
@@ -0,0 +1,78 @@
+//! Bridge of ACL events between a Nostr relay, Waku and other systems.
+//!
+//! The Nostr pipelines, the clients and the integrations are split by cargo
+//! features:
+//!
+//! - `nostr`: the relay client and the `App` pipelines.
+//! - `waku-ffi`: the embedded Waku node receiving `w2n` events, which needs
+//!   libwaku at runtime.
+//! - `waku-rest`: publishing `n2w` events through a Waku node's REST API.
+//! - `indexdb`: posting `n2i` invites to IndexDB over HTTP.
+//! - `cli`: the `nostr_gateway` binary.
+//! - `grpc` and `graphql`: the gRPC and GraphQL APIs.
+//! - `kafka`, `mqtt`, `nats`, `redis`, `sqs` and `smtp`: the sinks and
+//!   sources of the same name.
+//! - `plugins`: the WASM plugins.
+//! - `archive` and `anchor`: archival to object storage and on-chain
+//!   anchoring.
+//! - `sentry` and `telemetry`: error reporting and OpenTelemetry export.
+//!
+//! All of them are enabled by default. For example, a Nostr to IndexDB
+//! bridge without the embedded Waku node, the other integrations and the
+//! binary needs `default-features = false, features = ["indexdb"]`. A
+//! configuration using an integration the gateway was built without is
+//! rejected when it is validated.
+//!
+//! The webhook, Matrix and IPFS clients and the transform rules only need
+//! the HTTP client and are always built.
+//!
+//! Services embedding the bridge build a `Bridge` from a `Config`, see
+//! `services::bridge`. The clients and the storage it runs on are exported
+//! at the crate root as well.
+
+#[cfg(feature = "anchor")]
+pub mod anchor;
+#[cfg(feature = "archive")]
+pub mod archive;
+#[cfg(feature = "cli")]
+pub mod cli;
+pub mod common;
+pub mod db;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod indexdb;
+pub mod ipfs;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod matrix;
+pub mod metrics;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "nats")]
+pub mod nats;
+#[cfg(feature = "nostr")]
+pub mod nostr;
+pub mod plugin;
+#[cfg(feature = "redis")]
+pub mod redis;
+#[cfg(feature = "nostr")]
+pub mod services;
+#[cfg(feature = "smtp")]
+pub mod smtp;
+#[cfg(feature = "sqs")]
+pub mod sqs;
+pub mod transform;
+pub mod waku;
+pub mod webhook;
+
+pub use common::config::Config;
+pub use common::sink::{EventSink, EventSource, EventStream};
+pub use db::Storage;
+#[cfg(feature = "nostr")]
+pub use nostr::NostrClient;
+#[cfg(feature = "nostr")]
+pub use services::{Bridge, BridgeBuilder};
+#[cfg(feature = "waku-ffi")]
+pub use waku::WakuClient;
@@ -1,17 +1,7 @@
-mod cli;
-mod common;
-mod db;
-mod nostr;
-mod services;
-mod waku;
-mod indexdb;
-
-use crate::common::consts::LOG_PATH;
-use crate::common::logging;
+use nostr_gateway::{cli, common};
 
 #[tokio::main]
 async fn main() {
-    logging::logging_init(LOG_PATH).unwrap();
-
+    common::crash::install_panic_hook();
     cli::handle_cli().await;
 }
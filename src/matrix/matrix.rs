@@ -0,0 +1,142 @@
+//!This module provides a Matrix sink posting designated ACL events, such as
+//!invites and revocations, into a Matrix room as formatted notices, so human
+//!operators can follow the machine-to-machine traffic.
+
+use crate::common::config::MatrixConfig;
+use crate::common::correlation::CorrelationId;
+use crate::common::error;
+use crate::common::http::HttpClient;
+use crate::common::sink::EventSink;
+use crate::indexdb::InviteMsg;
+use async_trait::async_trait;
+use nostr_sdk::Event;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use serde_json::json;
+use url::Url;
+
+/// Posts ACL events to a Matrix room through the client-server API.
+pub struct MatrixSink {
+    client: HttpClient,
+    /// Url of the send endpoint of the room, without the transaction id.
+    send_url: String,
+    headers: HeaderMap,
+    event_types: Vec<String>,
+}
+
+impl MatrixSink {
+    pub fn new(config: &MatrixConfig, client: HttpClient) -> error::Result<Self> {
+        let mut send_url =
+            Url::parse(&config.homeserver_url).map_err(|e| error::Error::InvalidUrl {
+                field: "matrix.homeserver_url",
+                value: config.homeserver_url.clone(),
+                reason: e.to_string(),
+            })?;
+        send_url
+            .path_segments_mut()
+            .map_err(|_| error::Error::InvalidUrl {
+                field: "matrix.homeserver_url",
+                value: config.homeserver_url.clone(),
+                reason: "cannot be a base".to_string(),
+            })?
+            .pop_if_empty()
+            .extend([
+                "_matrix",
+                "client",
+                "v3",
+                "rooms",
+                &config.room_id,
+                "send",
+                "m.room.message",
+            ]);
+
+        let mut headers = HeaderMap::new();
+        let token = HeaderValue::from_str(&format!("Bearer {}", config.access_token.expose()))
+            .map_err(|e| error::Error::InvalidKey {
+                field: "matrix.access_token",
+                reason: e.to_string(),
+            })?;
+        headers.insert(AUTHORIZATION, token);
+
+        Ok(Self {
+            client,
+            send_url: send_url.to_string(),
+            headers,
+            event_types: config.event_types.clone(),
+        })
+    }
+}
+
+/// Formats an ACL event as a plain text and an HTML message.
+fn format_message(event: &Event, invite: &InviteMsg) -> (String, String) {
+    let plain = format!(
+        "ACL {}: {} -> {} on project {} (event {})",
+        invite.event_type(),
+        invite.inviter(),
+        invite.invitee(),
+        invite.project(),
+        event.id
+    );
+    let html = format!(
+        "<b>ACL {}</b>: <code>{}</code> &rarr; <code>{}</code> on project <code>{}</code><br/>event <code>{}</code>",
+        escape(invite.event_type()),
+        escape(invite.inviter()),
+        escape(invite.invitee()),
+        escape(invite.project()),
+        event.id
+    );
+    (plain, html)
+}
+
+/// Escapes the HTML special characters of `text`.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[async_trait]
+impl EventSink for MatrixSink {
+    fn name(&self) -> &'static str {
+        "matrix"
+    }
+
+    fn times_attempts(&self) -> bool {
+        true
+    }
+
+    /// Posts the event if it is an ACL event of one of the configured types,
+    /// and skips it otherwise. The event id is the transaction id, so the
+    /// homeserver ignores re-sent events.
+    async fn send(&self, event: &Event, _correlation_id: &CorrelationId) -> error::Result<()> {
+        let Ok(invite) = InviteMsg::try_from(event.clone()) else {
+            tracing::debug!(sink = "matrix", "event {} is not an ACL event", event.id);
+            return Ok(());
+        };
+        if !self.event_types.iter().any(|t| t == invite.event_type()) {
+            tracing::debug!(
+                sink = "matrix",
+                "skipping {} event {}",
+                invite.event_type(),
+                event.id
+            );
+            return Ok(());
+        }
+
+        let (plain, html) = format_message(event, &invite);
+        let body = json!({
+            "msgtype": "m.notice",
+            "body": plain,
+            "format": "org.matrix.custom.html",
+            "formatted_body": html,
+        });
+        let url = format!("{}/{}", self.send_url, event.id.to_hex());
+
+        self.client
+            .put_json("matrix send", &url, &self.headers, &body)
+            .await?;
+        tracing::debug!(sink = "matrix", "posted event {} to the room", event.id);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+//! State of the circuit breakers of the pipelines.
+//!
+//! Every circuit breaker records its transitions here and in the
+//! `bridge_circuit_breaker_state` and `bridge_circuit_breaker_trips_total`
+//! series, so the admin API, and through it the `status` command, can report
+//! them without a Prometheus server.
+
+use super::registry::metrics;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+
+/// State of a circuit breaker.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// Deliveries go through.
+    #[default]
+    Closed,
+    /// The sink keeps failing, deliveries are buffered in the retry queue.
+    Open,
+    /// Probe deliveries check whether the sink recovered.
+    HalfOpen,
+}
+
+impl BreakerState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BreakerState::Closed => "closed",
+            BreakerState::Open => "open",
+            BreakerState::HalfOpen => "half_open",
+        }
+    }
+
+    /// Value of the state gauge: 0 when closed, 1 when half open, 2 when
+    /// open.
+    fn gauge(&self) -> f64 {
+        match self {
+            BreakerState::Closed => 0.0,
+            BreakerState::HalfOpen => 1.0,
+            BreakerState::Open => 2.0,
+        }
+    }
+}
+
+/// A circuit breaker as of its last transition.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct BreakerStats {
+    pub direction: String,
+    pub sink: String,
+    pub state: BreakerState,
+    /// Transient failures in a row since the last success.
+    pub consecutive_failures: u32,
+    /// Times the breaker opened since the start.
+    pub trips: u64,
+    /// Unix timestamp of the last time the breaker opened.
+    pub opened_at: Option<i64>,
+}
+
+fn stats() -> &'static Mutex<BTreeMap<String, BreakerStats>> {
+    static STATS: OnceLock<Mutex<BTreeMap<String, BreakerStats>>> = OnceLock::new();
+    STATS.get_or_init(|| Mutex::new(BTreeMap::new()))
+}
+
+/// Records the state of the breaker of `sink` in `direction`.
+pub fn record_breaker(direction: &str, sink: &str, state: BreakerState, consecutive_failures: u32) {
+    metrics()
+        .circuit_breaker_state
+        .with_label_values(&[direction, sink])
+        .set(state.gauge());
+    let mut stats = stats().lock().unwrap_or_else(|e| e.into_inner());
+    let stats = stats
+        .entry(direction.to_string())
+        .or_insert_with(|| BreakerStats {
+            direction: direction.to_string(),
+            sink: sink.to_string(),
+            ..BreakerStats::default()
+        });
+    stats.state = state;
+    stats.consecutive_failures = consecutive_failures;
+}
+
+/// Records that the breaker of `sink` in `direction` opened.
+pub fn record_trip(direction: &str, sink: &str) {
+    metrics()
+        .circuit_breaker_trips_total
+        .with_label_values(&[direction, sink])
+        .inc();
+    let mut stats = stats().lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(stats) = stats.get_mut(direction) {
+        stats.trips += 1;
+        stats.opened_at = Some(Utc::now().timestamp());
+    }
+}
+
+/// Returns the circuit breakers of the running pipelines, by direction.
+pub fn breaker_stats() -> Vec<BreakerStats> {
+    stats()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .values()
+        .cloned()
+        .collect()
+}
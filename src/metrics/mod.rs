@@ -0,0 +1,16 @@
+//! In-process metrics of the bridge pipelines.
+//!
+//! Metrics are registered once in a global prometheus registry and updated
+//! by the pipelines; see `registry` for the available series.
+
+mod breakers;
+mod peers;
+mod registry;
+mod throughput;
+mod traffic;
+
+pub use breakers::*;
+pub use peers::*;
+pub use registry::*;
+pub use throughput::ThroughputWindow;
+pub use traffic::*;
@@ -0,0 +1,54 @@
+//! Peer statistics of the embedded Waku node.
+//!
+//! The peer monitor records every check here and in the `bridge_waku_peers`
+//! and `bridge_waku_peer_redials_total` series, so the admin API, and through
+//! it the `status` command, can report them without a Prometheus server.
+
+use super::registry::metrics;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::{Mutex, OnceLock};
+
+/// Peers of the embedded Waku node as of the last check.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PeerStats {
+    /// Connected peers, the node itself excluded.
+    pub connected: usize,
+    /// The bootstrap peers are redialed below this count.
+    pub min_peers: usize,
+    /// Redials since the start.
+    pub redials: u64,
+    /// Unix timestamp of the last check.
+    pub checked_at: Option<i64>,
+    /// Unix timestamp of the last redial.
+    pub last_redial_at: Option<i64>,
+}
+
+fn stats() -> &'static Mutex<Option<PeerStats>> {
+    static STATS: OnceLock<Mutex<Option<PeerStats>>> = OnceLock::new();
+    STATS.get_or_init(|| Mutex::new(None))
+}
+
+/// Records the connected peer count of a check.
+pub fn record_peers(connected: usize, min_peers: usize) {
+    metrics().waku_peers.set(connected as f64);
+    let mut stats = stats().lock().unwrap_or_else(|e| e.into_inner());
+    let stats = stats.get_or_insert_with(PeerStats::default);
+    stats.connected = connected;
+    stats.min_peers = min_peers;
+    stats.checked_at = Some(Utc::now().timestamp());
+}
+
+/// Records a redial of the bootstrap peers.
+pub fn record_redial() {
+    metrics().waku_peer_redials_total.inc();
+    let mut stats = stats().lock().unwrap_or_else(|e| e.into_inner());
+    let stats = stats.get_or_insert_with(PeerStats::default);
+    stats.redials += 1;
+    stats.last_redial_at = Some(Utc::now().timestamp());
+}
+
+/// Returns the peer statistics, or `None` if the peers are not monitored.
+pub fn peer_stats() -> Option<PeerStats> {
+    stats().lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
@@ -0,0 +1,375 @@
+//! Global metrics registry and the series exported by the bridge.
+
+use crate::common::error;
+use prometheus::{
+    Encoder, Gauge, GaugeVec, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts,
+    Registry, TextEncoder,
+};
+use std::sync::OnceLock;
+
+/// All metrics exported by the bridge.
+pub struct Metrics {
+    /// The registry every metric below is registered with.
+    pub registry: Registry,
+    /// Seconds between now and the checkpoint of a direction.
+    pub checkpoint_lag_seconds: GaugeVec,
+    /// Seconds since the checkpoint of a direction was last committed.
+    pub checkpoint_stale_seconds: GaugeVec,
+    /// Bridged events per second of a direction over a sliding window.
+    pub events_per_second: GaugeVec,
+    /// Age of the oldest event waiting for delivery in a direction.
+    pub oldest_pending_age_seconds: GaugeVec,
+    /// Events waiting for delivery in the queue of a direction.
+    pub queue_depth: GaugeVec,
+    /// Queued events of a direction whose delivery is not settled yet, which
+    /// hold its checkpoint back.
+    pub events_unacknowledged: GaugeVec,
+    /// Fetch rounds of a direction deferred because its queue was full.
+    pub fetch_backpressure_total: IntCounterVec,
+    /// Duration of external operations.
+    pub operation_duration_seconds: HistogramVec,
+    /// External operations slower than their configured threshold.
+    pub slow_operations_total: IntCounterVec,
+    /// Bridged events per direction, content topic and Nostr kind.
+    pub events_total: IntCounterVec,
+    /// Events fetched from the relays per direction, duplicates included.
+    pub events_fetched_total: IntCounterVec,
+    /// Events skipped per direction because they were already bridged.
+    pub events_deduplicated_total: IntCounterVec,
+    /// Received events dropped per direction because their id or signature
+    /// is invalid.
+    pub events_invalid_total: IntCounterVec,
+    /// Failed delivery attempts per direction.
+    pub events_failed_total: IntCounterVec,
+    /// Database writes held in memory while the database is unavailable.
+    pub db_buffered_writes: Gauge,
+    /// Restarts of supervised tasks after a panic.
+    pub task_restarts_total: IntCounterVec,
+    /// Surfaced errors per module, error class and pipeline.
+    pub errors_total: IntCounterVec,
+    /// Requests per sink that waited for a token of its rate limit.
+    pub rate_limited_total: IntCounterVec,
+    /// State of the circuit breaker of a sink: 0 closed, 1 half open, 2 open.
+    pub circuit_breaker_state: GaugeVec,
+    /// Times the circuit breaker of a sink opened.
+    pub circuit_breaker_trips_total: IntCounterVec,
+    /// Peers connected to the embedded Waku node.
+    pub waku_peers: Gauge,
+    /// Redials of the bootstrap peers after the peer count dropped.
+    pub waku_peer_redials_total: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let checkpoint_lag_seconds = GaugeVec::new(
+            Opts::new(
+                "bridge_checkpoint_lag_seconds",
+                "Seconds between now and the last fetch checkpoint",
+            ),
+            &["direction"],
+        )
+        .expect("valid metric");
+        let checkpoint_stale_seconds = GaugeVec::new(
+            Opts::new(
+                "bridge_checkpoint_stale_seconds",
+                "Seconds since the fetch checkpoint was last committed to the database",
+            ),
+            &["direction"],
+        )
+        .expect("valid metric");
+        let events_per_second = GaugeVec::new(
+            Opts::new(
+                "bridge_events_per_second",
+                "Bridged events per second over a sliding window",
+            ),
+            &["direction", "window"],
+        )
+        .expect("valid metric");
+        let oldest_pending_age_seconds = GaugeVec::new(
+            Opts::new(
+                "bridge_oldest_pending_age_seconds",
+                "Age of the oldest event waiting for delivery",
+            ),
+            &["direction"],
+        )
+        .expect("valid metric");
+        let queue_depth = GaugeVec::new(
+            Opts::new(
+                "bridge_queue_depth",
+                "Events waiting for delivery in the queue of a direction",
+            ),
+            &["direction"],
+        )
+        .expect("valid metric");
+        let events_unacknowledged = GaugeVec::new(
+            Opts::new(
+                "bridge_events_unacknowledged",
+                "Queued events whose delivery is not settled yet, holding the checkpoint back",
+            ),
+            &["direction"],
+        )
+        .expect("valid metric");
+        let fetch_backpressure_total = IntCounterVec::new(
+            Opts::new(
+                "bridge_fetch_backpressure_total",
+                "Fetch rounds deferred because the queue of the direction was full",
+            ),
+            &["direction"],
+        )
+        .expect("valid metric");
+
+        let operation_duration_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "bridge_operation_duration_seconds",
+                "Duration of external operations",
+            ),
+            &["operation"],
+        )
+        .expect("valid metric");
+        let slow_operations_total = IntCounterVec::new(
+            Opts::new(
+                "bridge_slow_operations_total",
+                "External operations slower than their threshold",
+            ),
+            &["operation"],
+        )
+        .expect("valid metric");
+
+        let events_total = IntCounterVec::new(
+            Opts::new("bridge_events_total", "Bridged events"),
+            &["direction", "content_topic", "kind"],
+        )
+        .expect("valid metric");
+        let events_fetched_total = IntCounterVec::new(
+            Opts::new(
+                "bridge_events_fetched_total",
+                "Events fetched from the relays, duplicates included",
+            ),
+            &["direction"],
+        )
+        .expect("valid metric");
+        let events_deduplicated_total = IntCounterVec::new(
+            Opts::new(
+                "bridge_events_deduplicated_total",
+                "Events skipped because they were already bridged",
+            ),
+            &["direction"],
+        )
+        .expect("valid metric");
+        let events_invalid_total = IntCounterVec::new(
+            Opts::new(
+                "bridge_events_invalid_total",
+                "Received events dropped because their id or signature is invalid",
+            ),
+            &["direction"],
+        )
+        .expect("valid metric");
+        let events_failed_total = IntCounterVec::new(
+            Opts::new("bridge_events_failed_total", "Failed delivery attempts"),
+            &["direction"],
+        )
+        .expect("valid metric");
+
+        let db_buffered_writes = Gauge::new(
+            "bridge_db_buffered_writes",
+            "Database writes held in memory while the database is unavailable",
+        )
+        .expect("valid metric");
+
+        let task_restarts_total = IntCounterVec::new(
+            Opts::new(
+                "bridge_task_restarts_total",
+                "Restarts of supervised tasks after a panic",
+            ),
+            &["direction", "sink"],
+        )
+        .expect("valid metric");
+
+        let errors_total = IntCounterVec::new(
+            Opts::new(
+                "bridge_errors_total",
+                "Surfaced errors per module, error class and pipeline",
+            ),
+            &["module", "class", "pipeline"],
+        )
+        .expect("valid metric");
+
+        let rate_limited_total = IntCounterVec::new(
+            Opts::new(
+                "bridge_rate_limited_total",
+                "Requests that waited for a token of the rate limit of their sink",
+            ),
+            &["sink"],
+        )
+        .expect("valid metric");
+
+        let circuit_breaker_state = GaugeVec::new(
+            Opts::new(
+                "bridge_circuit_breaker_state",
+                "State of the circuit breaker of a sink: 0 closed, 1 half open, 2 open",
+            ),
+            &["direction", "sink"],
+        )
+        .expect("valid metric");
+        let circuit_breaker_trips_total = IntCounterVec::new(
+            Opts::new(
+                "bridge_circuit_breaker_trips_total",
+                "Times the circuit breaker of a sink opened",
+            ),
+            &["direction", "sink"],
+        )
+        .expect("valid metric");
+
+        let waku_peers = Gauge::new(
+            "bridge_waku_peers",
+            "Peers connected to the embedded Waku node",
+        )
+        .expect("valid metric");
+        let waku_peer_redials_total = IntCounter::new(
+            "bridge_waku_peer_redials_total",
+            "Redials of the bootstrap peers after the peer count dropped",
+        )
+        .expect("valid metric");
+
+        registry
+            .register(Box::new(checkpoint_lag_seconds.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(checkpoint_stale_seconds.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(events_per_second.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(oldest_pending_age_seconds.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(queue_depth.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(events_unacknowledged.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(fetch_backpressure_total.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(operation_duration_seconds.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(slow_operations_total.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(events_total.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(events_fetched_total.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(events_deduplicated_total.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(events_invalid_total.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(events_failed_total.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(db_buffered_writes.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(task_restarts_total.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(errors_total.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(rate_limited_total.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(circuit_breaker_state.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(circuit_breaker_trips_total.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(waku_peers.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(waku_peer_redials_total.clone()))
+            .expect("metric registered once");
+
+        Self {
+            registry,
+            checkpoint_lag_seconds,
+            checkpoint_stale_seconds,
+            events_per_second,
+            oldest_pending_age_seconds,
+            queue_depth,
+            events_unacknowledged,
+            fetch_backpressure_total,
+            operation_duration_seconds,
+            slow_operations_total,
+            events_total,
+            events_fetched_total,
+            events_deduplicated_total,
+            events_invalid_total,
+            events_failed_total,
+            db_buffered_writes,
+            task_restarts_total,
+            errors_total,
+            rate_limited_total,
+            circuit_breaker_state,
+            circuit_breaker_trips_total,
+            waku_peers,
+            waku_peer_redials_total,
+        }
+    }
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// Returns the global metrics, registering them on first use.
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::new)
+}
+
+/// Updates the checkpoint lag gauge of a direction.
+///
+/// # Arguments
+///
+/// * `direction` - The pipeline direction, e.g. `n2w`.
+/// * `checkpoint` - The current checkpoint, in unix seconds.
+/// * `now` - The current time, in unix seconds.
+pub fn observe_checkpoint(direction: &str, checkpoint: u64, now: u64) {
+    metrics()
+        .checkpoint_lag_seconds
+        .with_label_values(&[direction])
+        .set(now.saturating_sub(checkpoint) as f64);
+}
+
+/// Counts an error surfaced by a pipeline.
+///
+/// # Arguments
+///
+/// * `pipeline` - The pipeline direction, e.g. `n2w`.
+/// * `module` - The module the error is attributed to when the error itself
+///   does not tell, e.g. the sink being delivered to.
+/// * `error` - The surfaced error.
+pub fn record_error(pipeline: &str, module: &str, error: &error::Error) {
+    metrics()
+        .errors_total
+        .with_label_values(&[error.module().unwrap_or(module), error.class(), pipeline])
+        .inc();
+}
+
+/// Encodes every metric in the Prometheus text exposition format.
+pub fn render() -> error::Result<String> {
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&metrics().registry.gather(), &mut buffer)
+        .map_err(|e| error::Error::CustomError(format!("encoding metrics: {}", e)))?;
+    String::from_utf8(buffer)
+        .map_err(|e| error::Error::CustomError(format!("encoding metrics: {}", e)))
+}
@@ -0,0 +1,60 @@
+//! Sliding window throughput tracking.
+
+use super::registry::metrics;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Windows over which the events/sec gauge is published.
+const WINDOWS: [(&str, Duration); 3] = [
+    ("1m", Duration::from_secs(60)),
+    ("5m", Duration::from_secs(5 * 60)),
+    ("15m", Duration::from_secs(15 * 60)),
+];
+
+/// Records bridged events of one direction and publishes events/sec gauges.
+#[derive(Debug)]
+pub struct ThroughputWindow {
+    direction: &'static str,
+    events: VecDeque<Instant>,
+}
+
+impl ThroughputWindow {
+    pub fn new(direction: &'static str) -> Self {
+        Self {
+            direction,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Records `count` events bridged now.
+    pub fn record(&mut self, count: usize) {
+        let now = Instant::now();
+        self.events.extend(std::iter::repeat(now).take(count));
+    }
+
+    /// Drops events older than the largest window and updates the gauges.
+    pub fn publish(&mut self) {
+        let now = Instant::now();
+        let longest = WINDOWS[WINDOWS.len() - 1].1;
+        while let Some(front) = self.events.front() {
+            if now.duration_since(*front) > longest {
+                self.events.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        for (label, window) in WINDOWS {
+            let count = self
+                .events
+                .iter()
+                .rev()
+                .take_while(|at| now.duration_since(**at) <= window)
+                .count();
+            metrics()
+                .events_per_second
+                .with_label_values(&[self.direction, label])
+                .set(count as f64 / window.as_secs_f64());
+        }
+    }
+}
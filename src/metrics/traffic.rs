@@ -0,0 +1,166 @@
+//! Bridged event counters per direction, content topic and Nostr kind.
+//!
+//! Totals are kept in the `bridge_events_total` counter of the registry;
+//! windowed counts are kept in per-second buckets so the admin API can report
+//! recent traffic without a Prometheus server.
+
+use super::registry::metrics;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Windows, in seconds, used until `set_traffic_windows` is called.
+const DEFAULT_WINDOWS: [u64; 3] = [60, 900, 3600];
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct TrafficKey {
+    direction: String,
+    content_topic: Option<String>,
+    kind: u16,
+}
+
+/// Bridged events of one key, one bucket per second with traffic.
+#[derive(Debug, Default)]
+struct Buckets(VecDeque<(Instant, u64)>);
+
+impl Buckets {
+    fn record(&mut self, now: Instant) {
+        match self.0.back_mut() {
+            Some((at, count)) if now.duration_since(*at) < Duration::from_secs(1) => *count += 1,
+            _ => self.0.push_back((now, 1)),
+        }
+    }
+
+    fn prune(&mut self, now: Instant, longest: Duration) {
+        while let Some((at, _)) = self.0.front() {
+            if now.duration_since(*at) > longest {
+                self.0.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn count(&self, now: Instant, window: Duration) -> u64 {
+        self.0
+            .iter()
+            .rev()
+            .take_while(|(at, _)| now.duration_since(*at) <= window)
+            .map(|(_, count)| count)
+            .sum()
+    }
+}
+
+/// Traffic of one direction, content topic and kind.
+#[derive(Debug, Serialize)]
+pub struct TrafficEntry {
+    pub direction: String,
+    /// Waku content topic, absent for sinks without topics.
+    pub content_topic: Option<String>,
+    pub kind: u16,
+    /// Events bridged since the process started.
+    pub total: u64,
+    /// Events bridged within each window, keyed by e.g. `60s`.
+    pub windows: BTreeMap<String, u64>,
+}
+
+/// Traffic counters as returned by the `/stats` endpoint.
+#[derive(Debug, Serialize)]
+pub struct TrafficSnapshot {
+    pub windows_secs: Vec<u64>,
+    pub entries: Vec<TrafficEntry>,
+}
+
+static WINDOWS: OnceLock<Vec<u64>> = OnceLock::new();
+static TRAFFIC: OnceLock<Mutex<HashMap<TrafficKey, Buckets>>> = OnceLock::new();
+
+/// Sets the windows traffic is reported over. Only the first call has an
+/// effect; until then the defaults apply.
+pub fn set_traffic_windows(windows_secs: Vec<u64>) {
+    let _ = WINDOWS.set(windows_secs);
+}
+
+fn windows() -> &'static [u64] {
+    WINDOWS.get_or_init(|| DEFAULT_WINDOWS.to_vec())
+}
+
+fn longest_window() -> Duration {
+    Duration::from_secs(windows().iter().copied().max().unwrap_or_default())
+}
+
+fn traffic() -> std::sync::MutexGuard<'static, HashMap<TrafficKey, Buckets>> {
+    TRAFFIC
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Records one bridged event.
+///
+/// # Arguments
+///
+/// * `direction` - The pipeline direction, e.g. `n2w`.
+/// * `content_topic` - The Waku content topic, if the sink has one.
+/// * `kind` - The Nostr kind of the event.
+pub fn record_traffic(direction: &str, content_topic: Option<&str>, kind: u16) {
+    metrics()
+        .events_total
+        .with_label_values(&[direction, content_topic.unwrap_or(""), &kind.to_string()])
+        .inc();
+
+    let now = Instant::now();
+    let key = TrafficKey {
+        direction: direction.to_string(),
+        content_topic: content_topic.map(str::to_string),
+        kind,
+    };
+    let mut traffic = traffic();
+    let buckets = traffic.entry(key).or_default();
+    buckets.prune(now, longest_window());
+    buckets.record(now);
+}
+
+/// Returns the traffic counters of every direction, content topic and kind
+/// seen since the process started.
+pub fn traffic_snapshot() -> TrafficSnapshot {
+    let now = Instant::now();
+    let longest = longest_window();
+    let mut traffic = traffic();
+    let mut entries: Vec<TrafficEntry> = traffic
+        .iter_mut()
+        .map(|(key, buckets)| {
+            buckets.prune(now, longest);
+            let total = metrics()
+                .events_total
+                .with_label_values(&[
+                    key.direction.as_str(),
+                    key.content_topic.as_deref().unwrap_or(""),
+                    &key.kind.to_string(),
+                ])
+                .get();
+            let windows = windows()
+                .iter()
+                .map(|secs| {
+                    let count = buckets.count(now, Duration::from_secs(*secs));
+                    (format!("{}s", secs), count)
+                })
+                .collect();
+            TrafficEntry {
+                direction: key.direction.clone(),
+                content_topic: key.content_topic.clone(),
+                kind: key.kind,
+                total,
+                windows,
+            }
+        })
+        .collect();
+    entries.sort_by(|a, b| {
+        (&a.direction, &a.content_topic, a.kind).cmp(&(&b.direction, &b.content_topic, b.kind))
+    });
+
+    TrafficSnapshot {
+        windows_secs: windows().to_vec(),
+        entries,
+    }
+}
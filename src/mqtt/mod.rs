@@ -0,0 +1,3 @@
+mod mqtt;
+
+pub use mqtt::*;
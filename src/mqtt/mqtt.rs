@@ -0,0 +1,177 @@
+//!This module provides an MQTT sink and source for bridged Nostr events, so
+//!deployments can consume and inject ACL updates over MQTT. Events are
+//!exchanged as their JSON encoding.
+
+use crate::common::backoff::Backoff;
+use crate::common::config::MqttConfig;
+use crate::common::correlation::CorrelationId;
+use crate::common::error;
+use crate::common::sink::{EventSink, EventSource};
+use async_trait::async_trait;
+use nostr_sdk::{Event, JsonUtil};
+use rumqttc::{AsyncClient, EventLoop, Incoming, MqttOptions, QoS};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Requests buffered by the client while the connection is down.
+const REQUEST_CAPACITY: usize = 100;
+
+/// Converts the configured QoS level.
+fn qos(level: u8) -> error::Result<QoS> {
+    match level {
+        0 => Ok(QoS::AtMostOnce),
+        1 => Ok(QoS::AtLeastOnce),
+        2 => Ok(QoS::ExactlyOnce),
+        _ => Err(error::Error::InvalidConfig(format!(
+            "mqtt.qos must be 0, 1 or 2, got {}",
+            level
+        ))),
+    }
+}
+
+/// Opens a client connection; `suffix` keeps the client ids of the sink and
+/// the source apart.
+fn connect(config: &MqttConfig, suffix: &str) -> (AsyncClient, EventLoop) {
+    let mut options = MqttOptions::new(
+        format!("{}-{}", config.client_id, suffix),
+        config.host.as_str(),
+        config.port,
+    );
+    options.set_keep_alive(Duration::from_secs(config.keep_alive_secs));
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        options.set_credentials(username, password.expose());
+    }
+    AsyncClient::new(options, REQUEST_CAPACITY)
+}
+
+/// Backoff between reconnects after a connection error.
+fn reconnect_backoff() -> Backoff {
+    Backoff::new(Duration::from_millis(500), Duration::from_secs(30))
+}
+
+/// Polls `eventloop`, which drives the connection of a sink, reconnecting
+/// with backoff after errors.
+async fn drive(mut eventloop: EventLoop) {
+    let mut backoff = reconnect_backoff();
+    loop {
+        match eventloop.poll().await {
+            Ok(_) => backoff.reset(),
+            Err(e) => {
+                let delay = backoff.next().unwrap_or_default();
+                tracing::warn!("mqtt connection error, reconnecting in {:?}: {}", delay, e);
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+/// Publishes bridged events to an MQTT topic.
+pub struct MqttSink {
+    client: AsyncClient,
+    topic: String,
+    qos: QoS,
+}
+
+impl MqttSink {
+    /// Connects to the configured broker and drives the connection in the
+    /// background.
+    pub fn new(config: &MqttConfig) -> error::Result<Self> {
+        let qos = qos(config.qos)?;
+        let (client, eventloop) = connect(config, "sink");
+        tokio::spawn(drive(eventloop));
+
+        Ok(Self {
+            client,
+            topic: config.topic.clone(),
+            qos,
+        })
+    }
+}
+
+#[async_trait]
+impl EventSink for MqttSink {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    async fn send(&self, event: &Event, correlation_id: &CorrelationId) -> error::Result<()> {
+        self.send_to(event, correlation_id, None).await
+    }
+
+    async fn send_to(
+        &self,
+        event: &Event,
+        _correlation_id: &CorrelationId,
+        topic: Option<&str>,
+    ) -> error::Result<()> {
+        self.client
+            .publish(
+                topic.unwrap_or(&self.topic),
+                self.qos,
+                false,
+                event.as_json(),
+            )
+            .await
+            .map_err(|e| error::Error::MqttError(e.to_string()))
+    }
+}
+
+/// Receives events from an MQTT topic.
+pub struct MqttSource {
+    config: MqttConfig,
+    qos: QoS,
+}
+
+impl MqttSource {
+    pub fn new(config: &MqttConfig) -> error::Result<Self> {
+        Ok(Self {
+            qos: qos(config.qos)?,
+            config: config.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl EventSource for MqttSource {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    async fn run(&self, tx: mpsc::Sender<Event>) -> error::Result<()> {
+        let topic = self
+            .config
+            .source_topic
+            .clone()
+            .unwrap_or_else(|| self.config.topic.clone());
+        let (client, mut eventloop) = connect(&self.config, "source");
+        let mut backoff = reconnect_backoff();
+        loop {
+            match eventloop.poll().await {
+                // The session is clean, so subscribe again on every connect.
+                Ok(rumqttc::Event::Incoming(Incoming::ConnAck(_))) => {
+                    backoff.reset();
+                    client
+                        .try_subscribe(&topic, self.qos)
+                        .map_err(|e| error::Error::MqttError(e.to_string()))?;
+                    tracing::info!("consuming events from mqtt topic {}", topic);
+                }
+                Ok(rumqttc::Event::Incoming(Incoming::Publish(publish))) => {
+                    match Event::from_json(&publish.payload) {
+                        Ok(event) => {
+                            if tx.send(event).await.is_err() {
+                                return Ok(());
+                            }
+                        }
+                        Err(e) => tracing::warn!("skipping malformed event from {}: {}", topic, e),
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    let delay = backoff.next().unwrap_or_default();
+                    tracing::warn!("mqtt connection error, reconnecting in {:?}: {}", delay, e);
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,3 @@
+mod nats;
+
+pub use nats::*;
@@ -0,0 +1,79 @@
+//!This module provides a NATS JetStream sink for bridged Nostr events.
+//!Every event is published with its id as `Nats-Msg-Id`, so the stream drops
+//!duplicates of an event re-published after a retry or a restart.
+
+use crate::common::config::{NatsConfig, SinkPayload};
+use crate::common::correlation::{CorrelationId, CORRELATION_HEADER};
+use crate::common::error;
+use crate::common::sink::{self, EventSink};
+use async_nats::jetstream;
+use async_nats::{ConnectOptions, HeaderMap};
+use async_trait::async_trait;
+use nostr_sdk::Event;
+
+/// Header JetStream deduplicates messages on.
+const MSG_ID_HEADER: &str = "Nats-Msg-Id";
+
+/// Publishes bridged events to a JetStream subject.
+pub struct NatsSink {
+    jetstream: jetstream::Context,
+    subject: String,
+    payload: SinkPayload,
+}
+
+impl NatsSink {
+    /// Connects to the configured NATS server.
+    pub async fn connect(config: &NatsConfig) -> error::Result<Self> {
+        let options = match &config.credentials_file {
+            Some(path) => ConnectOptions::with_credentials_file(path)
+                .await
+                .map_err(|e| error::Error::NatsError(format!("credentials {}: {}", path, e)))?,
+            None => ConnectOptions::new(),
+        };
+        let client = options
+            .name("nostr_gateway")
+            .connect(config.url.as_str())
+            .await
+            .map_err(|e| error::Error::NatsError(format!("connecting to {}: {}", config.url, e)))?;
+
+        Ok(Self {
+            jetstream: jetstream::new(client),
+            subject: config.subject.clone(),
+            payload: config.payload.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl EventSink for NatsSink {
+    fn name(&self) -> &'static str {
+        "nats"
+    }
+
+    async fn send(&self, event: &Event, correlation_id: &CorrelationId) -> error::Result<()> {
+        let payload = sink::encode(event, &self.payload)?;
+        let event_id = event.id.to_hex();
+        let mut headers = HeaderMap::new();
+        headers.insert(MSG_ID_HEADER, event_id.as_str());
+        headers.insert(CORRELATION_HEADER, correlation_id.as_str());
+
+        let ack = self
+            .jetstream
+            .publish_with_headers(self.subject.clone(), headers, payload.into())
+            .await
+            .map_err(|e| error::Error::NatsError(e.to_string()))?
+            .await
+            .map_err(|e| error::Error::NatsError(e.to_string()))?;
+        tracing::debug!(
+            sink = "nats",
+            stream = %ack.stream,
+            sequence = ack.sequence,
+            duplicate = ack.duplicate,
+            "published event {} to {}",
+            event_id,
+            self.subject
+        );
+
+        Ok(())
+    }
+}
@@ -2,69 +2,172 @@
 //!a decentralized messaging platform. The `NostrClient` struct enables
 //!convenient management of relays, event filtering, event fetching, and
 //!event publishing.
+//!
+//!The relay websockets are opened by the relay pool of `nostr-sdk`, which
+//!verifies `wss` relays against the system trust store. It takes no TLS
+//!settings, so the configuration is rejected if `nostr.tls` sets any for
+//!`wss` relays, and the other `tls` sections only apply to the HTTP clients.
 
+use crate::common::config::{NostrFilter, ProxyConfig};
+use crate::common::consts;
+use crate::common::correlation::CorrelationId;
 use crate::common::error;
+use crate::common::proxy;
+use crate::common::rate_limit::RateLimiter;
+use crate::common::retry::RetryPolicy;
+use crate::common::sink::EventSink;
+use crate::common::timing::{timed, Operation};
+use async_trait::async_trait;
 use nostr_sdk::prelude::*;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
+use tokio::sync::broadcast;
 
 /// Configuration for event filtering in Nostr.
-/// Includes event kind, tag, and limit for the number of events to fetch.
+/// Includes the filters events must match any of, and the limit for the
+/// number of events to fetch per filter.
 #[derive(Debug, Clone)]
 struct FilterConfig {
-    kind: Kind,   // The kind of Nostr event to filter.
-    tag: String,  // The tag used for filtering events.
-    limit: usize, // Maximum number of events to fetch.
+    filters: Vec<Filter>, // The filters, restricted to the checkpoint when used.
+    limit: usize,         // Maximum number of events to fetch.
 }
 
 impl FilterConfig {
-    /// Creates a new `FilterConfig` with the specified kind, tag, and limit.
-    fn new(k: Kind, t: &str, l: usize) -> Self {
-        Self {
-            kind: k,
-            tag: t.to_string(),
-            limit: l,
-        }
+    /// Creates a new `FilterConfig` with the specified filters and limit.
+    fn new(filters: Vec<Filter>, l: usize) -> Self {
+        Self { filters, limit: l }
+    }
+
+    /// Returns the filters restricted to the events created from `since` on,
+    /// or from their own `since` if later, up to `until` if given, and capped
+    /// at `limit` if given.
+    fn build(&self, since: u64, until: Option<u64>, limit: Option<usize>) -> Vec<Filter> {
+        self.filters
+            .iter()
+            .map(|filter| {
+                let since = filter.since.map_or(since, |own| own.as_u64().max(since));
+                let mut filter = filter.clone().since(since.into());
+                if let Some(until) = until {
+                    let until = filter.until.map_or(until, |own| own.as_u64().min(until));
+                    filter = filter.until(until.into());
+                }
+                match limit {
+                    Some(limit) => filter.limit(limit),
+                    None => filter,
+                }
+            })
+            .collect()
     }
 }
 
 impl Default for FilterConfig {
     fn default() -> Self {
-        /// Provides a default `FilterConfig` with kind as `TextNote`, tag as "waku",
-        /// and a limit of 100 events.
+        /// Provides a default `FilterConfig` matching the text notes tagged
+        /// "waku", with a limit of 100 events.
         Self {
-            kind: Kind::TextNote,
-            tag: "waku".to_string(),
+            filters: vec![default_filter()],
             limit: 100,
         }
     }
 }
 
+/// The filter used when `nostr.filters` is empty: the text notes tagged with
+/// the bridge hashtag.
+fn default_filter() -> Filter {
+    Filter::new()
+        .kind(Kind::TextNote)
+        .hashtag(consts::BRIDGE_HASHTAG)
+}
+
+/// Converts the `nostr.filters` of the configuration to relay filters.
+///
+/// # Returns
+/// The filters, the default one if `filters` is empty, or an
+/// `InvalidConfig` error for an invalid author or time range.
+pub fn build_filters(filters: &[NostrFilter]) -> error::Result<Vec<Filter>> {
+    if filters.is_empty() {
+        return Ok(vec![default_filter()]);
+    }
+    filters
+        .iter()
+        .enumerate()
+        .map(|(index, config)| {
+            let invalid =
+                |e: String| error::Error::InvalidConfig(format!("nostr.filters[{}]: {}", index, e));
+            if config.kinds.is_empty() && config.authors.is_empty() && config.hashtags.is_empty() {
+                return Err(invalid(
+                    "set at least one of kinds, authors or hashtags".to_string(),
+                ));
+            }
+            if let (Some(since), Some(until)) = (config.since, config.until) {
+                if since > until {
+                    return Err(invalid("since is later than until".to_string()));
+                }
+            }
+            let authors = config
+                .authors
+                .iter()
+                .map(|author| {
+                    PublicKey::parse(author).map_err(|e| invalid(format!("{}: {}", author, e)))
+                })
+                .collect::<error::Result<Vec<_>>>()?;
+
+            let mut filter = Filter::new();
+            if !config.kinds.is_empty() {
+                filter = filter.kinds(config.kinds.iter().copied().map(Kind::from));
+            }
+            if !authors.is_empty() {
+                filter = filter.authors(authors);
+            }
+            if !config.hashtags.is_empty() {
+                filter = filter.hashtags(config.hashtags.iter().cloned());
+            }
+            if let Some(since) = config.since {
+                filter = filter.since(since.into());
+            }
+            if let Some(until) = config.until {
+                filter = filter.until(until.into());
+            }
+            Ok(filter)
+        })
+        .collect()
+}
+
 /// A client for interacting with the Nostr protocol.
 /// Provides functionality to manage relays, filter and fetch events, and send events.
 #[derive(Debug)]
 pub struct NostrClient {
-    signer: Keys,         // The cryptographic keys used for signing events.
-    filter: FilterConfig, // Configuration for filtering events.
-    client: Client,       // The underlying Nostr SDK client.
+    signer: Keys,                      // The cryptographic keys used for signing events.
+    filter: RwLock<FilterConfig>,      // Configuration for filtering events, replaced on reloads.
+    client: Client,                    // The underlying Nostr SDK client.
+    retry: RetryPolicy,                // Retry policy applied when publishing events.
+    limiter: Option<Arc<RateLimiter>>, // Rate limit of the publishes, retries included.
 }
 
 impl NostrClient {
-    /// Creates a new `NostrClient` with the provided private key and optional relay URL.
+    /// Creates a new `NostrClient` with the provided private key and relay URLs.
     ///
     /// # Arguments
     /// - `priv_key`: A private key string for the Nostr client.
-    /// - `relay`: An optional relay URL to connect to.
+    /// - `relays`: The relay URLs to connect to, possibly none.
+    /// - `proxy`: Optional proxy settings for the relay websocket connections.
+    /// - `auth`: Whether to answer the NIP-42 AUTH challenges of the relays.
     ///
     /// # Returns
     /// A `Result` containing the initialized `NostrClient` or an error.
-    pub async fn new(priv_key: &str, relay: Option<&str>) -> error::Result<Self> {
+    pub async fn new(
+        priv_key: &str,
+        relays: &[String],
+        proxy: Option<&ProxyConfig>,
+        auth: bool,
+    ) -> error::Result<Self> {
         let keys = Keys::parse(priv_key)?;
-        let opts = Options::new().gossip(true);
+        let opts = Self::client_options(proxy, auth)?;
         let client_builder = Client::builder().signer(keys.clone()).opts(opts);
         let client = client_builder.build();
 
-        if let Some(url) = relay {
-            client.add_relay(url).await?;
+        for url in relays {
+            client.add_relay(url.as_str()).await?;
         }
         client.connect().await;
 
@@ -72,6 +175,8 @@ impl NostrClient {
             signer: keys,
             filter: Default::default(),
             client,
+            retry: Default::default(),
+            limiter: None,
         })
     }
 
@@ -79,26 +184,30 @@ impl NostrClient {
     ///
     /// # Arguments
     /// - `priv_key`: A private key string for the Nostr client.
-    /// - `relay`: An optional relay URL to connect to.
+    /// - `relays`: The relay URLs to connect to, possibly none.
     /// - `db`: A database implementation compatible with the Nostr SDK.
+    /// - `proxy`: Optional proxy settings for the relay websocket connections.
+    /// - `auth`: Whether to answer the NIP-42 AUTH challenges of the relays.
     ///
     /// # Returns
     /// A `Result` containing the initialized `NostrClient` or an error.
     pub async fn new_with_db<T: IntoNostrDatabase>(
         priv_key: &str,
-        relay: Option<&str>,
+        relays: &[String],
         db: T,
+        proxy: Option<&ProxyConfig>,
+        auth: bool,
     ) -> error::Result<Self> {
         let keys = Keys::parse(priv_key)?;
-        let opts = Options::new().gossip(true);
+        let opts = Self::client_options(proxy, auth)?;
         let client_builder = Client::builder()
             .signer(keys.clone())
             .opts(opts)
             .database(db);
         let client = client_builder.build();
 
-        if let Some(url) = relay {
-            client.add_relay(url).await?;
+        for url in relays {
+            client.add_relay(url.as_str()).await?;
         }
         client.connect().await;
 
@@ -106,41 +215,128 @@ impl NostrClient {
             signer: keys,
             filter: Default::default(),
             client,
+            retry: Default::default(),
+            limiter: None,
         })
     }
 
+    /// Builds the nostr-sdk client options, routing relay connections through
+    /// the configured SOCKS5 proxy when present.
+    ///
+    /// With `auth`, the client signs an authentication event (NIP-42) for
+    /// every AUTH challenge, including those sent after a reconnect, and
+    /// resubscribes to the relay once it accepted it. Requests rejected with
+    /// `auth-required` before that are retried by the next fetch round or
+    /// publish attempt.
+    fn client_options(proxy: Option<&ProxyConfig>, auth: bool) -> error::Result<Options> {
+        let opts = Options::new().gossip(true).automatic_authentication(auth);
+        match proxy::nostr_connection(proxy)? {
+            Some(connection) => Ok(opts.connection(connection)),
+            None => Ok(opts),
+        }
+    }
+
     /// Updates the filter configuration for the Nostr client.
     ///
     /// # Arguments
-    /// - `k`: The kind of events to filter.
-    /// - `t`: The tag used for filtering.
-    /// - `l`: The maximum number of events to fetch.
-    pub fn set_filter_config(&mut self, k: Kind, t: &str, l: usize) {
-        self.filter = FilterConfig::new(k, t, l);
+    /// - `filters`: The filters events must match any of, see `build_filters`.
+    /// - `l`: The maximum number of events to fetch per filter.
+    pub fn set_filter_config(&self, filters: Vec<Filter>, l: usize) {
+        *self.filter.write().unwrap_or_else(|e| e.into_inner()) = FilterConfig::new(filters, l);
+    }
+
+    /// Updates the retry policy used when publishing events.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry = policy;
     }
 
-    /// Fetches events from the relay based on the filter configuration.
+    /// Sets the rate limit of the publishes, see `rate_limit`.
+    pub fn set_rate_limit(&mut self, limiter: Option<Arc<RateLimiter>>) {
+        self.limiter = limiter;
+    }
+
+    /// Fetches events from a single relay based on the filter configuration.
+    /// Every filter is sent in the same request.
     ///
     /// # Arguments
+    /// - `relay`: The URL of the relay to fetch from, one of the client's relays.
     /// - `since`: A timestamp specifying the starting point for fetching events.
     ///
     /// # Returns
     /// A `Result` containing the fetched events or an error.
-    pub async fn fetch_from_relay(&self, since: u64) -> error::Result<Events> {
-        let filter = Filter::new()
-            .kind(self.filter.kind)
-            .hashtag(self.filter.tag.clone())
-            .since(since.into())
-            .limit(self.filter.limit);
+    pub async fn fetch_from_relay(&self, relay: &str, since: u64) -> error::Result<Events> {
+        let filter = self.filter();
+        let filters = filter.build(since, None, Some(filter.limit));
+
+        let events = self
+            .client
+            .fetch_events_from(vec![relay], filters, Some(Duration::from_secs(10)))
+            .await?;
+
+        Ok(events)
+    }
+
+    /// Fetches the events of a single relay created between `since` and
+    /// `until`, both included, based on the filter configuration. Relays
+    /// return the newest events first, so at most `limit` of the newest
+    /// events of each filter are returned.
+    ///
+    /// # Returns
+    /// A `Result` containing the fetched events or an error.
+    pub async fn fetch_window_from_relay(
+        &self,
+        relay: &str,
+        since: u64,
+        until: u64,
+    ) -> error::Result<Events> {
+        let filter = self.filter();
+        let filters = filter.build(since, Some(until), Some(filter.limit));
 
         let events = self
             .client
-            .fetch_events(vec![filter], Some(Duration::from_secs(10)))
+            .fetch_events_from(vec![relay], filters, Some(Duration::from_secs(10)))
             .await?;
 
         Ok(events)
     }
 
+    /// Returns the maximum number of events fetched per filter.
+    pub fn fetch_limit(&self) -> usize {
+        self.filter().limit
+    }
+
+    /// Returns the current filter configuration.
+    fn filter(&self) -> FilterConfig {
+        self.filter
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Subscribes on every relay to the events matching the filter
+    /// configuration that are created from `since` on. The events arrive as
+    /// notifications, see `notifications`.
+    ///
+    /// # Returns
+    /// A `Result` containing the id of the subscription or an error.
+    pub async fn subscribe(&self, since: u64) -> error::Result<SubscriptionId> {
+        let filters = self.filter().build(since, None, None);
+
+        Ok(self.client.subscribe(filters, None).await?.val)
+    }
+
+    /// Closes a subscription on every relay.
+    pub async fn unsubscribe(&self, id: SubscriptionId) {
+        self.client.unsubscribe(id).await;
+    }
+
+    /// Returns a receiver of the notifications of the relays, including the
+    /// events of the subscriptions. Only notifications sent after the call
+    /// are received.
+    pub fn notifications(&self) -> broadcast::Receiver<RelayPoolNotification> {
+        self.client.notifications()
+    }
+
     /// Fetches events from the local database based on the filter configuration.
     ///
     /// # Arguments
@@ -149,17 +345,25 @@ impl NostrClient {
     /// # Returns
     /// A `Result` containing the fetched events or an error.
     pub async fn fetch_from_db(&self, since: u64) -> error::Result<Events> {
-        let filter = Filter::new()
-            .kind(self.filter.kind)
-            .hashtag(self.filter.tag.clone())
-            .since(since.into())
-            .limit(self.filter.limit);
+        let filter = self.filter();
+        let filters = filter.build(since, None, Some(filter.limit));
 
-        let events = self.client.database().query(vec![filter]).await?;
+        let events = self.client.database().query(filters).await?;
 
         Ok(events)
     }
 
+    /// Signs an event with the client's keys.
+    ///
+    /// # Arguments
+    /// - `builder`: The unsigned event to sign.
+    ///
+    /// # Returns
+    /// A `Result` containing the signed event or an error.
+    pub fn sign(&self, builder: EventBuilder) -> error::Result<Event> {
+        Ok(builder.sign_with_keys(&self.signer)?)
+    }
+
     /// Sends an event to the Nostr network.
     ///
     /// # Arguments
@@ -168,6 +372,41 @@ impl NostrClient {
     /// # Returns
     /// A `Result` containing the event ID of the sent event or an error.
     pub async fn send_event(&self, event: Event) -> error::Result<EventId> {
-        Ok(self.client.send_event(event).await?.id().to_owned())
+        let client = &self.client;
+        let limiter = self.limiter.as_deref();
+        let event = &event;
+        self.retry
+            .retry("nostr publish", || async move {
+                if let Some(limiter) = limiter {
+                    limiter.acquire().await;
+                }
+                let output = timed(Operation::Sink, client.send_event(event.clone())).await?;
+                Ok(output.id().to_owned())
+            })
+            .await
+    }
+
+    /// Disconnects from every relay.
+    pub async fn disconnect(&self) -> error::Result<()> {
+        self.client.disconnect().await?;
+        Ok(())
+    }
+}
+
+/// Publishes the events of the pipelines reading from an `EventSource` to
+/// the relays.
+#[async_trait]
+impl EventSink for NostrClient {
+    fn name(&self) -> &'static str {
+        "nostr"
+    }
+
+    fn times_attempts(&self) -> bool {
+        true
+    }
+
+    async fn send(&self, event: &Event, _correlation_id: &CorrelationId) -> error::Result<()> {
+        self.send_event(event.clone()).await?;
+        Ok(())
     }
 }
@@ -1,3 +1,5 @@
 mod client;
+mod source;
 
 pub use client::*;
+pub use source::NostrSource;
@@ -0,0 +1,118 @@
+//!This module provides the relays as an `EventSource`, so they feed the same
+//!pipeline engine as the Waku, Kafka and MQTT sources. Events are either
+//!polled or received over a subscription, as selected by `nostr.mode`.
+//!Unlike the fetch loops of the `n2*` directions, the source keeps no
+//!checkpoint: it starts from the given timestamp every time it runs.
+
+use super::NostrClient;
+use crate::common::config::NostrMode;
+use crate::common::error;
+use crate::common::sink::EventSource;
+use async_trait::async_trait;
+use nostr_sdk::{Event, RelayPoolNotification};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+
+/// Receives the events matching the filter of the client from its relays.
+pub struct NostrSource {
+    client: Arc<NostrClient>,
+    relays: Vec<String>,
+    mode: NostrMode,
+    since: u64,
+    poll_interval: Duration,
+}
+
+impl NostrSource {
+    /// Creates a source receiving the events created from `since` on.
+    ///
+    /// # Arguments
+    /// - `relays`: The relays to poll, all of them connected by `client`.
+    /// - `poll_interval`: The delay between two polls, unused when
+    ///   subscribing.
+    pub fn new(
+        client: Arc<NostrClient>,
+        relays: Vec<String>,
+        mode: NostrMode,
+        since: u64,
+        poll_interval: Duration,
+    ) -> Self {
+        Self {
+            client,
+            relays,
+            mode,
+            since,
+            poll_interval,
+        }
+    }
+
+    /// Fetches the events newer than the last one received from every relay,
+    /// until `tx` is closed. The newest second is fetched again, so events
+    /// may be handed over twice; the pipeline skips those already bridged.
+    async fn poll(&self, tx: mpsc::Sender<Event>) -> error::Result<()> {
+        let mut since = self.since;
+        loop {
+            let mut newest = since;
+            for relay in &self.relays {
+                let events = match self.client.fetch_from_relay(relay, since).await {
+                    Ok(events) => events,
+                    Err(e) => {
+                        tracing::warn!("nostr source: fetching from {} failed: {}", relay, e);
+                        continue;
+                    }
+                };
+                for event in events.into_iter() {
+                    newest = newest.max(event.created_at.as_u64());
+                    if tx.send(event).await.is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+            since = newest;
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    /// Hands over the events of a subscription as they are published, until
+    /// `tx` or the relay pool is closed. Notifications missed by lagging
+    /// behind are not fetched again.
+    async fn subscribe(&self, tx: mpsc::Sender<Event>) -> error::Result<()> {
+        let mut notifications = self.client.notifications();
+        let id = self.client.subscribe(self.since).await?;
+        loop {
+            match notifications.recv().await {
+                Ok(RelayPoolNotification::Event {
+                    subscription_id,
+                    event,
+                    ..
+                }) if subscription_id == id => {
+                    if tx.send(*event).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(RelayPoolNotification::Shutdown) | Err(RecvError::Closed) => break,
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!("nostr source: missed {} notifications", missed);
+                }
+            }
+        }
+        self.client.unsubscribe(id).await;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventSource for NostrSource {
+    fn name(&self) -> &'static str {
+        "nostr"
+    }
+
+    async fn run(&self, tx: mpsc::Sender<Event>) -> error::Result<()> {
+        match self.mode {
+            NostrMode::Poll => self.poll(tx).await,
+            NostrMode::Subscribe => self.subscribe(tx).await,
+        }
+    }
+}
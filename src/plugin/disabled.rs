@@ -0,0 +1,46 @@
+//!Stands in for the WASM plugins when the gateway is built without the
+//!`plugins` feature: configuring a plugin is an error and every event goes
+//!through unchanged.
+
+use crate::common::config::PluginConfig;
+use crate::common::error;
+use nostr_sdk::Event;
+
+/// Plugins checked by `PluginChain::prepare`, always none.
+pub struct PreparedPlugins;
+
+/// The plugins of the configuration, always none.
+pub struct PluginChain;
+
+impl PluginChain {
+    /// Rejects any configured plugin.
+    pub fn new(configs: &[PluginConfig]) -> error::Result<Self> {
+        Self.prepare(configs)?;
+        Ok(Self)
+    }
+
+    /// Rejects any configured plugin, see `new`.
+    pub fn prepare(&self, configs: &[PluginConfig]) -> error::Result<PreparedPlugins> {
+        match configs.first() {
+            Some(config) => Err(error::Error::InvalidConfig(format!(
+                "plugins: {} needs the gateway built with the `plugins` feature",
+                config.path
+            ))),
+            None => Ok(PreparedPlugins),
+        }
+    }
+
+    /// Does nothing.
+    ///
+    /// # Returns
+    ///
+    /// The number of loaded plugins, zero.
+    pub fn install(&self, _plugins: PreparedPlugins) -> usize {
+        0
+    }
+
+    /// Returns `event` unchanged.
+    pub fn apply(&self, _direction: &str, event: Event) -> error::Result<Option<Event>> {
+        Ok(Some(event))
+    }
+}
@@ -0,0 +1,9 @@
+#[cfg(not(feature = "plugins"))]
+mod disabled;
+#[cfg(feature = "plugins")]
+mod plugin;
+
+#[cfg(not(feature = "plugins"))]
+pub use self::disabled::*;
+#[cfg(feature = "plugins")]
+pub use self::plugin::*;
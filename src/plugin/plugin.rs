@@ -0,0 +1,150 @@
+//!This module hosts user-supplied WASM plugins that transform or filter
+//!bridged events between the source and the sink of a pipeline.
+//!
+//!A plugin is a module without imports exporting:
+//!
+//!- `memory`, its linear memory.
+//!- `alloc(len: i32) -> i32`, returning the offset of `len` writable bytes.
+//!- `transform(ptr: i32, len: i32) -> i64`, called with the JSON object
+//!  `{"direction": "n2w", "event": {...}}` written at `ptr`. It returns the
+//!  offset of the event JSON to forward in the high 32 bits and its length
+//!  in the low 32 bits, or a length of zero to drop the event. The forwarded
+//!  event should keep the id of the input, which the pipelines dedupe on.
+//!
+//!Every call runs in a fresh instance with a fuel budget, so a plugin can
+//!neither keep state between events nor stall a pipeline. Inbound events
+//!are verified before the plugins run, and the relays reject the events
+//!altered by plugins of `*2n` directions, so those can only filter them.
+
+use crate::common::config::PluginConfig;
+use crate::common::error;
+use nostr_sdk::{Event, JsonUtil};
+use serde_json::json;
+use std::sync::RwLock;
+use wasmtime::{Engine, Linker, Module, Store};
+
+/// A loaded plugin.
+struct Plugin {
+    name: String,
+    module: Module,
+    directions: Vec<String>,
+    fuel: u64,
+}
+
+/// Plugins compiled by `PluginChain::prepare`, not running yet.
+pub struct PreparedPlugins(Vec<Plugin>);
+
+/// The plugins of the configuration, applied in order.
+pub struct PluginChain {
+    engine: Engine,
+    linker: Linker<()>,
+    plugins: RwLock<Vec<Plugin>>,
+}
+
+impl PluginChain {
+    /// Compiles the configured modules.
+    pub fn new(configs: &[PluginConfig]) -> error::Result<Self> {
+        let mut config = wasmtime::Config::new();
+        config.consume_fuel(true);
+        let engine =
+            Engine::new(&config).map_err(|e| error::Error::PluginError(format!("{:#}", e)))?;
+        let plugins = compile(&engine, configs)?;
+
+        Ok(Self {
+            linker: Linker::new(&engine),
+            engine,
+            plugins: RwLock::new(plugins),
+        })
+    }
+
+    /// Compiles the configured modules to replace the running plugins with,
+    /// see `install`. The running plugins are left as they are.
+    pub fn prepare(&self, configs: &[PluginConfig]) -> error::Result<PreparedPlugins> {
+        compile(&self.engine, configs).map(PreparedPlugins)
+    }
+
+    /// Replaces the plugins with prepared ones.
+    ///
+    /// # Returns
+    ///
+    /// The number of loaded plugins.
+    pub fn install(&self, plugins: PreparedPlugins) -> usize {
+        let loaded = plugins.0.len();
+        *self.plugins.write().unwrap_or_else(|e| e.into_inner()) = plugins.0;
+        loaded
+    }
+
+    /// Runs `event` through the plugins of `direction`.
+    ///
+    /// # Returns
+    ///
+    /// The event to forward, or `None` if a plugin dropped it.
+    pub fn apply(&self, direction: &str, mut event: Event) -> error::Result<Option<Event>> {
+        for plugin in self
+            .plugins
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            if !plugin.directions.is_empty() && !plugin.directions.iter().any(|d| d == direction) {
+                continue;
+            }
+            let input = serde_json::to_vec(&json!({
+                "direction": direction,
+                "event": event,
+            }))?;
+            let output = self
+                .call(plugin, &input)
+                .map_err(|e| error::Error::PluginError(format!("{}: {:#}", plugin.name, e)))?;
+            if output.is_empty() {
+                tracing::debug!("plugin {} dropped event {}", plugin.name, event.id);
+                return Ok(None);
+            }
+            event = Event::from_json(&output).map_err(|e| {
+                error::Error::PluginError(format!("{}: invalid event: {}", plugin.name, e))
+            })?;
+        }
+
+        Ok(Some(event))
+    }
+
+    /// Calls `transform` of a fresh instance of `plugin` with `input`.
+    fn call(&self, plugin: &Plugin, input: &[u8]) -> wasmtime::Result<Vec<u8>> {
+        let mut store = Store::new(&self.engine, ());
+        store.set_fuel(plugin.fuel)?;
+        let instance = self.linker.instantiate(&mut store, &plugin.module)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| wasmtime::Error::msg("missing `memory` export"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+        let transform = instance.get_typed_func::<(i32, i32), i64>(&mut store, "transform")?;
+
+        let len = i32::try_from(input.len())?;
+        let ptr = alloc.call(&mut store, len)?;
+        memory.write(&mut store, ptr as u32 as usize, input)?;
+        let packed = transform.call(&mut store, (ptr, len))? as u64;
+
+        let (ptr, len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+        let mut output = vec![0; len];
+        memory.read(&store, ptr, &mut output)?;
+        Ok(output)
+    }
+}
+
+/// Compiles the modules of `configs`.
+fn compile(engine: &Engine, configs: &[PluginConfig]) -> error::Result<Vec<Plugin>> {
+    configs
+        .iter()
+        .map(|config| {
+            let module = Module::from_file(engine, &config.path)
+                .map_err(|e| error::Error::PluginError(format!("{}: {:#}", config.path, e)))?;
+            tracing::info!("loaded plugin {}", config.path);
+            Ok(Plugin {
+                name: config.path.clone(),
+                module,
+                directions: config.directions.clone(),
+                fuel: config.fuel,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,89 @@
+//!This module provides a Redis Streams sink for bridged Nostr events. Every
+//!event is appended with XADD, guarded by a per-event dedupe key, so an event
+//!re-sent after a retry or a restart is only appended once.
+
+use crate::common::config::{RedisConfig, SinkPayload};
+use crate::common::correlation::CorrelationId;
+use crate::common::error;
+use crate::common::sink::{self, EventSink};
+use async_trait::async_trait;
+use nostr_sdk::Event;
+use redis::aio::ConnectionManager;
+use redis::Script;
+
+/// Sets the dedupe key of the event and appends it to the stream, in one
+/// atomic step. Returns the id of the new entry, or nil if the event was
+/// already appended.
+const XADD_ONCE: &str = r"
+if redis.call('SET', KEYS[2], '1', 'NX', 'EX', ARGV[1]) then
+  return redis.call('XADD', KEYS[1], '*', 'event_id', ARGV[2], 'correlation_id', ARGV[3], 'payload', ARGV[4])
+end
+return false
+";
+
+/// Appends bridged events to a Redis stream.
+pub struct RedisSink {
+    connection: ConnectionManager,
+    script: Script,
+    stream: String,
+    dedupe_ttl_secs: u64,
+    payload: SinkPayload,
+}
+
+impl RedisSink {
+    /// Connects to the configured Redis server. The connection is
+    /// re-established automatically after failures.
+    pub async fn connect(config: &RedisConfig) -> error::Result<Self> {
+        let client = redis::Client::open(config.url.as_str())?;
+        let connection = ConnectionManager::new(client).await?;
+
+        Ok(Self {
+            connection,
+            script: Script::new(XADD_ONCE),
+            stream: config.stream.clone(),
+            dedupe_ttl_secs: config.dedupe_ttl_secs,
+            payload: config.payload.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl EventSink for RedisSink {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn send(&self, event: &Event, correlation_id: &CorrelationId) -> error::Result<()> {
+        let payload = sink::encode(event, &self.payload)?;
+        let event_id = event.id.to_hex();
+        let dedupe_key = format!("{}:dedupe:{}", self.stream, event_id);
+
+        let entry: Option<String> = self
+            .script
+            .key(&self.stream)
+            .key(&dedupe_key)
+            .arg(self.dedupe_ttl_secs)
+            .arg(&event_id)
+            .arg(correlation_id.as_str())
+            .arg(payload)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        match entry {
+            Some(entry) => tracing::debug!(
+                sink = "redis",
+                entry,
+                "appended event {} to {}",
+                event_id,
+                self.stream
+            ),
+            None => tracing::debug!(
+                sink = "redis",
+                "event {} already appended to {}",
+                event_id,
+                self.stream
+            ),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+//! Checkpoints advanced as the sender acknowledges the fetched events.
+//!
+//! A fetch round records its new events as pending and queues them, but the
+//! checkpoint of a relay only moves past an event once the sender settled
+//! it: delivered, scheduled for a retry or dead-lettered. The checkpoint to
+//! commit is the one after the longest settled prefix of the queued events
+//! of the relay, so it is never past an event still waiting in the queue,
+//! however long the sink takes. Events dropped by a plugin need no delivery
+//! and are settled as soon as they are seen.
+//!
+//! The next round starts from the checkpoint after the last queued event, so
+//! the events in the queue are not fetched again.
+//!
+//! An event dropped without being settled, e.g. by a sender task that
+//! panicked or when the queue is dropped at shutdown, is settled as well so
+//! the checkpoint does not stall behind it. It is still recorded as pending
+//! in the database and is recovered by the next run.
+
+use crate::metrics;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default)]
+struct Relay {
+    /// Checkpoint after each queued event not settled yet, or settled
+    /// behind one that is not, by ticket.
+    queued: BTreeMap<u64, (u64, bool)>,
+    /// Checkpoint after the last queued event.
+    fetched: Option<u64>,
+    /// Checkpoint after the longest settled prefix of the queued events.
+    settled: Option<u64>,
+    /// Checkpoint last committed to the database.
+    committed: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    relays: HashMap<String, Relay>,
+    /// Ticket of the next queued event, never reused so the tickets of
+    /// events queued before a `reset` are ignored.
+    next: u64,
+    /// Queued events not settled yet.
+    unsettled: usize,
+}
+
+/// The acknowledgment state of the relays of a pipeline.
+#[derive(Debug)]
+pub(super) struct AckTracker {
+    direction: &'static str,
+    state: Mutex<State>,
+}
+
+/// Settles a queued event in its `AckTracker`, see `PipelineEvent::settle`.
+/// Dropping it settles the event too.
+#[derive(Debug)]
+pub(super) struct Ack {
+    tracker: Arc<AckTracker>,
+    relay: String,
+    ticket: u64,
+}
+
+impl AckTracker {
+    pub(super) fn new(direction: &'static str) -> Arc<Self> {
+        Arc::new(Self {
+            direction,
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    /// Returns the checkpoint after the last event queued from `relay`,
+    /// where its next round starts, if any was queued since the last reset.
+    pub(super) fn fetched(&self, relay: &str) -> Option<u64> {
+        self.lock()
+            .relays
+            .get(relay)
+            .and_then(|relay| relay.fetched)
+    }
+
+    /// Tracks an event queued from `relay`, after which the checkpoint is
+    /// `checkpoint`. The checkpoint is committed once the returned `Ack` and
+    /// those of the events queued before it are settled.
+    pub(super) fn track(self: &Arc<Self>, relay: &str, checkpoint: u64) -> Ack {
+        let mut state = self.lock();
+        let ticket = state.next;
+        state.next += 1;
+        let entry = state.relays.entry(relay.to_string()).or_default();
+        entry.queued.insert(ticket, (checkpoint, false));
+        entry.fetched = Some(checkpoint);
+        state.unsettled += 1;
+        self.observe(&state);
+        Ack {
+            tracker: self.clone(),
+            relay: relay.to_string(),
+            ticket,
+        }
+    }
+
+    /// Moves the checkpoint of `relay` to `checkpoint` past an event that
+    /// needs no delivery, once the events queued before it are settled.
+    pub(super) fn skip(&self, relay: &str, checkpoint: u64) {
+        let mut state = self.lock();
+        let ticket = state.next;
+        state.next += 1;
+        let entry = state.relays.entry(relay.to_string()).or_default();
+        entry.queued.insert(ticket, (checkpoint, true));
+        entry.fetched = Some(checkpoint);
+        Self::advance(entry);
+    }
+
+    /// Returns the settled checkpoints not committed yet, by relay.
+    pub(super) fn settled(&self) -> Vec<(String, u64)> {
+        self.lock()
+            .relays
+            .iter()
+            .filter_map(|(relay, entry)| match (entry.settled, entry.committed) {
+                (Some(settled), Some(committed)) if settled <= committed => None,
+                (Some(settled), _) => Some((relay.clone(), settled)),
+                (None, _) => None,
+            })
+            .collect()
+    }
+
+    /// Records that the checkpoint of `relay` was committed.
+    pub(super) fn committed(&self, relay: &str, checkpoint: u64) {
+        if let Some(entry) = self.lock().relays.get_mut(relay) {
+            entry.committed = Some(entry.committed.map_or(checkpoint, |c| c.max(checkpoint)));
+        }
+    }
+
+    /// Forgets every queued event, so the next round starts from the
+    /// committed checkpoints and the events still in the queue no longer
+    /// move them.
+    pub(super) fn reset(&self) {
+        let mut state = self.lock();
+        state.relays.clear();
+        state.unsettled = 0;
+        self.observe(&state);
+    }
+
+    fn settle(&self, relay: &str, ticket: u64) {
+        let mut state = self.lock();
+        let Some(entry) = state.relays.get_mut(relay) else {
+            return;
+        };
+        let Some((_, settled)) = entry.queued.get_mut(&ticket) else {
+            return;
+        };
+        if *settled {
+            return;
+        }
+        *settled = true;
+        Self::advance(entry);
+        state.unsettled -= 1;
+        self.observe(&state);
+    }
+
+    /// Pops the settled prefix of the queued events of a relay.
+    fn advance(entry: &mut Relay) {
+        while let Some(first) = entry.queued.first_entry() {
+            let (checkpoint, settled) = *first.get();
+            if !settled {
+                break;
+            }
+            first.remove();
+            entry.settled = Some(checkpoint);
+        }
+    }
+
+    /// Updates the gauge of the events waiting for their acknowledgment.
+    fn observe(&self, state: &State) {
+        metrics::metrics()
+            .events_unacknowledged
+            .with_label_values(&[self.direction])
+            .set(state.unsettled as f64);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Ack {
+    /// Settles the event. Settling it again does nothing.
+    pub(super) fn settle(&self) {
+        self.tracker.settle(&self.relay, self.ticket);
+    }
+}
+
+impl Drop for Ack {
+    fn drop(&mut self) {
+        self.settle();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settles_the_longest_prefix() {
+        let tracker = AckTracker::new("test");
+        let first = tracker.track("relay", 10);
+        let second = tracker.track("relay", 20);
+        assert_eq!(tracker.fetched("relay"), Some(20));
+
+        second.settle();
+        assert!(tracker.settled().is_empty());
+
+        first.settle();
+        assert_eq!(tracker.settled(), vec![("relay".to_string(), 20)]);
+    }
+
+    #[test]
+    fn skipped_events_wait_for_the_queued_ones() {
+        let tracker = AckTracker::new("test");
+        let queued = tracker.track("relay", 10);
+        tracker.skip("relay", 15);
+        assert!(tracker.settled().is_empty());
+
+        drop(queued);
+        assert_eq!(tracker.settled(), vec![("relay".to_string(), 15)]);
+    }
+
+    #[test]
+    fn relays_settle_independently() {
+        let tracker = AckTracker::new("test");
+        let _pending = tracker.track("a", 10);
+        tracker.track("b", 30).settle();
+
+        assert_eq!(tracker.settled(), vec![("b".to_string(), 30)]);
+    }
+
+    #[test]
+    fn committed_checkpoints_are_not_returned_again() {
+        let tracker = AckTracker::new("test");
+        tracker.track("relay", 10).settle();
+        tracker.committed("relay", 10);
+        assert!(tracker.settled().is_empty());
+
+        tracker.track("relay", 20).settle();
+        assert_eq!(tracker.settled(), vec![("relay".to_string(), 20)]);
+    }
+
+    #[test]
+    fn reset_ignores_events_queued_before() {
+        let tracker = AckTracker::new("test");
+        let stale = tracker.track("relay", 10);
+        tracker.reset();
+        assert_eq!(tracker.fetched("relay"), None);
+
+        stale.settle();
+        assert!(tracker.settled().is_empty());
+    }
+}
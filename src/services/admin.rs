@@ -0,0 +1,210 @@
+//! HTTP admin API of the bridge.
+//!
+//! The API listens on `server.host:server.port` and offers:
+//!
+//! - `GET /log-level`: returns the active log filter.
+//! - `PUT /log-level`: replaces the log filter with the request body, e.g.
+//!   `info,waku=debug`, without restarting the bridge.
+//! - `GET /metrics`: every metric in the Prometheus text format, e.g. the
+//!   fetched, bridged, deduplicated and failed events per direction, the
+//!   checkpoint lag and the queue depth.
+//! - `GET /stats`: bridged event counters per direction, content topic and
+//!   Nostr kind over the configured windows.
+//! - `GET /peers`: the peers of the embedded Waku node as of the last check
+//!   of the peer monitor, `404 Not Found` when the node does not run.
+//! - `GET /breakers`: the state of the circuit breaker of every pipeline
+//!   running with `delivery.circuit_breaker`.
+//! - `GET /events`: a websocket pushing every bridged event, with its
+//!   direction, sink and delivery status, as JSON. The `direction`, `sink`,
+//!   `status` and `kind` query parameters filter the events, each taking a
+//!   comma separated list of values; the client may replace the filter at any
+//!   time by sending it as a JSON object.
+
+use super::feed::{self, BridgedEvent};
+use crate::common::config::ServerConfig;
+use crate::common::error;
+use crate::common::logging;
+use crate::metrics::{self, BreakerStats, PeerStats, TrafficSnapshot};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::Query;
+use axum::http::{header, StatusCode};
+use axum::response::Response;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Serves the admin API until the task is dropped.
+pub struct AdminServer {
+    addr: String,
+}
+
+impl AdminServer {
+    pub fn new(config: &ServerConfig) -> Self {
+        Self {
+            addr: format!("{}:{}", config.host, config.port),
+        }
+    }
+
+    /// Binds the listen address and serves requests.
+    pub async fn run(self) -> error::Result<()> {
+        let listener = TcpListener::bind(&self.addr).await?;
+        tracing::info!("admin api listening on {}", self.addr);
+        axum::serve(listener, router()).await?;
+        Ok(())
+    }
+}
+
+fn router() -> Router {
+    Router::new()
+        .route("/log-level", get(get_log_level).put(put_log_level))
+        .route("/metrics", get(get_metrics))
+        .route("/stats", get(get_stats))
+        .route("/peers", get(get_peers))
+        .route("/breakers", get(get_breakers))
+        .route("/events", get(get_events))
+}
+
+async fn get_log_level() -> (StatusCode, String) {
+    match logging::log_filter() {
+        Some(filter) => (StatusCode::OK, filter),
+        None => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "logging is not initialized".to_string(),
+        ),
+    }
+}
+
+async fn put_log_level(body: String) -> (StatusCode, String) {
+    let directives = body.trim();
+    match logging::set_log_filter(directives) {
+        Ok(()) => (StatusCode::OK, directives.to_string()),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()),
+    }
+}
+
+async fn get_metrics() -> (StatusCode, [(header::HeaderName, &'static str); 1], String) {
+    let content_type = [(header::CONTENT_TYPE, "text/plain; version=0.0.4")];
+    match metrics::render() {
+        Ok(text) => (StatusCode::OK, content_type, text),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            content_type,
+            e.to_string(),
+        ),
+    }
+}
+
+async fn get_stats() -> Json<TrafficSnapshot> {
+    Json(metrics::traffic_snapshot())
+}
+
+async fn get_peers() -> (StatusCode, Json<Option<PeerStats>>) {
+    match metrics::peer_stats() {
+        Some(stats) => (StatusCode::OK, Json(Some(stats))),
+        None => (StatusCode::NOT_FOUND, Json(None)),
+    }
+}
+
+async fn get_breakers() -> Json<Vec<BreakerStats>> {
+    Json(metrics::breaker_stats())
+}
+
+/// Selects the events pushed to a websocket client. Unset fields match
+/// everything.
+#[derive(Debug, Default, Deserialize)]
+struct EventFilter {
+    direction: Option<String>,
+    sink: Option<String>,
+    status: Option<String>,
+    kind: Option<String>,
+}
+
+impl EventFilter {
+    fn matches(&self, bridged: &BridgedEvent) -> bool {
+        let allows = |filter: &Option<String>, value: &str| {
+            filter
+                .as_ref()
+                .map_or(true, |filter| filter.split(',').any(|v| v.trim() == value))
+        };
+        allows(&self.direction, bridged.direction)
+            && allows(&self.sink, &bridged.sink)
+            && allows(&self.status, bridged.status.as_str())
+            && allows(&self.kind, &bridged.event.kind.as_u16().to_string())
+    }
+}
+
+/// A bridged event as pushed to websocket clients.
+#[derive(Serialize)]
+struct EventMessage<'a> {
+    event_id: String,
+    direction: &'a str,
+    sink: &'a str,
+    status: &'a str,
+    correlation_id: &'a str,
+    kind: u16,
+    bridged_at: i64,
+    event: &'a nostr_sdk::Event,
+}
+
+impl<'a> From<&'a BridgedEvent> for EventMessage<'a> {
+    fn from(bridged: &'a BridgedEvent) -> Self {
+        Self {
+            event_id: bridged.event.id.to_hex(),
+            direction: bridged.direction,
+            sink: &bridged.sink,
+            status: bridged.status.as_str(),
+            correlation_id: &bridged.correlation_id,
+            kind: bridged.event.kind.as_u16(),
+            bridged_at: bridged.bridged_at.timestamp(),
+            event: &bridged.event,
+        }
+    }
+}
+
+async fn get_events(ws: WebSocketUpgrade, Query(filter): Query<EventFilter>) -> Response {
+    ws.on_upgrade(move |socket| push_events(socket, filter))
+}
+
+/// Pushes the matching bridged events to `socket` until the client leaves.
+async fn push_events(mut socket: WebSocket, mut filter: EventFilter) {
+    let mut events = feed::subscribe();
+    loop {
+        tokio::select! {
+            received = events.recv() => match received {
+                Ok(bridged) if filter.matches(&bridged) => {
+                    let text = match serde_json::to_string(&EventMessage::from(&bridged)) {
+                        Ok(text) => text,
+                        Err(e) => {
+                            tracing::warn!("failed to encode event {}: {}", bridged.event.id, e);
+                            continue;
+                        }
+                    };
+                    if socket.send(Message::Text(text)).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!("websocket client fell behind, skipped {} events", missed);
+                }
+                Err(RecvError::Closed) => return,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(replacement) => filter = replacement,
+                    Err(e) => {
+                        let error = json!({ "error": format!("invalid filter: {}", e) });
+                        if socket.send(Message::Text(error.to_string())).await.is_err() {
+                            return;
+                        }
+                    }
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
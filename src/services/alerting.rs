@@ -0,0 +1,181 @@
+//! Webhook alerting on sustained failures.
+//!
+//! Alerts are posted as Slack-compatible JSON (`{"text": ...}`) to the
+//! configured webhook. Each alert key (e.g. the error rate of a sink) has a
+//! cooldown so an ongoing incident does not produce an alert storm.
+
+use crate::common::config::AlertingConfig;
+use crate::common::error;
+use crate::common::http::HttpClient;
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// The condition an alert is raised for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertKind {
+    /// The failure ratio of a sink crossed the threshold.
+    ErrorRate,
+    /// A circuit breaker opened.
+    CircuitOpen,
+    /// The dead-letter queue grew beyond the limit.
+    DeadLetterGrowth,
+    /// A pipeline checkpoint was not committed within the SLA.
+    CheckpointLag,
+    /// A supervised task ran out of restarts.
+    TaskFailed,
+}
+
+impl AlertKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AlertKind::ErrorRate => "error_rate",
+            AlertKind::CircuitOpen => "circuit_open",
+            AlertKind::DeadLetterGrowth => "dead_letter_growth",
+            AlertKind::CheckpointLag => "checkpoint_lag",
+            AlertKind::TaskFailed => "task_failed",
+        }
+    }
+}
+
+#[derive(Default)]
+struct AlerterState {
+    /// Delivery outcomes per `direction/sink`, oldest first.
+    outcomes: HashMap<String, VecDeque<(Instant, bool)>>,
+    /// When an alert key last fired.
+    last_fired: HashMap<String, Instant>,
+}
+
+struct Inner {
+    config: AlertingConfig,
+    http: HttpClient,
+    state: Mutex<AlerterState>,
+}
+
+/// Raises alerts through the configured webhook.
+///
+/// Cloning is cheap. A disabled alerter ignores everything.
+#[derive(Clone, Default)]
+pub struct Alerter {
+    inner: Option<Arc<Inner>>,
+}
+
+impl Alerter {
+    pub fn new(config: Option<&AlertingConfig>, http: HttpClient) -> Self {
+        Self {
+            inner: config.map(|config| {
+                Arc::new(Inner {
+                    config: config.clone(),
+                    http,
+                    state: Mutex::new(AlerterState::default()),
+                })
+            }),
+        }
+    }
+
+    /// Records the outcome of a delivery and alerts when the failure ratio
+    /// over the window crosses the threshold.
+    pub async fn record_result(&self, direction: &str, sink: &str, ok: bool) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+
+        let key = format!("{}/{}", direction, sink);
+        let window = Duration::from_secs(inner.config.window_secs);
+        let now = Instant::now();
+
+        let ratio = {
+            let mut state = inner.state.lock().await;
+            let outcomes = state.outcomes.entry(key.clone()).or_default();
+            outcomes.push_back((now, ok));
+            while let Some((at, _)) = outcomes.front() {
+                if now.duration_since(*at) > window {
+                    outcomes.pop_front();
+                } else {
+                    break;
+                }
+            }
+
+            if outcomes.len() < inner.config.min_events {
+                return;
+            }
+            let failures = outcomes.iter().filter(|(_, ok)| !ok).count();
+            failures as f64 / outcomes.len() as f64
+        };
+
+        if ratio >= inner.config.error_rate_threshold {
+            self.notify(
+                AlertKind::ErrorRate,
+                &key,
+                format!(
+                    "{:.0}% of deliveries for {} failed in the last {}s",
+                    ratio * 100.0,
+                    key,
+                    inner.config.window_secs
+                ),
+            )
+            .await;
+        }
+    }
+
+    /// Alerts when the dead-letter queue size exceeds the configured limit.
+    pub async fn check_dead_letters(&self, size: u64) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+
+        if let Some(limit) = inner.config.dead_letter_limit {
+            if size > limit {
+                self.notify(
+                    AlertKind::DeadLetterGrowth,
+                    "dead_letter",
+                    format!("dead-letter queue holds {} events (limit {})", size, limit),
+                )
+                .await;
+            }
+        }
+    }
+
+    /// Sends an alert unless the same `kind`/`key` fired within the cooldown.
+    pub async fn notify(&self, kind: AlertKind, key: &str, message: String) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+
+        let alert_key = format!("{}:{}", kind.as_str(), key);
+        let cooldown = Duration::from_secs(inner.config.cooldown_secs);
+        {
+            let mut state = inner.state.lock().await;
+            if let Some(last) = state.last_fired.get(&alert_key) {
+                if last.elapsed() < cooldown {
+                    return;
+                }
+            }
+            state.last_fired.insert(alert_key.clone(), Instant::now());
+        }
+
+        if let Err(e) = inner.send(kind, &message).await {
+            tracing::error!("failed to send alert {}: {}", alert_key, e);
+        }
+    }
+}
+
+impl Inner {
+    async fn send(&self, kind: AlertKind, message: &str) -> error::Result<()> {
+        tracing::warn!(alert = kind.as_str(), "{}", message);
+        let body = json!({
+            "text": format!("[acl-relay] {}: {}", kind.as_str(), message),
+        });
+        self.http
+            .post_json(
+                "alert webhook",
+                self.config.webhook_url.as_str(),
+                &body,
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+}
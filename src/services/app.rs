@@ -1,38 +1,116 @@
 //! The `App` module manages the application state and provides methods for integrating
 //! with the `nostr` protocol, `waku` protocol, and other external systems like indexdb.
 //! It utilizes asynchronous processing to handle communication between different systems.
-use crate::common::config::Config;
-use crate::common::error;
+use super::acks::{Ack, AckTracker};
+use super::feed::{self, BridgedEvent};
+use super::{
+    control, retry, shutdown, spawn_supervised, watch_sighup, AdminServer, Alerter, AuditLog,
+    AuditRecord, CheckpointClock, CircuitBreaker, ControlServer, HealthServer, Heartbeat,
+    LagMonitor, Reloader, WakuStore,
+};
+#[cfg(feature = "anchor")]
+use crate::anchor::Anchorer;
+#[cfg(feature = "archive")]
+use crate::archive::Archiver;
+use crate::common::backoff::Backoff;
+#[cfg(feature = "gossipsub")]
+use crate::common::config::WakuTransport;
+use crate::common::config::{Config, IndexdbSink, NostrMode};
+use crate::common::correlation::CorrelationId;
+use crate::common::error::{self, ResultExt};
+use crate::common::http::HttpClient;
+use crate::common::rate_limit::RateLimiter;
+use crate::common::sink::{self, EventSink, EventSource, EventStream, FanOutSink};
+use crate::common::timing::{self, timed, Operation};
+use crate::common::{error_reporting, logging, systemd, validation};
 use crate::db;
+use crate::db::entities::prelude::DeadLetterActiveModel;
+#[cfg(feature = "graphql")]
+use crate::graphql::GraphqlServer;
+#[cfg(feature = "grpc")]
+use crate::grpc::GrpcServer;
+#[cfg(feature = "indexdb")]
+use crate::indexdb;
+use crate::ipfs;
+#[cfg(feature = "kafka")]
+use crate::kafka;
+use crate::matrix;
+use crate::metrics;
+#[cfg(feature = "mqtt")]
+use crate::mqtt;
+#[cfg(feature = "nats")]
+use crate::nats;
 use crate::nostr;
+use crate::plugin::PluginChain;
+#[cfg(feature = "redis")]
+use crate::redis;
+#[cfg(feature = "smtp")]
+use crate::smtp;
+#[cfg(feature = "sqs")]
+use crate::sqs;
+use crate::transform::TransformChain;
 use crate::waku;
-use crate::indexdb;
-use base64;
-use reqwest::{
-    header::{HeaderMap, HeaderValue, CONTENT_TYPE},
-    Client,
-};
+use crate::webhook;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use nostr_sdk::{JsonUtil, RelayPoolNotification, Timestamp};
+use sea_orm::Set;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use std::collections::HashSet;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::mpsc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{mpsc, watch, Mutex};
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::Instrument;
 
 /// The `App` struct holds the application state, including configurations, database storage,
 /// and clients for external protocols like `nostr`, `waku`, and HTTP.
 pub struct App {
     /// Database storage for managing application data.
     store: db::Storage,
+    /// Dedupe and checkpoint writes of the fetch loops, buffered in memory
+    /// during database outages.
+    pipeline_store: db::BufferedStorage,
     /// Application configuration containing settings for various integrations.
     config: Config,
     /// Client for interacting with the `nostr` protocol.
     nostr_client: Arc<nostr::NostrClient>,
-    /// Client for interacting with the `waku` protocol.
-    waku_client: Arc<waku::WakuClient>,
+    /// Client for interacting with the `waku` protocol, unset in simulated
+    /// runs.
+    #[cfg(feature = "waku-ffi")]
+    waku_client: Option<Arc<waku::WakuClient>>,
     /// HTTP client for sending data to external APIs, such as `indexdb`.
+    #[cfg(feature = "indexdb")]
     indexdb_client: Arc<indexdb::IndexdbServer>,
+    /// Gossipsub transport, started by the first direction using it.
+    #[cfg(feature = "gossipsub")]
+    gossipsub: std::sync::Mutex<Option<Arc<waku::Gossipsub>>>,
+    /// IPFS node large payloads are offloaded to, when configured.
+    ipfs: Option<Arc<ipfs::IpfsStore>>,
+    /// Seals the Waku payloads when `waku.encryption` is set.
+    waku_cipher: Option<Arc<waku::PayloadCipher>>,
+    /// Rate limit of `waku.rate_limit`, shared by every Waku REST request.
+    waku_limiter: Option<Arc<RateLimiter>>,
+    /// WASM plugins run on every event between source and sink.
+    plugins: Arc<PluginChain>,
+    /// Configured rules run on every event after the plugins.
+    transforms: Arc<TransformChain>,
+    /// Applies reloaded configurations to the pipelines.
+    reloader: Arc<Reloader>,
+    /// Append-only audit trail of bridged events.
+    audit: AuditLog,
+    /// Webhook alerting on sustained failures.
+    alerter: Alerter,
 }
 
+/// Upper bound of the extra delay after consecutive failed fetch rounds.
+const FETCH_MAX_BACKOFF: Duration = Duration::from_secs(120);
+/// Interval between two checks of a queue above `sync.high_watermark`.
+const BACKPRESSURE_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
 /// Represents a message sent through the `waku` protocol.
 /// Contains the payload data and content topic.
 #[derive(Debug, Serialize, Deserialize)]
@@ -43,6 +121,373 @@ pub struct WakuMessage {
     content_topic: String,
 }
 
+/// An event handed from a fetch loop to its sender task.
+pub(super) struct PipelineEvent {
+    /// The fetched nostr event.
+    pub(super) event: nostr_sdk::Event,
+    /// The direction of the pipeline the event travels through.
+    pub(super) direction: &'static str,
+    /// Correlation id assigned when the event entered the pipeline.
+    pub(super) correlation_id: CorrelationId,
+    /// Span covering the event from fetch to delivery.
+    span: tracing::Span,
+    /// Wall clock time at which the event entered the pipeline.
+    received_at: DateTime<Utc>,
+    /// Moves the checkpoint past the event once settled, for fetched events.
+    ack: Option<Ack>,
+}
+
+impl PipelineEvent {
+    /// Assigns a correlation id to a freshly fetched event and opens its span.
+    fn new(event: nostr_sdk::Event, direction: &'static str) -> Self {
+        Self::recovered(event, direction, CorrelationId::new())
+    }
+
+    /// Re-opens an event left pending by a previous run, keeping the
+    /// correlation id it was recorded with.
+    pub(super) fn recovered(
+        event: nostr_sdk::Event,
+        direction: &'static str,
+        correlation_id: CorrelationId,
+    ) -> Self {
+        let span = tracing::info_span!(
+            "bridge_event",
+            direction = direction,
+            event_id = %event.id,
+            correlation_id = %correlation_id,
+            relay = tracing::field::Empty
+        );
+        Self {
+            event,
+            direction,
+            correlation_id,
+            span,
+            received_at: Utc::now(),
+            ack: None,
+        }
+    }
+
+    /// Acknowledges that the sender is done with the event: delivered,
+    /// scheduled for a retry or dead-lettered. See `AckTracker`.
+    fn settle(&self) {
+        if let Some(ack) = &self.ack {
+            ack.settle();
+        }
+    }
+
+    /// Builds the record marking this event as pending in the database.
+    fn record(&self) -> db::NewEvent {
+        db::NewEvent {
+            event_id: self.event.id.into(),
+            correlation_id: self.correlation_id.to_string(),
+            direction: self.direction.to_string(),
+            payload: self.event.as_json(),
+        }
+    }
+
+    /// Builds the audit record of a delivery attempt of this event.
+    fn audit_record(&self, source: &str, sink: &str, result: &error::Result<()>) -> AuditRecord {
+        AuditRecord::new(
+            self.event.id.to_hex(),
+            self.correlation_id.to_string(),
+            self.direction,
+            source,
+            sink,
+            self.event.created_at.as_u64(),
+            self.received_at,
+            result,
+        )
+    }
+}
+
+/// The retry queue of a source pipeline, see `App::from_source_to_sink`.
+struct SourceRetries {
+    /// Re-queues a failed event if its retry cannot be scheduled.
+    requeue: mpsc::Sender<PipelineEvent>,
+    /// The due retries, and the pending events of a previous run.
+    due: mpsc::Receiver<PipelineEvent>,
+}
+
+/// The outcome of checking a fetched event before it is queued.
+enum Admission {
+    /// The event was already received.
+    Duplicate,
+    /// A plugin dropped the event.
+    Dropped,
+    /// The event is new and to be delivered.
+    Queue(PipelineEvent),
+}
+
+/// Stores an event whose delivery failed permanently or ran out of retries,
+/// so it can be inspected and replayed, and checks the dead-letter alert.
+async fn dead_letter(
+    store: &db::Storage,
+    alerter: &Alerter,
+    item: &PipelineEvent,
+    sink: &str,
+    error: &error::Error,
+) {
+    let record = DeadLetterActiveModel {
+        event_id: Set(item.event.id.to_hex()),
+        correlation_id: Set(item.correlation_id.to_string()),
+        direction: Set(item.direction.to_string()),
+        sink: Set(sink.to_string()),
+        payload: Set(item.event.as_json()),
+        error: Set(error.to_string()),
+        created_at: Set(Utc::now().into()),
+        ..Default::default()
+    };
+    if let Err(e) = store.add_dead_letter(record).await {
+        metrics::record_error(item.direction, "db", &e);
+        logging::error_deduped(
+            &format!("dead_letter:{}", e.class()),
+            format_args!("failed to dead-letter event {}: {}", item.event.id, e),
+        );
+        return;
+    }
+    tracing::warn!(
+        sink,
+        "event {} moved to the dead-letter queue",
+        item.event.id
+    );
+    set_delivery_status(store, item, sink, db::DeliveryStatus::DeadLettered).await;
+
+    match store.count_dead_letters().await {
+        Ok(size) => alerter.check_dead_letters(size).await,
+        Err(e) => tracing::warn!("failed to count dead letters: {}", e),
+    }
+}
+
+/// Counts a delivery attempt of `item` and returns the number of attempts
+/// made so far. An item that already used up its attempts, e.g. because it
+/// crashed the sender every time, is dead-lettered instead and `None` is
+/// returned.
+async fn start_attempt(
+    store: &db::Storage,
+    alerter: &Alerter,
+    item: &PipelineEvent,
+    sink: &str,
+    max_attempts: u32,
+) -> Option<u32> {
+    let attempts = match store
+        .record_attempt(item.direction, &item.event.id.to_hex())
+        .await
+    {
+        Ok(attempts) => attempts,
+        Err(e) => {
+            metrics::record_error(item.direction, "db", &e);
+            logging::error_deduped(
+                &format!("delivery_attempt:{}", e.class()),
+                format_args!("failed to count attempt of event {}: {}", item.event.id, e),
+            );
+            1
+        }
+    };
+    if attempts > max_attempts {
+        let e =
+            error::Error::CustomError(format!("gave up after {} delivery attempts", max_attempts));
+        dead_letter(store, alerter, item, sink, &e).await;
+        return None;
+    }
+    Some(attempts)
+}
+
+/// Settles a failed delivery attempt. A transient failure with attempts left
+/// schedules a retry after the `backoff` delay of the attempt, or re-queues
+/// the event behind the pending ones if the retry queue is unavailable;
+/// anything else moves it to the dead-letter queue.
+#[allow(clippy::too_many_arguments)]
+async fn settle_failure(
+    store: &db::Storage,
+    alerter: &Alerter,
+    requeue: &mpsc::Sender<PipelineEvent>,
+    backoff: &Backoff,
+    item: PipelineEvent,
+    attempts: u32,
+    max_attempts: u32,
+    sink: &str,
+    error: &error::Error,
+) {
+    metrics::metrics()
+        .events_failed_total
+        .with_label_values(&[item.direction])
+        .inc();
+    let item = if error.is_transient() && attempts < max_attempts {
+        let delay = backoff.delay(attempts);
+        match retry::schedule(store, &item, sink, attempts, delay, error).await {
+            Ok(()) => {
+                tracing::warn!(
+                    sink,
+                    attempts,
+                    "retrying event {} in {:?} after a failed delivery",
+                    item.event.id,
+                    delay
+                );
+                item.settle();
+                return;
+            }
+            Err(e) => {
+                metrics::record_error(item.direction, "db", &e);
+                tracing::warn!(
+                    sink,
+                    attempts,
+                    "re-queueing event {} after a failed delivery, scheduling a retry failed: {}",
+                    item.event.id,
+                    e
+                );
+            }
+        }
+        match requeue.try_send(item) {
+            Ok(()) => return,
+            Err(e) => e.into_inner(),
+        }
+    } else {
+        item
+    };
+    dead_letter(store, alerter, &item, sink, error).await;
+    item.settle();
+}
+
+/// Buffers an item dequeued while the circuit breaker of its sink is open
+/// in the retry queue, due when the next probe is. The attempts recorded so
+/// far are kept, as the sink was not called. Returns the item back if the
+/// retry queue is unavailable.
+async fn hold_back(
+    store: &db::Storage,
+    item: PipelineEvent,
+    sink: &'static str,
+    delay: Duration,
+) -> Option<PipelineEvent> {
+    let attempts = match store
+        .direction_event_state(item.direction, &item.event.id.to_hex())
+        .await
+    {
+        Ok(state) => state.map_or(0, |state| state.attempts.max(0) as u32),
+        Err(_) => 0,
+    };
+    let error = error::Error::CircuitOpen(sink);
+    match retry::schedule(store, &item, sink, attempts, delay, &error).await {
+        Ok(()) => {
+            tracing::debug!(
+                sink,
+                "holding back event {} for {:?} while the circuit breaker is open",
+                item.event.id,
+                delay
+            );
+            item.settle();
+            None
+        }
+        Err(e) => {
+            metrics::record_error(item.direction, "db", &e);
+            tracing::warn!(
+                sink,
+                "holding back event {} failed, waiting for the circuit breaker: {}",
+                item.event.id,
+                e
+            );
+            Some(item)
+        }
+    }
+}
+
+/// Records the delivery outcome of an event and publishes it to the feed of
+/// bridged events. A failure only means the event is checked again on the
+/// next startup, so it is logged and not propagated.
+async fn set_delivery_status(
+    store: &db::Storage,
+    item: &PipelineEvent,
+    sink: &str,
+    status: db::DeliveryStatus,
+) {
+    match store
+        .set_delivery_status(item.direction, &item.event.id.to_hex(), status)
+        .await
+    {
+        Ok(true) => feed::publish(BridgedEvent {
+            event: item.event.clone(),
+            direction: item.direction,
+            sink: sink.to_string(),
+            status,
+            correlation_id: item.correlation_id.to_string(),
+            bridged_at: Utc::now(),
+        }),
+        Ok(false) => {}
+        Err(e) => {
+            metrics::record_error(item.direction, "db", &e);
+            logging::error_deduped(
+                &format!("delivery_status:{}", e.class()),
+                format_args!(
+                    "failed to mark event {} as {}: {}",
+                    item.event.id,
+                    status.as_str(),
+                    e
+                ),
+            );
+        }
+    }
+}
+
+/// The error of a configuration using `what`, which needs the gateway built
+/// with `feature`.
+#[cfg(not(all(
+    feature = "kafka",
+    feature = "mqtt",
+    feature = "nats",
+    feature = "redis",
+    feature = "smtp",
+    feature = "sqs"
+)))]
+fn needs_feature(what: &str, feature: &str) -> error::Error {
+    error::Error::InvalidConfig(format!(
+        "{} needs the gateway built with the `{}` feature",
+        what, feature
+    ))
+}
+
+/// What follows a page of a relay fetch, see `App::fetch_pages`.
+#[derive(Debug, PartialEq)]
+enum NextPage {
+    /// The page was not full, every event was fetched.
+    Done,
+    /// The page was full, the older events are fetched up to this time.
+    Until(u64),
+    /// The page was full of events created at this time, which no cursor
+    /// can page through.
+    Stuck(u64),
+}
+
+/// Returns what follows `page`, fetched up to `until` with `limit`.
+fn next_page(page: &[nostr_sdk::Event], limit: usize, until: Option<u64>) -> NextPage {
+    let oldest = page.iter().map(|event| event.created_at.as_u64()).min();
+    match oldest {
+        Some(oldest) if page.len() >= limit && Some(oldest) == until => NextPage::Stuck(oldest),
+        Some(oldest) if page.len() >= limit => NextPage::Until(oldest),
+        _ => NextPage::Done,
+    }
+}
+
+/// Returns whether the id and the signature of `event`, received by
+/// `direction`, are valid. Invalid events are counted and logged.
+fn is_authentic(direction: &str, event: &nostr_sdk::Event) -> bool {
+    let Err(e) = event.verify() else {
+        return true;
+    };
+    metrics::metrics()
+        .events_invalid_total
+        .with_label_values(&[direction])
+        .inc();
+    tracing::warn!("{}: dropping invalid event {}: {}", direction, event.id, e);
+    false
+}
+
+/// Updates the queue depth gauge of a sender.
+fn observe_queue_depth(direction: &str, depth: usize) {
+    metrics::metrics()
+        .queue_depth
+        .with_label_values(&[direction])
+        .set(depth as f64);
+}
+
 impl App {
     /// Creates a new instance of the `App` with the given configuration.
     ///
@@ -54,169 +499,2009 @@ impl App {
     ///
     /// An `App` instance wrapped in a `Result`.
     pub async fn new(config: Config) -> error::Result<App> {
+        validation::validate(&config)?;
+
         // Initialize database storage.
-        let store = db::Storage::new(config.database.clone()).await;
+        let store = db::Storage::connect(config.database.clone())
+            .await
+            .context(|| "connecting to the database".to_string())?;
+
+        Self::open(config, store).await
+    }
+
+    /// Creates an `App` on an already connected `store`, starting the
+    /// embedded Waku node. The configuration must have been validated.
+    pub(super) async fn open(config: Config, store: db::Storage) -> error::Result<App> {
+        #[allow(unused_mut)]
+        let mut app = Self::with_store(config, store).await?;
+
+        // Initialize the waku client.
+        #[cfg(feature = "waku-ffi")]
+        {
+            let wclient = waku::WakuClient::new(app.config.waku.clone())
+                .await
+                .map_err(|e| error::Error::CustomError(format!("starting the waku node: {}", e)))?;
+            app.waku_client = Some(Arc::new(wclient));
+        }
+
+        Ok(app)
+    }
+
+    /// Creates an `App` on `store` without starting the embedded Waku node,
+    /// as used by simulated runs.
+    pub async fn with_store(config: Config, store: db::Storage) -> error::Result<App> {
+        timing::set_thresholds(config.slow_ops.clone());
+        timing::set_timeouts(config.timeouts.clone());
+        metrics::set_traffic_windows(config.stats.windows_secs.clone());
 
         // Initialize the nostr client.
-        let nclient = nostr::NostrClient::new(
-            config.nostr.priv_key.as_str(),
-            Some(config.nostr.ws_url.as_str()),
+        let mut nclient = nostr::NostrClient::new(
+            config.nostr.priv_key.expose(),
+            &config.nostr.relays(),
+            config.proxy.as_ref(),
+            config.nostr.auth,
         )
         .await?;
+        nclient.set_retry_policy(config.retry_policy(config.nostr.retry.as_ref()));
+        nclient.set_rate_limit(
+            RateLimiter::from_config("nostr", config.nostr.rate_limit.as_ref()).map(Arc::new),
+        );
+        nclient.set_filter_config(
+            nostr::build_filters(&config.nostr.filters)?,
+            config.sync.batch_limit,
+        );
 
-        // Initialize the waku client.
-        let wclient = waku::WakuClient::new(config.waku.clone()).await.unwrap();
+        // Open the audit trail.
+        let audit = AuditLog::new(config.audit.as_ref(), &store).await?;
+
+        // Initialize alerting.
+        let alerter = Alerter::new(
+            config.alerting.as_ref(),
+            HttpClient::new(
+                &config.http,
+                config.proxy.as_ref(),
+                None,
+                config.retry_policy(None),
+            )?,
+        );
+
+        // Connect to the IPFS node large payloads are offloaded to.
+        let ipfs = match &config.ipfs {
+            Some(ipfs) => Some(Arc::new(ipfs::IpfsStore::new(
+                ipfs,
+                HttpClient::new(
+                    &config.http,
+                    config.proxy.as_ref(),
+                    ipfs.tls.as_ref(),
+                    config.retry_policy(ipfs.retry.as_ref()),
+                )?,
+            ))),
+            None => None,
+        };
+
+        // Load the key of the Waku payload encryption.
+        let waku_cipher = match &config.waku.encryption {
+            Some(encryption) => Some(Arc::new(waku::PayloadCipher::new(encryption)?)),
+            None => None,
+        };
+
+        // Load the plugins.
+        let plugins = Arc::new(PluginChain::new(&config.plugins)?);
+        let transforms = Arc::new(TransformChain::new(&config.transforms)?);
+        let nostr_client = Arc::new(nclient);
+        let reloader = Arc::new(Reloader::new(
+            &config,
+            nostr_client.clone(),
+            plugins.clone(),
+            transforms.clone(),
+        ));
 
         // Return the app instance.
         Ok(App {
+            pipeline_store: db::BufferedStorage::new(
+                store.clone(),
+                config.database.fallback_buffer,
+            ),
             store,
-            config:config.clone(),
-            nostr_client: Arc::new(nclient),
-            waku_client: Arc::new(wclient),
-            indexdb_client: Arc::new(indexdb::IndexdbServer::new()),
+            config: config.clone(),
+            nostr_client,
+            #[cfg(feature = "waku-ffi")]
+            waku_client: None,
+            #[cfg(feature = "indexdb")]
+            indexdb_client: Arc::new(
+                indexdb::IndexdbServer::new(
+                    HttpClient::new(
+                        &config.http,
+                        config.proxy.as_ref(),
+                        config.indexdb_backend.tls.as_ref(),
+                        config.retry_policy(config.indexdb_backend.retry.as_ref()),
+                    )?
+                    .with_rate_limit(
+                        RateLimiter::from_config(
+                            "indexdb",
+                            config.indexdb_backend.rate_limit.as_ref(),
+                        )
+                        .map(Arc::new),
+                    )
+                    .with_timing(Operation::Indexdb),
+                )
+                .with_ipfs(ipfs.clone())
+                .with_format(config.indexdb_backend.format.clone(), &config.nostr.ws_url),
+            ),
+            #[cfg(feature = "gossipsub")]
+            gossipsub: std::sync::Mutex::new(None),
+            ipfs,
+            waku_cipher,
+            waku_limiter: RateLimiter::from_config("waku", config.waku.rate_limit.as_ref())
+                .map(Arc::new),
+            plugins,
+            transforms,
+            reloader,
+            audit,
+            alerter,
         })
     }
 
+    /// Returns the configuration the app was created with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Returns the client of the relays.
+    pub fn nostr_client(&self) -> Arc<nostr::NostrClient> {
+        self.nostr_client.clone()
+    }
+
+    /// Builds the HTTP client of the Waku REST API, with the timeout of
+    /// `waku.timeout_secs` if set and the shared rate limit.
+    fn waku_http_client(&self) -> error::Result<HttpClient> {
+        let mut http = self.config.http.clone();
+        if let Some(timeout_secs) = self.config.waku.timeout_secs {
+            http.timeout_secs = timeout_secs;
+        }
+        Ok(HttpClient::new(
+            &http,
+            self.config.proxy.as_ref(),
+            self.config.waku.tls.as_ref(),
+            self.config.retry_policy(self.config.waku.retry.as_ref()),
+        )?
+        .with_rate_limit(self.waku_limiter.clone())
+        .with_timing(Operation::Waku))
+    }
+
+    /// Builds the client publishing to `waku.send_api`.
+    fn waku_rest_client(&self) -> error::Result<waku::WakuRestClient> {
+        Ok(waku::WakuRestClient::new(
+            &self.config.waku,
+            self.waku_http_client()?,
+        ))
+    }
+
+    /// Returns the backoff between the delivery attempts of an event.
+    fn delivery_backoff(&self) -> Backoff {
+        Backoff::new(
+            Duration::from_millis(self.config.delivery.retry_base_delay_ms),
+            Duration::from_millis(self.config.delivery.retry_max_delay_ms),
+        )
+    }
+
+    /// Starts the gossipsub transport selected by `waku.transport`.
+    ///
+    /// The swarm is started once and shared by both directions.
+    #[cfg(feature = "gossipsub")]
+    fn gossipsub(&self) -> error::Result<Arc<waku::Gossipsub>> {
+        let mut shared = self.gossipsub.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(gossipsub) = shared.as_ref() {
+            return Ok(gossipsub.clone());
+        }
+        let gossipsub = Arc::new(
+            waku::Gossipsub::start(&self.config.waku, &self.config.waku.gossipsub)?
+                .with_ipfs(self.ipfs.clone())
+                .with_cipher(self.waku_cipher.clone()),
+        );
+        *shared = Some(gossipsub.clone());
+        Ok(gossipsub)
+    }
+
+    /// Cleans up after the pipelines stopped on shutdown: flushes the
+    /// buffered dedupe records and checkpoints, disconnects from the relays
+    /// and stops the gossipsub transport. The embedded Waku node stops with
+    /// the process.
+    pub async fn shutdown(&self) {
+        if let Err(e) = self.pipeline_store.flush_pending().await {
+            tracing::error!("flushing the last checkpoint failed: {}", e);
+        }
+        if let Err(e) = self.nostr_client.disconnect().await {
+            tracing::warn!("disconnecting from the relays failed: {}", e);
+        }
+        #[cfg(feature = "gossipsub")]
+        if let Some(gossipsub) = self
+            .gossipsub
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+        {
+            gossipsub.stop();
+        }
+        tracing::info!("shutdown complete");
+    }
+
+    /// Starts publishing heartbeats in the background if they are configured.
+    pub fn start_heartbeat(&self) -> error::Result<()> {
+        let Some(config) = self.config.heartbeat.clone() else {
+            return Ok(());
+        };
+
+        let heartbeat = Heartbeat::new(
+            config,
+            self.store.clone(),
+            self.nostr_client.clone(),
+            self.waku_rest_client()?,
+        );
+        error_reporting::spawn_reported("heartbeat", "waku", heartbeat.run());
+        Ok(())
+    }
+
+    /// Starts archiving bridged events in the background if it is
+    /// configured, which the validation only allows with the `archive`
+    /// feature.
+    pub fn start_archiver(&self) -> error::Result<()> {
+        #[cfg(feature = "archive")]
+        if let Some(config) = self.config.archive.clone() {
+            let archiver = Archiver::new(config, self.store.clone())?;
+            error_reporting::spawn_reported("archive", "object_store", archiver.run());
+        }
+        Ok(())
+    }
+
+    /// Starts anchoring bridged events on chain in the background if it is
+    /// configured, which the validation only allows with the `anchor`
+    /// feature.
+    pub fn start_anchorer(&self) -> error::Result<()> {
+        #[cfg(feature = "anchor")]
+        if let Some(config) = &self.config.anchor {
+            let anchorer = Anchorer::new(config, self.store.clone())?;
+            error_reporting::spawn_reported("anchor", "anchor", anchorer.run());
+        }
+        Ok(())
+    }
+
+    /// Starts checking the database checkpoint of `direction` in the
+    /// background if the checkpoint alarm is configured.
+    pub fn start_lag_monitor(&self, direction: &'static str) {
+        let Some(config) = self.config.checkpoint_alarm.clone() else {
+            return;
+        };
+
+        let monitor = LagMonitor::new(direction, config, self.store.clone(), self.alerter.clone());
+        error_reporting::spawn_reported(direction, "database", monitor.run());
+    }
+
+    /// Starts monitoring the peers of the embedded Waku node in the
+    /// background, if the node runs.
+    pub fn start_peer_monitor(&self) {
+        #[cfg(feature = "waku-ffi")]
+        if let Some(wclient) = self.waku_client.clone() {
+            let monitor = super::PeerMonitor::new(wclient, self.config.waku.peers.clone());
+            error_reporting::spawn_reported("peers", "waku", monitor.run());
+        }
+    }
+
+    /// Starts the admin API in the background.
+    pub fn start_admin(&self) {
+        let admin = AdminServer::new(&self.config.server);
+        error_reporting::spawn_reported("admin", "http", async move {
+            if let Err(e) = admin.run().await {
+                tracing::error!("admin api stopped: {}", e);
+            }
+        });
+    }
+
+    /// Starts the gRPC API in the background if a port is configured, which
+    /// the validation only allows with the `grpc` feature.
+    pub fn start_grpc(&self) {
+        #[cfg(feature = "grpc")]
+        if let Some(port) = &self.config.server.grpc_port {
+            let grpc = GrpcServer::new(
+                &self.config.server.host,
+                port,
+                self.store.clone(),
+                self.nostr_client.clone(),
+            );
+            error_reporting::spawn_reported("grpc", "grpc", async move {
+                if let Err(e) = grpc.run().await {
+                    tracing::error!("grpc api stopped: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Starts the GraphQL API in the background if a port is configured,
+    /// which the validation only allows with the `graphql` feature.
+    pub fn start_graphql(&self) {
+        #[cfg(feature = "graphql")]
+        if let Some(port) = &self.config.server.graphql_port {
+            let graphql = GraphqlServer::new(&self.config.server.host, port, self.store.clone());
+            error_reporting::spawn_reported("graphql", "http", async move {
+                if let Err(e) = graphql.run().await {
+                    tracing::error!("graphql api stopped: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Starts the health check endpoints in the background if a port is
+    /// configured.
+    pub fn start_health(&self, directions: &[&str]) {
+        let Some(port) = &self.config.server.health_port else {
+            return;
+        };
+        let health = HealthServer::new(&self.config, port, self.store.clone(), directions);
+        error_reporting::spawn_reported("health", "http", async move {
+            if let Err(e) = health.run().await {
+                tracing::error!("health checks stopped: {}", e);
+            }
+        });
+    }
+
+    /// Starts the control socket in the background if a path is configured.
+    pub fn start_control(&self, config_file: PathBuf, profile: Option<String>, direction: &str) {
+        let Some(path) = &self.config.server.control_socket else {
+            return;
+        };
+        let control = ControlServer::new(
+            path,
+            direction,
+            config_file,
+            profile,
+            self.store.clone(),
+            self.reloader.clone(),
+        );
+        error_reporting::spawn_reported("control", "socket", async move {
+            if let Err(e) = control.run().await {
+                tracing::error!("control socket stopped: {}", e);
+            }
+        });
+    }
+
+    /// Reloads the configuration file on SIGHUP in the background, see
+    /// `Reloader`.
+    pub fn start_config_watcher(&self, config_file: PathBuf, profile: Option<String>) {
+        watch_sighup(config_file, profile, self.reloader.clone());
+    }
+
     /// Fetches events from `nostr` and sends them to the `waku` protocol.
     ///
     /// This method continuously retrieves events from the `nostr` relay, encodes them,
     /// and forwards them to a `waku` node using its API.
-    pub async fn from_nostr_to_waku(&self) {
-        let (tx, mut rx) = mpsc::channel(100);
-        let wclient = self.waku_client.clone();
-        let client = Client::new();
-        let url = self.config.waku.send_api.clone();
-        let content_topic = self.config.waku.content_topic.clone();
-
-        // Spawn a background task to process and send events to Waku.
-        tokio::task::spawn(async move {
-            while let Some(event) = rx.recv().await {
-                // Encode the event payload in base64 format.
-                let encoded_payload = base64::encode(serde_json::to_string(&event).unwrap());
-
-                // Prepare the HTTP request body.
-                let body = json!({
-                    "payload": encoded_payload,
-                    "contentTopic": content_topic
-                });
-
-                // Send the payload to the Waku node.
-                let response = client
-                    .post(url.clone())
-                    .header("Content-Type", "application/json")
-                    .json(&body)
-                    .send()
+    pub async fn from_nostr_to_waku(&self) -> error::Result<()> {
+        self.from_nostr_to_named_sink("n2w", "waku").await
+    }
+
+    /// Listens for events from the `waku` protocol and forwards them to the `nostr` client.
+    pub async fn from_waku_to_nostr(&self) -> error::Result<()> {
+        let source = self
+            .waku_source("w2n")
+            .context(|| "w2n: receiving from waku".to_string())?;
+        self.from_source_to_nostr("w2n", source).await;
+        Ok(())
+    }
+
+    /// Listens for events from the `waku` protocol and posts them as invites
+    /// to IndexDB.
+    ///
+    /// Messages that do not decode to a Nostr event are skipped by the
+    /// source, and events with an invalid signature are not posted.
+    pub async fn from_waku_to_indexdb(&self) -> error::Result<()> {
+        let source = self
+            .waku_source("w2i")
+            .context(|| "w2i: receiving from waku".to_string())?;
+        let sink = self
+            .sink("indexdb")
+            .await
+            .context(|| "w2i: creating the indexdb sink".to_string())?;
+        self.from_source_to_sink("w2i", source, sink).await;
+        Ok(())
+    }
+
+    /// Returns the source of the events received over Waku: the gossipsub
+    /// transport if configured, otherwise the embedded Waku node.
+    fn waku_source(&self, direction: &str) -> error::Result<Arc<dyn EventSource>> {
+        #[cfg(feature = "gossipsub")]
+        if self.config.waku.transport == WakuTransport::Gossipsub {
+            return Ok(self.gossipsub()?);
+        }
+        #[cfg(feature = "waku-ffi")]
+        if let Some(wclient) = self.waku_client.clone() {
+            return Ok(Arc::new(waku::WakuNodeSource::new(
+                wclient,
+                &self.config.waku,
+                self.store.clone(),
+                self.ipfs.clone(),
+                self.waku_cipher.clone(),
+            )));
+        }
+        Err(error::Error::InvalidConfig(format!(
+            "{} needs the gossipsub transport or the embedded waku node of the `waku-ffi` feature",
+            direction
+        )))
+    }
+
+    /// Returns the relays as a source of the events created from `since`
+    /// on, received as configured by `nostr.mode`. Unlike the `n2*`
+    /// directions, the source keeps no checkpoint.
+    pub fn nostr_source(&self, since: u64) -> Arc<dyn EventSource> {
+        Arc::new(nostr::NostrSource::new(
+            self.nostr_client.clone(),
+            self.config.nostr.relays(),
+            self.config.nostr.mode,
+            since,
+            Duration::from_secs(self.config.sync.poll_interval_secs),
+        ))
+    }
+
+    /// Runs `n2w` and `w2n` concurrently.
+    ///
+    /// Both directions share the dedupe table: events fetched from the relay
+    /// are recorded before they are published to Waku, and events received
+    /// over Waku once they are published to the relay, so neither is bridged
+    /// back to where it came from.
+    pub async fn from_nostr_to_waku_and_back(&self) -> error::Result<()> {
+        let (n2w, w2n) = tokio::join!(self.from_nostr_to_waku(), self.from_waku_to_nostr());
+        n2w.and(w2n)
+    }
+
+    /// Fetches events from `nostr` and sends them to an indexdb service.
+    ///
+    /// This method continuously retrieves events from the `nostr` relay and forwards them
+    /// to an external indexdb service for indexing.
+    ///
+    /// With `indexdb_backend.sink: nats` the invites are published to NATS
+    /// JetStream instead, and with `redis` appended to a Redis stream.
+    pub async fn from_nostr_to_indexdb(&self) -> error::Result<()> {
+        self.from_nostr_to_named_sink("n2i", "indexdb").await
+    }
+
+    /// Fetches events from `nostr` and produces them to Kafka.
+    pub async fn from_nostr_to_kafka(&self) -> error::Result<()> {
+        self.from_nostr_to_named_sink("n2k", "kafka").await
+    }
+
+    /// Consumes events from Kafka and publishes them to `nostr`.
+    pub async fn from_kafka_to_nostr(&self) -> error::Result<()> {
+        #[cfg(not(feature = "kafka"))]
+        return Err(needs_feature("k2n", "kafka"));
+        #[cfg(feature = "kafka")]
+        {
+            let source = self
+                .config
+                .kafka
+                .as_ref()
+                .ok_or_else(|| error::Error::InvalidConfig("missing `kafka` section".to_string()))
+                .and_then(kafka::KafkaSource::new)
+                .context(|| "k2n: creating the kafka source".to_string())?;
+            self.from_source_to_nostr("k2n", Arc::new(source)).await;
+            Ok(())
+        }
+    }
+
+    /// Fetches events from `nostr` and publishes them to MQTT.
+    pub async fn from_nostr_to_mqtt(&self) -> error::Result<()> {
+        self.from_nostr_to_named_sink("n2m", "mqtt").await
+    }
+
+    /// Receives events from MQTT and publishes them to `nostr`.
+    pub async fn from_mqtt_to_nostr(&self) -> error::Result<()> {
+        #[cfg(not(feature = "mqtt"))]
+        return Err(needs_feature("m2n", "mqtt"));
+        #[cfg(feature = "mqtt")]
+        {
+            let source = self
+                .config
+                .mqtt
+                .as_ref()
+                .ok_or_else(|| error::Error::InvalidConfig("missing `mqtt` section".to_string()))
+                .and_then(mqtt::MqttSource::new)
+                .context(|| "m2n: creating the mqtt source".to_string())?;
+            self.from_source_to_nostr("m2n", Arc::new(source)).await;
+            Ok(())
+        }
+    }
+
+    /// Fetches events from `nostr` and posts them to the configured webhooks.
+    pub async fn from_nostr_to_webhook(&self) -> error::Result<()> {
+        self.from_nostr_to_named_sink("n2h", "webhook").await
+    }
+
+    /// Fetches events from `nostr` and posts the designated ACL events to a
+    /// Matrix room.
+    pub async fn from_nostr_to_matrix(&self) -> error::Result<()> {
+        self.from_nostr_to_named_sink("n2x", "matrix").await
+    }
+
+    /// Fetches events from `nostr` and emails the designated ACL events to
+    /// their configured recipients.
+    pub async fn from_nostr_to_smtp(&self) -> error::Result<()> {
+        self.from_nostr_to_named_sink("n2e", "smtp").await
+    }
+
+    /// Fetches events from `nostr` and sends them to an SQS queue or an SNS
+    /// topic.
+    pub async fn from_nostr_to_sqs(&self) -> error::Result<()> {
+        self.from_nostr_to_named_sink("n2q", "sqs").await
+    }
+
+    /// Fetches events from `nostr` and delivers them to every sink listed in
+    /// `sinks`, in turn.
+    pub async fn from_nostr_to_sinks(&self) -> error::Result<()> {
+        let sink = self.configured_sinks().await?;
+        self.from_nostr_to_sink("n2s", sink).await;
+        Ok(())
+    }
+
+    /// Builds the sinks listed in `sinks`, fanned out to in turn if several.
+    async fn configured_sinks(&self) -> error::Result<Arc<dyn EventSink>> {
+        let mut sinks = Vec::with_capacity(self.config.sinks.len());
+        for name in &self.config.sinks {
+            sinks.push(
+                self.sink(name)
                     .await
-                    .unwrap();
+                    .context(|| format!("creating the {} sink", name))?,
+            );
+        }
+        match sinks.len() {
+            0 => Err(error::Error::InvalidConfig(
+                "n2s needs at least one sink in `sinks`".to_string(),
+            )),
+            1 => Ok(sinks.remove(0)),
+            _ => Ok(Arc::new(FanOutSink::new(sinks))),
+        }
+    }
 
-                tracing::info!("Response from server: {}", response.status());
-                match response.text().await {
-                    Ok(body) => tracing::info!("Response from server: {}", body),
-                    Err(e) => tracing::error!("Response from server: {}", e),
-                }
+    /// Builds the sink the `n2*` direction `direction` delivers to.
+    pub async fn direction_sink(&self, direction: &str) -> error::Result<Arc<dyn EventSink>> {
+        let name = match direction {
+            "n2w" | "n2w2n" => "waku",
+            "n2i" => "indexdb",
+            "n2k" => "kafka",
+            "n2m" => "mqtt",
+            "n2h" => "webhook",
+            "n2x" => "matrix",
+            "n2q" => "sqs",
+            "n2e" => "smtp",
+            "n2s" => return self.configured_sinks().await,
+            _ => {
+                return Err(error::Error::InvalidConfig(format!(
+                    "direction `{}` does not fetch from nostr",
+                    direction
+                )))
+            }
+        };
+        self.sink(name).await
+    }
+
+    /// Fetches events from `nostr` and delivers them to the sink `name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error creating the sink. Once the pipeline runs, failed
+    /// deliveries are retried or dead-lettered and failed fetch rounds are
+    /// retried with backoff, so it only returns on shutdown.
+    async fn from_nostr_to_named_sink(
+        &self,
+        direction: &'static str,
+        name: &str,
+    ) -> error::Result<()> {
+        let sink = self
+            .sink(name)
+            .await
+            .context(|| format!("{}: creating the {} sink", direction, name))?;
+        self.from_nostr_to_sink(direction, sink).await;
+        Ok(())
+    }
+
+    /// Builds the sink `name`, one of `sink::SINK_NAMES`, from its section
+    /// of the configuration. Its urls follow the reloaded configurations.
+    pub async fn sink(&self, name: &str) -> error::Result<Arc<dyn EventSink>> {
+        let sink = self.build_sink(name).await?;
+        self.reloader.register_sink(sink.clone());
+        Ok(sink)
+    }
+
+    async fn build_sink(&self, name: &str) -> error::Result<Arc<dyn EventSink>> {
+        let missing =
+            |section: &str| error::Error::InvalidConfig(format!("missing `{}` section", section));
+        let ws_url = &self.config.nostr.ws_url;
+        Ok(match name {
+            "waku" => self.waku_sink()?,
+            "indexdb" => self.indexdb_sink().await?,
+            #[cfg(feature = "kafka")]
+            "kafka" => {
+                let config = self.config.kafka.as_ref().ok_or_else(|| missing("kafka"))?;
+                Arc::new(kafka::KafkaSink::new(config, ws_url)?)
+            }
+            #[cfg(feature = "mqtt")]
+            "mqtt" => {
+                let config = self.config.mqtt.as_ref().ok_or_else(|| missing("mqtt"))?;
+                Arc::new(mqtt::MqttSink::new(config)?)
+            }
+            "webhook" => {
+                let config = self
+                    .config
+                    .webhook
+                    .as_ref()
+                    .ok_or_else(|| missing("webhook"))?;
+                let client = HttpClient::new(
+                    &self.config.http,
+                    self.config.proxy.as_ref(),
+                    config.tls.as_ref(),
+                    self.config.retry_policy(config.retry.as_ref()),
+                )?
+                .with_timing(Operation::Sink);
+                Arc::new(webhook::WebhookSink::new(config, client, ws_url)?)
+            }
+            "matrix" => {
+                let config = self
+                    .config
+                    .matrix
+                    .as_ref()
+                    .ok_or_else(|| missing("matrix"))?;
+                let client = HttpClient::new(
+                    &self.config.http,
+                    self.config.proxy.as_ref(),
+                    config.tls.as_ref(),
+                    self.config.retry_policy(config.retry.as_ref()),
+                )?
+                .with_timing(Operation::Sink);
+                Arc::new(matrix::MatrixSink::new(config, client)?)
+            }
+            #[cfg(feature = "smtp")]
+            "smtp" => {
+                let config = self.config.smtp.as_ref().ok_or_else(|| missing("smtp"))?;
+                Arc::new(smtp::SmtpSink::new(config)?)
+            }
+            #[cfg(feature = "sqs")]
+            "sqs" => {
+                let config = self.config.sqs.as_ref().ok_or_else(|| missing("sqs"))?;
+                Arc::new(sqs::SqsSink::connect(config, ws_url).await?)
+            }
+            #[cfg(not(feature = "kafka"))]
+            "kafka" => return Err(needs_feature("the kafka sink", "kafka")),
+            #[cfg(not(feature = "mqtt"))]
+            "mqtt" => return Err(needs_feature("the mqtt sink", "mqtt")),
+            #[cfg(not(feature = "smtp"))]
+            "smtp" => return Err(needs_feature("the smtp sink", "smtp")),
+            #[cfg(not(feature = "sqs"))]
+            "sqs" => return Err(needs_feature("the sqs sink", "sqs")),
+            _ => {
+                return Err(error::Error::InvalidConfig(format!(
+                    "unknown sink `{}`",
+                    name
+                )))
+            }
+        })
+    }
+
+    /// Returns the Waku sink: the gossipsub transport if configured,
+    /// otherwise the REST API of a Waku node.
+    fn waku_sink(&self) -> error::Result<Arc<dyn EventSink>> {
+        #[cfg(feature = "gossipsub")]
+        if self.config.waku.transport == WakuTransport::Gossipsub {
+            return Ok(self.gossipsub()?);
+        }
+        #[cfg(feature = "waku-rest")]
+        return Ok(Arc::new(waku::WakuRestSink::new(
+            &self.config.waku,
+            self.waku_rest_client()?,
+            self.ipfs.clone(),
+            self.waku_cipher.clone(),
+        )));
+        #[cfg(not(feature = "waku-rest"))]
+        Err(error::Error::InvalidConfig(
+            "waku needs the gossipsub transport or the `waku-rest` feature".to_string(),
+        ))
+    }
+
+    /// Returns the IndexDB sink selected by `indexdb_backend.sink`.
+    async fn indexdb_sink(&self) -> error::Result<Arc<dyn EventSink>> {
+        match self.config.indexdb_backend.sink {
+            #[cfg(feature = "nats")]
+            IndexdbSink::Nats => {
+                let config = self.config.nats.as_ref().ok_or_else(|| {
+                    error::Error::InvalidConfig(
+                        "indexdb_backend.sink is nats but the `nats` section is missing"
+                            .to_string(),
+                    )
+                })?;
+                Ok(Arc::new(nats::NatsSink::connect(config).await?))
+            }
+            #[cfg(feature = "redis")]
+            IndexdbSink::Redis => {
+                let config = self.config.redis.as_ref().ok_or_else(|| {
+                    error::Error::InvalidConfig(
+                        "indexdb_backend.sink is redis but the `redis` section is missing"
+                            .to_string(),
+                    )
+                })?;
+                Ok(Arc::new(redis::RedisSink::connect(config).await?))
             }
+            #[cfg(not(feature = "nats"))]
+            IndexdbSink::Nats => Err(needs_feature("indexdb_backend.sink nats", "nats")),
+            #[cfg(not(feature = "redis"))]
+            IndexdbSink::Redis => Err(needs_feature("indexdb_backend.sink redis", "redis")),
+            #[cfg(feature = "indexdb")]
+            IndexdbSink::Http => Ok(Arc::new(indexdb::AclSink::new(
+                self.indexdb_client.clone(),
+                &self.config.indexdb_backend,
+            ))),
+            #[cfg(not(feature = "indexdb"))]
+            IndexdbSink::Http => Err(error::Error::InvalidConfig(
+                "indexdb needs a nats or redis sink or the `indexdb` feature".to_string(),
+            )),
+        }
+    }
+
+    /// Fetches events from `nostr` and delivers them to `sink`.
+    ///
+    /// Runs the same sender as the built-in pipelines: every delivery is
+    /// timed, audited and counted, transient failures are re-queued until
+    /// `delivery.max_attempts`, and the rest is dead-lettered. With
+    /// `delivery.circuit_breaker` the sink is wrapped in a `CircuitBreaker`,
+    /// and while it is open the events are held back in the retry queue and
+    /// the fetch rounds are skipped.
+    pub async fn from_nostr_to_sink(&self, direction: &'static str, sink: Arc<dyn EventSink>) {
+        let breaker = self
+            .config
+            .delivery
+            .circuit_breaker
+            .as_ref()
+            .map(|config| Arc::new(CircuitBreaker::new(direction, sink.clone(), config)));
+        let sink = match &breaker {
+            Some(breaker) => breaker.clone() as Arc<dyn EventSink>,
+            None => sink,
+        };
+        let (tx, rx) = mpsc::channel::<PipelineEvent>(self.config.sync.channel_capacity);
+        let name = sink.name();
+        let audit = self.audit.clone();
+        let alerter = self.alerter.clone();
+        let store = self.store.clone();
+        let source = self.config.nostr.ws_url.clone();
+        let transforms = self.transforms.clone();
+        let requeue = tx.clone();
+        let max_attempts = self.config.delivery.max_attempts;
+        let rx = Arc::new(Mutex::new(rx));
+        let (draining, drain) = watch::channel(false);
+        let backoff = self.delivery_backoff();
+        let sink_breaker = breaker.clone();
+        let sender = spawn_supervised(
+            direction,
+            name,
+            self.config.supervisor.clone(),
+            self.alerter.clone(),
+            move || {
+                let (rx, requeue, sink, transforms, audit, alerter, store, source) = (
+                    rx.clone(),
+                    requeue.clone(),
+                    sink.clone(),
+                    transforms.clone(),
+                    audit.clone(),
+                    alerter.clone(),
+                    store.clone(),
+                    source.clone(),
+                );
+                let breaker = sink_breaker.clone();
+                let mut drain = drain.clone();
+                let backoff = backoff.clone();
+                async move {
+                    let mut rx = rx.lock().await;
+                    let mut throughput = metrics::ThroughputWindow::new(direction);
+                    while let Some(item) = shutdown::recv_draining(&mut *rx, &mut drain).await {
+                        observe_queue_depth(direction, rx.len());
+                        let item = match &breaker {
+                            Some(breaker) if breaker.is_open() => {
+                                let delay = breaker.retry_after();
+                                let Some(item) = hold_back(&store, item, name, delay).await else {
+                                    continue;
+                                };
+                                // The item goes out as the next probe, unless the
+                                // pipeline stops first: it is still pending and not
+                                // acknowledged, so the next run delivers it.
+                                tokio::select! {
+                                    _ = tokio::time::sleep(breaker.retry_after()) => item,
+                                    _ = shutdown::wait_requested() => break,
+                                    _ = drain.wait_for(|draining| *draining) => break,
+                                }
+                            }
+                            _ => item,
+                        };
+                        let Some(attempts) =
+                            start_attempt(&store, &alerter, &item, name, max_attempts).await
+                        else {
+                            item.settle();
+                            continue;
+                        };
+                        let topic = transforms.route(direction, &item.event);
+                        let result = sink::deliver(
+                            sink.as_ref(),
+                            &item.event,
+                            &item.correlation_id,
+                            topic.as_deref(),
+                        )
+                        .instrument(item.span.clone())
+                        .await
+                        .context(|| {
+                            format!(
+                                "{}: delivering event {} to {}",
+                                direction, item.event.id, name
+                            )
+                        });
+                        if let Err(e) = &result {
+                            metrics::record_error(direction, name, e);
+                            logging::error_deduped(&format!("{}:{}", name, e.class()), e);
+                        }
+
+                        audit
+                            .record(item.audit_record(&source, name, &result))
+                            .await;
+                        alerter.record_result(direction, name, result.is_ok()).await;
+                        if result.is_ok() {
+                            throughput.record(1);
+                            metrics::record_traffic(
+                                direction,
+                                topic.as_deref().or(sink.topic(&item.event)),
+                                item.event.kind.as_u16(),
+                            );
+                        }
+                        throughput.publish();
+                        match &result {
+                            Ok(()) => {
+                                set_delivery_status(
+                                    &store,
+                                    &item,
+                                    name,
+                                    db::DeliveryStatus::Delivered,
+                                )
+                                .await;
+                                item.settle();
+                            }
+                            Err(e) => {
+                                settle_failure(
+                                    &store,
+                                    &alerter,
+                                    &requeue,
+                                    &backoff,
+                                    item,
+                                    attempts,
+                                    max_attempts,
+                                    name,
+                                    e,
+                                )
+                                .await
+                            }
+                        }
+                        if rx.is_empty() {
+                            observe_queue_depth(direction, 0);
+                        }
+                    }
+                }
+            },
+        );
+
+        systemd::notify_ready();
+
+        let acks = AckTracker::new(direction);
+        self.fetch_loop(direction, tx, breaker, &acks).await;
+        self.drain(direction, draining, sender).await;
+        if let Err(e) = self.commit_acknowledged(direction, &acks).await {
+            metrics::record_error(direction, "db", &e);
+            tracing::error!("{}: committing checkpoints failed: {}", direction, e);
+        }
+    }
+
+    /// Receives events from `source` and publishes them to the `nostr` relay.
+    ///
+    /// Events with an invalid signature are dropped, as the relay would
+    /// reject them anyway.
+    pub async fn from_source_to_nostr(
+        &self,
+        direction: &'static str,
+        source: Arc<dyn EventSource>,
+    ) {
+        self.from_source_to_sink(direction, source, self.nostr_client.clone())
+            .await
+    }
+
+    /// Receives events from `source` and delivers them to `sink`.
+    ///
+    /// Events with an invalid signature are dropped, so only genuine Nostr
+    /// events are bridged. The others are recorded as pending and delivered
+    /// at least once: a failed delivery is retried with the backoff of
+    /// `delivery` or dead-lettered, and the events left pending by a
+    /// previous run are delivered again.
+    pub async fn from_source_to_sink(
+        &self,
+        direction: &'static str,
+        source: Arc<dyn EventSource>,
+        sink: Arc<dyn EventSink>,
+    ) {
+        let (tx, rx) = mpsc::channel::<nostr_sdk::Event>(self.config.sync.channel_capacity);
+        let name = source.name();
+        let receiver = spawn_supervised(
+            direction,
+            name,
+            self.config.supervisor.clone(),
+            self.alerter.clone(),
+            move || {
+                let (source, tx) = (source.clone(), tx.clone());
+                async move {
+                    if let Err(e) = source.run(tx).await {
+                        tracing::error!("{}: {} source stopped: {}", direction, name, e);
+                    }
+                }
+            },
+        );
+
+        // Stopping the source on shutdown closes the channel once the events
+        // it already received are published.
+        error_reporting::spawn_reported(direction, name, async move {
+            shutdown::wait_requested().await;
+            receiver.abort();
         });
 
-        // Main loop for fetching events from Nostr and forwarding them to Waku.
+        // Failed deliveries go to the retry queue, as for the `n2*`
+        // directions, and the events a previous run left pending are
+        // delivered again.
+        let (requeue, due) = mpsc::channel::<PipelineEvent>(self.config.sync.channel_capacity);
+        let worker = retry::RetryWorker::new(self.store.clone(), direction, requeue.clone());
+        error_reporting::spawn_reported(direction, "retry", worker.run());
+        let recovery = async {
+            if let Err(e) = self.recover_pending(direction, &requeue).await {
+                metrics::record_error(direction, "db", &e);
+                tracing::error!("{}: recovering pending deliveries failed: {}", direction, e);
+            }
+        };
+        let retries = SourceRetries {
+            requeue: requeue.clone(),
+            due,
+        };
+        let delivery = self.deliver_stream(
+            direction,
+            ReceiverStream::new(rx).boxed(),
+            sink,
+            Some(retries),
+        );
+        tokio::join!(recovery, delivery);
+    }
+
+    /// Delivers the events of `events` to `sink`, until the stream ends.
+    ///
+    /// Events already bridged are skipped, events whose id or signature is
+    /// invalid are dropped and counted, and the rest go through the plugins
+    /// and transform rules before they are delivered. A failed delivery is
+    /// counted and logged, not retried: the backfills deliver the events
+    /// missing when run again. Callers end the stream on shutdown.
+    pub async fn from_stream_to_sink(
+        &self,
+        direction: &'static str,
+        events: EventStream,
+        sink: Arc<dyn EventSink>,
+    ) {
+        self.deliver_stream(direction, events, sink, None).await
+    }
+
+    /// This is the engine of the source pipelines and of the backfills, see
+    /// `from_stream_to_sink`. With `retries`, the events are recorded as
+    /// pending before they are delivered, failed deliveries are settled as
+    /// in the `n2*` directions, retried or dead-lettered, and the due
+    /// retries are delivered in between the events of the stream.
+    async fn deliver_stream(
+        &self,
+        direction: &'static str,
+        mut events: EventStream,
+        sink: Arc<dyn EventSink>,
+        mut retries: Option<SourceRetries>,
+    ) {
+        systemd::notify_ready();
+        let backoff = self.delivery_backoff();
         loop {
-            // Retrieve the last fetch time from the database.
-            let mut last_fetch_time = self.store.get_last_update(0).await.unwrap();
+            let item = match retries.as_mut() {
+                Some(retries) => tokio::select! {
+                    Some(item) = retries.due.recv() => Some(item),
+                    event = events.next() => match event {
+                        Some(event) => self.admit_from_source(direction, event, true).await,
+                        None => break,
+                    },
+                },
+                None => match events.next().await {
+                    Some(event) => self.admit_from_source(direction, event, false).await,
+                    None => break,
+                },
+            };
+            let Some(item) = item else {
+                continue;
+            };
+            tokio::select! {
+                _ = control::wait_resumed() => {}
+                _ = shutdown::wait_requested() => {}
+            }
+            match &retries {
+                Some(retries) => {
+                    self.deliver_retrying(item, &sink, &retries.requeue, &backoff)
+                        .await
+                }
+                None => self.deliver_once(item, &sink).await,
+            }
+            systemd::progress();
+        }
+    }
 
-            // fetch nostr events
-            let events = self
-                .nostr_client
-                .fetch_from_relay(last_fetch_time)
+    /// Checks an event received from a source before it is delivered, see
+    /// `deliver_stream`. With `record`, the event is recorded as pending so
+    /// its attempts are counted.
+    ///
+    /// # Returns
+    ///
+    /// The event to deliver, or `None` if it is skipped.
+    async fn admit_from_source(
+        &self,
+        direction: &'static str,
+        event: nostr_sdk::Event,
+        record: bool,
+    ) -> Option<PipelineEvent> {
+        let event_id = event.id;
+        // Events fetched from the relay by the opposite direction, or
+        // already published, come back over the source; publishing them
+        // again would bridge them back and forth forever.
+        let existed = match self
+            .pipeline_store
+            .is_event_existed(direction, event_id.to_hex())
+            .await
+        {
+            Ok(existed) => existed,
+            Err(e) => {
+                metrics::record_error(direction, "db", &e);
+                tracing::warn!("{}: skipping event {}: {}", direction, event_id, e);
+                return None;
+            }
+        };
+        if existed {
+            tracing::debug!("{}: event {} was already bridged", direction, event_id);
+            metrics::metrics()
+                .events_deduplicated_total
+                .with_label_values(&[direction])
+                .inc();
+            return None;
+        }
+        // Anyone can publish to the source, so the event is checked
+        // before the plugins and rules alter it without signing it again.
+        if !is_authentic(direction, &event) {
+            return None;
+        }
+        let event = match self.plugins.apply(direction, event) {
+            Ok(Some(event)) => event,
+            Ok(None) => return None,
+            Err(e) => {
+                metrics::record_error(direction, "plugin", &e);
+                tracing::warn!("{}: skipping event {}: {}", direction, event_id, e);
+                return None;
+            }
+        };
+        let event = self.transforms.apply(direction, event)?;
+        let item = PipelineEvent::new(event, direction);
+        if record {
+            if let Err(e) = self.pipeline_store.add_new_event(item.record()).await {
+                metrics::record_error(direction, "db", &e);
+                tracing::warn!("{}: skipping event {}: {}", direction, event_id, e);
+                return None;
+            }
+        }
+
+        Some(item)
+    }
+
+    /// Delivers a recorded event of a source pipeline, settling a failure
+    /// with a retry or in the dead-letter queue.
+    async fn deliver_retrying(
+        &self,
+        item: PipelineEvent,
+        sink: &Arc<dyn EventSink>,
+        requeue: &mpsc::Sender<PipelineEvent>,
+        backoff: &Backoff,
+    ) {
+        let name = sink.name();
+        let max_attempts = self.config.delivery.max_attempts;
+        let Some(attempts) =
+            start_attempt(&self.store, &self.alerter, &item, name, max_attempts).await
+        else {
+            return;
+        };
+        match self.deliver(&item, sink).await {
+            Ok(()) => {
+                set_delivery_status(&self.store, &item, name, db::DeliveryStatus::Delivered).await
+            }
+            Err(e) => {
+                settle_failure(
+                    &self.store,
+                    &self.alerter,
+                    requeue,
+                    backoff,
+                    item,
+                    attempts,
+                    max_attempts,
+                    name,
+                    &e,
+                )
                 .await
-                .unwrap();
+            }
+        }
+    }
 
-            // Process each event and send it to the Waku client.
-            for event in events.into_iter() {
-                if let Some(_) = self.store.is_event_existed(event.id.into()).await {
-                    if event.created_at.as_u64() > last_fetch_time {
-                        last_fetch_time = event.created_at.as_u64();
-                    }
+    /// Delivers an event of a backfill once, recording it only if it was
+    /// delivered.
+    async fn deliver_once(&self, item: PipelineEvent, sink: &Arc<dyn EventSink>) {
+        let direction = item.direction;
+        if self.deliver(&item, sink).await.is_err() {
+            metrics::metrics()
+                .events_failed_total
+                .with_label_values(&[direction])
+                .inc();
+            return;
+        }
+        if let Err(e) = self.record_published(&item).await {
+            metrics::record_error(direction, "db", &e);
+            tracing::warn!(
+                "{}: recording event {} failed: {}",
+                direction,
+                item.event.id,
+                e
+            );
+        }
+        feed::publish(BridgedEvent {
+            event: item.event,
+            direction,
+            sink: sink.name().to_string(),
+            status: db::DeliveryStatus::Delivered,
+            correlation_id: item.correlation_id.to_string(),
+            bridged_at: Utc::now(),
+        });
+    }
 
-                    self.store.add_new_event(event.id.into()).await.unwrap();
+    /// Makes one delivery attempt of an event of a source pipeline or a
+    /// backfill. A failure is logged and counted as an error.
+    async fn deliver(&self, item: &PipelineEvent, sink: &Arc<dyn EventSink>) -> error::Result<()> {
+        let (direction, name) = (item.direction, sink.name());
+        let topic = self.transforms.route(direction, &item.event);
+        let result = sink::deliver(
+            sink.as_ref(),
+            &item.event,
+            &item.correlation_id,
+            topic.as_deref(),
+        )
+        .instrument(item.span.clone())
+        .await
+        .context(|| {
+            format!(
+                "{}: delivering event {} to {}",
+                direction, item.event.id, name
+            )
+        });
 
-                    let _ = tx.send(event).await;
+        self.alerter
+            .record_result(direction, name, result.is_ok())
+            .await;
+        match &result {
+            Ok(()) => metrics::record_traffic(
+                direction,
+                topic.as_deref().or(sink.topic(&item.event)),
+                item.event.kind.as_u16(),
+            ),
+            Err(e) => {
+                metrics::record_error(direction, name, e);
+                logging::error_deduped(&format!("{}:{}:{}", direction, name, e.class()), e);
+            }
+        }
+        result
+    }
+
+    /// Records an event delivered by a backfill, so it is not bridged again,
+    /// e.g. back from the relay by the directions fetching from it.
+    async fn record_published(&self, item: &PipelineEvent) -> error::Result<()> {
+        self.pipeline_store.add_new_event(item.record()).await?;
+        self.store
+            .set_delivery_status(
+                item.direction,
+                &item.event.id.to_hex(),
+                db::DeliveryStatus::Delivered,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Fetches events from the relay every `sync.poll_interval_secs` and
+    /// queues the new ones for the sender task. A failed round is logged and
+    /// retried in the next round, so a flaky relay or database never stops the pipeline. While
+    /// rounds keep failing, e.g. because the relay is down, the next round is
+    /// additionally delayed with backoff, up to `FETCH_MAX_BACKOFF`.
+    ///
+    /// The rounds wait while more than `sync.high_watermark` events are
+    /// queued, so a slow sink slows the fetching down instead of piling up
+    /// events, and the checkpoints acknowledged by the sender are committed
+    /// in between, see `AckTracker`.
+    ///
+    /// With `nostr.mode: subscribe` the events are received as they are
+    /// published instead, see `subscribe_loop`, and are held back by the
+    /// sender while `breaker` is open rather than not received.
+    ///
+    /// Returns once a shutdown is requested, after the current round.
+    async fn fetch_loop(
+        &self,
+        direction: &'static str,
+        tx: mpsc::Sender<PipelineEvent>,
+        breaker: Option<Arc<CircuitBreaker>>,
+        acks: &Arc<AckTracker>,
+    ) {
+        let worker = retry::RetryWorker::new(self.store.clone(), direction, tx.clone());
+        error_reporting::spawn_reported(direction, "retry", worker.run());
+
+        if let Err(e) = self.recover_pending(direction, &tx).await {
+            metrics::record_error(direction, "db", &e);
+            tracing::error!("{}: recovering pending deliveries failed: {}", direction, e);
+        }
+
+        let clock = CheckpointClock::new(self.config.timestamps.clone());
+        if self.config.nostr.mode == NostrMode::Subscribe {
+            self.subscribe_loop(direction, &clock, &tx, acks).await;
+            tracing::info!("{}: stopped receiving", direction);
+            return;
+        }
+
+        let high_watermark = self.config.sync.high_watermark();
+        let mut backoff = Backoff::new(self.reloader.poll_interval(), FETCH_MAX_BACKOFF);
+        while !shutdown::is_requested() {
+            if let Err(e) = self.commit_acknowledged(direction, acks).await {
+                metrics::record_error(direction, "db", &e);
+                tracing::error!("{}: committing checkpoints failed: {}", direction, e);
+            }
+            self.observe_oldest_pending(direction).await;
+            let interval = self.reloader.poll_interval();
+            let depth = tx.max_capacity() - tx.capacity();
+            metrics::metrics()
+                .queue_depth
+                .with_label_values(&[direction])
+                .set(depth as f64);
+            if depth > high_watermark {
+                metrics::metrics()
+                    .fetch_backpressure_total
+                    .with_label_values(&[direction])
+                    .inc();
+                tracing::debug!(
+                    "{}: {} events queued, waiting for the sender before fetching",
+                    direction,
+                    depth
+                );
+                systemd::progress();
+                tokio::select! {
+                    _ = tokio::time::sleep(BACKPRESSURE_CHECK_INTERVAL.min(interval)) => {}
+                    _ = shutdown::wait_requested() => {}
+                }
+                continue;
+            }
+            // While paused, or while the sink is known to fail, the rounds
+            // are skipped rather than awaited, so the systemd watchdog still
+            // sees the loop progressing.
+            if control::is_paused() || breaker.as_ref().is_some_and(|b| b.is_open()) {
+                systemd::progress();
+                tokio::select! {
+                    _ = tokio::time::sleep(interval) => {}
+                    _ = shutdown::wait_requested() => {}
                 }
+                continue;
+            }
+            if let Err(e) = self.replay_requested(direction, &tx).await {
+                metrics::record_error(direction, "db", &e);
+                tracing::error!("{}: replaying requested events failed: {}", direction, e);
             }
 
-            //update last fetch time in database
-            self.store
-                .update_last_update(last_fetch_time)
-                .await
-                .unwrap();
+            let delay = match self.fetch_round(direction, &clock, &tx, acks).await {
+                Ok(()) => {
+                    backoff.reset();
+                    interval
+                }
+                Err(e) => {
+                    metrics::record_error(direction, "nostr", &e);
+                    logging::error_deduped(&format!("{}:fetch:{}", direction, e.class()), e);
+                    interval + backoff.next().unwrap_or(FETCH_MAX_BACKOFF)
+                }
+            };
+            systemd::progress();
 
-            tokio::time::sleep(Duration::from_secs(10)).await
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = shutdown::wait_requested() => {}
+            }
         }
+        tracing::info!("{}: stopped fetching", direction);
     }
 
-    /// Listens for events from the `waku` protocol and forwards them to the `nostr` client.
-    pub async fn from_waku_to_nostr(&self) {
-        let (tx, mut rx) = mpsc::channel(100);
+    /// Lets the sender task deliver the events still queued, giving up after
+    /// `shutdown.drain_timeout_secs`. Events left over stay pending in the
+    /// database and are recovered by the next run.
+    async fn drain(
+        &self,
+        direction: &'static str,
+        draining: watch::Sender<bool>,
+        sender: JoinHandle<()>,
+    ) {
+        draining.send_replace(true);
+        let limit = Duration::from_secs(self.config.shutdown.drain_timeout_secs);
+        match tokio::time::timeout(limit, sender).await {
+            Ok(_) => tracing::info!("{}: queued events delivered", direction),
+            Err(_) => tracing::warn!(
+                "{}: queued events not delivered within {:?}, leaving them to the next run",
+                direction,
+                limit
+            ),
+        }
+    }
 
-        let wclient = self.waku_client.clone();
-        tokio::task::spawn(async move {
-            wclient.listening_message_gowrapper(tx).await;
-        });
+    /// Queues again the events a previous run recorded but did not deliver.
+    ///
+    /// For `n2w`, events that already reached the Waku store are marked
+    /// delivered instead, when `waku.store_api` is configured. If the store
+    /// cannot be queried every pending event is queued again, as delivering
+    /// an event twice is preferable to losing it.
+    async fn recover_pending(
+        &self,
+        direction: &'static str,
+        tx: &mpsc::Sender<PipelineEvent>,
+    ) -> error::Result<()> {
+        let pending = self
+            .store
+            .pending_events(direction)
+            .await
+            .context(|| format!("{}: reading pending events", direction))?;
+        if pending.is_empty() {
+            return Ok(());
+        }
+        // Events waiting for a retry are re-queued by the retry worker.
+        let scheduled = self
+            .store
+            .scheduled_retries(direction)
+            .await
+            .context(|| format!("{}: reading scheduled retries", direction))?;
+
+        let mut published = HashSet::new();
+        let oldest = pending.iter().map(|row| row.updated_at).min();
+        if direction == "n2w" {
+            if let (Some(url), Some(since)) = (&self.config.waku.store_api, oldest) {
+                let ids = pending.iter().map(|row| row.event_id.clone()).collect();
+                let store = WakuStore::new(
+                    self.waku_http_client()?,
+                    url.clone(),
+                    waku::TopicRouter::new(&self.config.waku).topics().join(","),
+                )
+                .with_cipher(self.waku_cipher.clone());
+                match store.published(&ids, since).await {
+                    Ok(found) => published = found,
+                    Err(e) => tracing::warn!(
+                        "{}: waku store query failed, re-publishing all pending events: {}",
+                        direction,
+                        e
+                    ),
+                }
+            }
+        }
+
+        let (mut requeued, mut delivered) = (0, 0);
+        for row in pending {
+            if scheduled.contains(&row.event_id) {
+                continue;
+            }
+            if published.contains(&row.event_id) {
+                self.store
+                    .set_delivery_status(direction, &row.event_id, db::DeliveryStatus::Delivered)
+                    .await
+                    .context(|| {
+                        format!("{}: marking event {} delivered", direction, row.event_id)
+                    })?;
+                delivered += 1;
+                continue;
+            }
+
+            let Some(event) = row
+                .payload
+                .as_deref()
+                .and_then(|payload| nostr_sdk::Event::from_json(payload).ok())
+            else {
+                tracing::warn!(
+                    "{}: pending event {} has no usable payload, skipping",
+                    direction,
+                    row.event_id
+                );
+                continue;
+            };
+            let correlation_id = row
+                .correlation_id
+                .map(CorrelationId::from)
+                .unwrap_or_else(CorrelationId::new);
+            tx.send(PipelineEvent::recovered(event, direction, correlation_id))
+                .await
+                .map_err(|_| {
+                    error::Error::CustomError(format!("{} sender task stopped", direction))
+                })?;
+            requeued += 1;
+        }
 
-        //self.waku_client.listening_message(tx).await;
+        tracing::info!(
+            "{}: recovered pending deliveries, {} re-queued, {} already delivered",
+            direction,
+            requeued,
+            delivered
+        );
+        Ok(())
+    }
 
-        let nclient = self.nostr_client.clone();
-        while let Some(event) = rx.recv().await {
-            tracing::info!("got event: {:?}", event);
-            //let _ = nclient.send_event(event).await;
+    /// Queues the events of `direction` whose replay was requested over the
+    /// control socket.
+    async fn replay_requested(
+        &self,
+        direction: &'static str,
+        tx: &mpsc::Sender<PipelineEvent>,
+    ) -> error::Result<()> {
+        for event_id in control::take_replays(direction) {
+            let Some(row) = self
+                .store
+                .direction_event_state(direction, &event_id)
+                .await
+                .context(|| format!("{}: reading event {}", direction, event_id))?
+            else {
+                continue;
+            };
+            let Some(event) = row
+                .payload
+                .as_deref()
+                .and_then(|payload| nostr_sdk::Event::from_json(payload).ok())
+            else {
+                tracing::warn!(
+                    "{}: event {} has no usable payload, not replaying it",
+                    direction,
+                    event_id
+                );
+                continue;
+            };
+            let correlation_id = row
+                .correlation_id
+                .map(CorrelationId::from)
+                .unwrap_or_else(CorrelationId::new);
+            tx.send(PipelineEvent::recovered(event, direction, correlation_id))
+                .await
+                .map_err(|_| {
+                    error::Error::CustomError(format!("{} sender task stopped", direction))
+                })?;
+            tracing::info!("{}: replaying event {}", direction, event_id);
         }
+        Ok(())
     }
 
-    /// Fetches events from `nostr` and sends them to an indexdb service.
+    /// Runs one fetch round over every configured relay.
     ///
-    /// This method continuously retrieves events from the `nostr` relay and forwards them
-    /// to an external indexdb service for indexing.
-    pub async fn from_nostr_to_indexdb(&self) {
-        let (tx, mut rx) = mpsc::channel::<nostr_sdk::Event>(100);
-        let iclient = self.indexdb_client.clone();
-	let invite_url = self.config.indexdb_backend.invite_url.clone();
-        tokio::task::spawn(async move {
-            while let Some(event) = rx.recv().await {
-                let _ = iclient
-                    .send_invite_event_to_indexdb(invite_url.as_str(), event)
-                    .await;
+    /// A relay that fails is logged and fetched again next round from its own
+    /// checkpoint; the round only fails if every relay failed.
+    async fn fetch_round(
+        &self,
+        direction: &'static str,
+        clock: &CheckpointClock,
+        tx: &mpsc::Sender<PipelineEvent>,
+        acks: &Arc<AckTracker>,
+    ) -> error::Result<()> {
+        let mut checkpoint: Option<u64> = None;
+        let mut failure = None;
+        for relay in self.config.nostr.relays() {
+            match self
+                .fetch_relay_round(direction, &relay, clock, tx, acks)
+                .await
+            {
+                Ok(last) => checkpoint = Some(checkpoint.map_or(last, |c| c.min(last))),
+                Err(e) => {
+                    tracing::warn!("{}: fetching from {} failed: {}", direction, relay, e);
+                    failure = Some(e);
+                }
             }
-        });
+        }
 
-        loop {
-            // fetch last fetch time from database
-            let mut last_fetch_time = self.store.get_last_update(0).await.unwrap();
+        match (checkpoint, failure) {
+            (Some(checkpoint), _) => {
+                metrics::observe_checkpoint(direction, checkpoint, Timestamp::now().as_u64());
+                Ok(())
+            }
+            (None, Some(e)) => Err(e),
+            (None, None) => Ok(()),
+        }
+    }
+
+    /// Fetches the events of `relay` newer than its last queued event,
+    /// records the new ones as pending and queues them. Returns the committed
+    /// checkpoint.
+    ///
+    /// The checkpoint of the relay is not committed here: it moves past the
+    /// events as the sender acknowledges them, see `AckTracker`. Events
+    /// recorded but not delivered before a crash are recovered as pending by
+    /// the next run. Events already received from another relay are skipped
+    /// by the dedupe table.
+    async fn fetch_relay_round(
+        &self,
+        direction: &'static str,
+        relay: &str,
+        clock: &CheckpointClock,
+        tx: &mpsc::Sender<PipelineEvent>,
+        acks: &Arc<AckTracker>,
+    ) -> error::Result<u64> {
+        // Start after the events already queued, or from the checkpoint.
+        let committed = self
+            .pipeline_store
+            .get_last_update(direction, relay, 0)
+            .await
+            .context(|| format!("{}: reading checkpoint of {}", direction, relay))?;
+        let last_fetch_time = acks
+            .fetched(relay)
+            .map_or(committed, |fetched| fetched.max(committed));
 
-            // fetch nostr events
-            let events = self
-                .nostr_client
-                .fetch_from_relay(last_fetch_time)
+        let events = self.fetch_pages(direction, relay, last_fetch_time).await?;
+
+        metrics::metrics()
+            .events_fetched_total
+            .with_label_values(&[direction])
+            .inc_by(events.len() as u64);
+
+        // Process each event, with the checkpoint after it, then record the
+        // new ones. Dropped events only move the checkpoint.
+        let received_at = Timestamp::now().as_u64();
+        let mut items = Vec::new();
+        let mut created = Vec::new();
+        for event in events.into_iter() {
+            let created_at = event.created_at.as_u64();
+            let item = match self.admit_event(direction, relay, event).await? {
+                Admission::Duplicate => continue,
+                Admission::Dropped => None,
+                Admission::Queue(item) => Some(item),
+            };
+            created.push((created_at, item.is_some()));
+            items.push(item);
+        }
+        let checkpoints = clock.advance_round(last_fetch_time, &created, received_at);
+        let admitted: Vec<_> = items.into_iter().zip(checkpoints).collect();
+
+        let records: Vec<_> = admitted
+            .iter()
+            .filter_map(|(item, _)| item.as_ref().map(PipelineEvent::record))
+            .collect();
+        let count = records.len();
+        // The committed checkpoint is kept, the events are acknowledged later.
+        self.pipeline_store
+            .commit_batch(direction, relay, records, committed)
+            .await
+            .context(|| format!("{}: recording {} events of {}", direction, count, relay))?;
+
+        // Hand the recorded events to the sender task.
+        for (item, checkpoint) in admitted {
+            let Some(mut item) = item else {
+                acks.skip(relay, checkpoint);
+                continue;
+            };
+            item.ack = Some(acks.track(relay, checkpoint));
+            tx.send(item).await.map_err(|_| {
+                error::Error::CustomError(format!("{} sender task stopped", direction))
+            })?;
+        }
+
+        Ok(committed)
+    }
+
+    /// Fetches every event of `relay` created from `since` on, oldest first.
+    ///
+    /// Relays return the newest events of a filter first and at most
+    /// `sync.batch_limit` of them, so advancing the checkpoint past a full
+    /// page would skip the older events left out. A full page is followed by
+    /// another request up to its oldest event, until a page is not full.
+    async fn fetch_pages(
+        &self,
+        direction: &'static str,
+        relay: &str,
+        since: u64,
+    ) -> error::Result<Vec<nostr_sdk::Event>> {
+        let retry = self.config.retry_policy(self.config.nostr.retry.as_ref());
+        let limit = self.nostr_client.fetch_limit();
+        let mut events = Vec::new();
+        let mut seen = HashSet::new();
+        let mut until: Option<u64> = None;
+        loop {
+            // fetch nostr events, bounding each attempt so a hung relay is retried
+            let client = &self.nostr_client;
+            let page = retry
+                .retry("relay fetch", || {
+                    timed(Operation::Fetch, async move {
+                        match until {
+                            Some(until) => {
+                                client.fetch_window_from_relay(relay, since, until).await
+                            }
+                            None => client.fetch_from_relay(relay, since).await,
+                        }
+                    })
+                })
+                .instrument(tracing::info_span!(
+                    "fetch",
+                    direction = direction,
+                    relay = relay
+                ))
                 .await
-                .unwrap();
+                .context(|| {
+                    format!(
+                        "{}: fetching events since {} from {}",
+                        direction, since, relay
+                    )
+                })?;
 
-            //process events
-            for event in events.into_iter() {
-                if let Some(_) = self.store.is_event_existed(event.id.into()).await {
-                    if event.created_at.as_u64() > last_fetch_time {
-                        last_fetch_time = event.created_at.as_u64();
-                    }
+            let next = next_page(&page, limit, until);
+            events.extend(page.into_iter().filter(|event| seen.insert(event.id)));
+            match next {
+                NextPage::Until(oldest) => until = Some(oldest),
+                NextPage::Stuck(at) => {
+                    tracing::warn!(
+                        "{}: more than {} events created at {} on {}, some were skipped",
+                        direction,
+                        limit,
+                        at,
+                        relay
+                    );
+                    break;
+                }
+                NextPage::Done => break,
+            }
+        }
+
+        events.sort_by_key(|event| event.created_at);
+        Ok(events)
+    }
 
-                    self.store.add_new_event(event.id.into()).await.unwrap();
+    /// Checks an event fetched from `relay` against the dedupe table of
+    /// `direction` and runs the plugins and transform rules on it.
+    async fn admit_event(
+        &self,
+        direction: &'static str,
+        relay: &str,
+        event: nostr_sdk::Event,
+    ) -> error::Result<Admission> {
+        if self
+            .pipeline_store
+            .is_event_existed(direction, event.id.into())
+            .await
+            .context(|| format!("{}: checking event {}", direction, event.id))?
+        {
+            metrics::metrics()
+                .events_deduplicated_total
+                .with_label_values(&[direction])
+                .inc();
+            return Ok(Admission::Duplicate);
+        }
+
+        let event_id = event.id;
+        let Some(event) = self
+            .plugins
+            .apply(direction, event)
+            .context(|| format!("{}: running plugins on event {}", direction, event_id))?
+        else {
+            return Ok(Admission::Dropped);
+        };
+        let Some(event) = self.transforms.apply(direction, event) else {
+            return Ok(Admission::Dropped);
+        };
+        let item = PipelineEvent::new(event, direction);
+        item.span.record("relay", relay);
+        Ok(Admission::Queue(item))
+    }
+
+    /// Receives the events matching the filter on every relay as they are
+    /// published and queues the new ones for the sender task.
+    ///
+    /// The subscription starts now and a fetch round catches up on the
+    /// events published since the checkpoints; events received both ways are
+    /// skipped by the dedupe table. The same catch up runs after a pause, a
+    /// failed event or dropped notifications. The checkpoints acknowledged by
+    /// the sender are committed every `sync.poll_interval_secs`, and a catch
+    /// up forgets the events in flight so no event is skipped.
+    ///
+    /// Returns once a shutdown is requested or the relay pool shut down.
+    async fn subscribe_loop(
+        &self,
+        direction: &'static str,
+        clock: &CheckpointClock,
+        tx: &mpsc::Sender<PipelineEvent>,
+        acks: &Arc<AckTracker>,
+    ) {
+        let interval = Duration::from_secs(self.config.sync.poll_interval_secs);
+        let mut backoff = Backoff::new(interval, FETCH_MAX_BACKOFF);
+        let mut notifications = self.nostr_client.notifications();
+        let subscription = loop {
+            match self.nostr_client.subscribe(Timestamp::now().as_u64()).await {
+                Ok(subscription) => break subscription,
+                Err(e) => {
+                    metrics::record_error(direction, "nostr", &e);
+                    logging::error_deduped(&format!("{}:subscribe:{}", direction, e.class()), e);
+                }
+            }
+            systemd::progress();
+            tokio::select! {
+                _ = tokio::time::sleep(backoff.next().unwrap_or(FETCH_MAX_BACKOFF)) => {}
+                _ = shutdown::wait_requested() => return,
+            }
+        };
+        backoff.reset();
+        tracing::info!(
+            "{}: subscribed to {} relay(s)",
+            direction,
+            self.config.nostr.relays().len()
+        );
+
+        let mut catch_up = true;
+        let mut commit = tokio::time::interval(interval);
+        while !shutdown::is_requested() {
+            if control::is_paused() {
+                systemd::progress();
+                tokio::select! {
+                    _ = tokio::time::sleep(interval) => {}
+                    _ = shutdown::wait_requested() => {}
+                }
+                catch_up = true;
+                continue;
+            }
+            if catch_up {
+                // Notifications received until now are covered by the round.
+                notifications = notifications.resubscribe();
+                acks.reset();
+                if let Err(e) = self.fetch_round(direction, clock, tx, acks).await {
+                    metrics::record_error(direction, "nostr", &e);
+                    logging::error_deduped(&format!("{}:fetch:{}", direction, e.class()), e);
+                    systemd::progress();
+                    tokio::select! {
+                        _ = tokio::time::sleep(backoff.next().unwrap_or(FETCH_MAX_BACKOFF)) => {}
+                        _ = shutdown::wait_requested() => {}
+                    }
+                    continue;
+                }
+                backoff.reset();
+                catch_up = false;
+            }
 
-                    let _ = tx.send(event).await;
+            tokio::select! {
+                notification = notifications.recv() => match notification {
+                    Ok(RelayPoolNotification::Event { relay_url, event, .. }) => {
+                        if control::is_paused() {
+                            continue;
+                        }
+                        let Some(relay) = self.config.nostr.relays().into_iter().find(|relay| {
+                            relay.trim_end_matches('/') == relay_url.as_str().trim_end_matches('/')
+                        }) else {
+                            continue;
+                        };
+                        metrics::metrics()
+                            .events_fetched_total
+                            .with_label_values(&[direction])
+                            .inc();
+                        if let Err(e) = self
+                            .receive_event(direction, relay, *event, clock, tx, acks)
+                            .await
+                        {
+                            metrics::record_error(direction, "db", &e);
+                            tracing::error!("{}: queueing a received event failed: {}", direction, e);
+                            catch_up = true;
+                        }
+                    }
+                    Ok(RelayPoolNotification::Shutdown) | Err(RecvError::Closed) => {
+                        tracing::warn!("{}: relay pool shut down, stopped receiving", direction);
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::warn!("{}: missed {} notifications, catching up", direction, missed);
+                        catch_up = true;
+                    }
+                },
+                _ = commit.tick() => {
+                    if let Err(e) = self.commit_acknowledged(direction, acks).await {
+                        metrics::record_error(direction, "db", &e);
+                        tracing::error!("{}: committing checkpoints failed: {}", direction, e);
+                    }
+                    self.observe_oldest_pending(direction).await;
+                    if let Err(e) = self.replay_requested(direction, tx).await {
+                        metrics::record_error(direction, "db", &e);
+                        tracing::error!("{}: replaying requested events failed: {}", direction, e);
+                    }
+                    systemd::progress();
                 }
+                _ = shutdown::wait_requested() => {}
             }
+        }
 
-            //update last fetch time in database
-            self.store
-                .update_last_update(last_fetch_time)
+        self.nostr_client.unsubscribe(subscription).await;
+    }
+
+    /// Records an event received from `relay` and queues it for the sender
+    /// task, unless it was already received. The checkpoint of the relay
+    /// moves past it once acknowledged, or at once if a plugin dropped it.
+    async fn receive_event(
+        &self,
+        direction: &'static str,
+        relay: String,
+        event: nostr_sdk::Event,
+        clock: &CheckpointClock,
+        tx: &mpsc::Sender<PipelineEvent>,
+        acks: &Arc<AckTracker>,
+    ) -> error::Result<()> {
+        let created_at = event.created_at.as_u64();
+        let item = match self.admit_event(direction, &relay, event).await? {
+            Admission::Duplicate => return Ok(()),
+            Admission::Dropped => None,
+            Admission::Queue(item) => Some(item),
+        };
+        let current = match acks.fetched(&relay) {
+            Some(current) => current,
+            None => self
+                .pipeline_store
+                .get_last_update(direction, &relay, 0)
                 .await
-                .unwrap();
+                .context(|| format!("{}: reading checkpoint of {}", direction, relay))?,
+        };
+        let last = clock.advance(current, created_at, Timestamp::now().as_u64());
+        let Some(mut item) = item else {
+            acks.skip(&relay, last);
+            return Ok(());
+        };
+        self.pipeline_store
+            .add_new_event(item.record())
+            .await
+            .context(|| format!("{}: recording event {}", direction, item.event.id))?;
 
-            tokio::time::sleep(Duration::from_secs(10)).await
+        item.ack = Some(acks.track(&relay, last));
+        tx.send(item)
+            .await
+            .map_err(|_| error::Error::CustomError(format!("{} sender task stopped", direction)))?;
+        Ok(())
+    }
+
+    /// Commits the checkpoints the sender acknowledged since the last commit,
+    /// see `AckTracker`.
+    async fn commit_acknowledged(
+        &self,
+        direction: &'static str,
+        acks: &AckTracker,
+    ) -> error::Result<()> {
+        let settled = acks.settled();
+        for (relay, last) in &settled {
+            self.pipeline_store
+                .update_last_update(direction, relay, *last)
+                .await
+                .context(|| {
+                    format!("{}: committing checkpoint {} of {}", direction, last, relay)
+                })?;
+            acks.committed(relay, *last);
+        }
+        if let Some(checkpoint) = settled.iter().map(|(_, last)| *last).min() {
+            metrics::observe_checkpoint(direction, checkpoint, Timestamp::now().as_u64());
         }
+        Ok(())
+    }
+
+    /// Updates the gauge of the age of the oldest event of `direction`
+    /// waiting for delivery, from the events recorded as pending, so it
+    /// covers the events held back in the retry queue too.
+    async fn observe_oldest_pending(&self, direction: &'static str) {
+        let oldest = match self.store.oldest_pending(direction).await {
+            Ok(oldest) => oldest,
+            Err(e) => {
+                metrics::record_error(direction, "db", &e);
+                tracing::warn!(
+                    "{}: reading the oldest pending event failed: {}",
+                    direction,
+                    e
+                );
+                return;
+            }
+        };
+        let age = oldest.map_or(0.0, |oldest| {
+            (Utc::now() - oldest.to_utc()).num_milliseconds().max(0) as f64 / 1000.0
+        });
+        metrics::metrics()
+            .oldest_pending_age_seconds
+            .with_label_values(&[direction])
+            .set(age);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{Event, EventBuilder, Keys, Kind};
+
+    fn created_at(times: &[u64]) -> Vec<Event> {
+        let keys = Keys::generate();
+        times
+            .iter()
+            .map(|time| {
+                EventBuilder::new(Kind::TextNote, "")
+                    .custom_created_at(Timestamp::from(*time))
+                    .sign_with_keys(&keys)
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    fn invalid_count(direction: &str) -> u64 {
+        metrics::metrics()
+            .events_invalid_total
+            .with_label_values(&[direction])
+            .get()
+    }
+
+    #[test]
+    fn admits_signed_events() {
+        let event = EventBuilder::new(Kind::TextNote, "invite")
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        assert!(is_authentic("test-authentic", &event));
+        assert_eq!(invalid_count("test-authentic"), 0);
+    }
+
+    #[test]
+    fn drops_tampered_events() {
+        let event = EventBuilder::new(Kind::TextNote, "invite")
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        let tampered = Event::new(
+            event.id,
+            event.pubkey,
+            event.created_at,
+            event.kind,
+            event.tags.iter().cloned(),
+            "revoke",
+            event.sig,
+        );
+        let forged = Event::new(
+            event.id,
+            Keys::generate().public_key(),
+            event.created_at,
+            event.kind,
+            event.tags.iter().cloned(),
+            event.content.clone(),
+            event.sig,
+        );
+        assert!(!is_authentic("test-tampered", &tampered));
+        assert!(!is_authentic("test-tampered", &forged));
+        assert_eq!(invalid_count("test-tampered"), 2);
+    }
+
+    #[test]
+    fn pages_through_full_pages() {
+        assert_eq!(
+            next_page(&created_at(&[30, 10, 20]), 3, None),
+            NextPage::Until(10)
+        );
+        assert_eq!(
+            next_page(&created_at(&[10, 8, 9]), 3, Some(10)),
+            NextPage::Until(8)
+        );
+    }
+
+    #[test]
+    fn stops_at_a_short_page() {
+        assert_eq!(next_page(&created_at(&[30, 10]), 3, None), NextPage::Done);
+        assert_eq!(next_page(&[], 3, Some(10)), NextPage::Done);
+    }
+
+    #[test]
+    fn stops_when_a_full_page_shares_the_cursor() {
+        assert_eq!(
+            next_page(&created_at(&[10, 10, 10]), 3, Some(10)),
+            NextPage::Stuck(10)
+        );
     }
 }
@@ -0,0 +1,145 @@
+//! Append-only audit trail of every bridged event.
+//!
+//! Each delivery attempt outcome is recorded with the event id, direction,
+//! source, sink, timestamps and result. Records go to the `audit_log` table
+//! (which rejects updates and deletes on Postgres) and/or to a JSON lines
+//! file opened in append mode, separate from the operational logs.
+
+use crate::common::config::AuditConfig;
+use crate::common::{error, logging};
+use crate::db;
+use crate::db::entities::prelude::AuditLogActiveModel;
+use chrono::{DateTime, Utc};
+use sea_orm::Set;
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// Outcome of a delivery recorded in the audit trail.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditRecord {
+    pub event_id: String,
+    pub correlation_id: String,
+    pub direction: String,
+    pub source: String,
+    pub sink: String,
+    pub event_created_at: u64,
+    pub received_at: DateTime<Utc>,
+    pub completed_at: DateTime<Utc>,
+    /// `delivered` or `failed`.
+    pub result: String,
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    /// Builds a record completed now from a delivery result.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        event_id: String,
+        correlation_id: String,
+        direction: &str,
+        source: &str,
+        sink: &str,
+        event_created_at: u64,
+        received_at: DateTime<Utc>,
+        result: &error::Result<()>,
+    ) -> Self {
+        let (result, error) = match result {
+            Ok(()) => ("delivered".to_string(), None),
+            Err(e) => ("failed".to_string(), Some(e.to_string())),
+        };
+        Self {
+            event_id,
+            correlation_id,
+            direction: direction.to_string(),
+            source: source.to_string(),
+            sink: sink.to_string(),
+            event_created_at,
+            received_at,
+            completed_at: Utc::now(),
+            result,
+            error,
+        }
+    }
+}
+
+/// Writes audit records to the configured destinations.
+///
+/// Cloning is cheap; clones share the same file handle. A disabled log
+/// ignores every record.
+#[derive(Clone, Default)]
+pub struct AuditLog {
+    store: Option<db::Storage>,
+    file: Option<Arc<Mutex<File>>>,
+}
+
+impl AuditLog {
+    /// Opens the audit destinations described by `config`.
+    pub async fn new(config: Option<&AuditConfig>, store: &db::Storage) -> error::Result<Self> {
+        let Some(config) = config else {
+            return Ok(Self::default());
+        };
+
+        let file = match &config.file {
+            Some(path) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await?;
+                Some(Arc::new(Mutex::new(file)))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            store: config.database.then(|| store.clone()),
+            file,
+        })
+    }
+
+    /// Appends a record. Failures are logged and never interrupt delivery.
+    pub async fn record(&self, record: AuditRecord) {
+        if let Some(store) = &self.store {
+            let model = AuditLogActiveModel {
+                event_id: Set(record.event_id.clone()),
+                correlation_id: Set(record.correlation_id.clone()),
+                direction: Set(record.direction.clone()),
+                source: Set(record.source.clone()),
+                sink: Set(record.sink.clone()),
+                event_created_at: Set(record.event_created_at as i64),
+                received_at: Set(record.received_at.into()),
+                completed_at: Set(record.completed_at.into()),
+                result: Set(record.result.clone()),
+                error: Set(record.error.clone()),
+                ..Default::default()
+            };
+            if let Err(e) = store.add_audit_record(model).await {
+                logging::error_deduped(
+                    &format!("audit:{}", e.class()),
+                    format_args!("failed to write audit record to database: {}", e),
+                );
+            }
+        }
+
+        if let Some(file) = &self.file {
+            let mut line = match serde_json::to_string(&record) {
+                Ok(line) => line,
+                Err(e) => {
+                    tracing::error!("failed to serialize audit record: {}", e);
+                    return;
+                }
+            };
+            line.push('\n');
+
+            let mut file = file.lock().await;
+            if let Err(e) = file.write_all(line.as_bytes()).await {
+                tracing::error!("failed to write audit record to file: {}", e);
+            } else if let Err(e) = file.sync_data().await {
+                tracing::error!("failed to sync audit file: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Backfills of historical events, started with the `backfill` command.
+//!
+//! The events created between two timestamps are fetched from every relay
+//! in consecutive windows and delivered by the source pipeline engine, with
+//! its dedupe, verification, plugins and transform rules, to the sink of an
+//! `n2*` direction. The checkpoints of the live pipelines are neither read
+//! nor moved, so a backfill can run next to them; events they already
+//! bridged are skipped.
+
+use super::App;
+use crate::common::correlation::CorrelationId;
+use crate::common::error;
+use crate::common::sink::EventSink;
+use async_trait::async_trait;
+use futures::StreamExt;
+use nostr_sdk::{Event, EventId};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Settings of a backfill.
+#[derive(Debug, Clone)]
+pub struct BackfillOptions {
+    /// Unix timestamp of the oldest event to bridge.
+    pub since: u64,
+    /// Unix timestamp of the newest event to bridge.
+    pub until: u64,
+    /// Length of the windows fetched at once, in seconds.
+    pub window_secs: u64,
+}
+
+/// Outcome of a backfill.
+#[derive(Debug, Default)]
+pub struct BackfillReport {
+    pub windows: u64,
+    /// Events fetched from the relays, counted once per event.
+    pub fetched: u64,
+    pub delivered: u64,
+    pub failed: u64,
+}
+
+/// Counts the deliveries of the wrapped sink.
+struct CountingSink {
+    inner: Arc<dyn EventSink>,
+    delivered: AtomicU64,
+    failed: AtomicU64,
+}
+
+#[async_trait]
+impl EventSink for CountingSink {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn topic(&self, event: &Event) -> Option<&str> {
+        self.inner.topic(event)
+    }
+
+    async fn send(&self, event: &Event, correlation_id: &CorrelationId) -> error::Result<()> {
+        self.send_to(event, correlation_id, None).await
+    }
+
+    async fn send_to(
+        &self,
+        event: &Event,
+        correlation_id: &CorrelationId,
+        topic: Option<&str>,
+    ) -> error::Result<()> {
+        let result = self.inner.send_to(event, correlation_id, topic).await;
+        let counter = if result.is_ok() {
+            &self.delivered
+        } else {
+            &self.failed
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+}
+
+/// Bridges the events created between `options.since` and `options.until`
+/// to the sink of `direction`, one of the `n2*` directions.
+///
+/// Failed deliveries are counted and logged, not retried: running the same
+/// backfill again delivers the events missing.
+///
+/// # Errors
+///
+/// Returns `InvalidConfig` for an empty time range or a direction not
+/// fetching from nostr, and the first error fetching from a relay.
+pub async fn backfill(
+    app: &App,
+    direction: &'static str,
+    options: BackfillOptions,
+) -> error::Result<BackfillReport> {
+    if options.since > options.until || options.window_secs == 0 {
+        return Err(error::Error::InvalidConfig(format!(
+            "cannot backfill from {} to {} in windows of {} seconds",
+            options.since, options.until, options.window_secs
+        )));
+    }
+    let sink = Arc::new(CountingSink {
+        inner: app.direction_sink(direction).await?,
+        delivered: AtomicU64::new(0),
+        failed: AtomicU64::new(0),
+    });
+
+    let (tx, rx) = mpsc::channel(app.config().sync.channel_capacity);
+    let fetcher = fetch_windows(app, direction, &options, tx);
+    let deliverer =
+        app.from_stream_to_sink(direction, ReceiverStream::new(rx).boxed(), sink.clone());
+    let ((windows, fetched), ()) = tokio::join!(fetcher, deliverer);
+    let windows = windows?;
+
+    Ok(BackfillReport {
+        windows,
+        fetched,
+        delivered: sink.delivered.load(Ordering::Relaxed),
+        failed: sink.failed.load(Ordering::Relaxed),
+    })
+}
+
+/// Fetches the windows of `options` from every relay and hands their events
+/// over to `tx`, oldest window first. Closes `tx` when done or failed.
+///
+/// # Returns
+///
+/// The number of windows fetched, or the error that stopped the backfill,
+/// and the number of events handed over.
+async fn fetch_windows(
+    app: &App,
+    direction: &str,
+    options: &BackfillOptions,
+    tx: mpsc::Sender<Event>,
+) -> (error::Result<u64>, u64) {
+    let client = app.nostr_client();
+    let relays = app.config().nostr.relays();
+    let limit = client.fetch_limit();
+    let total = (options.until - options.since) / options.window_secs + 1;
+    let mut fetched = 0;
+
+    for (index, start) in (options.since..=options.until)
+        .step_by(options.window_secs as usize)
+        .enumerate()
+    {
+        let end = start
+            .saturating_add(options.window_secs - 1)
+            .min(options.until);
+        let mut seen: HashSet<EventId> = HashSet::new();
+        for relay in &relays {
+            // A full page may leave older events of the window behind, so
+            // the window is fetched again up to the oldest event received.
+            let mut until = end;
+            loop {
+                let events = match client.fetch_window_from_relay(relay, start, until).await {
+                    Ok(events) => events,
+                    Err(e) => {
+                        let e = error::Error::CustomError(format!(
+                            "fetching {}..{} from {}: {}",
+                            start, end, relay, e
+                        ));
+                        return (Err(e), fetched);
+                    }
+                };
+                let page = events.len();
+                let oldest = events
+                    .iter()
+                    .map(|event| event.created_at.as_u64())
+                    .min()
+                    .unwrap_or(start);
+                for event in events.into_iter() {
+                    if !seen.insert(event.id) {
+                        continue;
+                    }
+                    fetched += 1;
+                    if tx.send(event).await.is_err() {
+                        return (Ok(index as u64), fetched);
+                    }
+                }
+                if page < limit {
+                    break;
+                }
+                if oldest >= until {
+                    tracing::warn!(
+                        "backfill {}: more than {} events at {} on {}, some were skipped",
+                        direction,
+                        limit,
+                        until,
+                        relay
+                    );
+                    break;
+                }
+                until = oldest;
+            }
+        }
+        tracing::info!(
+            "backfill {}: window {}/{} ({}..{}) fetched, {} events so far",
+            direction,
+            index + 1,
+            total,
+            start,
+            end,
+            fetched
+        );
+    }
+
+    (Ok(total), fetched)
+}
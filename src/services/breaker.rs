@@ -0,0 +1,315 @@
+//! Circuit breaker around the sink of a pipeline.
+//!
+//! After `failure_threshold` transient failures in a row the breaker opens:
+//! the sender buffers the events it dequeues in the retry queue instead of
+//! delivering them, and the fetch loop skips its rounds. Once `open_secs`
+//! have passed the breaker is half open and lets one delivery at a time
+//! through as a probe; `probe_successes` successful probes in a row close
+//! it, and a failed one opens it again.
+//!
+//! Only transient errors count as failures: a permanent rejection means the
+//! sink is up and refused that one event.
+
+use crate::common::config::{CircuitBreakerConfig, Config};
+use crate::common::correlation::CorrelationId;
+use crate::common::error;
+use crate::common::sink::EventSink;
+use crate::metrics::{self, BreakerState};
+use async_trait::async_trait;
+use nostr_sdk::Event;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default)]
+struct Breaker {
+    state: BreakerState,
+    /// Transient failures in a row since the last success.
+    failures: u32,
+    /// Successful probes in a row while half open.
+    successes: u32,
+    /// When the breaker last opened.
+    opened_at: Option<Instant>,
+    /// A probe is in flight while half open.
+    probing: bool,
+}
+
+/// Wraps the sink of a pipeline, see the module documentation.
+pub struct CircuitBreaker {
+    direction: &'static str,
+    sink: Arc<dyn EventSink>,
+    config: CircuitBreakerConfig,
+    breaker: Mutex<Breaker>,
+}
+
+impl CircuitBreaker {
+    pub fn new(
+        direction: &'static str,
+        sink: Arc<dyn EventSink>,
+        config: &CircuitBreakerConfig,
+    ) -> Self {
+        metrics::record_breaker(direction, sink.name(), BreakerState::Closed, 0);
+        Self {
+            direction,
+            sink,
+            config: config.clone(),
+            breaker: Mutex::new(Breaker::default()),
+        }
+    }
+
+    /// Tells whether deliveries are held back: the breaker is open and no
+    /// probe is due yet.
+    pub fn is_open(&self) -> bool {
+        !self.retry_after().is_zero()
+    }
+
+    /// Returns the time left until the next probe, zero unless the breaker
+    /// is open.
+    pub fn retry_after(&self) -> Duration {
+        let breaker = self.lock();
+        match (breaker.state, breaker.opened_at) {
+            (BreakerState::Open, Some(opened_at)) => {
+                self.open_duration().saturating_sub(opened_at.elapsed())
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Tells whether a delivery may go through, turning a breaker open for
+    /// `open_secs` half open and reserving its probe.
+    fn admit(&self) -> bool {
+        let mut breaker = self.lock();
+        match breaker.state {
+            BreakerState::Closed => true,
+            BreakerState::Open => {
+                if breaker
+                    .opened_at
+                    .is_some_and(|at| at.elapsed() < self.open_duration())
+                {
+                    return false;
+                }
+                breaker.state = BreakerState::HalfOpen;
+                breaker.successes = 0;
+                breaker.probing = true;
+                tracing::info!(
+                    sink = self.sink.name(),
+                    "{}: circuit breaker half open, probing the sink",
+                    self.direction
+                );
+                self.publish(&breaker);
+                true
+            }
+            BreakerState::HalfOpen if breaker.probing => false,
+            BreakerState::HalfOpen => {
+                breaker.probing = true;
+                true
+            }
+        }
+    }
+
+    /// Updates the breaker with the outcome of an admitted delivery.
+    fn record(&self, result: &error::Result<()>) {
+        let mut breaker = self.lock();
+        breaker.probing = false;
+        let failed = result.as_ref().is_err_and(|e| e.is_transient());
+        match (breaker.state, failed) {
+            (BreakerState::HalfOpen, false) => {
+                breaker.successes += 1;
+                if breaker.successes < self.config.probe_successes {
+                    return;
+                }
+                breaker.state = BreakerState::Closed;
+                breaker.failures = 0;
+                tracing::info!(
+                    sink = self.sink.name(),
+                    "{}: circuit breaker closed, the sink recovered",
+                    self.direction
+                );
+            }
+            (_, false) => {
+                if breaker.failures == 0 {
+                    return;
+                }
+                breaker.failures = 0;
+            }
+            (BreakerState::HalfOpen, true) => {
+                breaker.failures += 1;
+                self.open(&mut breaker);
+            }
+            (_, true) => {
+                breaker.failures += 1;
+                if breaker.state == BreakerState::Closed
+                    && breaker.failures >= self.config.failure_threshold
+                {
+                    self.open(&mut breaker);
+                }
+            }
+        }
+        self.publish(&breaker);
+    }
+
+    fn open(&self, breaker: &mut Breaker) {
+        breaker.state = BreakerState::Open;
+        breaker.opened_at = Some(Instant::now());
+        metrics::record_trip(self.direction, self.sink.name());
+        tracing::warn!(
+            sink = self.sink.name(),
+            failures = breaker.failures,
+            "{}: circuit breaker open, pausing deliveries for {:?}",
+            self.direction,
+            self.open_duration()
+        );
+    }
+
+    fn publish(&self, breaker: &Breaker) {
+        metrics::record_breaker(
+            self.direction,
+            self.sink.name(),
+            breaker.state,
+            breaker.failures,
+        );
+    }
+
+    fn open_duration(&self) -> Duration {
+        Duration::from_secs(self.config.open_secs)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Breaker> {
+        self.breaker.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl EventSink for CircuitBreaker {
+    fn name(&self) -> &'static str {
+        self.sink.name()
+    }
+
+    fn topic(&self, event: &Event) -> Option<&str> {
+        self.sink.topic(event)
+    }
+
+    fn times_attempts(&self) -> bool {
+        self.sink.times_attempts()
+    }
+
+    async fn send(&self, event: &Event, correlation_id: &CorrelationId) -> error::Result<()> {
+        self.send_to(event, correlation_id, None).await
+    }
+
+    async fn send_to(
+        &self,
+        event: &Event,
+        correlation_id: &CorrelationId,
+        topic: Option<&str>,
+    ) -> error::Result<()> {
+        if !self.admit() {
+            return Err(error::Error::CircuitOpen(self.sink.name()));
+        }
+        let result = self.sink.send_to(event, correlation_id, topic).await;
+        self.record(&result);
+        result
+    }
+
+    fn reload(&self, config: &Config) {
+        self.sink.reload(config);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Stub;
+
+    #[async_trait]
+    impl EventSink for Stub {
+        fn name(&self) -> &'static str {
+            "stub"
+        }
+
+        async fn send(&self, _event: &Event, _correlation_id: &CorrelationId) -> error::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn breaker(open_secs: u64, probe_successes: u32) -> CircuitBreaker {
+        let config = CircuitBreakerConfig {
+            failure_threshold: 2,
+            open_secs,
+            probe_successes,
+        };
+        CircuitBreaker::new("test", Arc::new(Stub), &config)
+    }
+
+    fn transient() -> error::Result<()> {
+        Err(error::Error::Conflict("busy".to_string()))
+    }
+
+    fn state(breaker: &CircuitBreaker) -> BreakerState {
+        breaker.lock().state
+    }
+
+    fn trip(breaker: &CircuitBreaker) {
+        for _ in 0..2 {
+            assert!(breaker.admit());
+            breaker.record(&transient());
+        }
+        assert_eq!(state(breaker), BreakerState::Open);
+    }
+
+    #[test]
+    fn opens_after_the_threshold() {
+        let breaker = breaker(60, 1);
+        breaker.record(&transient());
+        assert_eq!(state(&breaker), BreakerState::Closed);
+        breaker.record(&Ok(()));
+        breaker.record(&transient());
+        assert_eq!(state(&breaker), BreakerState::Closed);
+
+        breaker.record(&transient());
+        assert_eq!(state(&breaker), BreakerState::Open);
+        assert!(breaker.is_open());
+        assert!(!breaker.admit());
+        assert!(breaker.retry_after() > Duration::from_secs(59));
+    }
+
+    #[test]
+    fn permanent_errors_do_not_open() {
+        let breaker = breaker(60, 1);
+        for _ in 0..3 {
+            breaker.record(&Err(error::Error::InvalidConfig("bad".to_string())));
+        }
+        assert_eq!(state(&breaker), BreakerState::Closed);
+        assert!(breaker.admit());
+    }
+
+    #[test]
+    fn successful_probes_close_it() {
+        let breaker = breaker(0, 2);
+        trip(&breaker);
+        assert!(!breaker.is_open());
+
+        assert!(breaker.admit());
+        assert_eq!(state(&breaker), BreakerState::HalfOpen);
+        assert!(!breaker.admit(), "one probe at a time");
+        breaker.record(&Ok(()));
+        assert_eq!(state(&breaker), BreakerState::HalfOpen);
+
+        assert!(breaker.admit());
+        breaker.record(&Ok(()));
+        assert_eq!(state(&breaker), BreakerState::Closed);
+        assert!(breaker.admit());
+        assert!(breaker.admit());
+    }
+
+    #[test]
+    fn a_failed_probe_opens_it_again() {
+        let breaker = breaker(0, 1);
+        trip(&breaker);
+        assert!(breaker.admit());
+        assert_eq!(state(&breaker), BreakerState::HalfOpen);
+
+        breaker.record(&transient());
+        assert_eq!(state(&breaker), BreakerState::Open);
+    }
+}
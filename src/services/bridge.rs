@@ -0,0 +1,229 @@
+//! Entry point for embedding the bridge in another service.
+//!
+//! A `Bridge` runs one or several directions on one `App`, as the `run`
+//! command does, without the signal handling and servers of the binary:
+//!
+//! ```no_run
+//! # async fn example(config: nostr_gateway::Config) -> nostr_gateway::common::error::Result<()> {
+//! let bridge = nostr_gateway::Bridge::builder(config)
+//!     .direction("n2w")
+//!     .direction("w2i")
+//!     .build()
+//!     .await?;
+//! bridge.run().await;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! `run` returns once `services::shutdown::request` is called and the queued events
+//! are delivered.
+
+use super::App;
+use crate::common::config::Config;
+use crate::common::{error, validation};
+use crate::db;
+use futures::future::join_all;
+
+/// The directions a `Bridge` can run:
+/// - `n2w`: from nostr to waku.
+/// - `w2n`: from waku to nostr.
+/// - `w2i`: from waku to index db.
+/// - `n2w2n`: from nostr to waku and back, without looping events.
+/// - `n2i`: from nostr to index db.
+/// - `n2k` and `k2n`: from nostr to kafka and back.
+/// - `n2m` and `m2n`: from nostr to mqtt and back.
+/// - `n2h`: from nostr to webhooks.
+/// - `n2x`: from nostr to a matrix room.
+/// - `n2q`: from nostr to an sqs queue or sns topic.
+/// - `n2e`: from nostr to email recipients.
+/// - `n2s`: from nostr to every sink listed in `sinks`.
+pub const DIRECTIONS: &[&str] = &[
+    "n2w", "w2n", "w2i", "n2w2n", "n2i", "n2k", "k2n", "n2m", "m2n", "n2h", "n2x", "n2q", "n2e",
+    "n2s",
+];
+
+/// Checks the directions to run together, dropping repeated ones.
+///
+/// Directions keep their own checkpoints, dedupe records and retries, so
+/// any of them can run together, except `n2w2n` with the `n2w` or `w2n`
+/// it already runs.
+///
+/// # Errors
+///
+/// Returns `Error::InvalidConfig` if a direction is unknown or `n2w2n` runs
+/// along with `n2w` or `w2n`.
+pub fn check_directions<'a>(
+    directions: impl IntoIterator<Item = &'a str>,
+) -> error::Result<Vec<&'static str>> {
+    let mut checked: Vec<&'static str> = Vec::new();
+    for direction in directions {
+        let Some(known) = DIRECTIONS.iter().copied().find(|known| *known == direction) else {
+            return Err(error::Error::InvalidConfig(format!(
+                "unknown direction `{}`",
+                direction
+            )));
+        };
+        if !checked.contains(&known) {
+            checked.push(known);
+        }
+    }
+
+    if checked.contains(&"n2w2n") {
+        if let Some(twice) = checked
+            .iter()
+            .find(|direction| ["n2w", "w2n"].contains(direction))
+        {
+            return Err(error::Error::InvalidConfig(format!(
+                "n2w2n already runs {}, drop one of them",
+                twice
+            )));
+        }
+    }
+    Ok(checked)
+}
+
+/// Builds a `Bridge`.
+pub struct BridgeBuilder {
+    config: Config,
+    store: Option<db::Storage>,
+    directions: Vec<String>,
+}
+
+impl BridgeBuilder {
+    /// Adds a direction to run, see `DIRECTIONS`.
+    pub fn direction(mut self, direction: &str) -> Self {
+        self.directions.push(direction.to_string());
+        self
+    }
+
+    /// Uses an already connected `store` instead of connecting to
+    /// `database.db_url`.
+    pub fn store(mut self, store: db::Storage) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Validates the configuration and the directions, then connects to the
+    /// database, the relays and the embedded Waku node.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidConfig` if no direction was added or the
+    /// directions cannot run together, and any error of `App::new`.
+    pub async fn build(self) -> error::Result<Bridge> {
+        let directions = check_directions(self.directions.iter().map(String::as_str))?;
+        if directions.is_empty() {
+            return Err(error::Error::InvalidConfig(
+                "a bridge needs at least one direction".to_string(),
+            ));
+        }
+
+        let app = match self.store {
+            Some(store) => {
+                validation::validate(&self.config)?;
+                App::open(self.config, store).await?
+            }
+            None => App::new(self.config).await?,
+        };
+        Ok(Bridge { app, directions })
+    }
+}
+
+/// Runs the pipelines of one or several directions.
+pub struct Bridge {
+    app: App,
+    directions: Vec<&'static str>,
+}
+
+impl Bridge {
+    /// Starts building a bridge running with `config`.
+    pub fn builder(config: Config) -> BridgeBuilder {
+        BridgeBuilder {
+            config,
+            store: None,
+            directions: Vec::new(),
+        }
+    }
+
+    /// Returns the app running the pipelines, e.g. to start its admin API.
+    pub fn app(&self) -> &App {
+        &self.app
+    }
+
+    /// Returns the directions the bridge runs.
+    pub fn directions(&self) -> &[&'static str] {
+        &self.directions
+    }
+
+    /// Runs the directions concurrently until a shutdown is requested, then
+    /// flushes the buffered writes and disconnects, see `App::shutdown`.
+    pub async fn run(&self) {
+        join_all(
+            self.directions
+                .iter()
+                .map(|direction| run_direction(&self.app, direction)),
+        )
+        .await;
+        self.app.shutdown().await;
+    }
+}
+
+/// Runs the pipeline of `direction` until it stops.
+///
+/// A pipeline that cannot start, e.g. because its sink is misconfigured,
+/// is logged without stopping the other directions.
+async fn run_direction(app: &App, direction: &str) {
+    let result = match direction {
+        "n2w" => {
+            app.start_lag_monitor("n2w");
+            app.from_nostr_to_waku().await
+        }
+        "w2n" => app.from_waku_to_nostr().await,
+        "w2i" => app.from_waku_to_indexdb().await,
+        "n2w2n" => {
+            app.start_lag_monitor("n2w");
+            app.from_nostr_to_waku_and_back().await
+        }
+        "n2i" => {
+            app.start_lag_monitor("n2i");
+            app.from_nostr_to_indexdb().await
+        }
+        "n2k" => {
+            app.start_lag_monitor("n2k");
+            app.from_nostr_to_kafka().await
+        }
+        "k2n" => app.from_kafka_to_nostr().await,
+        "n2m" => {
+            app.start_lag_monitor("n2m");
+            app.from_nostr_to_mqtt().await
+        }
+        "m2n" => app.from_mqtt_to_nostr().await,
+        "n2h" => {
+            app.start_lag_monitor("n2h");
+            app.from_nostr_to_webhook().await
+        }
+        "n2x" => {
+            app.start_lag_monitor("n2x");
+            app.from_nostr_to_matrix().await
+        }
+        "n2q" => {
+            app.start_lag_monitor("n2q");
+            app.from_nostr_to_sqs().await
+        }
+        "n2e" => {
+            app.start_lag_monitor("n2e");
+            app.from_nostr_to_smtp().await
+        }
+        "n2s" => {
+            app.start_lag_monitor("n2s");
+            app.from_nostr_to_sinks().await
+        }
+        _ => Err(error::Error::InvalidConfig(format!(
+            "unknown direction `{}`",
+            direction
+        ))),
+    };
+    if let Err(e) = result {
+        tracing::error!("{} stopped: {}", direction, e);
+    }
+}
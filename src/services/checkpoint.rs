@@ -0,0 +1,152 @@
+//! Computes how far the `since` checkpoint may advance for fetched events.
+//!
+//! A relay (or a publisher) with a broken clock can report `created_at`
+//! values far in the future. Advancing the checkpoint to such a value would
+//! make every later fetch skip real events, so timestamps are checked against
+//! the local clock and the configured tolerance before they are used.
+
+use crate::common::config::{FutureTimestampPolicy, TimestampConfig, TimestampSource};
+
+/// Applies the `timestamps` config section to checkpoint updates.
+#[derive(Debug, Clone)]
+pub struct CheckpointClock {
+    config: TimestampConfig,
+}
+
+impl CheckpointClock {
+    pub fn new(config: TimestampConfig) -> Self {
+        Self { config }
+    }
+
+    /// Returns the checkpoint after seeing an event.
+    ///
+    /// # Arguments
+    ///
+    /// * `current` - The checkpoint before the event.
+    /// * `created_at` - The `created_at` reported by the event.
+    /// * `received_at` - The local time at which the event was fetched.
+    pub fn advance(&self, current: u64, created_at: u64, received_at: u64) -> u64 {
+        let candidate = match self.config.source {
+            TimestampSource::ReceivedAt => received_at,
+            TimestampSource::CreatedAt => {
+                let limit = received_at.saturating_add(self.config.max_future_skew_secs);
+                if created_at <= limit {
+                    created_at
+                } else {
+                    tracing::warn!(
+                        "event created_at {} is {}s ahead of local time",
+                        created_at,
+                        created_at - received_at
+                    );
+                    match self.config.on_future {
+                        FutureTimestampPolicy::Clamp => received_at,
+                        FutureTimestampPolicy::Ignore => current,
+                    }
+                }
+            }
+        };
+
+        current.max(candidate)
+    }
+
+    /// Returns the checkpoint after each event of a fetch round.
+    ///
+    /// The checkpoint after an event is committed once that event is
+    /// settled, while the events after it may still wait for their delivery.
+    /// A checkpoint taken from the local clock, with `received_at` or a
+    /// clamped `created_at`, could pass them, so each checkpoint is kept at
+    /// or below the `created_at` of the queued events after it.
+    ///
+    /// # Arguments
+    ///
+    /// * `current` - The checkpoint before the round.
+    /// * `events` - The `created_at` of the events, oldest first, and whether
+    ///   each is queued for delivery.
+    /// * `received_at` - The local time at which the events were fetched.
+    pub fn advance_round(
+        &self,
+        current: u64,
+        events: &[(u64, bool)],
+        received_at: u64,
+    ) -> Vec<u64> {
+        let mut last = current;
+        let mut checkpoints: Vec<u64> = events
+            .iter()
+            .map(|&(created_at, _)| {
+                last = self.advance(last, created_at, received_at);
+                last
+            })
+            .collect();
+
+        let mut oldest_queued = u64::MAX;
+        for (checkpoint, &(created_at, queued)) in checkpoints.iter_mut().zip(events).rev() {
+            *checkpoint = (*checkpoint).min(oldest_queued).max(current);
+            if queued {
+                oldest_queued = oldest_queued.min(created_at);
+            }
+        }
+        checkpoints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(source: TimestampSource, on_future: FutureTimestampPolicy) -> CheckpointClock {
+        CheckpointClock::new(TimestampConfig {
+            source,
+            max_future_skew_secs: 60,
+            on_future,
+        })
+    }
+
+    #[test]
+    fn advances_to_created_at() {
+        let clock = clock(TimestampSource::CreatedAt, FutureTimestampPolicy::Clamp);
+        assert_eq!(clock.advance(100, 150, 1000), 150);
+        assert_eq!(clock.advance(100, 1050, 1000), 1050);
+    }
+
+    #[test]
+    fn never_moves_back() {
+        let clock = clock(TimestampSource::CreatedAt, FutureTimestampPolicy::Clamp);
+        assert_eq!(clock.advance(200, 150, 1000), 200);
+    }
+
+    #[test]
+    fn future_created_at_is_clamped_or_ignored() {
+        let clamp = clock(TimestampSource::CreatedAt, FutureTimestampPolicy::Clamp);
+        assert_eq!(clamp.advance(100, 5000, 1000), 1000);
+
+        let ignore = clock(TimestampSource::CreatedAt, FutureTimestampPolicy::Ignore);
+        assert_eq!(ignore.advance(100, 5000, 1000), 100);
+    }
+
+    #[test]
+    fn received_at_source_uses_the_local_time() {
+        let clock = clock(TimestampSource::ReceivedAt, FutureTimestampPolicy::Clamp);
+        assert_eq!(clock.advance(100, 150, 1000), 1000);
+    }
+
+    #[test]
+    fn round_checkpoints_stay_behind_later_queued_events() {
+        let clock = clock(TimestampSource::ReceivedAt, FutureTimestampPolicy::Clamp);
+        let checkpoints = clock.advance_round(50, &[(100, false), (200, true)], 1000);
+        assert_eq!(checkpoints, vec![200, 1000]);
+    }
+
+    #[test]
+    fn round_checkpoints_follow_created_at() {
+        let clock = clock(TimestampSource::CreatedAt, FutureTimestampPolicy::Clamp);
+        let checkpoints = clock.advance_round(50, &[(100, true), (200, true)], 1000);
+        assert_eq!(checkpoints, vec![100, 200]);
+    }
+
+    #[test]
+    fn round_checkpoints_never_fall_below_the_current_one() {
+        let clock = clock(TimestampSource::CreatedAt, FutureTimestampPolicy::Clamp);
+        let checkpoints = clock.advance_round(300, &[(100, false), (200, true)], 1000);
+        assert_eq!(checkpoints, vec![300, 300]);
+    }
+}
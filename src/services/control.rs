@@ -0,0 +1,206 @@
+//! Local control socket of the bridge.
+//!
+//! The socket at `server.control_socket` accepts one JSON request per line
+//! and answers each with one JSON line, so operators on the host can steer
+//! the bridge without exposing the admin API. A request is an object with a
+//! `command`:
+//!
+//! - `status`: the direction, whether fetching is paused, the checkpoint,
+//!   the number of dead letters and the traffic counters.
+//! - `pause`: stops fetching new events until `resume`. Queued events are
+//!   still delivered.
+//! - `resume`: resumes fetching.
+//! - `replay`: delivers the recorded event `event_id` again.
+//! - `reload`: re-reads the configuration file and applies it as SIGHUP
+//!   does, see `Reloader`.
+//!
+//! A response carries `"ok": true` and the `result`, or `"ok": false` and
+//! the `error`.
+
+use super::Reloader;
+use crate::common::config::Config;
+use crate::common::error;
+use crate::db;
+use crate::metrics;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::watch;
+
+static PAUSED: OnceLock<watch::Sender<bool>> = OnceLock::new();
+/// Events to replay, with the direction delivering them again.
+static REPLAYS: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// A request sent over the control socket.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase")]
+pub enum ControlRequest {
+    Status,
+    Pause,
+    Resume,
+    Replay { event_id: String },
+    Reload,
+}
+
+fn paused() -> &'static watch::Sender<bool> {
+    PAUSED.get_or_init(|| watch::channel(false).0)
+}
+
+/// Returns whether fetching was paused over the control socket.
+pub fn is_paused() -> bool {
+    *paused().borrow()
+}
+
+/// Waits until fetching is not paused.
+pub async fn wait_resumed() {
+    let mut paused = paused().subscribe();
+    let _ = paused.wait_for(|paused| !paused).await;
+}
+
+/// Takes the ids of the events `direction` was asked to replay since its
+/// last call.
+pub fn take_replays(direction: &str) -> Vec<String> {
+    let mut replays = REPLAYS.lock().unwrap_or_else(|e| e.into_inner());
+    let (taken, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut *replays)
+        .into_iter()
+        .partition(|(replayed_by, _)| replayed_by == direction);
+    *replays = kept;
+    taken.into_iter().map(|(_, event_id)| event_id).collect()
+}
+
+/// Serves the control socket until the task is dropped.
+pub struct ControlServer {
+    path: String,
+    direction: String,
+    config_file: PathBuf,
+    profile: Option<String>,
+    store: db::Storage,
+    reloader: Arc<Reloader>,
+}
+
+impl ControlServer {
+    /// Creates a server on the socket at `path` for the bridge running
+    /// `direction` with the configuration `config_file` and `profile`.
+    pub fn new(
+        path: &str,
+        direction: &str,
+        config_file: PathBuf,
+        profile: Option<String>,
+        store: db::Storage,
+        reloader: Arc<Reloader>,
+    ) -> Self {
+        Self {
+            path: path.to_string(),
+            direction: direction.to_string(),
+            config_file,
+            profile,
+            store,
+            reloader,
+        }
+    }
+
+    pub async fn run(self) -> error::Result<()> {
+        // A socket left behind by a previous run would make the bind fail,
+        // but one still accepting connections belongs to a running gateway.
+        if UnixStream::connect(&self.path).await.is_ok() {
+            return Err(error::Error::CustomError(format!(
+                "control socket {} is in use by another process",
+                self.path
+            )));
+        }
+        let _ = std::fs::remove_file(&self.path);
+        let listener = UnixListener::bind(&self.path)?;
+        // Only the user running the gateway may steer it.
+        std::fs::set_permissions(&self.path, std::fs::Permissions::from_mode(0o600))?;
+        tracing::info!("control socket listening on {}", self.path);
+
+        let server = Arc::new(self);
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = server.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.serve(stream).await {
+                    tracing::debug!("control connection closed: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Answers the requests of one connection until the client closes it.
+    async fn serve(&self, stream: UnixStream) -> error::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            let response = match serde_json::from_str::<ControlRequest>(&line) {
+                Ok(request) => match self.handle(request).await {
+                    Ok(result) => json!({ "ok": true, "result": result }),
+                    Err(e) => {
+                        json!({ "ok": false, "error": e.to_string(), "code": e.error_code() })
+                    }
+                },
+                Err(e) => json!({ "ok": false, "error": format!("invalid request: {}", e) }),
+            };
+            writer
+                .write_all(format!("{}\n", response).as_bytes())
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn handle(&self, request: ControlRequest) -> error::Result<Value> {
+        match request {
+            ControlRequest::Status => {
+                let checkpoint = self.store.get_checkpoint().await?;
+                Ok(json!({
+                    "direction": self.direction,
+                    "paused": is_paused(),
+                    "checkpoint": checkpoint.map(|c| c.last_update),
+                    "dead_letters": self.store.count_dead_letters().await?,
+                    "traffic": metrics::traffic_snapshot(),
+                }))
+            }
+            ControlRequest::Pause => {
+                paused().send_replace(true);
+                tracing::info!(
+                    "{}: fetching paused over the control socket",
+                    self.direction
+                );
+                Ok(json!({ "paused": true }))
+            }
+            ControlRequest::Resume => {
+                paused().send_replace(false);
+                tracing::info!(
+                    "{}: fetching resumed over the control socket",
+                    self.direction
+                );
+                Ok(json!({ "paused": false }))
+            }
+            ControlRequest::Replay { event_id } => {
+                let directions = self.store.reopen_event(&event_id).await?;
+                if directions.is_empty() {
+                    return Err(error::Error::CustomError(format!(
+                        "event {} was never recorded",
+                        event_id
+                    )));
+                }
+                REPLAYS.lock().unwrap_or_else(|e| e.into_inner()).extend(
+                    directions
+                        .iter()
+                        .map(|direction| (direction.clone(), event_id.clone())),
+                );
+                Ok(json!({ "queued": event_id, "directions": directions }))
+            }
+            ControlRequest::Reload => {
+                let config =
+                    Config::load_profile(self.config_file.clone(), self.profile.as_deref())?;
+                let summary = self.reloader.reload(&config)?;
+                tracing::info!("reloaded {} over the control socket", summary);
+                Ok(summary)
+            }
+        }
+    }
+}
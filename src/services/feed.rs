@@ -0,0 +1,42 @@
+//! In-process feed of bridged events.
+//!
+//! Every event whose delivery settles, delivered or dead-lettered, is
+//! published to a broadcast channel that API clients subscribe to. A
+//! subscriber that falls behind misses events instead of slowing down the
+//! pipelines.
+
+use crate::db::DeliveryStatus;
+use chrono::{DateTime, Utc};
+use nostr_sdk::Event;
+use std::sync::OnceLock;
+use tokio::sync::broadcast;
+
+/// Number of events buffered for the slowest subscriber.
+const FEED_CAPACITY: usize = 1024;
+
+/// A bridged event and the outcome of its delivery.
+#[derive(Clone, Debug)]
+pub struct BridgedEvent {
+    pub event: Event,
+    pub direction: &'static str,
+    /// Name of the sink the event was delivered to, e.g. `waku`.
+    pub sink: String,
+    pub status: DeliveryStatus,
+    pub correlation_id: String,
+    pub bridged_at: DateTime<Utc>,
+}
+
+fn feed() -> &'static broadcast::Sender<BridgedEvent> {
+    static FEED: OnceLock<broadcast::Sender<BridgedEvent>> = OnceLock::new();
+    FEED.get_or_init(|| broadcast::channel(FEED_CAPACITY).0)
+}
+
+/// Publishes a settled event to the current subscribers, if any.
+pub fn publish(event: BridgedEvent) {
+    let _ = feed().send(event);
+}
+
+/// Subscribes to the events settled from now on.
+pub fn subscribe() -> broadcast::Receiver<BridgedEvent> {
+    feed().subscribe()
+}
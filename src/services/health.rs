@@ -0,0 +1,158 @@
+//! Health check endpoints for orchestrators such as Kubernetes or systemd.
+//!
+//! The endpoints listen on `server.host:server.health_port`:
+//!
+//! - `GET /healthz`: checks every dependency of the running directions, i.e.
+//!   the database, the Nostr relays, the Waku node and the IndexDB backend,
+//!   and answers `503 Service Unavailable` if one of them is down, so the
+//!   bridge gets restarted.
+//! - `GET /readyz`: runs the same checks and also fails while the bridge is
+//!   paused or shutting down, so no traffic is routed to it.
+//!
+//! Both answer with the status of each dependency as JSON, e.g.
+//! `{"status":"down","dependencies":{"database":"up","waku node":"down: ..."}}`.
+
+use super::startup::{self, Dependency};
+use super::{control, shutdown};
+use crate::common::config::Config;
+use crate::common::error;
+use crate::db;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use futures::future::join_all;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// Serves the health check endpoints until the task is dropped.
+pub struct HealthServer {
+    addr: String,
+    checker: Arc<HealthChecker>,
+}
+
+impl HealthServer {
+    pub fn new(config: &Config, port: &str, store: db::Storage, directions: &[&str]) -> Self {
+        let mut dependencies = Vec::new();
+        for dependency in directions
+            .iter()
+            .flat_map(|direction| startup::dependencies(config, direction))
+        {
+            if !dependencies.contains(&dependency) {
+                dependencies.push(dependency);
+            }
+        }
+        Self {
+            addr: format!("{}:{}", config.server.host, port),
+            checker: Arc::new(HealthChecker {
+                dependencies,
+                config: config.clone(),
+                store,
+            }),
+        }
+    }
+
+    /// Binds the listen address and serves requests.
+    pub async fn run(self) -> error::Result<()> {
+        let listener = TcpListener::bind(&self.addr).await?;
+        tracing::info!("health checks listening on {}", self.addr);
+        let router = Router::new()
+            .route("/healthz", get(get_healthz))
+            .route("/readyz", get(get_readyz))
+            .with_state(self.checker);
+        axum::serve(listener, router).await?;
+        Ok(())
+    }
+}
+
+/// Checks the dependencies of the running directions.
+struct HealthChecker {
+    config: Config,
+    store: db::Storage,
+    dependencies: Vec<Dependency>,
+}
+
+impl HealthChecker {
+    /// Checks every dependency concurrently. The bridge is reported down if
+    /// one of them is, or if `reason` is set.
+    async fn report(&self, reason: Option<&'static str>) -> (StatusCode, Json<HealthReport>) {
+        let results = join_all(
+            self.dependencies
+                .iter()
+                .map(|dependency| self.check(*dependency)),
+        )
+        .await;
+
+        let healthy = reason.is_none() && results.iter().all(Result::is_ok);
+        let dependencies = self
+            .dependencies
+            .iter()
+            .zip(results)
+            .map(|(dependency, result)| {
+                let status = match result {
+                    Ok(()) => "up".to_string(),
+                    Err(e) => format!("down: {}", e),
+                };
+                (dependency.as_str(), status)
+            })
+            .collect();
+
+        let status = if healthy {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        let report = HealthReport {
+            status: if healthy { "up" } else { "down" },
+            reason,
+            dependencies,
+        };
+        (status, Json(report))
+    }
+
+    /// Checks a single dependency. The database is pinged over the pool of
+    /// the bridge rather than connected to again, as `startup` does.
+    async fn check(&self, dependency: Dependency) -> error::Result<()> {
+        if dependency != Dependency::Database {
+            return startup::check_one(&self.config, &self.config.startup, dependency).await;
+        }
+
+        let limit = Duration::from_secs(self.config.startup.check_timeout_secs);
+        match tokio::time::timeout(limit, self.store.ping()).await {
+            Ok(result) => result,
+            Err(_) => Err(error::Error::Timeout {
+                operation: dependency.as_str(),
+                after: limit,
+            }),
+        }
+    }
+}
+
+/// The answer of the health check endpoints.
+#[derive(Serialize)]
+struct HealthReport {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'static str>,
+    dependencies: BTreeMap<&'static str, String>,
+}
+
+async fn get_healthz(
+    State(checker): State<Arc<HealthChecker>>,
+) -> (StatusCode, Json<HealthReport>) {
+    checker.report(None).await
+}
+
+async fn get_readyz(State(checker): State<Arc<HealthChecker>>) -> (StatusCode, Json<HealthReport>) {
+    let reason = if shutdown::is_requested() {
+        Some("shutting down")
+    } else if control::is_paused() {
+        Some("paused")
+    } else {
+        None
+    };
+    checker.report(reason).await
+}
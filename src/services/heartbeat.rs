@@ -0,0 +1,102 @@
+//! Periodic heartbeat messages for remote monitoring.
+//!
+//! The heartbeat is a small status document (version, uptime, checkpoint)
+//! wrapped in a Nostr event signed with the bridge key. It is published to a
+//! dedicated Waku content topic and/or as a Nostr event of a dedicated kind,
+//! so a monitor can alert when heartbeats stop arriving.
+
+use crate::common::config::HeartbeatConfig;
+use crate::common::consts;
+use crate::common::error;
+use crate::db;
+use crate::metrics;
+use crate::nostr;
+use crate::waku::{WakuMessage, WakuRestClient};
+use nostr_sdk::{EventBuilder, JsonUtil, Kind, Tag};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Content of a heartbeat message.
+#[derive(Debug, Serialize)]
+struct HeartbeatStatus {
+    version: &'static str,
+    uptime_secs: u64,
+    last_update: u64,
+}
+
+/// Publishes heartbeats until the task is dropped.
+pub struct Heartbeat {
+    config: HeartbeatConfig,
+    store: db::Storage,
+    nostr_client: Arc<nostr::NostrClient>,
+    waku: WakuRestClient,
+    started: Instant,
+}
+
+impl Heartbeat {
+    pub fn new(
+        config: HeartbeatConfig,
+        store: db::Storage,
+        nostr_client: Arc<nostr::NostrClient>,
+        waku: WakuRestClient,
+    ) -> Self {
+        Self {
+            config,
+            store,
+            nostr_client,
+            waku,
+            started: Instant::now(),
+        }
+    }
+
+    /// Publishes a heartbeat every `interval_secs` seconds.
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval_secs));
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.beat().await {
+                metrics::record_error("heartbeat", "waku", &e);
+                tracing::warn!("failed to publish heartbeat: {}", e);
+            }
+        }
+    }
+
+    async fn beat(&self) -> error::Result<()> {
+        let status = HeartbeatStatus {
+            version: consts::CLI_VERSION,
+            uptime_secs: self.started.elapsed().as_secs(),
+            last_update: self
+                .store
+                .get_checkpoint()
+                .await?
+                .map_or(0, |row| row.last_update as u64),
+        };
+        let content = serde_json::to_string(&status)
+            .map_err(|e| error::Error::CustomError(format!("heartbeat serialization: {}", e)))?;
+
+        let kind = Kind::from(self.config.nostr_kind.unwrap_or(consts::HEARTBEAT_KIND));
+        // The default kind is parameterized replaceable, addressed by the key
+        // and the `d` tag.
+        let identifier = self
+            .config
+            .identifier
+            .as_deref()
+            .unwrap_or(consts::HEARTBEAT_IDENTIFIER);
+        let event = self
+            .nostr_client
+            .sign(EventBuilder::new(kind, content).tag(Tag::identifier(identifier)))?;
+
+        if let Some(topic) = &self.config.waku_content_topic {
+            let message = WakuMessage::new(event.as_json().as_bytes(), topic);
+            self.waku.publish(&message, None).await?;
+        }
+
+        if self.config.nostr_kind.is_some() {
+            self.nostr_client.send_event(event).await?;
+        }
+
+        tracing::debug!("published heartbeat: {:?}", status);
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+//! Checkpoint lag alarm.
+//!
+//! The fetch loop commits its checkpoint to the database every round. The
+//! monitor reads it back from the database, independently of the loop, and
+//! raises an alert when the checkpoint has not been committed for longer than
+//! the configured SLA. This catches a pipeline loop that died or hangs while
+//! the process itself stays alive.
+
+use super::{AlertKind, Alerter};
+use crate::common::config::CheckpointAlarmConfig;
+use crate::common::error;
+use crate::db;
+use crate::metrics;
+use chrono::Utc;
+use std::time::Duration;
+
+/// Watches the database checkpoint of one direction.
+pub struct LagMonitor {
+    direction: &'static str,
+    config: CheckpointAlarmConfig,
+    store: db::Storage,
+    alerter: Alerter,
+}
+
+impl LagMonitor {
+    pub fn new(
+        direction: &'static str,
+        config: CheckpointAlarmConfig,
+        store: db::Storage,
+        alerter: Alerter,
+    ) -> Self {
+        Self {
+            direction,
+            config,
+            store,
+            alerter,
+        }
+    }
+
+    /// Checks the checkpoint every `check_interval_secs` seconds.
+    pub async fn run(self) {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(self.config.check_interval_secs));
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.check().await {
+                tracing::warn!("failed to check checkpoint lag: {}", e);
+            }
+        }
+    }
+
+    async fn check(&self) -> error::Result<()> {
+        let Some(checkpoint) = self.store.direction_checkpoint(self.direction).await? else {
+            return Ok(());
+        };
+
+        let now = Utc::now();
+        metrics::observe_checkpoint(
+            self.direction,
+            checkpoint.last_update as u64,
+            now.timestamp() as u64,
+        );
+        let stale_secs = (now - checkpoint.updated_at.to_utc()).num_seconds().max(0) as u64;
+        metrics::metrics()
+            .checkpoint_stale_seconds
+            .with_label_values(&[self.direction])
+            .set(stale_secs as f64);
+
+        if stale_secs > self.config.sla_secs {
+            tracing::error!(
+                direction = self.direction,
+                stale_secs,
+                "checkpoint has not advanced for {}s",
+                stale_secs
+            );
+            self.alerter
+                .notify(
+                    AlertKind::CheckpointLag,
+                    self.direction,
+                    format!(
+                        "checkpoint of {} has not been committed for {}s (SLA {}s)",
+                        self.direction, stale_secs, self.config.sla_secs
+                    ),
+                )
+                .await;
+        }
+        Ok(())
+    }
+}
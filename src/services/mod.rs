@@ -1,3 +1,44 @@
+mod acks;
+mod admin;
+mod alerting;
 mod app;
+mod audit;
+mod backfill;
+mod breaker;
+mod bridge;
+mod checkpoint;
+pub mod control;
+pub mod feed;
+mod health;
+mod heartbeat;
+mod lag_monitor;
+#[cfg(feature = "waku-ffi")]
+mod peer_monitor;
+mod recovery;
+mod reload;
+mod retry;
+pub mod shutdown;
+mod simulation;
+mod startup;
+mod supervisor;
 
+pub use admin::AdminServer;
+pub use alerting::{AlertKind, Alerter};
 pub use app::*;
+pub use audit::{AuditLog, AuditRecord};
+pub use backfill::{backfill, BackfillOptions, BackfillReport};
+pub use breaker::CircuitBreaker;
+pub use bridge::{check_directions, Bridge, BridgeBuilder, DIRECTIONS};
+pub use checkpoint::CheckpointClock;
+pub use control::ControlServer;
+pub use feed::BridgedEvent;
+pub use health::HealthServer;
+pub use heartbeat::Heartbeat;
+pub use lag_monitor::LagMonitor;
+#[cfg(feature = "waku-ffi")]
+pub use peer_monitor::PeerMonitor;
+pub use recovery::WakuStore;
+pub use reload::{watch_sighup, Reloader};
+pub use simulation::{simulate, MemorySink, SimulationOptions, SimulationReport};
+pub use startup::check_dependencies;
+pub use supervisor::spawn_supervised;
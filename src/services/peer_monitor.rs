@@ -0,0 +1,61 @@
+//! Peer management of the embedded Waku node.
+//!
+//! The node dials `waku.node_addr` once at startup; when that peer goes away
+//! the `w2n` direction silently stops receiving. The monitor counts the
+//! connected peers every `waku.peers.check_interval_secs` seconds and dials
+//! the bootstrap and DNS-discovered peers again while fewer than
+//! `waku.peers.min_peers` are connected.
+
+use crate::common::config::WakuPeersConfig;
+use crate::metrics;
+use crate::waku::WakuClient;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Watches the peers of the embedded node.
+pub struct PeerMonitor {
+    client: Arc<WakuClient>,
+    config: WakuPeersConfig,
+}
+
+impl PeerMonitor {
+    pub fn new(client: Arc<WakuClient>, config: WakuPeersConfig) -> Self {
+        Self { client, config }
+    }
+
+    /// Checks the peers every `check_interval_secs` seconds.
+    pub async fn run(self) {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(self.config.check_interval_secs));
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.check().await {
+                tracing::warn!("failed to check the waku peers: {}", e);
+            }
+        }
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        let connected = self.client.connected_peers()?;
+        metrics::record_peers(connected, self.config.min_peers);
+        if connected >= self.config.min_peers {
+            return Ok(());
+        }
+
+        tracing::warn!(
+            connected,
+            min_peers = self.config.min_peers,
+            "waku node has too few peers, redialing the bootstrap peers"
+        );
+        metrics::record_redial();
+        // Dialing blocks on the node until the peers answer or time out.
+        let client = self.client.clone();
+        let dialed = tokio::task::spawn_blocking(move || client.dial_bootstrap_peers())
+            .await
+            .map_err(|e| e.to_string())??;
+        let connected = self.client.connected_peers()?;
+        metrics::record_peers(connected, self.config.min_peers);
+        tracing::info!(dialed, connected, "redialed the waku bootstrap peers");
+        Ok(())
+    }
+}
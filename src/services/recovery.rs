@@ -0,0 +1,127 @@
+//! Startup recovery of pending deliveries.
+//!
+//! Every fetched event is recorded as `pending` before it is queued and is
+//! marked `delivered` or `dead_lettered` by the sender. Rows still pending at
+//! startup were queued by a previous run that stopped before delivering them.
+//! Before they are queued again, the Waku store is asked whether the publish
+//! went through after all, so a crash between the publish and the status
+//! update does not publish the event twice.
+
+use crate::common::error;
+use crate::common::http::HttpClient;
+use crate::common::timing::{timed, Operation};
+use crate::waku::PayloadCipher;
+use chrono::{DateTime, FixedOffset};
+use nostr_sdk::{Event, JsonUtil};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Number of messages requested per store page.
+const PAGE_SIZE: usize = 100;
+/// Upper bound on the pages scanned by one lookup.
+const MAX_PAGES: usize = 20;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoreResponse {
+    #[serde(default)]
+    messages: Vec<StoreMessage>,
+    pagination_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StoreMessage {
+    message: Option<StoreMessagePayload>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StoreMessagePayload {
+    payload: String,
+}
+
+/// Read access to the store query REST API of a Waku node.
+pub struct WakuStore {
+    client: HttpClient,
+    url: String,
+    /// The queried content topics, comma separated.
+    content_topics: String,
+    cipher: Option<Arc<PayloadCipher>>,
+}
+
+impl WakuStore {
+    pub fn new(client: HttpClient, url: String, content_topics: String) -> Self {
+        Self {
+            client,
+            url,
+            content_topics,
+            cipher: None,
+        }
+    }
+
+    /// Opens the stored payloads with `cipher`, as sealed by the sinks.
+    pub fn with_cipher(mut self, cipher: Option<Arc<PayloadCipher>>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Returns the ids of the events published to the content topics since
+    /// `since`, out of the given `ids`.
+    ///
+    /// Stops as soon as every id was found, or after `MAX_PAGES` pages.
+    pub async fn published(
+        &self,
+        ids: &HashSet<String>,
+        since: DateTime<FixedOffset>,
+    ) -> error::Result<HashSet<String>> {
+        let mut found = HashSet::new();
+        let mut cursor: Option<String> = None;
+        let start_time = since.timestamp_nanos_opt().unwrap_or_default();
+
+        for _ in 0..MAX_PAGES {
+            let mut query = vec![
+                ("contentTopics", self.content_topics.clone()),
+                ("startTime", start_time.to_string()),
+                ("includeData", "true".to_string()),
+                ("pageSize", PAGE_SIZE.to_string()),
+            ];
+            if let Some(cursor) = &cursor {
+                query.push(("cursor", cursor.clone()));
+            }
+
+            let page: StoreResponse = timed(
+                Operation::Waku,
+                self.client.get_json("waku store query", &self.url, &query),
+            )
+            .await?;
+            for message in page.messages.iter().filter_map(|m| m.message.as_ref()) {
+                let Some(event) = decode_event(&message.payload, self.cipher.as_deref()) else {
+                    continue;
+                };
+                let id = event.id.to_hex();
+                if ids.contains(&id) {
+                    found.insert(id);
+                }
+            }
+
+            if found.len() == ids.len() {
+                break;
+            }
+            match page.pagination_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+/// Decodes a published payload back into the nostr event.
+fn decode_event(payload: &str, cipher: Option<&PayloadCipher>) -> Option<Event> {
+    let mut json = base64::decode(payload).ok()?;
+    if let Some(cipher) = cipher {
+        json = cipher.open(&json).ok()?;
+    }
+    Event::from_json(json).ok()
+}
@@ -0,0 +1,209 @@
+//! Configuration reloads without a restart.
+//!
+//! The configuration file is read again on SIGHUP, and on the `reload`
+//! command of the control socket. A valid configuration is applied to the
+//! running pipelines:
+//!
+//! - `sync.poll_interval_secs`, from the next fetch round of the `n2*`
+//!   directions polling the relays.
+//! - `nostr.filters` and `sync.batch_limit`, from the next fetch round. An
+//!   open subscription keeps its filters until it is opened again.
+//! - the urls of the sinks: `waku.send_api`, the `indexdb_backend` urls of
+//!   the ACL event types and `webhook.urls`.
+//! - the WASM plugins and the transform rules.
+//!
+//! Every other setting, e.g. the database url, the private key, the server
+//! ports, the brokers, the Waku encryption, the rate limits or the HTTP
+//! timeouts, is read once at startup. A configuration changing any of them
+//! is rejected as a whole, naming them, and the running configuration is
+//! kept. So is a configuration with an invalid filter, plugin or transform
+//! rule: everything is validated before anything is applied.
+
+use crate::common::config::Config;
+use crate::common::error;
+use crate::common::error_reporting;
+use crate::common::secret;
+use crate::common::sink::EventSink;
+use crate::nostr::{self, NostrClient};
+use crate::plugin::PluginChain;
+use crate::transform::TransformChain;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+
+/// Applies reloaded configurations to the running pipelines.
+pub struct Reloader {
+    /// The configuration applied last.
+    applied: Mutex<Config>,
+    poll_interval_secs: AtomicU64,
+    nostr_client: Arc<NostrClient>,
+    plugins: Arc<PluginChain>,
+    transforms: Arc<TransformChain>,
+    /// The sinks built so far, updated with the reloaded urls.
+    sinks: Mutex<Vec<Arc<dyn EventSink>>>,
+}
+
+impl Reloader {
+    pub fn new(
+        config: &Config,
+        nostr_client: Arc<NostrClient>,
+        plugins: Arc<PluginChain>,
+        transforms: Arc<TransformChain>,
+    ) -> Self {
+        Self {
+            applied: Mutex::new(config.clone()),
+            poll_interval_secs: AtomicU64::new(config.sync.poll_interval_secs),
+            nostr_client,
+            plugins,
+            transforms,
+            sinks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the delay between two fetch rounds.
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.poll_interval_secs.load(Ordering::Relaxed))
+    }
+
+    /// Registers a sink to update on reloads.
+    pub fn register_sink(&self, sink: Arc<dyn EventSink>) {
+        self.sinks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(sink);
+    }
+
+    /// Applies `config`, already validated, to the running pipelines.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidConfig` naming the settings that need a restart, and
+    /// the errors of the filters, plugins and transform rules. The running
+    /// configuration is kept in both cases.
+    ///
+    /// # Returns
+    ///
+    /// A summary of the applied settings.
+    pub fn reload(&self, config: &Config) -> error::Result<Value> {
+        let mut applied = self.applied.lock().unwrap_or_else(|e| e.into_inner());
+        let changed = restart_required(&applied, config)?;
+        if !changed.is_empty() {
+            return Err(error::Error::InvalidConfig(format!(
+                "{} changed and need a restart, nothing was reloaded",
+                changed.join(", ")
+            )));
+        }
+
+        // Build everything first, so a failure leaves the pipelines as they
+        // are.
+        let filters = nostr::build_filters(&config.nostr.filters)?;
+        let plugins = self.plugins.prepare(&config.plugins)?;
+        let transforms = self.transforms.prepare(&config.transforms)?;
+
+        let plugins = self.plugins.install(plugins);
+        let transforms = self.transforms.install(transforms);
+        self.nostr_client
+            .set_filter_config(filters, config.sync.batch_limit);
+        self.poll_interval_secs
+            .store(config.sync.poll_interval_secs, Ordering::Relaxed);
+        let sinks = self.sinks.lock().unwrap_or_else(|e| e.into_inner());
+        for sink in sinks.iter() {
+            sink.reload(config);
+        }
+        *applied = config.clone();
+
+        Ok(json!({
+            "plugins": plugins,
+            "transforms": transforms,
+            "filters": config.nostr.filters.len(),
+            "poll_interval_secs": config.sync.poll_interval_secs,
+            "sinks": sinks.len(),
+        }))
+    }
+}
+
+/// The settings `Reloader::reload` applies, as paths in the configuration.
+const RELOADABLE: [&str; 12] = [
+    "sync.poll_interval_secs",
+    "sync.batch_limit",
+    "nostr.filters",
+    "waku.send_api",
+    "indexdb_backend.invite_url",
+    "indexdb_backend.auth_url",
+    "indexdb_backend.grant_url",
+    "indexdb_backend.revoke_url",
+    "indexdb_backend.membership_url",
+    "webhook.urls",
+    "plugins",
+    "transforms",
+];
+
+/// Returns the settings of `current` that `reloaded` changes and that need
+/// a restart to apply, i.e. all the changed ones but `RELOADABLE`.
+///
+/// The secrets are compared as they are, only their paths are reported.
+fn restart_required(current: &Config, reloaded: &Config) -> error::Result<Vec<String>> {
+    let (current, reloaded) = secret::revealed(|| {
+        Ok::<_, serde_json::Error>((
+            serde_json::to_value(current)?,
+            serde_json::to_value(reloaded)?,
+        ))
+    })?;
+    let mut changed = Vec::new();
+    diff("", &current, &reloaded, &mut changed);
+    Ok(changed)
+}
+
+/// Adds the paths under `path` whose values differ between `current` and
+/// `reloaded` to `changed`, down to the first differing value of each
+/// branch.
+fn diff(path: &str, current: &Value, reloaded: &Value, changed: &mut Vec<String>) {
+    if current == reloaded || RELOADABLE.contains(&path) {
+        return;
+    }
+    match (current, reloaded) {
+        (Value::Object(current), Value::Object(reloaded)) => {
+            let keys: BTreeSet<&String> = current.keys().chain(reloaded.keys()).collect();
+            for key in keys {
+                let path = match path {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", path, key),
+                };
+                diff(
+                    &path,
+                    current.get(key).unwrap_or(&Value::Null),
+                    reloaded.get(key).unwrap_or(&Value::Null),
+                    changed,
+                );
+            }
+        }
+        _ => changed.push(path.to_string()),
+    }
+}
+
+/// Reloads the configuration file on every SIGHUP until the process exits.
+pub fn watch_sighup(config_file: PathBuf, profile: Option<String>, reloader: Arc<Reloader>) {
+    error_reporting::spawn_reported("reload", "signal", async move {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                tracing::error!("failed to listen for SIGHUP: {}", e);
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            let reloaded = Config::load_profile(config_file.clone(), profile.as_deref())
+                .and_then(|config| reloader.reload(&config));
+            match reloaded {
+                Ok(summary) => tracing::info!("received SIGHUP, reloaded {}", summary),
+                Err(e) => {
+                    tracing::error!("received SIGHUP, keeping the running configuration: {}", e)
+                }
+            }
+        }
+    });
+}
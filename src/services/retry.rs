@@ -0,0 +1,126 @@
+//! Persistent retry queue of failed deliveries.
+//!
+//! A delivery failing with a transient error is stored in the `retry_queue`
+//! table along with the time of its next attempt, backing off exponentially
+//! with the attempts made so far. The worker of a direction moves the due
+//! retries back into the queue of its sender task, which dead-letters the
+//! event once `delivery.max_attempts` is used up. Scheduled retries survive
+//! a restart, as the recovery of pending events leaves them to the worker.
+
+use super::app::PipelineEvent;
+use super::shutdown;
+use crate::common::correlation::CorrelationId;
+use crate::common::error;
+use crate::db;
+use crate::db::entities::prelude::RetryQueueActiveModel;
+use crate::metrics;
+use chrono::Utc;
+use nostr_sdk::JsonUtil;
+use sea_orm::Set;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Interval between two checks for due retries.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Retries moved to the sender queue per check.
+const BATCH_SIZE: u64 = 100;
+
+/// Schedules another attempt to deliver `item` to `sink` after `delay`.
+pub(super) async fn schedule(
+    store: &db::Storage,
+    item: &PipelineEvent,
+    sink: &str,
+    attempts: u32,
+    delay: Duration,
+    error: &error::Error,
+) -> error::Result<()> {
+    let now = Utc::now();
+    let next_attempt_at = now + chrono::Duration::from_std(delay).unwrap_or_default();
+    store
+        .schedule_retry(RetryQueueActiveModel {
+            event_id: Set(item.event.id.to_hex()),
+            correlation_id: Set(item.correlation_id.to_string()),
+            direction: Set(item.direction.to_string()),
+            sink: Set(sink.to_string()),
+            payload: Set(item.event.as_json()),
+            attempts: Set(attempts as i32),
+            last_error: Set(error.to_string()),
+            next_attempt_at: Set(next_attempt_at.into()),
+            created_at: Set(now.into()),
+            ..Default::default()
+        })
+        .await
+}
+
+/// Moves the due retries of a direction into the queue of its sender task
+/// until a shutdown is requested.
+pub(super) struct RetryWorker {
+    store: db::Storage,
+    direction: &'static str,
+    tx: mpsc::Sender<PipelineEvent>,
+}
+
+impl RetryWorker {
+    pub(super) fn new(
+        store: db::Storage,
+        direction: &'static str,
+        tx: mpsc::Sender<PipelineEvent>,
+    ) -> Self {
+        Self {
+            store,
+            direction,
+            tx,
+        }
+    }
+
+    pub(super) async fn run(self) {
+        while !shutdown::is_requested() {
+            if let Err(e) = self.requeue_due().await {
+                metrics::record_error(self.direction, "db", &e);
+                tracing::error!("{}: re-queueing due retries failed: {}", self.direction, e);
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                _ = shutdown::wait_requested() => {}
+            }
+        }
+    }
+
+    /// Hands the due retries to the sender task.
+    async fn requeue_due(&self) -> error::Result<()> {
+        let due = self
+            .store
+            .take_due_retries(self.direction, BATCH_SIZE)
+            .await?;
+        for retry in due {
+            let event = match nostr_sdk::Event::from_json(&retry.payload) {
+                Ok(event) => event,
+                Err(e) => {
+                    tracing::warn!(
+                        "{}: dropping retry of event {} with an unusable payload: {}",
+                        self.direction,
+                        retry.event_id,
+                        e
+                    );
+                    continue;
+                }
+            };
+            tracing::info!(
+                sink = retry.sink.as_str(),
+                attempts = retry.attempts,
+                "{}: retrying event {}",
+                self.direction,
+                retry.event_id
+            );
+            let item = PipelineEvent::recovered(
+                event,
+                self.direction,
+                CorrelationId::from(retry.correlation_id),
+            );
+            self.tx.send(item).await.map_err(|_| {
+                error::Error::CustomError(format!("{} sender task stopped", self.direction))
+            })?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+//! Graceful shutdown on SIGINT and SIGTERM.
+//!
+//! Once a signal is received the fetch loops stop after their current round,
+//! so the last checkpoint they commit covers every event they queued. The
+//! sender tasks then deliver the events still queued, within
+//! `shutdown.drain_timeout_secs`, and the app flushes the buffered database
+//! writes and disconnects from the relays. A second signal exits at once.
+
+use crate::common::error_reporting;
+use crate::common::logging;
+use std::sync::OnceLock;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, watch};
+
+static REQUESTED: OnceLock<watch::Sender<bool>> = OnceLock::new();
+
+fn requested() -> &'static watch::Sender<bool> {
+    REQUESTED.get_or_init(|| watch::channel(false).0)
+}
+
+/// Returns whether a shutdown was requested.
+pub fn is_requested() -> bool {
+    *requested().borrow()
+}
+
+/// Requests a shutdown, as a signal does. Lets embedders stop the pipelines
+/// of a `Bridge`.
+pub fn request() {
+    requested().send_replace(true);
+}
+
+/// Waits until a shutdown is requested.
+pub async fn wait_requested() {
+    let mut requested = requested().subscribe();
+    let _ = requested.wait_for(|requested| *requested).await;
+}
+
+/// Listens for SIGINT and SIGTERM in the background, requesting a shutdown
+/// on the first signal and exiting on the second.
+pub fn listen() {
+    error_reporting::spawn_reported("shutdown", "signal", async {
+        let (mut interrupt, mut terminate) = match (
+            signal(SignalKind::interrupt()),
+            signal(SignalKind::terminate()),
+        ) {
+            (Ok(interrupt), Ok(terminate)) => (interrupt, terminate),
+            (Err(e), _) | (_, Err(e)) => {
+                tracing::error!("failed to listen for shutdown signals: {}", e);
+                return;
+            }
+        };
+
+        let name = tokio::select! {
+            _ = interrupt.recv() => "SIGINT",
+            _ = terminate.recv() => "SIGTERM",
+        };
+        tracing::info!("received {}, shutting down", name);
+        request();
+
+        tokio::select! {
+            _ = interrupt.recv() => {}
+            _ = terminate.recv() => {}
+        }
+        tracing::warn!("received a second signal, exiting without draining");
+        logging::flush();
+        std::process::exit(1);
+    });
+}
+
+/// Receives the next item of a sender task. Once `draining` is set, only the
+/// items already queued are returned, then `None`.
+pub async fn recv_draining<T>(
+    rx: &mut mpsc::Receiver<T>,
+    draining: &mut watch::Receiver<bool>,
+) -> Option<T> {
+    tokio::select! {
+        biased;
+        item = rx.recv() => item,
+        _ = draining.wait_for(|draining| *draining) => rx.try_recv().ok(),
+    }
+}
@@ -0,0 +1,235 @@
+//! Simulated runs of the bridge, started with `run --simulate`.
+//!
+//! Every external service is replaced by an in-memory stand-in: the relay by
+//! a mock relay, the database by an in-memory SQLite database, and the Waku
+//! or IndexDB sink by a `MemorySink` that fails a share of the deliveries
+//! transiently. Synthetic ACL invites, some of them published twice, are
+//! bridged by the regular pipeline, with its dedupe, checkpoints and retries,
+//! until each of them is delivered or dead-lettered. The report then tells
+//! whether an event was lost or delivered twice.
+
+use super::App;
+use crate::common::config::Config;
+use crate::common::consts;
+use crate::common::correlation::CorrelationId;
+use crate::common::error;
+use crate::common::sink::EventSink;
+use crate::common::validation;
+use crate::db;
+use crate::indexdb::InviteMsg;
+use crate::nostr::NostrClient;
+use async_trait::async_trait;
+use nostr_relay_builder::MockRelay;
+use nostr_sdk::{Event, EventBuilder, Keys, Kind, Tag, Timestamp};
+use rand::Rng;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Share of the synthetic events published a second time.
+const DUPLICATE_RATE: f64 = 0.1;
+/// Interval between two checks of the delivered events.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Settings of a simulated run.
+#[derive(Debug, Clone)]
+pub struct SimulationOptions {
+    /// Number of synthetic events to bridge.
+    pub events: u32,
+    /// Share of the deliveries failing with a transient error.
+    pub failure_rate: f64,
+    /// Time after which the events not bridged yet count as lost.
+    pub timeout: Duration,
+}
+
+/// Outcome of a simulated run.
+#[derive(Debug, Default)]
+pub struct SimulationReport {
+    pub published: usize,
+    pub delivered: usize,
+    pub dead_lettered: u64,
+    /// Events delivered more than once.
+    pub duplicated: usize,
+    /// Deliveries failed on purpose, to be retried by the pipeline.
+    pub failed_attempts: u64,
+    /// Ids of the events neither delivered nor dead-lettered in time.
+    pub missing: Vec<String>,
+}
+
+impl SimulationReport {
+    /// Tells whether every event was bridged exactly once.
+    pub fn is_success(&self) -> bool {
+        self.missing.is_empty() && self.duplicated == 0
+    }
+}
+
+/// An in-memory stand-in for the Waku or IndexDB sink.
+pub struct MemorySink {
+    name: &'static str,
+    failure_rate: f64,
+    delivered: Mutex<HashMap<String, u32>>,
+    failed_attempts: AtomicU64,
+}
+
+impl MemorySink {
+    pub fn new(name: &'static str, failure_rate: f64) -> Self {
+        Self {
+            name,
+            failure_rate,
+            delivered: Mutex::new(HashMap::new()),
+            failed_attempts: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns how often each event was delivered.
+    pub fn deliveries(&self) -> HashMap<String, u32> {
+        self.delivered
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+#[async_trait]
+impl EventSink for MemorySink {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Records the event, after failing with a timeout for a
+    /// `failure_rate` share of the calls. The IndexDB stand-in rejects events
+    /// that are not ACL invites, as the real sink does.
+    async fn send(&self, event: &Event, _correlation_id: &CorrelationId) -> error::Result<()> {
+        if rand::thread_rng().gen_bool(self.failure_rate) {
+            self.failed_attempts.fetch_add(1, Ordering::Relaxed);
+            return Err(error::Error::Timeout {
+                operation: self.name,
+                after: Duration::ZERO,
+            });
+        }
+        if self.name == "indexdb" {
+            InviteMsg::try_from(event.clone())?;
+        }
+
+        *self
+            .delivered
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(event.id.to_hex())
+            .or_default() += 1;
+        Ok(())
+    }
+}
+
+/// Builds a synthetic ACL invite or revocation.
+fn synthetic_event(publisher: &NostrClient, n: u32) -> error::Result<Event> {
+    let content = json!({
+        "inviter": format!("simulated-admin-{}", n % 7),
+        "invitee": format!("simulated-user-{}", n),
+        "projectId": format!("simulated-project-{}", n % 3),
+        "metadata": {
+            "message": "simulated",
+            "timestamp": Timestamp::now().as_u64(),
+            "platform": "simulation",
+            "version": "1",
+            "clock": n,
+        },
+        "type": if n % 5 == 0 { "revoke" } else { "invite" },
+    });
+    publisher.sign(
+        EventBuilder::new(Kind::TextNote, content.to_string())
+            .tag(Tag::hashtag(consts::BRIDGE_HASHTAG)),
+    )
+}
+
+/// Bridges synthetic events through the `direction` pipeline, `n2w` or
+/// `n2i`, with in-memory services in place of the configured ones.
+///
+/// # Errors
+///
+/// Returns `InvalidConfig` for other directions, and the error of any
+/// stand-in that failed to start.
+pub async fn simulate(
+    mut config: Config,
+    direction: &'static str,
+    options: SimulationOptions,
+) -> error::Result<SimulationReport> {
+    let name = match direction {
+        "n2w" => "waku",
+        "n2i" => "indexdb",
+        _ => {
+            return Err(error::Error::InvalidConfig(format!(
+                "direction `{}` cannot be simulated, use n2w or n2i",
+                direction
+            )))
+        }
+    };
+
+    let relay = MockRelay::run()
+        .await
+        .map_err(|e| error::Error::CustomError(format!("starting the mock relay: {}", e)))?;
+    config.nostr.ws_url = relay.url();
+    validation::validate(&config)?;
+    let store = db::Storage::in_memory().await?;
+    let app = App::with_store(config, store.clone()).await?;
+    let sink = Arc::new(MemorySink::new(name, options.failure_rate));
+
+    let publisher = NostrClient::new(
+        &Keys::generate().secret_key().to_secret_hex(),
+        &[relay.url()],
+        None,
+        false,
+    )
+    .await?;
+    let mut published = Vec::with_capacity(options.events as usize);
+    for n in 0..options.events {
+        let event = synthetic_event(&publisher, n)?;
+        publisher.send_event(event.clone()).await?;
+        if rand::thread_rng().gen_bool(DUPLICATE_RATE) {
+            publisher.send_event(event.clone()).await?;
+        }
+        published.push(event.id.to_hex());
+    }
+    tracing::info!("simulation: published {} events", published.len());
+
+    let settled = async {
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            let delivered = sink.deliveries().len() as u64;
+            match store.count_dead_letters().await {
+                Ok(dead_lettered) if delivered + dead_lettered >= published.len() as u64 => return,
+                Ok(_) => {}
+                Err(e) => tracing::warn!("simulation: counting dead letters failed: {}", e),
+            }
+        }
+    };
+    tokio::select! {
+        _ = app.from_nostr_to_sink(direction, sink.clone()) => {}
+        _ = tokio::time::timeout(options.timeout, settled) => {}
+    }
+
+    let deliveries = sink.deliveries();
+    let mut missing = Vec::new();
+    for event_id in &published {
+        if deliveries.contains_key(event_id) {
+            continue;
+        }
+        let state = store.direction_event_state(direction, event_id).await?;
+        if state.map_or(true, |row| {
+            row.status != db::DeliveryStatus::DeadLettered.as_str()
+        }) {
+            missing.push(event_id.clone());
+        }
+    }
+
+    Ok(SimulationReport {
+        published: published.len(),
+        delivered: deliveries.len(),
+        dead_lettered: store.count_dead_letters().await?,
+        duplicated: deliveries.values().filter(|&&count| count > 1).count(),
+        failed_attempts: sink.failed_attempts.load(Ordering::Relaxed),
+        missing,
+    })
+}
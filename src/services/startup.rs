@@ -0,0 +1,182 @@
+//! Dependency checks run before the pipelines start.
+//!
+//! Each dependency of the selected direction is checked with its own timeout
+//! and every unavailable one is reported by name, instead of the process
+//! panicking halfway through `App::new`. With `startup.wait` set the checks
+//! are repeated with backoff until all dependencies are up or the deadline
+//! expires, so the gateway can be started alongside its dependencies.
+
+use crate::common::backoff::Backoff;
+use crate::common::config::{Config, IndexdbSink, StartupConfig, TlsConfig, WakuTransport};
+use crate::common::error;
+use crate::common::http::HttpClient;
+use crate::db;
+use futures::future::join_all;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use url::Url;
+
+/// An external system the gateway depends on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dependency {
+    Database,
+    Relay,
+    Waku,
+    Indexdb,
+}
+
+impl Dependency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Dependency::Database => "database",
+            Dependency::Relay => "nostr relay",
+            Dependency::Waku => "waku node",
+            Dependency::Indexdb => "indexdb",
+        }
+    }
+
+    /// Returns the dependencies of a pipeline direction.
+    pub fn of_direction(direction: &str) -> Vec<Dependency> {
+        match direction {
+            "n2w" | "w2n" | "n2w2n" => {
+                vec![Dependency::Database, Dependency::Relay, Dependency::Waku]
+            }
+            "n2i" => vec![Dependency::Database, Dependency::Relay, Dependency::Indexdb],
+            "w2i" => vec![Dependency::Database, Dependency::Waku, Dependency::Indexdb],
+            "k2n" | "m2n" => vec![Dependency::Relay],
+            _ if direction.starts_with("n2") => vec![Dependency::Database, Dependency::Relay],
+            _ => vec![Dependency::Database],
+        }
+    }
+}
+
+/// Checks the dependencies of `direction`, waiting for them if configured.
+///
+/// # Errors
+///
+/// Returns `Error::DependencyUnavailable` naming every dependency that was
+/// still unavailable after the last check.
+pub async fn check_dependencies(config: &Config, direction: &str) -> error::Result<()> {
+    let startup = &config.startup;
+    let dependencies = dependencies(config, direction);
+    let deadline = Instant::now() + Duration::from_secs(startup.deadline_secs);
+    let mut backoff = Backoff::new(
+        Duration::from_millis(startup.base_delay_ms),
+        Duration::from_millis(startup.max_delay_ms),
+    );
+
+    loop {
+        let results = join_all(
+            dependencies
+                .iter()
+                .map(|dependency| check_one(config, startup, *dependency)),
+        )
+        .await;
+
+        let failures: Vec<String> = dependencies
+            .iter()
+            .zip(results)
+            .filter_map(|(dependency, result)| {
+                result
+                    .err()
+                    .map(|e| format!("{} ({})", dependency.as_str(), e))
+            })
+            .collect();
+        if failures.is_empty() {
+            tracing::info!("dependencies available: {:?}", dependencies);
+            return Ok(());
+        }
+
+        let delay = backoff.next().unwrap_or_default();
+        if !startup.wait || Instant::now() + delay > deadline {
+            return Err(error::Error::DependencyUnavailable(failures.join(", ")));
+        }
+        tracing::warn!(
+            "waiting for unavailable dependencies, retrying in {:?}: {}",
+            delay,
+            failures.join(", ")
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Returns the dependencies `direction` reaches over the network with
+/// `config`.
+pub(super) fn dependencies(config: &Config, direction: &str) -> Vec<Dependency> {
+    let mut dependencies = Dependency::of_direction(direction);
+    if config.indexdb_backend.sink != IndexdbSink::Http {
+        dependencies.retain(|dependency| *dependency != Dependency::Indexdb);
+    }
+    if config.waku.transport == WakuTransport::Gossipsub {
+        dependencies.retain(|dependency| *dependency != Dependency::Waku);
+    }
+    dependencies
+}
+
+/// Checks a single dependency within `startup.check_timeout_secs`.
+pub(super) async fn check_one(
+    config: &Config,
+    startup: &StartupConfig,
+    dependency: Dependency,
+) -> error::Result<()> {
+    let limit = Duration::from_secs(startup.check_timeout_secs);
+    let check = async {
+        match dependency {
+            Dependency::Database => db::Storage::connect(config.database.clone())
+                .await
+                .map(|_| ()),
+            Dependency::Relay => check_relay(config).await,
+            Dependency::Waku => {
+                probe_http(config, config.waku.tls.as_ref(), &config.waku.send_api).await
+            }
+            Dependency::Indexdb => {
+                probe_http(
+                    config,
+                    config.indexdb_backend.tls.as_ref(),
+                    &config.indexdb_backend.invite_url,
+                )
+                .await
+            }
+        }
+    };
+
+    match tokio::time::timeout(limit, check).await {
+        Ok(result) => result,
+        Err(_) => Err(error::Error::Timeout {
+            operation: dependency.as_str(),
+            after: limit,
+        }),
+    }
+}
+
+/// Opens a TCP connection to every relay. Relays reached through the SOCKS5
+/// proxy are not checked, as only the proxy knows how to reach them.
+async fn check_relay(config: &Config) -> error::Result<()> {
+    if config
+        .proxy
+        .as_ref()
+        .is_some_and(|proxy| proxy.nostr_socks5.is_some())
+    {
+        tracing::info!("nostr relay is reached through the proxy, skipping its check");
+        return Ok(());
+    }
+
+    for relay in config.nostr.relays() {
+        let url = Url::parse(&relay)
+            .map_err(|e| error::Error::InvalidConfig(format!("nostr relay {}: {}", relay, e)))?;
+        let host = url.host_str().ok_or_else(|| {
+            error::Error::InvalidConfig(format!("nostr relay {} has no host", relay))
+        })?;
+        let port = url.port_or_known_default().unwrap_or(443);
+        TcpStream::connect((host, port)).await?;
+    }
+
+    Ok(())
+}
+
+/// Sends a single request to an HTTP dependency. Any response counts as
+/// available; only connection failures and timeouts do not.
+async fn probe_http(config: &Config, tls: Option<&TlsConfig>, url: &str) -> error::Result<()> {
+    let client = HttpClient::new(&config.http, config.proxy.as_ref(), tls, Default::default())?;
+    client.probe(url).await
+}
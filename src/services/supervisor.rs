@@ -0,0 +1,93 @@
+//! Restart policy of the pipeline tasks.
+//!
+//! A sender task that panics, e.g. on a poisoned message, is restarted with
+//! exponential backoff instead of silently halting its pipeline. After
+//! `max_restarts` restarts the supervisor gives up and raises an alert.
+
+use super::{AlertKind, Alerter};
+use crate::common::backoff::Backoff;
+use crate::common::config::SupervisorConfig;
+use crate::common::crash;
+use crate::common::error_reporting;
+use crate::metrics;
+use std::future::Future;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Spawns the task produced by `make` and restarts it whenever it panics.
+///
+/// `make` is called for every (re)start and must build a fresh future, e.g.
+/// from cloned handles. The supervisor returns once a run completes.
+///
+/// # Arguments
+///
+/// * `direction` - The pipeline direction, used in logs, metrics and reports.
+/// * `sink` - The sink the task delivers to.
+/// * `config` - The restart budget and backoff.
+/// * `alerter` - Notified when the supervisor gives up.
+/// * `make` - Builds the future of one run.
+pub fn spawn_supervised<F, Fut>(
+    direction: &'static str,
+    sink: &'static str,
+    config: SupervisorConfig,
+    alerter: Alerter,
+    mut make: F,
+) -> JoinHandle<()>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    error_reporting::spawn_reported(direction, sink, async move {
+        let mut backoff = Backoff::new(
+            Duration::from_millis(config.base_delay_ms),
+            Duration::from_millis(config.max_delay_ms),
+        )
+        .with_budget(config.max_restarts);
+        loop {
+            let Err(message) = crash::catch_panic(make()).await else {
+                return;
+            };
+
+            metrics::metrics()
+                .task_restarts_total
+                .with_label_values(&[direction, sink])
+                .inc();
+            let restarts = backoff.retries();
+            let Some(delay) = backoff.next() else {
+                tracing::error!(
+                    direction,
+                    sink,
+                    "{} {} task panicked {} times, giving up: {}",
+                    direction,
+                    sink,
+                    restarts + 1,
+                    message
+                );
+                alerter
+                    .notify(
+                        AlertKind::TaskFailed,
+                        &format!("{}/{}", direction, sink),
+                        format!(
+                            "{} {} task stopped after {} restarts: {}",
+                            direction, sink, restarts, message
+                        ),
+                    )
+                    .await;
+                return;
+            };
+
+            tracing::warn!(
+                direction,
+                sink,
+                "{} {} task panicked, restart {}/{} in {:?}: {}",
+                direction,
+                sink,
+                backoff.retries(),
+                config.max_restarts,
+                delay,
+                message
+            );
+            tokio::time::sleep(delay).await;
+        }
+    })
+}
@@ -0,0 +1,3 @@
+mod smtp;
+
+pub use self::smtp::*;
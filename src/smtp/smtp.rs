@@ -0,0 +1,177 @@
+//!This module provides an SMTP sink emailing notifications of selected ACL
+//!events, such as admin grants or revocations, to the recipients configured
+//!for their event type. Subject and body are rendered from templates.
+
+use crate::common::config::{SmtpConfig, SmtpSecurity};
+use crate::common::correlation::CorrelationId;
+use crate::common::error;
+use crate::common::sink::EventSink;
+use crate::common::template::render;
+use crate::indexdb::InviteMsg;
+use async_trait::async_trait;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use nostr_sdk::Event;
+use std::collections::BTreeMap;
+
+/// Names of the values templates can reference.
+const PLACEHOLDERS: [&str; 7] = [
+    "event_type",
+    "inviter",
+    "invitee",
+    "project",
+    "id",
+    "pubkey",
+    "created_at",
+];
+
+/// Emails notifications of ACL events through an SMTP server.
+pub struct SmtpSink {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    recipients: BTreeMap<String, Vec<Mailbox>>,
+    subject: String,
+    body: String,
+}
+
+impl SmtpSink {
+    /// Creates the sink, rejecting invalid addresses and templates with
+    /// unknown placeholders.
+    pub fn new(config: &SmtpConfig) -> error::Result<Self> {
+        for (what, template) in [
+            ("smtp.subject", &config.subject),
+            ("smtp.body", &config.body),
+        ] {
+            render(what, template, |name| {
+                PLACEHOLDERS.contains(&name).then(String::new)
+            })?;
+        }
+        let from = mailbox("smtp.from", &config.from)?;
+        let recipients = config
+            .recipients
+            .iter()
+            .map(|(event_type, addresses)| {
+                let mailboxes = addresses
+                    .iter()
+                    .map(|address| mailbox("smtp.recipients", address))
+                    .collect::<error::Result<Vec<_>>>()?;
+                Ok((event_type.clone(), mailboxes))
+            })
+            .collect::<error::Result<_>>()?;
+
+        let mut builder = match config.security {
+            SmtpSecurity::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)
+                    .map_err(smtp_error)?
+            }
+            SmtpSecurity::Tls => {
+                AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host).map_err(smtp_error)?
+            }
+            SmtpSecurity::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+            }
+        }
+        .port(config.port);
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                password.expose().clone(),
+            ));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from,
+            recipients,
+            subject: config.subject.clone(),
+            body: config.body.clone(),
+        })
+    }
+}
+
+/// Parses an address of the `field` setting.
+fn mailbox(field: &str, address: &str) -> error::Result<Mailbox> {
+    address
+        .parse()
+        .map_err(|e| error::Error::InvalidConfig(format!("{} `{}`: {}", field, address, e)))
+}
+
+fn smtp_error(e: lettre::transport::smtp::Error) -> error::Error {
+    error::Error::SmtpError {
+        reason: e.to_string(),
+        transient: !e.is_permanent(),
+    }
+}
+
+/// Returns the value of a placeholder for `event`.
+fn value(name: &str, event: &Event, invite: &InviteMsg) -> Option<String> {
+    match name {
+        "event_type" => Some(invite.event_type().to_string()),
+        "inviter" => Some(invite.inviter().to_string()),
+        "invitee" => Some(invite.invitee().to_string()),
+        "project" => Some(invite.project().to_string()),
+        "id" => Some(event.id.to_hex()),
+        "pubkey" => Some(event.pubkey.to_hex()),
+        "created_at" => Some(event.created_at.to_human_datetime()),
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl EventSink for SmtpSink {
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+
+    /// Emails the event if recipients are configured for its ACL event type,
+    /// and skips it otherwise. The message id derives from the event id, so
+    /// mail clients can thread or drop re-sent notifications.
+    async fn send(&self, event: &Event, _correlation_id: &CorrelationId) -> error::Result<()> {
+        let Ok(invite) = InviteMsg::try_from(event.clone()) else {
+            tracing::debug!(sink = "smtp", "event {} is not an ACL event", event.id);
+            return Ok(());
+        };
+        let Some(recipients) = self
+            .recipients
+            .get(invite.event_type())
+            .filter(|r| !r.is_empty())
+        else {
+            tracing::debug!(
+                sink = "smtp",
+                "skipping {} event {}",
+                invite.event_type(),
+                event.id
+            );
+            return Ok(());
+        };
+
+        let subject = render("smtp.subject", &self.subject, |name| {
+            value(name, event, &invite)
+        })?;
+        let body = render("smtp.body", &self.body, |name| value(name, event, &invite))?;
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .subject(subject)
+            .message_id(Some(format!("<{}@nostr_gateway>", event.id.to_hex())))
+            .header(ContentType::TEXT_PLAIN);
+        for recipient in recipients {
+            builder = builder.to(recipient.clone());
+        }
+        let message = builder.body(body).map_err(|e| error::Error::SmtpError {
+            reason: e.to_string(),
+            transient: false,
+        })?;
+
+        self.transport.send(message).await.map_err(smtp_error)?;
+        tracing::debug!(
+            sink = "smtp",
+            "emailed event {} to {} recipients",
+            event.id,
+            recipients.len()
+        );
+
+        Ok(())
+    }
+}
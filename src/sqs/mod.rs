@@ -0,0 +1,3 @@
+mod sqs;
+
+pub use self::sqs::*;
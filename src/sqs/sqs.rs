@@ -0,0 +1,362 @@
+//!This module provides an AWS sink for bridged Nostr events, sending them to
+//!an SQS queue or publishing them to an SNS topic. Events are grouped into
+//!batches of up to ten messages, the API limit, and every message carries the
+//!event id as `event_id` attribute. FIFO queues and topics additionally get it
+//!as deduplication id, so an event re-sent after a retry is delivered once.
+
+use crate::common::config::{SinkFormat, SinkPayload, SqsConfig};
+use crate::common::correlation::CorrelationId;
+use crate::common::error;
+use crate::common::sink::{self, EventSink};
+use async_trait::async_trait;
+use aws_config::sts::AssumeRoleProvider;
+use aws_config::{BehaviorVersion, Region, SdkConfig};
+use aws_sdk_sqs::config::{Credentials, SharedCredentialsProvider};
+use aws_sdk_sqs::error::DisplayErrorContext;
+use nostr_sdk::Event;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+/// Session name of the assumed role.
+const SESSION_NAME: &str = "nostr_gateway";
+/// Maximum number of entries of a batch request, an API limit.
+const MAX_BATCH_SIZE: usize = 10;
+
+/// A message waiting for its batch to be sent.
+struct Pending {
+    event_id: String,
+    body: String,
+    reply: oneshot::Sender<error::Result<()>>,
+}
+
+/// Destination of the batches.
+enum Target {
+    Queue {
+        client: aws_sdk_sqs::Client,
+        url: String,
+    },
+    Topic {
+        client: aws_sdk_sns::Client,
+        arn: String,
+    },
+}
+
+/// Sends bridged events to an SQS queue or an SNS topic.
+pub struct SqsSink {
+    name: &'static str,
+    requests: mpsc::Sender<Pending>,
+    payload: SinkPayload,
+    format: SinkFormat,
+    /// CloudEvents source of the sent events.
+    source: String,
+}
+
+impl SqsSink {
+    /// Resolves the credentials and starts the batching task. `source` is
+    /// the CloudEvents source of the sent events.
+    pub async fn connect(config: &SqsConfig, source: &str) -> error::Result<Self> {
+        let sdk = sdk_config(config).await;
+        let (name, target, fifo) = match (&config.queue_url, &config.topic_arn) {
+            (Some(url), None) => (
+                "sqs",
+                Target::Queue {
+                    client: aws_sdk_sqs::Client::new(&sdk),
+                    url: url.clone(),
+                },
+                url.ends_with(".fifo"),
+            ),
+            (None, Some(arn)) => (
+                "sns",
+                Target::Topic {
+                    client: aws_sdk_sns::Client::new(&sdk),
+                    arn: arn.clone(),
+                },
+                arn.ends_with(".fifo"),
+            ),
+            _ => {
+                return Err(error::Error::InvalidConfig(
+                    "sqs needs exactly one of queue_url and topic_arn".to_string(),
+                ))
+            }
+        };
+
+        let batch_size = config.batch_size.clamp(1, MAX_BATCH_SIZE);
+        let (requests, pending) = mpsc::channel(batch_size * 10);
+        let group_id = fifo.then(|| config.message_group_id.clone());
+        tokio::spawn(drive(
+            target,
+            pending,
+            batch_size,
+            Duration::from_millis(config.linger_ms),
+            group_id,
+        ));
+
+        Ok(Self {
+            name,
+            requests,
+            payload: config.payload.clone(),
+            format: config.format.clone(),
+            source: source.to_string(),
+        })
+    }
+}
+
+/// Loads the AWS configuration from the standard chain, overridden by the
+/// settings of `config`.
+async fn sdk_config(config: &SqsConfig) -> SdkConfig {
+    let mut loader = aws_config::defaults(BehaviorVersion::latest());
+    if let Some(region) = &config.region {
+        loader = loader.region(Region::new(region.clone()));
+    }
+    if let Some(endpoint) = &config.endpoint {
+        loader = loader.endpoint_url(endpoint);
+    }
+    if let (Some(key), Some(secret)) = (&config.access_key_id, &config.secret_access_key) {
+        loader = loader.credentials_provider(Credentials::new(
+            key,
+            secret.expose(),
+            None,
+            None,
+            SESSION_NAME,
+        ));
+    }
+    let sdk = loader.load().await;
+
+    let Some(role_arn) = &config.role_arn else {
+        return sdk;
+    };
+    let provider = AssumeRoleProvider::builder(role_arn)
+        .session_name(SESSION_NAME)
+        .configure(&sdk)
+        .build()
+        .await;
+    sdk.into_builder()
+        .credentials_provider(SharedCredentialsProvider::new(provider))
+        .build()
+}
+
+/// Collects the pending messages into batches of up to `batch_size`, waiting
+/// at most `linger` for a batch to fill, and sends them until the sink is
+/// dropped.
+async fn drive(
+    target: Target,
+    mut pending: mpsc::Receiver<Pending>,
+    batch_size: usize,
+    linger: Duration,
+    group_id: Option<String>,
+) {
+    while let Some(batch) = next_batch(&mut pending, batch_size, linger).await {
+        let mut results = match target.send(&batch, group_id.as_deref()).await {
+            Ok(failed) => failed,
+            Err(e) => vec![Some(e); batch.len()],
+        };
+        for (index, message) in batch.into_iter().enumerate() {
+            let result = match results[index].take() {
+                Some(e) => Err(error::Error::SqsError(e)),
+                None => Ok(()),
+            };
+            let _ = message.reply.send(result);
+        }
+    }
+}
+
+/// Waits for the next batch of up to `batch_size` messages, at most `linger`
+/// after its first one.
+///
+/// # Returns
+///
+/// The batch, or `None` once the sink is dropped and every message was
+/// batched.
+async fn next_batch(
+    pending: &mut mpsc::Receiver<Pending>,
+    batch_size: usize,
+    linger: Duration,
+) -> Option<Vec<Pending>> {
+    let mut batch = vec![pending.recv().await?];
+    let deadline = tokio::time::sleep(linger);
+    tokio::pin!(deadline);
+    while batch.len() < batch_size {
+        tokio::select! {
+            message = pending.recv() => match message {
+                Some(message) => batch.push(message),
+                None => break,
+            },
+            _ = &mut deadline => break,
+        }
+    }
+
+    Some(batch)
+}
+
+impl Target {
+    /// Sends one batch. Entries are identified by their index in `batch`.
+    ///
+    /// # Returns
+    ///
+    /// The error of every entry that failed, by index, or the error of the
+    /// whole request.
+    async fn send(
+        &self,
+        batch: &[Pending],
+        group_id: Option<&str>,
+    ) -> Result<Vec<Option<String>>, String> {
+        let mut failed = vec![None; batch.len()];
+        let mut fail = |id: &str, code: &str, message: Option<&str>| {
+            if let Some(entry) = id.parse::<usize>().ok().and_then(|i| failed.get_mut(i)) {
+                *entry = Some(format!("{}: {}", code, message.unwrap_or_default()));
+            }
+        };
+
+        match self {
+            Target::Queue { client, url } => {
+                use aws_sdk_sqs::types::{MessageAttributeValue, SendMessageBatchRequestEntry};
+                let entries = batch
+                    .iter()
+                    .enumerate()
+                    .map(|(index, message)| {
+                        SendMessageBatchRequestEntry::builder()
+                            .id(index.to_string())
+                            .message_body(&message.body)
+                            .message_attributes(
+                                "event_id",
+                                MessageAttributeValue::builder()
+                                    .data_type("String")
+                                    .string_value(&message.event_id)
+                                    .build()?,
+                            )
+                            .set_message_deduplication_id(
+                                group_id.map(|_| message.event_id.clone()),
+                            )
+                            .set_message_group_id(group_id.map(str::to_string))
+                            .build()
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| e.to_string())?;
+                let output = client
+                    .send_message_batch()
+                    .queue_url(url)
+                    .set_entries(Some(entries))
+                    .send()
+                    .await
+                    .map_err(|e| DisplayErrorContext(e).to_string())?;
+                for entry in output.failed() {
+                    fail(entry.id(), entry.code(), entry.message());
+                }
+            }
+            Target::Topic { client, arn } => {
+                use aws_sdk_sns::types::{MessageAttributeValue, PublishBatchRequestEntry};
+                let entries = batch
+                    .iter()
+                    .enumerate()
+                    .map(|(index, message)| {
+                        PublishBatchRequestEntry::builder()
+                            .id(index.to_string())
+                            .message(&message.body)
+                            .message_attributes(
+                                "event_id",
+                                MessageAttributeValue::builder()
+                                    .data_type("String")
+                                    .string_value(&message.event_id)
+                                    .build()?,
+                            )
+                            .set_message_deduplication_id(
+                                group_id.map(|_| message.event_id.clone()),
+                            )
+                            .set_message_group_id(group_id.map(str::to_string))
+                            .build()
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| e.to_string())?;
+                let output = client
+                    .publish_batch()
+                    .topic_arn(arn)
+                    .set_publish_batch_request_entries(Some(entries))
+                    .send()
+                    .await
+                    .map_err(|e| DisplayErrorContext(e).to_string())?;
+                for entry in output.failed() {
+                    fail(entry.id(), entry.code(), entry.message());
+                }
+            }
+        }
+
+        Ok(failed)
+    }
+}
+
+#[async_trait]
+impl EventSink for SqsSink {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Queues the event for the next batch and waits for the batch to be
+    /// sent.
+    async fn send(&self, event: &Event, _correlation_id: &CorrelationId) -> error::Result<()> {
+        let body = sink::wrap(
+            event,
+            sink::encode(event, &self.payload)?,
+            &self.source,
+            &self.format,
+        )?;
+        let (reply, result) = oneshot::channel();
+        let stopped = || error::Error::SqsError("the batching task stopped".to_string());
+        self.requests
+            .send(Pending {
+                event_id: event.id.to_hex(),
+                body: String::from_utf8_lossy(&body).into_owned(),
+                reply,
+            })
+            .await
+            .map_err(|_| stopped())?;
+        result.await.map_err(|_| stopped())??;
+        tracing::debug!(sink = self.name, "sent event {}", event.id);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(sender: &mpsc::Sender<Pending>, count: usize) {
+        for n in 0..count {
+            let (reply, _) = oneshot::channel();
+            sender
+                .try_send(Pending {
+                    event_id: n.to_string(),
+                    body: String::new(),
+                    reply,
+                })
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn batches_up_to_the_api_limit() {
+        let (sender, mut pending) = mpsc::channel(100);
+        queue(&sender, 25);
+        drop(sender);
+
+        let linger = Duration::from_secs(3600);
+        let mut sizes = Vec::new();
+        while let Some(batch) = next_batch(&mut pending, MAX_BATCH_SIZE, linger).await {
+            sizes.push(batch.len());
+        }
+        assert_eq!(sizes, [10, 10, 5]);
+    }
+
+    #[tokio::test]
+    async fn sends_a_partial_batch_after_the_linger() {
+        let (sender, mut pending) = mpsc::channel(100);
+        queue(&sender, 3);
+
+        let batch = next_batch(&mut pending, MAX_BATCH_SIZE, Duration::from_millis(10))
+            .await
+            .unwrap();
+        let ids: Vec<_> = batch.iter().map(|m| m.event_id.as_str()).collect();
+        assert_eq!(ids, ["0", "1", "2"]);
+        assert!(!sender.is_closed());
+    }
+}
@@ -0,0 +1,3 @@
+mod transform;
+
+pub use self::transform::*;
@@ -0,0 +1,332 @@
+//!This module applies the rules of `transforms` to the bridged events, after
+//!the WASM plugins. A rule matches events on their kind, tags, author and
+//!content, and drops them, rewrites or strips their tags, strips their
+//!content, or routes them to another topic of the sink.
+//!
+//!Altered events keep their id, which the pipelines dedupe on, and their
+//!signature, which no longer verifies. Inbound events are verified before
+//!the rules run, and only `drop` applies to the `*2n` directions, since the
+//!relays would reject altered events. For the same reason the rules altering
+//!events skip the Waku directions, whose events are published to relays by
+//!the `w2n` gateways, and listing one of those directions in such a rule is
+//!a configuration error. Altering rules only apply to the other sinks.
+
+use crate::common::config::{TransformAction, TransformMatch, TransformRule};
+use crate::common::error;
+use nostr_sdk::{Event, PublicKey, Tag};
+use regex::Regex;
+use std::sync::RwLock;
+
+/// A rule with its matcher compiled.
+struct Rule {
+    directions: Vec<String>,
+    kinds: Vec<u16>,
+    /// Tag names and, if given, the value they must have.
+    tags: Vec<(String, Option<String>)>,
+    pubkeys: Vec<PublicKey>,
+    content: Option<Regex>,
+    action: TransformAction,
+}
+
+impl Rule {
+    fn compile(index: usize, rule: &TransformRule) -> error::Result<Self> {
+        let invalid =
+            |e: String| error::Error::InvalidConfig(format!("transforms[{}].match: {}", index, e));
+        let TransformMatch {
+            kinds,
+            tags,
+            pubkeys,
+            content,
+        } = &rule.matches;
+        let pubkeys = pubkeys
+            .iter()
+            .map(|pubkey| {
+                PublicKey::parse(pubkey).map_err(|e| invalid(format!("{}: {}", pubkey, e)))
+            })
+            .collect::<error::Result<_>>()?;
+        let content = content
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| invalid(e.to_string()))?;
+        if alters(&rule.action) {
+            if let Some(direction) = rule.directions.iter().find(|d| reaches_relays(d)) {
+                return Err(error::Error::InvalidConfig(format!(
+                    "transforms[{}].action: {} events reach Nostr relays, which reject altered events",
+                    index, direction
+                )));
+            }
+        }
+
+        Ok(Self {
+            directions: rule.directions.clone(),
+            kinds: kinds.clone(),
+            tags: tags
+                .iter()
+                .map(|tag| match tag.split_once(':') {
+                    Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                    None => (tag.clone(), None),
+                })
+                .collect(),
+            pubkeys,
+            content,
+            action: rule.action.clone(),
+        })
+    }
+
+    /// Returns whether the rule applies to `event` of `direction`.
+    fn matches(&self, direction: &str, event: &Event) -> bool {
+        if !self.directions.is_empty() && !self.directions.iter().any(|d| d == direction) {
+            return false;
+        }
+        if direction.ends_with("2n") && self.action != TransformAction::Drop {
+            return false;
+        }
+        if alters(&self.action) && reaches_relays(direction) {
+            return false;
+        }
+        (self.kinds.is_empty() || self.kinds.contains(&event.kind.as_u16()))
+            && (self.tags.is_empty()
+                || event.tags.iter().any(|tag| {
+                    self.tags.iter().any(|(name, value)| {
+                        let tag = tag.as_slice();
+                        tag.first() == Some(name)
+                            && value
+                                .as_ref()
+                                .map_or(true, |value| tag.get(1) == Some(value))
+                    })
+                }))
+            && (self.pubkeys.is_empty() || self.pubkeys.contains(&event.pubkey))
+            && self
+                .content
+                .as_ref()
+                .map_or(true, |content| content.is_match(&event.content))
+    }
+}
+
+/// Rules compiled by `TransformChain::prepare`, not running yet.
+pub struct PreparedRules(Vec<Rule>);
+
+/// The rules of the configuration, applied in order.
+pub struct TransformChain {
+    rules: RwLock<Vec<Rule>>,
+}
+
+impl TransformChain {
+    /// Compiles the configured rules.
+    pub fn new(rules: &[TransformRule]) -> error::Result<Self> {
+        Ok(Self {
+            rules: RwLock::new(compile(rules)?),
+        })
+    }
+
+    /// Compiles the configured rules to replace the running ones with, see
+    /// `install`. The running rules are left as they are.
+    pub fn prepare(&self, rules: &[TransformRule]) -> error::Result<PreparedRules> {
+        compile(rules).map(PreparedRules)
+    }
+
+    /// Replaces the rules with prepared ones.
+    ///
+    /// # Returns
+    ///
+    /// The number of loaded rules.
+    pub fn install(&self, rules: PreparedRules) -> usize {
+        let loaded = rules.0.len();
+        *self.rules.write().unwrap_or_else(|e| e.into_inner()) = rules.0;
+        loaded
+    }
+
+    /// Runs `event` through the rules of `direction`. Each rule sees the
+    /// event as altered by the previous ones.
+    ///
+    /// # Returns
+    ///
+    /// The event to forward, or `None` if a rule dropped it.
+    pub fn apply(&self, direction: &str, mut event: Event) -> Option<Event> {
+        for rule in self.rules.read().unwrap_or_else(|e| e.into_inner()).iter() {
+            if !rule.matches(direction, &event) {
+                continue;
+            }
+            event = match &rule.action {
+                TransformAction::Drop => {
+                    tracing::debug!("transform dropped event {}", event.id);
+                    return None;
+                }
+                TransformAction::RewriteTag { name, from, to } => rebuild(&event, |tags| {
+                    for tag in tags.iter_mut() {
+                        if tag.first() == Some(name) && tag.get(1) == Some(from) {
+                            tag[1] = to.clone();
+                        }
+                    }
+                }),
+                TransformAction::StripTag(name) => {
+                    rebuild(&event, |tags| tags.retain(|tag| tag.first() != Some(name)))
+                }
+                TransformAction::StripContent => Event::new(
+                    event.id,
+                    event.pubkey,
+                    event.created_at,
+                    event.kind,
+                    event.tags.iter().cloned(),
+                    "",
+                    event.sig,
+                ),
+                TransformAction::Route(_) => event,
+            };
+        }
+
+        Some(event)
+    }
+
+    /// Returns the topic the first matching `route` rule of `direction`
+    /// delivers `event` to, if any.
+    pub fn route(&self, direction: &str, event: &Event) -> Option<String> {
+        self.rules
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .find_map(|rule| match &rule.action {
+                TransformAction::Route(topic) if rule.matches(direction, event) => {
+                    Some(topic.clone())
+                }
+                _ => None,
+            })
+    }
+}
+
+/// Returns whether `action` changes the event, breaking its signature.
+fn alters(action: &TransformAction) -> bool {
+    matches!(
+        action,
+        TransformAction::RewriteTag { .. }
+            | TransformAction::StripTag(_)
+            | TransformAction::StripContent
+    )
+}
+
+/// Returns whether the events of `direction` end up on Nostr relays, which
+/// verify their signature: the `*2n` directions publish them, and the Waku
+/// ones carry them to the `w2n` gateways publishing them.
+fn reaches_relays(direction: &str) -> bool {
+    direction.ends_with("2n") || direction.starts_with("n2w")
+}
+
+/// Compiles the rules of `rules`.
+fn compile(rules: &[TransformRule]) -> error::Result<Vec<Rule>> {
+    rules
+        .iter()
+        .enumerate()
+        .map(|(index, rule)| Rule::compile(index, rule))
+        .collect()
+}
+
+/// Returns `event` with its tags edited by `edit`, as lists of strings.
+fn rebuild(event: &Event, edit: impl FnOnce(&mut Vec<Vec<String>>)) -> Event {
+    let mut tags: Vec<Vec<String>> = event
+        .tags
+        .iter()
+        .map(|tag| tag.as_slice().to_vec())
+        .collect();
+    edit(&mut tags);
+    let tags = tags
+        .iter()
+        .filter_map(|tag| Tag::parse(tag.as_slice()).ok())
+        .collect::<Vec<_>>();
+    Event::new(
+        event.id,
+        event.pubkey,
+        event.created_at,
+        event.kind,
+        tags,
+        event.content.clone(),
+        event.sig,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{EventBuilder, Keys, Kind};
+
+    fn chain(rules: &str) -> error::Result<TransformChain> {
+        TransformChain::new(&serde_yaml::from_str::<Vec<TransformRule>>(rules).unwrap())
+    }
+
+    fn event(content: &str, hashtags: &[&str]) -> Event {
+        EventBuilder::new(Kind::TextNote, content)
+            .tags(hashtags.iter().map(|t| Tag::hashtag(*t)))
+            .sign_with_keys(&Keys::generate())
+            .unwrap()
+    }
+
+    fn hashtags(event: &Event) -> Vec<String> {
+        event
+            .tags
+            .iter()
+            .map(|tag| tag.as_slice())
+            .filter(|tag| tag.first().is_some_and(|name| name == "t"))
+            .filter_map(|tag| tag.get(1).cloned())
+            .collect()
+    }
+
+    #[test]
+    fn drops_matching_events() {
+        let chain = chain("- match: {kinds: [1], tags: ['t:spam']}\n  action: drop\n").unwrap();
+        assert!(chain.apply("n2k", event("buy now", &["spam"])).is_none());
+        assert!(chain.apply("w2n", event("buy now", &["spam"])).is_none());
+        assert!(chain.apply("n2k", event("hello", &["acl"])).is_some());
+    }
+
+    #[test]
+    fn alters_events_in_order() {
+        let chain = chain(
+            "- action: {rewrite_tag: {name: t, from: acl, to: access}}\n\
+             - match: {tags: ['t:access']}\n  action: strip_content\n\
+             - action: {strip_tag: t}\n  directions: [n2m]\n",
+        )
+        .unwrap();
+        let original = event("invite", &["acl", "other"]);
+
+        let altered = chain.apply("n2k", original.clone()).unwrap();
+        assert_eq!(hashtags(&altered), ["access", "other"]);
+        assert_eq!(altered.content, "");
+        assert_eq!(altered.id, original.id);
+
+        let stripped = chain.apply("n2m", original).unwrap();
+        assert!(hashtags(&stripped).is_empty());
+    }
+
+    #[test]
+    fn altering_rules_skip_the_directions_reaching_relays() {
+        let chain = chain("- action: strip_content\n").unwrap();
+        for direction in ["w2n", "k2n", "n2w"] {
+            let event = chain.apply(direction, event("invite", &[])).unwrap();
+            assert_eq!(event.content, "invite", "{direction}");
+        }
+    }
+
+    #[test]
+    fn routes_to_the_first_matching_topic() {
+        let chain = chain(
+            "- match: {content: '^urgent'}\n  action: {route: alerts}\n\
+             - action: {route: events}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            chain.route("n2k", &event("urgent: revoke", &[])).as_deref(),
+            Some("alerts")
+        );
+        assert_eq!(
+            chain.route("n2k", &event("invite", &[])).as_deref(),
+            Some("events")
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        assert!(chain("- directions: [n2w]\n  action: {strip_tag: t}\n").is_err());
+        assert!(chain("- match: {content: '('}\n  action: drop\n").is_err());
+        assert!(chain("- match: {pubkeys: [nobody]}\n  action: drop\n").is_err());
+    }
+}
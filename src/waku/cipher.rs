@@ -0,0 +1,204 @@
+//!This module encrypts the payloads published to Waku, as configured by
+//!`waku.encryption`, so only the bridges holding the key can read the events
+//!relayed on the pubsub topic. The payload, the event or its IPFS reference,
+//!is sealed before it is published and opened when received:
+//!
+//!- `symmetric`: AES-256-GCM with a shared key, sealed as the 12 byte nonce
+//!  followed by the ciphertext.
+//!- `asymmetric`: ECIES to a recipient key over secp256k1. Each payload is
+//!  encrypted with NIP-44 v2 from a fresh ephemeral key, sealed as its 32
+//!  byte x-only public key followed by the NIP-44 payload. NIP-44 limits the
+//!  payload to 65535 bytes, so larger events need `ipfs`.
+
+use crate::common::config::WakuEncryption;
+use crate::common::error;
+use aes_gcm::aead::{Aead, AeadCore, OsRng};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use nostr_sdk::nips::nip44::v2::{self, ConversationKey};
+use nostr_sdk::{Keys, PublicKey};
+
+/// Length of an AES-GCM nonce.
+const NONCE_LEN: usize = 12;
+/// Length of an x-only public key.
+const PUBLIC_KEY_LEN: usize = 32;
+
+/// Seals and opens Waku payloads.
+pub enum PayloadCipher {
+    Symmetric(Aes256Gcm),
+    Asymmetric {
+        /// Needed to seal.
+        recipient: Option<PublicKey>,
+        /// Needed to open.
+        keys: Option<Keys>,
+    },
+}
+
+impl PayloadCipher {
+    /// Parses the key material of `config`.
+    pub fn new(config: &WakuEncryption) -> error::Result<Self> {
+        let invalid = |field: &str, e: String| {
+            error::Error::InvalidConfig(format!("waku.encryption.{}: {}", field, e))
+        };
+        match config {
+            WakuEncryption::Symmetric { key } => {
+                let key = hex::decode(key.expose().trim_start_matches("0x"))
+                    .map_err(|e| invalid("key", e.to_string()))?;
+                let cipher = Aes256Gcm::new_from_slice(&key)
+                    .map_err(|_| invalid("key", "expected 32 bytes".to_string()))?;
+                Ok(PayloadCipher::Symmetric(cipher))
+            }
+            WakuEncryption::Asymmetric {
+                public_key,
+                secret_key,
+            } => {
+                if public_key.is_none() && secret_key.is_none() {
+                    return Err(invalid(
+                        "public_key",
+                        "set public_key to publish or secret_key to receive".to_string(),
+                    ));
+                }
+                let keys = secret_key
+                    .as_ref()
+                    .map(|key| Keys::parse(key.expose()))
+                    .transpose()
+                    .map_err(|e| invalid("secret_key", e.to_string()))?;
+                let recipient = match public_key {
+                    Some(public_key) => Some(
+                        PublicKey::parse(public_key)
+                            .map_err(|e| invalid("public_key", e.to_string()))?,
+                    ),
+                    None => keys.as_ref().map(Keys::public_key),
+                };
+                Ok(PayloadCipher::Asymmetric { recipient, keys })
+            }
+        }
+    }
+
+    /// Encrypts a payload before it is published.
+    pub fn seal(&self, payload: &[u8]) -> error::Result<Vec<u8>> {
+        match self {
+            PayloadCipher::Symmetric(cipher) => {
+                let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+                let ciphertext = cipher
+                    .encrypt(&nonce, payload)
+                    .map_err(|e| cipher_error("encrypting", e))?;
+                Ok([nonce.as_slice(), &ciphertext].concat())
+            }
+            PayloadCipher::Asymmetric { recipient, .. } => {
+                let recipient = recipient.as_ref().ok_or_else(|| {
+                    error::Error::InvalidConfig(
+                        "waku.encryption.public_key is needed to publish".to_string(),
+                    )
+                })?;
+                let ephemeral = Keys::generate();
+                let conversation = ConversationKey::derive(ephemeral.secret_key(), recipient);
+                let ciphertext = v2::encrypt_to_bytes(&conversation, payload)
+                    .map_err(|e| cipher_error("encrypting", e))?;
+                Ok([ephemeral.public_key().to_bytes().as_slice(), &ciphertext].concat())
+            }
+        }
+    }
+
+    /// Decrypts a received payload.
+    pub fn open(&self, sealed: &[u8]) -> error::Result<Vec<u8>> {
+        match self {
+            PayloadCipher::Symmetric(cipher) => {
+                if sealed.len() < NONCE_LEN {
+                    return Err(cipher_error("decrypting", "truncated payload"));
+                }
+                let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+                cipher
+                    .decrypt(Nonce::from_slice(nonce), ciphertext)
+                    .map_err(|e| cipher_error("decrypting", e))
+            }
+            PayloadCipher::Asymmetric { keys, .. } => {
+                let keys = keys.as_ref().ok_or_else(|| {
+                    error::Error::InvalidConfig(
+                        "waku.encryption.secret_key is needed to receive".to_string(),
+                    )
+                })?;
+                if sealed.len() < PUBLIC_KEY_LEN {
+                    return Err(cipher_error("decrypting", "truncated payload"));
+                }
+                let (sender, ciphertext) = sealed.split_at(PUBLIC_KEY_LEN);
+                let sender =
+                    PublicKey::from_slice(sender).map_err(|e| cipher_error("decrypting", e))?;
+                let conversation = ConversationKey::derive(keys.secret_key(), &sender);
+                v2::decrypt_to_bytes(&conversation, ciphertext)
+                    .map_err(|e| cipher_error("decrypting", e))
+            }
+        }
+    }
+}
+
+fn cipher_error(action: &str, e: impl std::fmt::Display) -> error::Error {
+    error::Error::CustomError(format!("{} waku payload: {}", action, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::secret::Secret;
+
+    fn symmetric(key: &str) -> error::Result<PayloadCipher> {
+        PayloadCipher::new(&WakuEncryption::Symmetric {
+            key: Secret::new(key.to_string()),
+        })
+    }
+
+    fn asymmetric(keys: &Keys) -> PayloadCipher {
+        PayloadCipher::new(&WakuEncryption::Asymmetric {
+            public_key: None,
+            secret_key: Some(Secret::new(keys.secret_key().to_secret_hex())),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn symmetric_round_trip() {
+        let cipher = symmetric(&"11".repeat(32)).unwrap();
+        let sealed = cipher.seal(b"event").unwrap();
+        assert_ne!(&sealed[NONCE_LEN..], b"event");
+        assert_eq!(cipher.open(&sealed).unwrap(), b"event");
+    }
+
+    #[test]
+    fn symmetric_rejects_the_wrong_key() {
+        let sealed = symmetric(&"11".repeat(32)).unwrap().seal(b"event").unwrap();
+        let other = symmetric(&"22".repeat(32)).unwrap();
+        assert!(other.open(&sealed).is_err());
+        assert!(other.open(&sealed[..NONCE_LEN - 1]).is_err());
+    }
+
+    #[test]
+    fn symmetric_needs_a_32_byte_key() {
+        assert!(symmetric(&"11".repeat(16)).is_err());
+        assert!(symmetric("not hex").is_err());
+    }
+
+    #[test]
+    fn asymmetric_round_trip() {
+        let cipher = asymmetric(&Keys::generate());
+        let sealed = cipher.seal(b"event").unwrap();
+        assert_eq!(cipher.open(&sealed).unwrap(), b"event");
+    }
+
+    #[test]
+    fn asymmetric_rejects_the_wrong_key() {
+        let sealed = asymmetric(&Keys::generate()).seal(b"event").unwrap();
+        assert!(asymmetric(&Keys::generate()).open(&sealed).is_err());
+    }
+
+    #[test]
+    fn asymmetric_needs_a_secret_key_to_open() {
+        let recipient = Keys::generate();
+        let publisher = PayloadCipher::new(&WakuEncryption::Asymmetric {
+            public_key: Some(recipient.public_key().to_hex()),
+            secret_key: None,
+        })
+        .unwrap();
+        let sealed = publisher.seal(b"event").unwrap();
+        assert!(publisher.open(&sealed).is_err());
+        assert_eq!(asymmetric(&recipient).open(&sealed).unwrap(), b"event");
+    }
+}
@@ -0,0 +1,329 @@
+//! Direct gossipsub transport, an alternative to publishing through a Waku
+//! node.
+//!
+//! The gateway joins the Waku relay mesh itself with rust-libp2p: it speaks
+//! the `/vac/waku/relay/2.0.0` gossipsub protocol on the configured pubsub
+//! topic and exchanges protobuf encoded Waku messages, so nodes of the mesh
+//! see it as one more relay peer. Only built with the `gossipsub` feature.
+
+use super::{PayloadCipher, TopicRouter};
+use crate::common::config::{GossipsubConfig, WakuConfig};
+use crate::common::correlation::CorrelationId;
+use crate::common::error;
+use crate::common::sink::{EventSink, EventSource};
+use crate::ipfs::IpfsStore;
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::StreamExt;
+use libp2p::gossipsub::{self, IdentTopic, MessageAuthenticity, PublishError, ValidationMode};
+use libp2p::swarm::SwarmEvent;
+use libp2p::{identity, noise, tcp, yamux, Multiaddr, Swarm};
+use nostr_sdk::{Event, JsonUtil};
+use prost::Message as _;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, oneshot, Notify};
+
+/// Gossipsub protocol id of the Waku relay.
+const WAKU_RELAY_PROTOCOL: &str = "/vac/waku/relay/2.0.0";
+/// Number of received messages buffered for the slowest reader.
+const INBOUND_CAPACITY: usize = 1024;
+
+/// A Waku message as exchanged on the relay.
+#[derive(Clone, PartialEq, prost::Message)]
+struct WakuMessage {
+    #[prost(bytes = "vec", tag = "1")]
+    payload: Vec<u8>,
+    #[prost(string, tag = "2")]
+    content_topic: String,
+    #[prost(uint32, optional, tag = "3")]
+    version: Option<u32>,
+    #[prost(sint64, optional, tag = "10")]
+    timestamp: Option<i64>,
+}
+
+/// A publish request handed to the swarm task.
+struct Publish {
+    data: Vec<u8>,
+    reply: oneshot::Sender<error::Result<()>>,
+}
+
+/// Handle of the swarm task, which owns the libp2p swarm.
+pub struct Gossipsub {
+    publish: mpsc::Sender<Publish>,
+    inbound: broadcast::Sender<Vec<u8>>,
+    stop: Arc<Notify>,
+    router: TopicRouter,
+    ipfs: Option<Arc<IpfsStore>>,
+    cipher: Option<Arc<PayloadCipher>>,
+}
+
+impl Gossipsub {
+    /// Starts a swarm listening on `gossipsub.listen_addr`, dials the
+    /// bootstrap peers, or `waku.node_addr` if there are none, and subscribes
+    /// to the pubsub topic.
+    pub fn start(waku: &WakuConfig, config: &GossipsubConfig) -> error::Result<Self> {
+        let failed = |e: String| error::Error::GossipsubError(e);
+        let keypair =
+            match &waku.node_key {
+                Some(key) => {
+                    let mut bytes =
+                        hex::decode(key.expose().trim_start_matches("0x")).map_err(|e| {
+                            error::Error::InvalidKey {
+                                field: "waku.node_key",
+                                reason: e.to_string(),
+                            }
+                        })?;
+                    let secret = identity::secp256k1::SecretKey::try_from_bytes(&mut bytes)
+                        .map_err(|e| error::Error::InvalidKey {
+                            field: "waku.node_key",
+                            reason: e.to_string(),
+                        })?;
+                    identity::secp256k1::Keypair::from(secret).into()
+                }
+                None => identity::Keypair::generate_secp256k1(),
+            };
+
+        let gossipsub_config = gossipsub::ConfigBuilder::default()
+            .protocol_id(WAKU_RELAY_PROTOCOL, gossipsub::Version::V1_1)
+            .validation_mode(ValidationMode::Anonymous)
+            .message_id_fn(|message| {
+                gossipsub::MessageId::from(Sha256::digest(&message.data).to_vec())
+            })
+            .heartbeat_interval(Duration::from_secs(1))
+            .build()
+            .map_err(|e| failed(e.to_string()))?;
+        let mut behaviour =
+            gossipsub::Behaviour::new(MessageAuthenticity::Anonymous, gossipsub_config)
+                .map_err(|e| failed(e.to_string()))?;
+        let topic = IdentTopic::new(waku.pubsub_topic.as_str());
+        behaviour
+            .subscribe(&topic)
+            .map_err(|e| failed(e.to_string()))?;
+
+        let mut swarm = libp2p::SwarmBuilder::with_existing_identity(keypair)
+            .with_tokio()
+            .with_tcp(
+                tcp::Config::default(),
+                noise::Config::new,
+                yamux::Config::default,
+            )
+            .map_err(|e| failed(e.to_string()))?
+            .with_dns()
+            .map_err(|e| failed(e.to_string()))?
+            .with_behaviour(|_| behaviour)
+            .map_err(|e| failed(e.to_string()))?
+            .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+            .build();
+        tracing::info!("gossipsub peer id: {}", swarm.local_peer_id());
+
+        let listen_addr: Multiaddr = parse_addr("waku.gossipsub.listen_addr", &config.listen_addr)?;
+        swarm
+            .listen_on(listen_addr)
+            .map_err(|e| failed(e.to_string()))?;
+        let peers = if config.bootstrap_peers.is_empty() {
+            vec![waku.node_addr.clone()]
+        } else {
+            config.bootstrap_peers.clone()
+        };
+        for peer in &peers {
+            let addr = parse_addr("waku.gossipsub.bootstrap_peers", peer)?;
+            if let Err(e) = swarm.dial(addr) {
+                tracing::warn!("failed to dial gossipsub peer {}: {}", peer, e);
+            }
+        }
+
+        let (publish, requests) = mpsc::channel(100);
+        let (inbound, _) = broadcast::channel(INBOUND_CAPACITY);
+        let stop = Arc::new(Notify::new());
+        tokio::spawn(drive(swarm, topic, requests, inbound.clone(), stop.clone()));
+
+        Ok(Self {
+            publish,
+            inbound,
+            stop,
+            router: TopicRouter::new(waku),
+            ipfs: None,
+            cipher: None,
+        })
+    }
+
+    /// Offloads large payloads to `ipfs` and resolves received references.
+    pub fn with_ipfs(mut self, ipfs: Option<Arc<IpfsStore>>) -> Self {
+        self.ipfs = ipfs;
+        self
+    }
+
+    /// Seals the published payloads with `cipher` and opens the received
+    /// ones.
+    pub fn with_cipher(mut self, cipher: Option<Arc<PayloadCipher>>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Stops the swarm task, closing the connections to the peers.
+    pub fn stop(&self) {
+        self.stop.notify_one();
+    }
+}
+
+fn parse_addr(field: &'static str, value: &str) -> error::Result<Multiaddr> {
+    value
+        .parse()
+        .map_err(|e: libp2p::multiaddr::Error| error::Error::InvalidUrl {
+            field,
+            value: value.to_string(),
+            reason: e.to_string(),
+        })
+}
+
+/// Runs the swarm: publishes the requested messages and hands the received
+/// ones to `inbound`, until every handle is dropped or `stop` is notified.
+async fn drive(
+    mut swarm: Swarm<gossipsub::Behaviour>,
+    topic: IdentTopic,
+    mut requests: mpsc::Receiver<Publish>,
+    inbound: broadcast::Sender<Vec<u8>>,
+    stop: Arc<Notify>,
+) {
+    loop {
+        tokio::select! {
+            _ = stop.notified() => {
+                tracing::info!("gossipsub transport stopped");
+                return;
+            }
+            request = requests.recv() => {
+                let Some(request) = request else {
+                    return;
+                };
+                let result = match swarm.behaviour_mut().publish(topic.clone(), request.data) {
+                    Ok(_) | Err(PublishError::Duplicate) => Ok(()),
+                    Err(e) => Err(error::Error::GossipsubError(e.to_string())),
+                };
+                let _ = request.reply.send(result);
+            }
+            event = swarm.select_next_some() => match event {
+                SwarmEvent::Behaviour(gossipsub::Event::Message { message, .. }) => {
+                    let _ = inbound.send(message.data);
+                }
+                SwarmEvent::NewListenAddr { address, .. } => {
+                    tracing::info!("gossipsub listening on {}", address);
+                }
+                SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                    tracing::debug!("connected to gossipsub peer {}", peer_id);
+                }
+                SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
+                    tracing::warn!("gossipsub connection to {:?} failed: {}", peer_id, error);
+                }
+                _ => {}
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl EventSink for Gossipsub {
+    fn name(&self) -> &'static str {
+        "gossipsub"
+    }
+
+    fn topic(&self, event: &Event) -> Option<&str> {
+        Some(self.router.topic(event))
+    }
+
+    async fn send(&self, event: &Event, correlation_id: &CorrelationId) -> error::Result<()> {
+        self.send_to(event, correlation_id, None).await
+    }
+
+    async fn send_to(
+        &self,
+        event: &Event,
+        _correlation_id: &CorrelationId,
+        topic: Option<&str>,
+    ) -> error::Result<()> {
+        let payload = match &self.ipfs {
+            Some(ipfs) => ipfs.offload(event).await?,
+            None => event.as_json(),
+        };
+        let payload = match &self.cipher {
+            Some(cipher) => cipher.seal(payload.as_bytes())?,
+            None => payload.into_bytes(),
+        };
+        let message = WakuMessage {
+            payload,
+            content_topic: topic
+                .unwrap_or_else(|| self.router.topic(event))
+                .to_string(),
+            version: Some(0),
+            timestamp: Utc::now().timestamp_nanos_opt(),
+        };
+        let (reply, result) = oneshot::channel();
+        let stopped = || error::Error::GossipsubError("the swarm task stopped".to_string());
+        self.publish
+            .send(Publish {
+                data: message.encode_to_vec(),
+                reply,
+            })
+            .await
+            .map_err(|_| stopped())?;
+        result.await.map_err(|_| stopped())?
+    }
+}
+
+#[async_trait]
+impl EventSource for Gossipsub {
+    fn name(&self) -> &'static str {
+        "gossipsub"
+    }
+
+    async fn run(&self, tx: mpsc::Sender<Event>) -> error::Result<()> {
+        let mut inbound = self.inbound.subscribe();
+        loop {
+            let data = match inbound.recv().await {
+                Ok(data) => data,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    tracing::warn!("gossipsub reader fell behind, skipped {} messages", missed);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            };
+            let message = match WakuMessage::decode(data.as_slice()) {
+                Ok(message) if self.router.contains(&message.content_topic) => message,
+                Ok(_) => continue,
+                Err(e) => {
+                    tracing::warn!("skipping malformed waku message: {}", e);
+                    continue;
+                }
+            };
+            let payload = match &self.cipher {
+                Some(cipher) => match cipher.open(&message.payload) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        tracing::warn!("skipping undecryptable waku message: {}", e);
+                        continue;
+                    }
+                },
+                None => message.payload,
+            };
+            let payload = match &self.ipfs {
+                Some(ipfs) => match ipfs.resolve(&payload).await {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        tracing::warn!("skipping unresolvable ipfs reference: {}", e);
+                        continue;
+                    }
+                },
+                None => payload,
+            };
+            match Event::from_json(&payload) {
+                Ok(event) => {
+                    if tx.send(event).await.is_err() {
+                        return Ok(());
+                    }
+                }
+                Err(e) => tracing::warn!("skipping malformed event: {}", e),
+            }
+        }
+    }
+}
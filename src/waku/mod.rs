@@ -1,3 +1,23 @@
+mod cipher;
+#[cfg(feature = "gossipsub")]
+mod gossipsub;
+#[cfg(feature = "waku-ffi")]
+mod node;
+#[cfg(feature = "waku-ffi")]
 mod pubsub;
+mod rest;
+mod routes;
+#[cfg(feature = "waku-rest")]
+mod sink;
 
+pub use cipher::PayloadCipher;
+#[cfg(feature = "gossipsub")]
+pub use gossipsub::*;
+#[cfg(feature = "waku-ffi")]
+pub use node::WakuNodeSource;
+#[cfg(feature = "waku-ffi")]
 pub use pubsub::*;
+pub use rest::{PublishReceipt, WakuMessage, WakuRestClient};
+pub use routes::TopicRouter;
+#[cfg(feature = "waku-rest")]
+pub use sink::WakuRestSink;
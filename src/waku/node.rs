@@ -0,0 +1,227 @@
+//!This module provides the `w2n` event source of the embedded Waku node. The
+//!node wrapper prints every received message as a JSON line; the source
+//!decodes their base64 payloads, decrypting them and resolving IPFS references
+//!when configured, into Nostr events, and persists the timestamp of the last
+//!processed message so messages delivered again after a restart are skipped.
+//!After a restart, the messages published while the bridge was offline are
+//!fetched first from the store of a connected peer.
+
+use super::{PayloadCipher, TopicRouter, WakuClient};
+use crate::common::config::WakuConfig;
+use crate::common::error;
+use crate::common::sink::EventSource;
+use crate::common::timing::{timed, Operation};
+use crate::db;
+use crate::ipfs::IpfsStore;
+use async_trait::async_trait;
+use base64::Engine;
+use nostr_sdk::{Event, JsonUtil};
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// A message as printed by the node wrapper.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NodeMessage {
+    /// The base64 encoded event, or its IPFS reference.
+    payload: String,
+    content_topic: Option<String>,
+    /// Sender timestamp in nanoseconds.
+    timestamp: Option<i64>,
+}
+
+/// Receives the events published on the content topics through the
+/// embedded Waku node.
+pub struct WakuNodeSource {
+    client: Arc<WakuClient>,
+    /// Key of the checkpoint, shared by every routed topic.
+    content_topic: String,
+    router: TopicRouter,
+    store: db::Storage,
+    ipfs: Option<Arc<IpfsStore>>,
+    cipher: Option<Arc<PayloadCipher>>,
+}
+
+impl WakuNodeSource {
+    pub fn new(
+        client: Arc<WakuClient>,
+        waku: &WakuConfig,
+        store: db::Storage,
+        ipfs: Option<Arc<IpfsStore>>,
+        cipher: Option<Arc<PayloadCipher>>,
+    ) -> Self {
+        Self {
+            client,
+            content_topic: waku.content_topic.clone(),
+            router: TopicRouter::new(waku),
+            store,
+            ipfs,
+            cipher,
+        }
+    }
+
+    /// Decodes the event carried by a message of the wrapper.
+    async fn decode(&self, message: &NodeMessage) -> error::Result<Event> {
+        let payload = base64::engine::general_purpose::STANDARD
+            .decode(message.payload.trim())
+            .map_err(|e| error::Error::CustomError(format!("invalid base64 payload: {}", e)))?;
+        self.decode_payload(payload).await
+    }
+
+    /// Decodes the event carried by a published payload.
+    async fn decode_payload(&self, payload: Vec<u8>) -> error::Result<Event> {
+        let payload = match &self.cipher {
+            Some(cipher) => cipher.open(&payload)?,
+            None => payload,
+        };
+        let payload = match &self.ipfs {
+            Some(ipfs) => ipfs.resolve(&payload).await?,
+            None => payload,
+        };
+        Event::from_json(&payload)
+            .map_err(|e| error::Error::CustomError(format!("invalid event: {}", e)))
+    }
+
+    /// Hands the events published after `checkpoint` and kept by the store of
+    /// a peer to `tx`, committing the checkpoint as it goes.
+    ///
+    /// A failed store query is logged and only costs the missed messages, so
+    /// is one that outlasts `timeouts.waku_secs`.
+    ///
+    /// # Returns
+    ///
+    /// The checkpoint after the replay, or `None` once `tx` is closed.
+    async fn replay_history(&self, checkpoint: i64, tx: &mpsc::Sender<Event>) -> Option<i64> {
+        let client = self.client.clone();
+        let until = chrono::Utc::now().timestamp_nanos_opt().unwrap_or(i64::MAX);
+        let history = timed(Operation::Waku, async move {
+            tokio::task::spawn_blocking(move || client.fetch_history(checkpoint, until))
+                .await
+                .map_err(|e| error::Error::CustomError(e.to_string()))?
+                .map_err(error::Error::CustomError)
+        })
+        .await;
+        let messages = match history {
+            Ok(messages) => messages,
+            Err(e) => {
+                tracing::warn!("fetching the waku history failed: {}", e);
+                return Some(checkpoint);
+            }
+        };
+        tracing::info!(
+            "replaying {} waku messages published since {}",
+            messages.len(),
+            checkpoint
+        );
+
+        let mut checkpoint = checkpoint;
+        for message in messages {
+            if message.timestamp <= checkpoint || !self.router.contains(&message.content_topic) {
+                continue;
+            }
+            match self.decode_payload(message.payload).await {
+                Ok(event) => {
+                    if tx.send(event).await.is_err() {
+                        return None;
+                    }
+                }
+                Err(e) => tracing::warn!("skipping stored waku message: {}", e),
+            }
+            checkpoint = message.timestamp;
+            if let Err(e) = self
+                .store
+                .set_waku_checkpoint(&self.content_topic, checkpoint)
+                .await
+            {
+                tracing::warn!("committing waku checkpoint {} failed: {}", checkpoint, e);
+            }
+        }
+        Some(checkpoint)
+    }
+}
+
+#[async_trait]
+impl EventSource for WakuNodeSource {
+    fn name(&self) -> &'static str {
+        "waku"
+    }
+
+    /// Hands the decoded events to `tx` in the order they were received,
+    /// after the stored ones published since the checkpoint.
+    ///
+    /// The checkpoint is committed once an event is handed over, and
+    /// messages not newer than it are skipped. Messages without a timestamp
+    /// are always processed.
+    async fn run(&self, tx: mpsc::Sender<Event>) -> error::Result<()> {
+        let mut checkpoint = self.store.waku_checkpoint(&self.content_topic).await?;
+
+        // The wrapper is read with blocking I/O, so it gets its own task. It
+        // is started before the replay so no message falls in between.
+        let (lines_tx, mut lines) = mpsc::channel(100);
+        let client = self.client.clone();
+        let wrapper =
+            tokio::spawn(async move { client.listening_message_gowrapper(lines_tx).await });
+
+        if let Some(last) = checkpoint {
+            match self.replay_history(last, &tx).await {
+                Some(last) => checkpoint = Some(last),
+                None => lines.close(),
+            }
+        }
+
+        while let Some(line) = lines.recv().await {
+            let message = match serde_json::from_str::<NodeMessage>(&line) {
+                Ok(message) => message,
+                Err(_) => {
+                    tracing::debug!("skipping wrapper output: {}", line);
+                    continue;
+                }
+            };
+            if message
+                .content_topic
+                .as_ref()
+                .is_some_and(|topic| !self.router.contains(topic))
+            {
+                continue;
+            }
+            if let (Some(timestamp), Some(last)) = (message.timestamp, checkpoint) {
+                if timestamp <= last {
+                    tracing::debug!("skipping waku message of {}, already processed", timestamp);
+                    continue;
+                }
+            }
+
+            match self.decode(&message).await {
+                Ok(event) => {
+                    if tx.send(event).await.is_err() {
+                        break;
+                    }
+                }
+                Err(e) => tracing::warn!("skipping waku message: {}", e),
+            }
+
+            if let Some(timestamp) = message.timestamp {
+                checkpoint = Some(timestamp);
+                if let Err(e) = self
+                    .store
+                    .set_waku_checkpoint(&self.content_topic, timestamp)
+                    .await
+                {
+                    tracing::warn!("committing waku checkpoint {} failed: {}", timestamp, e);
+                }
+            }
+        }
+
+        // A wrapper that exited on its own reports why; stopping it once
+        // `tx` is closed is not a failure.
+        wrapper.abort();
+        match wrapper.await {
+            Ok(Err(e)) => Err(error::Error::CustomError(format!(
+                "waku node wrapper: {}",
+                e
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
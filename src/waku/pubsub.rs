@@ -3,9 +3,9 @@
 /// This module provides a Rust client for interacting with the Waku protocol, which is a decentralized
 /// messaging protocol. The client allows sending and receiving messages, connecting to peers, and
 /// retrieving message history.
-use crate::common::config::WakuConfig;
+use super::TopicRouter;
+use crate::common::config::{WakuConfig, WakuPublish};
 use aes_gcm::{Aes256Gcm, KeyInit};
-use chrono::Utc;
 use nostr_sdk::prelude::Event as NostrEvent;
 use rand::thread_rng;
 use secp256k1::SecretKey;
@@ -16,12 +16,30 @@ use std::str::FromStr;
 use std::time::{Duration, SystemTime};
 use std::{collections::HashSet, str::from_utf8};
 use tokio::sync::mpsc::{self};
+use url::Url;
 use waku_bindings::{
-    waku_default_pubsub_topic, waku_new, waku_set_event_callback, ContentFilter, Encoding, Event,
-    Key, MessageId, Multiaddr, PagingOptions, ProtocolId, Running, StoreQuery, WakuContentTopic,
-    WakuLogLevel, WakuMessage, WakuNodeConfig, WakuNodeHandle, WakuPubSubTopic,
+    waku_default_pubsub_topic, waku_dns_discovery, waku_new, waku_set_event_callback,
+    ContentFilter, Encoding, Event, Key, MessageId, Multiaddr, PagingOptions, ProtocolId, Running,
+    StoreQuery, WakuContentTopic, WakuLogLevel, WakuMessage, WakuNodeConfig, WakuNodeHandle,
+    WakuPubSubTopic,
 };
 
+/// Number of messages requested per store page.
+const HISTORY_PAGE_SIZE: usize = 100;
+/// Upper bound on the pages fetched by one history query.
+const HISTORY_MAX_PAGES: usize = 50;
+
+/// A message fetched from the store of a peer.
+#[derive(Clone, Debug)]
+pub struct StoredMessage {
+    pub content_topic: String,
+    /// The payload as published, i.e. the event JSON, possibly encrypted or
+    /// replaced by its IPFS reference.
+    pub payload: Vec<u8>,
+    /// Sender timestamp in nanoseconds.
+    pub timestamp: i64,
+}
+
 /// Struct representing a Waku client.
 ///
 /// This struct contains configuration for the client, a handle to the running Waku node, an elliptic
@@ -45,8 +63,16 @@ impl WakuClient {
     pub async fn new(config: WakuConfig) -> Result<WakuClient, String> {
         let node_url = config.node_url.clone();
         let node_addr = config.node_addr.clone();
+        let node_key = match &config.node_key {
+            Some(key) => Some(
+                SecretKey::from_str(key.expose().trim_start_matches("0x"))
+                    .map_err(|e| format!("waku.node_key: {}", e))?,
+            ),
+            None => None,
+        };
         let node_config = WakuNodeConfig {
             host: IpAddr::from_str(node_url.as_str()).ok(),
+            node_key,
             log_level: Some(WakuLogLevel::Error),
             ..Default::default()
         };
@@ -55,29 +81,34 @@ impl WakuClient {
         let node = node.start()?;
         tracing::info!("Node peer id: {}", node.peer_id()?);
 
-        let address: Multiaddr = node_addr.parse().unwrap();
+        let address: Multiaddr = node_addr
+            .parse()
+            .map_err(|e| format!("waku.node_addr '{}': {}", node_addr, e))?;
         let peer_id = node.add_peer(&address, ProtocolId::Relay)?;
         node.connect_peer_with_id(&peer_id, None)?;
 
-        let content_topic: WakuContentTopic = config.content_topic.parse().unwrap();
-        let content_filter = ContentFilter::new(
-            Some(config.pubsub_topic.parse().unwrap()),
-            vec![content_topic.clone()],
-        );
+        let content_topic: WakuContentTopic = config
+            .content_topic
+            .parse()
+            .map_err(|e| format!("waku.content_topic '{}': {}", config.content_topic, e))?;
+        let pubsub_topic: WakuPubSubTopic = config
+            .pubsub_topic
+            .parse()
+            .map_err(|e| format!("waku.pubsub_topic '{}': {}", config.pubsub_topic, e))?;
+        let content_filter =
+            ContentFilter::new(Some(pubsub_topic.clone()), vec![content_topic.clone()]);
         node.relay_subscribe(&content_filter)?;
 
         let sk = SecretKey::new(&mut thread_rng());
         let ssk = Aes256Gcm::generate_key(&mut thread_rng());
 
-	let pubsub = config.pubsub_topic.clone();
-
         Ok(WakuClient {
             config,
             ec_privkey: sk,
             aes_key: ssk,
             node_handle: node,
             content_topic,
-            pubsub_topic: pubsub.parse().unwrap(),
+            pubsub_topic,
         })
     }
 
@@ -88,82 +119,162 @@ impl WakuClient {
     }
 
     fn try_publish_lightpush_messages(
-        self,
+        &self,
         msg: &WakuMessage,
     ) -> Result<HashSet<MessageId>, String> {
+        let self_id = self.node_handle.peer_id()?;
         let peer_id = self
             .node_handle
-            .peers()
-            .unwrap()
+            .peers()?
             .iter()
             .map(|peer| peer.peer_id())
-            .find(|id| id.as_str() != self.node_handle.peer_id().unwrap().as_str())
-            .unwrap()
-            .clone();
+            .find(|id| id.as_str() != self_id.as_str())
+            .cloned()
+            .ok_or_else(|| "no lightpush peer connected".to_string())?;
 
         Ok(HashSet::from([self
             .node_handle
             .lightpush_publish(msg, None, peer_id, None)?]))
     }
 
-    /// Sends a message through the Waku relay.
+    /// Returns the number of connected peers, the node itself excluded.
+    pub fn connected_peers(&self) -> Result<usize, String> {
+        let self_id = self.node_handle.peer_id()?;
+        Ok(self
+            .node_handle
+            .peers()?
+            .iter()
+            .filter(|peer| peer.connected() && peer.peer_id() != &self_id)
+            .count())
+    }
+
+    /// Dials `node_addr`, the `peers.bootstrap_peers` and the peers found at
+    /// `peers.dns_discovery_url`.
+    ///
+    /// Blocks on the node. Peers that cannot be dialed are logged and
+    /// skipped.
+    ///
+    /// # Returns
+    ///
+    /// The number of peers dialed successfully.
+    pub fn dial_bootstrap_peers(&self) -> Result<usize, String> {
+        let mut addresses = Vec::new();
+        let configured =
+            std::iter::once(&self.config.node_addr).chain(&self.config.peers.bootstrap_peers);
+        for address in configured {
+            match address.parse::<Multiaddr>() {
+                Ok(address) => addresses.push(address),
+                Err(e) => tracing::warn!("skipping waku peer '{}': {}", address, e),
+            }
+        }
+        if let Some(url) = &self.config.peers.dns_discovery_url {
+            let url = Url::parse(url)
+                .map_err(|e| format!("waku.peers.dns_discovery_url '{}': {}", url, e))?;
+            match waku_dns_discovery(&url, None, Some(Duration::from_secs(10))) {
+                Ok(found) => addresses.extend(found.into_iter().flat_map(|info| info.addresses)),
+                Err(e) => tracing::warn!("waku dns discovery at {} failed: {}", url, e),
+            }
+        }
+
+        let mut dialed = 0;
+        for address in addresses {
+            let result = self
+                .node_handle
+                .add_peer(&address, ProtocolId::Relay)
+                .and_then(|peer_id| {
+                    self.node_handle
+                        .connect_peer_with_id(&peer_id, Some(Duration::from_secs(10)))
+                });
+            match result {
+                Ok(()) => dialed += 1,
+                Err(e) => tracing::warn!("dialing waku peer {} failed: {}", address, e),
+            }
+        }
+        Ok(dialed)
+    }
+
+    /// Sends a message through the Waku relay, or a lightpush peer when
+    /// `waku.publish` is `lightpush`.
     ///
-    /// This method creates a new Waku message, publishes it through the relay, and returns the
+    /// This method creates a new Waku message, publishes it, and returns the
     /// message IDs of the successfully sent messages.
+    /// Failures of the node are returned instead of panicking.
     pub async fn send_message(&self, content: String) -> Result<HashSet<MessageId>, String> {
+        let timestamp: usize = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(|e| format!("system clock before unix epoch: {}", e))?
+            .as_millis()
+            .try_into()
+            .map_err(|e| format!("message timestamp out of range: {}", e))?;
         let message = WakuMessage::new(
             content,
             self.content_topic.clone(),
             1,
-            SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_millis()
-                .try_into()
-                .unwrap(),
+            timestamp,
             Vec::new(),
             false,
         );
 
-        let ids = self
-            .try_publish_relay_messages(&message)
-            .expect("send relay messages");
-
-        Ok(ids)
+        match self.config.publish {
+            WakuPublish::Relay => self.try_publish_relay_messages(&message),
+            WakuPublish::Lightpush => self.try_publish_lightpush_messages(&message),
+        }
+        .map_err(|e| format!("publishing waku message: {}", e))
     }
 
-    pub async fn listening_message_gowrapper(&self, tx: mpsc::Sender<String>) {
+    /// Runs the Go wrapper of the node and hands the lines it prints to `tx`.
+    ///
+    /// Returns once the wrapper exits or `tx` is closed, with an error if the
+    /// wrapper could not be started or exited with a failure status.
+    pub async fn listening_message_gowrapper(&self, tx: mpsc::Sender<String>) -> Result<(), String> {
         let mut child = Command::new(self.config.waku_bin.clone())
             .arg("verify")
             .arg("--shard")
-            .arg(self.config.shared.clone())
+            .arg(
+                self.config
+                    .shards
+                    .iter()
+                    .map(u32::to_string)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            )
             .arg("--maddr")
             .arg(self.config.node_addr.clone())
             .stdout(Stdio::piped())
             .spawn()
-            .unwrap();
+            .map_err(|e| format!("starting {}: {}", self.config.waku_bin, e))?;
 
-        let stdout = child.stdout.take().expect("Failed to capture stdout");
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| "capturing the output of the wrapper".to_string())?;
 
         let reader = io::BufReader::new(stdout);
 
         for line in reader.lines() {
             match line {
                 Ok(line) => {
-                    println!("Received from Go: {}", line);
-                    tx.send(line).await;
+                    tracing::debug!("received from the wrapper: {}", line);
+                    if tx.send(line).await.is_err() {
+                        let _ = child.kill();
+                        break;
+                    }
                 }
-                Err(e) => eprintln!("Error reading line: {}", e),
+                Err(e) => tracing::warn!("reading the output of the wrapper: {}", e),
             }
         }
 
-        let status = child.wait().unwrap();
-        println!("Go server exited with status: {}", status);
+        let status = child
+            .wait()
+            .map_err(|e| format!("waiting for the wrapper: {}", e))?;
+        if !status.success() && !tx.is_closed() {
+            return Err(format!("wrapper exited with {}", status));
+        }
+        tracing::info!("wrapper exited with {}", status);
+        Ok(())
     }
 
     pub async fn listening_message(&self, tx: mpsc::Sender<NostrEvent>) {
-        //let history = self.retrieve_history();
-
 	let content_topic_cl = self.content_topic.clone();
         waku_set_event_callback(move |signal| {
             if let Event::WakuMessage(message) = signal.event() {
@@ -175,11 +286,18 @@ impl WakuClient {
                     return;
                 }
                 let payload = message.payload().to_vec();
-                let msg = from_utf8(&payload).expect("should be valid message");
+                let msg = match from_utf8(&payload) {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        tracing::warn!("skipping waku message {:?}: {}", id, e);
+                        return;
+                    }
+                };
                 match serde_json::from_str::<NostrEvent>(msg) {
                     Ok(event) => {
-                        futures::executor::block_on(tx.send(event))
-                            .expect("send response to the receiver");
+                        if futures::executor::block_on(tx.send(event)).is_err() {
+                            tracing::warn!("dropping waku message {:?}, the receiver is closed", id);
+                        }
                     }
                     Err(e) => {
                         tracing::error!("{:?}", e);
@@ -189,44 +307,64 @@ impl WakuClient {
         });
     }
 
-    fn retrieve_history(&self) -> waku_bindings::Result<Vec<NostrEvent>> {
-        let self_id = self.node_handle.peer_id().unwrap();
+    /// Fetches the messages published on the content topics between `since`
+    /// and `until`, in nanoseconds since the epoch, from the store of a
+    /// connected peer, oldest first.
+    ///
+    /// Blocks on the node, and stops after `HISTORY_MAX_PAGES` pages. Fails
+    /// if no store peer is connected.
+    pub fn fetch_history(&self, since: i64, until: i64) -> Result<Vec<StoredMessage>, String> {
+        let self_id = self.node_handle.peer_id()?;
         let peer = self
             .node_handle
             .peers()?
             .iter()
             .find(|&peer| peer.peer_id() != &self_id)
             .cloned()
-            .unwrap();
-
-        let result = self.node_handle.store_query(
-            &StoreQuery {
-                pubsub_topic: None,
-                content_topics: vec![self.content_topic.clone()],
-                start_time: Some(
-                    (Duration::from_secs(Utc::now().timestamp() as u64)
-                        - Duration::from_secs(60 * 60 * 24))
-                    .as_nanos() as usize,
-                ),
-                end_time: None,
-                paging_options: Some(PagingOptions {
-                    page_size: 25,
-                    cursor: None,
-                    forward: true,
-                }),
-            },
-            peer.peer_id(),
-            Some(Duration::from_secs(10)),
-        )?;
-
-        Ok(result
-            .messages()
-            .iter()
-            .map(|waku_message| {
-                let msg = from_utf8(waku_message.payload()).expect("should be valid message");
-                serde_json::from_str::<NostrEvent>(msg)
-                    .expect("Toy chat messages should be decodeable")
+            .ok_or_else(|| "no store peer connected".to_string())?;
+        let content_topics = TopicRouter::new(&self.config)
+            .topics()
+            .into_iter()
+            .map(|topic| {
+                topic
+                    .parse::<WakuContentTopic>()
+                    .map_err(|e| format!("content topic '{}': {}", topic, e))
             })
-            .collect())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut messages = Vec::new();
+        let mut cursor = None;
+        for _ in 0..HISTORY_MAX_PAGES {
+            let response = self.node_handle.store_query(
+                &StoreQuery {
+                    pubsub_topic: Some(self.pubsub_topic.clone()),
+                    content_topics: content_topics.clone(),
+                    start_time: Some(since.max(0) as usize),
+                    end_time: Some(until.max(0) as usize),
+                    paging_options: Some(PagingOptions {
+                        page_size: HISTORY_PAGE_SIZE,
+                        cursor: cursor.take(),
+                        forward: true,
+                    }),
+                },
+                peer.peer_id(),
+                Some(Duration::from_secs(10)),
+            )?;
+
+            messages.extend(response.messages().iter().map(|message| StoredMessage {
+                content_topic: message.content_topic().to_string(),
+                payload: message.payload().to_vec(),
+                timestamp: message.timestamp() as i64,
+            }));
+            match response
+                .paging_options()
+                .and_then(|paging| paging.cursor.clone())
+            {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+
+        Ok(messages)
     }
 }
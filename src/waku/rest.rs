@@ -0,0 +1,158 @@
+//!This module provides the client of the REST API of a nwaku node. Messages
+//!are published either through the relay endpoints, e.g.
+//!`/relay/v1/auto/messages`, or through a lightpush service node, e.g.
+//!`/lightpush/v1/message`, as selected by `waku.publish`. Every request goes
+//!through `HttpClient`, so the retry policy of `waku.retry` and the timeouts
+//!of `waku.timeout_secs` and `timeouts.waku_secs` apply.
+
+use crate::common::config::{WakuConfig, WakuPublish};
+use crate::common::correlation::CorrelationId;
+use crate::common::error;
+use crate::common::http::HttpClient;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+
+/// A message as published through the REST API.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WakuMessage {
+    /// The base64 encoded payload.
+    pub payload: String,
+    pub content_topic: String,
+    /// Publish time, in nanoseconds since the epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+}
+
+impl WakuMessage {
+    /// Builds a message of `payload`, stamped with the current time.
+    pub fn new(payload: &[u8], content_topic: &str) -> Self {
+        Self {
+            payload: base64::engine::general_purpose::STANDARD.encode(payload),
+            content_topic: content_topic.to_string(),
+            timestamp: chrono::Utc::now().timestamp_nanos_opt(),
+        }
+    }
+}
+
+/// Body of a lightpush request.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LightpushRequest<'a> {
+    pubsub_topic: &'a str,
+    message: &'a WakuMessage,
+}
+
+/// JSON body of a lightpush v3 response. Older nodes answer in plain text.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LightpushResponse {
+    status_desc: Option<String>,
+    relay_peer_count: Option<u32>,
+}
+
+/// Outcome of a successful publish.
+#[derive(Clone, Debug)]
+pub struct PublishReceipt {
+    /// HTTP status of the response.
+    pub status: u16,
+    /// Description of the node, e.g. `OK`.
+    pub description: String,
+    /// Peers the message was relayed to, when the node tells.
+    pub relay_peer_count: Option<u32>,
+}
+
+/// Publishes messages through the REST API of a nwaku node.
+pub struct WakuRestClient {
+    http: HttpClient,
+    /// `waku.send_api`, updated on reloads.
+    url: RwLock<String>,
+    publish: WakuPublish,
+    pubsub_topic: String,
+}
+
+impl WakuRestClient {
+    pub fn new(waku: &WakuConfig, http: HttpClient) -> Self {
+        Self {
+            http,
+            url: RwLock::new(waku.send_api.clone()),
+            publish: waku.publish,
+            pubsub_topic: waku.pubsub_topic.clone(),
+        }
+    }
+
+    /// Points the client to another `send_api`.
+    pub fn set_url(&self, url: &str) {
+        *self.url.write().unwrap_or_else(|e| e.into_inner()) = url.to_string();
+    }
+
+    /// Publishes `message` with the protocol of `waku.publish`.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message to publish.
+    /// * `correlation_id` - Correlation id of the bridged event, if any.
+    pub async fn publish(
+        &self,
+        message: &WakuMessage,
+        correlation_id: Option<&CorrelationId>,
+    ) -> error::Result<PublishReceipt> {
+        match self.publish {
+            WakuPublish::Relay => self.relay(message, correlation_id).await,
+            WakuPublish::Lightpush => self.lightpush(message, correlation_id).await,
+        }
+    }
+
+    /// Posts `message` to a relay endpoint, which answers in plain text.
+    pub async fn relay(
+        &self,
+        message: &WakuMessage,
+        correlation_id: Option<&CorrelationId>,
+    ) -> error::Result<PublishReceipt> {
+        let url = self.url();
+        let response = self
+            .http
+            .post_json("waku relay publish", &url, message, correlation_id)
+            .await?;
+        let status = response.status().as_u16();
+        let description = response.text().await?;
+
+        Ok(PublishReceipt {
+            status,
+            description,
+            relay_peer_count: None,
+        })
+    }
+
+    /// Posts `message` to a lightpush endpoint, on the configured pubsub
+    /// topic.
+    pub async fn lightpush(
+        &self,
+        message: &WakuMessage,
+        correlation_id: Option<&CorrelationId>,
+    ) -> error::Result<PublishReceipt> {
+        let url = self.url();
+        let body = LightpushRequest {
+            pubsub_topic: &self.pubsub_topic,
+            message,
+        };
+        let response = self
+            .http
+            .post_json("waku lightpush publish", &url, &body, correlation_id)
+            .await?;
+        let status = response.status().as_u16();
+        let text = response.text().await?;
+        let parsed: LightpushResponse = serde_json::from_str(&text).unwrap_or_default();
+
+        Ok(PublishReceipt {
+            status,
+            description: parsed.status_desc.unwrap_or(text),
+            relay_peer_count: parsed.relay_peer_count,
+        })
+    }
+
+    fn url(&self) -> String {
+        self.url.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
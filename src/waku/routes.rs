@@ -0,0 +1,119 @@
+//!This module chooses the content topic of each event published to Waku from
+//!`waku.routes`, e.g. invites to `/acl/1/invites/proto` and notes to
+//!`/acl/1/notes/proto`. The receiving pipelines accept every routed topic.
+
+use crate::common::config::{WakuConfig, WakuTopicRoute};
+use nostr_sdk::Event;
+
+/// The content topics of the Waku pipelines.
+#[derive(Clone, Debug)]
+pub struct TopicRouter {
+    content_topic: String,
+    routes: Vec<WakuTopicRoute>,
+}
+
+impl TopicRouter {
+    pub fn new(waku: &WakuConfig) -> Self {
+        Self {
+            content_topic: waku.content_topic.clone(),
+            routes: waku.routes.clone(),
+        }
+    }
+
+    /// Returns the content topic of the first route listing the kind or a
+    /// hashtag of `event`, otherwise `content_topic`.
+    pub fn topic(&self, event: &Event) -> &str {
+        self.routes
+            .iter()
+            .find(|route| {
+                route.kinds.contains(&event.kind.as_u16())
+                    || event.tags.iter().any(|tag| match tag.as_slice() {
+                        [name, value, ..] => name == "t" && route.hashtags.contains(value),
+                        _ => false,
+                    })
+            })
+            .map_or(&self.content_topic, |route| &route.content_topic)
+    }
+
+    /// Returns whether events are published to `topic`.
+    pub fn contains(&self, topic: &str) -> bool {
+        self.content_topic == topic || self.routes.iter().any(|r| r.content_topic == topic)
+    }
+
+    /// Returns every content topic, `content_topic` first, without duplicates.
+    pub fn topics(&self) -> Vec<&str> {
+        let mut topics = vec![self.content_topic.as_str()];
+        for route in &self.routes {
+            if !topics.contains(&route.content_topic.as_str()) {
+                topics.push(&route.content_topic);
+            }
+        }
+        topics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{EventBuilder, Keys, Kind, Tag};
+
+    fn router() -> TopicRouter {
+        let route = |content_topic: &str, kinds: &[u16], hashtags: &[&str]| WakuTopicRoute {
+            content_topic: content_topic.to_string(),
+            kinds: kinds.to_vec(),
+            hashtags: hashtags.iter().map(|h| h.to_string()).collect(),
+        };
+        TopicRouter {
+            content_topic: "/acl/1/events/proto".to_string(),
+            routes: vec![
+                route("/acl/1/invites/proto", &[], &["invite"]),
+                route("/acl/1/notes/proto", &[1], &[]),
+                route("/acl/1/invites/proto", &[30078], &[]),
+            ],
+        }
+    }
+
+    fn event(kind: u16, hashtags: &[&str]) -> Event {
+        EventBuilder::new(Kind::from(kind), "")
+            .tags(hashtags.iter().map(|h| Tag::hashtag(*h)))
+            .sign_with_keys(&Keys::generate())
+            .unwrap()
+    }
+
+    #[test]
+    fn routes_on_the_first_matching_route() {
+        let router = router();
+        assert_eq!(router.topic(&event(1, &["invite"])), "/acl/1/invites/proto");
+        assert_eq!(router.topic(&event(1, &["other"])), "/acl/1/notes/proto");
+        assert_eq!(router.topic(&event(30078, &[])), "/acl/1/invites/proto");
+    }
+
+    #[test]
+    fn falls_back_to_the_content_topic() {
+        let router = router();
+        assert_eq!(router.topic(&event(7, &["other"])), "/acl/1/events/proto");
+        let unrouted = TopicRouter {
+            routes: Vec::new(),
+            ..router
+        };
+        assert_eq!(
+            unrouted.topic(&event(1, &["invite"])),
+            "/acl/1/events/proto"
+        );
+    }
+
+    #[test]
+    fn lists_every_topic_once() {
+        let router = router();
+        assert_eq!(
+            router.topics(),
+            [
+                "/acl/1/events/proto",
+                "/acl/1/invites/proto",
+                "/acl/1/notes/proto"
+            ]
+        );
+        assert!(router.contains("/acl/1/notes/proto"));
+        assert!(!router.contains("/acl/1/unknown/proto"));
+    }
+}
@@ -0,0 +1,105 @@
+//!This module provides the `n2w` sink publishing events through the REST API
+//!of a Waku node, see `WakuRestClient`. Each event, or its IPFS reference when
+//!it is too large, is published to its content topic, see `TopicRouter`, and
+//!encrypted first when `waku.encryption` is set.
+
+use super::{PayloadCipher, TopicRouter, WakuMessage, WakuRestClient};
+use crate::common::config::{Config, WakuConfig};
+use crate::common::correlation::CorrelationId;
+use crate::common::error::{self, ResultExt};
+use crate::common::sink::EventSink;
+use crate::ipfs::IpfsStore;
+use async_trait::async_trait;
+use nostr_sdk::{Event, JsonUtil};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Publishes events to their content topic through `waku.send_api`.
+pub struct WakuRestSink {
+    client: WakuRestClient,
+    router: TopicRouter,
+    ipfs: Option<Arc<IpfsStore>>,
+    cipher: Option<Arc<PayloadCipher>>,
+}
+
+impl WakuRestSink {
+    pub fn new(
+        waku: &WakuConfig,
+        client: WakuRestClient,
+        ipfs: Option<Arc<IpfsStore>>,
+        cipher: Option<Arc<PayloadCipher>>,
+    ) -> Self {
+        Self {
+            client,
+            router: TopicRouter::new(waku),
+            ipfs,
+            cipher,
+        }
+    }
+}
+
+#[async_trait]
+impl EventSink for WakuRestSink {
+    fn name(&self) -> &'static str {
+        "waku"
+    }
+
+    fn times_attempts(&self) -> bool {
+        true
+    }
+
+    fn topic(&self, event: &Event) -> Option<&str> {
+        Some(self.router.topic(event))
+    }
+
+    async fn send(&self, event: &Event, correlation_id: &CorrelationId) -> error::Result<()> {
+        self.send_to(event, correlation_id, None).await
+    }
+
+    async fn send_to(
+        &self,
+        event: &Event,
+        correlation_id: &CorrelationId,
+        topic: Option<&str>,
+    ) -> error::Result<()> {
+        let content_topic = topic.unwrap_or_else(|| self.router.topic(event));
+        // Encode the event payload, or its IPFS reference.
+        let payload = match &self.ipfs {
+            Some(ipfs) => ipfs.offload(event).await,
+            None => Ok(event.as_json()),
+        }
+        .context(|| format!("encoding event {}", event.id))?;
+        let payload = match &self.cipher {
+            Some(cipher) => cipher.seal(payload.as_bytes())?,
+            None => payload.into_bytes(),
+        };
+        let message = WakuMessage::new(&payload, content_topic);
+
+        // Send the message to the Waku node.
+        let started = Instant::now();
+        let receipt = self
+            .client
+            .publish(&message, Some(correlation_id))
+            .await
+            .context(|| {
+                format!(
+                    "publishing event {} to waku topic {}",
+                    event.id, content_topic
+                )
+            })?;
+
+        tracing::info!(
+            sink = "waku",
+            latency_ms = started.elapsed().as_millis() as u64,
+            status = receipt.status,
+            relay_peers = receipt.relay_peer_count,
+            "Response from server: {}",
+            receipt.description
+        );
+        Ok(())
+    }
+
+    fn reload(&self, config: &Config) {
+        self.client.set_url(&config.waku.send_api);
+    }
+}
@@ -0,0 +1,191 @@
+//!This module provides a webhook sink posting every bridged Nostr event to a
+//!list of urls, with a templated body and headers. Bodies can be signed with
+//!HMAC-SHA256 so receivers can authenticate the gateway.
+
+use crate::common::config::{Config, WebhookConfig};
+use crate::common::correlation::{CorrelationId, CORRELATION_HEADER};
+use crate::common::error;
+use crate::common::http::HttpClient;
+use crate::common::sink::{self, EventSink};
+use crate::common::template::render;
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use nostr_sdk::Event;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use sha2::Sha256;
+use std::sync::RwLock;
+
+/// Header carrying the id of the posted event, for receivers to dedupe on.
+const EVENT_ID_HEADER: &str = "X-Nostr-Event-Id";
+
+/// Names of the values templates can reference.
+const PLACEHOLDERS: [&str; 7] = [
+    "id",
+    "pubkey",
+    "kind",
+    "created_at",
+    "content",
+    "correlation_id",
+    "payload",
+];
+
+/// Posts bridged events to the configured urls.
+pub struct WebhookSink {
+    client: HttpClient,
+    config: WebhookConfig,
+    /// `webhook.urls`, updated on reloads.
+    urls: RwLock<Vec<String>>,
+    body_template: String,
+    escape_json: bool,
+    /// CloudEvents source of the posted events.
+    source: String,
+}
+
+impl WebhookSink {
+    /// Creates the sink, rejecting templates with unknown placeholders.
+    /// `source` is the CloudEvents source of the posted events.
+    pub fn new(config: &WebhookConfig, client: HttpClient, source: &str) -> error::Result<Self> {
+        let body_template = config
+            .body_template
+            .clone()
+            .unwrap_or_else(|| "{{payload}}".to_string());
+        for template in std::iter::once(&body_template).chain(config.headers.values()) {
+            render("webhook template", template, |name| {
+                PLACEHOLDERS.contains(&name).then(String::new)
+            })?;
+        }
+
+        Ok(Self {
+            client,
+            body_template,
+            escape_json: config.content_type.contains("json"),
+            source: source.to_string(),
+            urls: RwLock::new(config.urls.clone()),
+            config: config.clone(),
+        })
+    }
+
+    /// Returns the value of a placeholder for `event`. Strings are JSON
+    /// escaped, without the surrounding quotes, when `escape` is set.
+    fn value(
+        &self,
+        name: &str,
+        event: &Event,
+        correlation_id: &CorrelationId,
+        payload: &str,
+        escape: bool,
+    ) -> Option<String> {
+        let text = |value: String| {
+            if escape {
+                let quoted = serde_json::Value::String(value).to_string();
+                quoted[1..quoted.len() - 1].to_string()
+            } else {
+                value
+            }
+        };
+        match name {
+            "id" => Some(event.id.to_hex()),
+            "pubkey" => Some(event.pubkey.to_hex()),
+            "kind" => Some(event.kind.as_u16().to_string()),
+            "created_at" => Some(event.created_at.as_u64().to_string()),
+            "content" => Some(text(event.content.clone())),
+            "correlation_id" => Some(text(correlation_id.as_str().to_string())),
+            "payload" => Some(payload.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Builds the headers of a post of `body`.
+    fn headers(
+        &self,
+        event: &Event,
+        correlation_id: &CorrelationId,
+        payload: &str,
+        body: &[u8],
+    ) -> error::Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        let mut insert = |name: &str, value: &str| -> error::Result<()> {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| error::Error::InvalidConfig(format!("webhook.headers: {}", e)))?;
+            let value = HeaderValue::from_str(value).map_err(|e| {
+                error::Error::InvalidConfig(format!("webhook.headers.{}: {}", name, e))
+            })?;
+            headers.insert(name, value);
+            Ok(())
+        };
+
+        insert(CONTENT_TYPE.as_str(), &self.config.content_type)?;
+        insert(CORRELATION_HEADER, correlation_id.as_str())?;
+        insert(EVENT_ID_HEADER, &event.id.to_hex())?;
+        for (name, template) in &self.config.headers {
+            let value = render("webhook template", template, |placeholder| {
+                self.value(placeholder, event, correlation_id, payload, false)
+            })?;
+            insert(name, &value)?;
+        }
+        if let Some(secret) = &self.config.secret {
+            insert(&self.config.signature_header, &sign(secret.expose(), body)?)?;
+        }
+
+        Ok(headers)
+    }
+}
+
+/// Returns the `sha256=<hex>` HMAC signature of `body`.
+fn sign(secret: &str, body: &[u8]) -> error::Result<String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).map_err(|e| {
+        error::Error::InvalidKey {
+            field: "webhook.secret",
+            reason: e.to_string(),
+        }
+    })?;
+    mac.update(body);
+    Ok(format!(
+        "sha256={}",
+        hex::encode(mac.finalize().into_bytes())
+    ))
+}
+
+#[async_trait]
+impl EventSink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn times_attempts(&self) -> bool {
+        true
+    }
+
+    /// Posts the event to every url. Fails if any post fails, in which case
+    /// the pipeline re-sends it to all of them; receivers dedupe on the
+    /// `X-Nostr-Event-Id` header.
+    async fn send(&self, event: &Event, correlation_id: &CorrelationId) -> error::Result<()> {
+        let payload = sink::wrap(
+            event,
+            sink::encode(event, &self.config.payload)?,
+            &self.source,
+            &self.config.format,
+        )?;
+        let payload = String::from_utf8_lossy(&payload).into_owned();
+        let body = render("webhook template", &self.body_template, |name| {
+            self.value(name, event, correlation_id, &payload, self.escape_json)
+        })?;
+        let headers = self.headers(event, correlation_id, &payload, body.as_bytes())?;
+
+        let urls = self.urls.read().unwrap_or_else(|e| e.into_inner()).clone();
+        for url in &urls {
+            self.client
+                .post_bytes("webhook", url, &headers, body.as_bytes())
+                .await?;
+            tracing::debug!(sink = "webhook", "posted event {} to {}", event.id, url);
+        }
+
+        Ok(())
+    }
+
+    fn reload(&self, config: &Config) {
+        if let Some(webhook) = &config.webhook {
+            *self.urls.write().unwrap_or_else(|e| e.into_inner()) = webhook.urls.clone();
+        }
+    }
+}